@@ -0,0 +1,48 @@
+// Backup encryption and streaming shared by the talos-web and talos-storage
+// binaries, so both tiers write archives with the same scrypt cost and the
+// same chunking.
+use age::secrecy::SecretString;
+use axum::body::Bytes;
+use std::env;
+use std::io::{self, Write};
+use tokio::sync::mpsc;
+
+pub fn backup_passphrase() -> Option<SecretString> {
+    env::var("BACKUP_PASSPHRASE").ok()
+        .filter(|p| !p.is_empty())
+        .map(SecretString::from)
+}
+
+// scrypt needs 128 * r * 2^log_n bytes; age calibrates log_n to a second of
+// CPU, which on fast hosts exceeds the container memory limit, so it is fixed
+// (64 MiB).
+const SCRYPT_WORK_FACTOR: u8 = 16;
+
+pub fn encrypt_writer<W: Write>(out: W, passphrase: SecretString) -> Result<age::stream::StreamWriter<W>, String> {
+    let mut recipient = age::scrypt::Recipient::new(passphrase);
+    recipient.set_work_factor(SCRYPT_WORK_FACTOR);
+    let encryptor = age::Encryptor::with_recipients(std::iter::once(&recipient as &dyn age::Recipient)).map_err(|e| e.to_string())?;
+    encryptor.wrap_output(out).map_err(|e| e.to_string())
+}
+
+// Archives are streamed in chunks of this size through a channel of
+// STREAM_CHUNKS slots, so memory use does not grow with the archive.
+pub const CHUNK_SIZE: usize = 64 * 1024;
+pub const STREAM_CHUNKS: usize = 8;
+
+pub type Chunk = Result<Bytes, io::Error>;
+
+// Write side of a streamed response body; blocks while the client catches up
+pub struct ChannelWriter(pub mpsc::Sender<Chunk>);
+
+impl Write for ChannelWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "client disconnected"))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -10,13 +10,11 @@ use tokio::process::Command;
 use tokio::io::AsyncWriteExt;
 use std::sync::Mutex;
 use once_cell::sync::Lazy;
-use uuid::Uuid;
 use base64::{Engine as _, engine::general_purpose};
 use zeroize::Zeroize;
 use chrono::Utc;
 use hmac::{Hmac, Mac};
 use sha2::Sha256;
-use hex;
 
 type HmacSha256 = Hmac<Sha256>;
 // In-Memory Vault for the Master Key. Never written to disk.
@@ -28,6 +26,9 @@ pub struct CryptTask {
     pub mode: String,
     pub passphrase: Option<String>,
     pub key_type: Option<String>,
+    // Encryption recipients resolved by Storage from the nearest .gpg-id (defaults to GPG_ID)
+    #[serde(default)]
+    pub recipients: Option<Vec<String>>,
 }
 
 #[derive(Serialize)]
@@ -45,8 +46,47 @@ fn sign_response(result: &str) -> String {
     let shared_secret = env::var("SHARED_SECRET").unwrap_or_default();
     let mut mac = HmacSha256::new_from_slice(shared_secret.as_bytes()).unwrap();
     mac.update(result.as_bytes());
-    let signature = hex::encode(mac.finalize().into_bytes());
-    signature
+    hex::encode(mac.finalize().into_bytes())
+}
+
+// Fingerprint of the GPG_ID primary key; backups are bound to it
+async fn key_fingerprint(gpg_id: &str) -> Option<String> {
+    let output = Command::new("gpg")
+        .args(["--batch", "--with-colons", "--fingerprint", gpg_id])
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find(|l| l.starts_with("fpr:"))
+        .and_then(|l| l.split(':').nth(9))
+        .map(|f| f.to_string())
+}
+
+// Runs gpg feeding `input` on stdin
+async fn run_gpg(args: &[&str], input: &[u8]) -> Option<std::process::Output> {
+    let mut child = Command::new("gpg")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input).await.ok()?;
+    }
+    child.wait_with_output().await.ok()
+}
+
+#[derive(Deserialize)]
+struct VerifyPayload {
+    // Base64 of the signed bytes
+    data: String,
+    // Armored detached signature
+    signature: String,
 }
 
 pub async fn process_gpg(State(_state): State<AppState>, headers: HeaderMap, Json(req): Json<CryptTask>) -> Json<CryptResponse> {
@@ -114,6 +154,32 @@ pub async fn process_gpg(State(_state): State<AppState>, headers: HeaderMap, Jso
             }
         },
 
+        // Re-authentication: compares a key against the one held in memory
+        // without replacing it, so a wrong guess never seals the vault
+        "verify_key" => {
+            let stored = match VAULT_KEY.lock() {
+                Ok(guard) => match guard.as_ref() {
+                    Some(key) => key.clone(),
+                    None => return Json(CryptResponse { result: "ERROR_VAULT_SEALED".to_string(), signature: None }),
+                },
+                Err(_) => return Json(CryptResponse { result: "ERROR_LOCK_FAILED".to_string(), signature: None }),
+            };
+            // Comparing MACs keeps the check constant-time
+            let tag = |key: &[u8]| {
+                let mut mac = HmacSha256::new_from_slice(key).unwrap();
+                mac.update(b"talos-key-verify");
+                mac
+            };
+            let valid = tag(&stored).verify_slice(&tag(req.payload.as_bytes()).finalize().into_bytes()).is_ok();
+            let mut stored = stored;
+            stored.zeroize();
+
+            log_audit_event("vault_verify", if valid { "success" } else { "failed" }, "master key re-authentication");
+            let result = if valid { "KEY_VALID" } else { "KEY_INVALID" }.to_string();
+            let signature = sign_response(&result);
+            Json(CryptResponse { result, signature: Some(signature) })
+        },
+
         "initialize" => {
             log_audit_event("gpg_init", "started", &format!("initializing key for {}", gpg_id));
             
@@ -123,10 +189,10 @@ pub async fn process_gpg(State(_state): State<AppState>, headers: HeaderMap, Jso
                 .output()
                 .await;
                 
-            if let Ok(output) = check {
-                if output.status.success() {
-                    return Json(CryptResponse { result: "ERROR_ALREADY_INIT".to_string(), signature: None });
-                }
+            if let Ok(output) = check
+                && output.status.success()
+            {
+                return Json(CryptResponse { result: "ERROR_ALREADY_INIT".to_string(), signature: None });
             }
 
             let passphrase = &req.payload;
@@ -191,7 +257,6 @@ pub async fn process_gpg(State(_state): State<AppState>, headers: HeaderMap, Jso
         "import" => {
             let key_data = req.payload;
             let passphrase = req.passphrase.unwrap_or_default();
-            let gpg_id = env::var("GPG_ID").unwrap_or_default();
 
             let child = Command::new("gpg")
                 .args(["--batch", "--import"])
@@ -232,19 +297,22 @@ pub async fn process_gpg(State(_state): State<AppState>, headers: HeaderMap, Jso
                 .output()
                 .await;
             
-            return match output {
+            match output {
                 Ok(o) => Json(CryptResponse { result: String::from_utf8_lossy(&o.stdout).to_string(), signature: None }),
                 Err(_) => Json(CryptResponse { result: "ERROR_EXPORT".to_string(), signature: None }),
             }
         },
 
-        "decrypt" | "encrypt" => {
+        // "encrypt_binary" writes what `pass insert` writes: a binary,
+        // uncompressed message for the recipients only, returned as base64
+        "decrypt" | "encrypt" | "encrypt_binary" => {
             log_audit_event(&format!("gpg_{}", req.mode), "started", &format!("operation for {}", gpg_id));
             if req.mode == "decrypt" {
                 args.extend(["-d"]);
             } else {
                 args.extend(["-e", "-r", &gpg_id, "--armor"]);
             }
+            let binary = req.mode == "encrypt_binary";
 
             // Retrieve Key from Memory
             let passphrase = match VAULT_KEY.lock() {
@@ -257,15 +325,16 @@ pub async fn process_gpg(State(_state): State<AppState>, headers: HeaderMap, Jso
 
             let input = req.payload;
 
-            // Decode base64 if the payload is base64 encoded (from storage)
+            // Decode base64 if the payload is base64 encoded (from storage).
+            // Binary encryption always takes the plaintext as is.
             let _decoded_input = match general_purpose::STANDARD.decode(&input) {
-                Ok(decoded) => decoded,
-                Err(_) => input.into_bytes(),
+                Ok(decoded) if !binary => decoded,
+                _ => input.into_bytes(),
             };
 
             // Create temporary file for passphrase (less secure but works)
             let passphrase_file = "/tmp/gpg_passphrase";
-            if let Err(_) = fs::write(passphrase_file, &passphrase) {
+            if fs::write(passphrase_file, &passphrase).is_err() {
                 let mut p = passphrase;
                 p.zeroize();
                 return Json(CryptResponse { result: "ERROR_WRITE_PASSPHRASE_FILE".to_string(), signature: None });
@@ -273,11 +342,24 @@ pub async fn process_gpg(State(_state): State<AppState>, headers: HeaderMap, Jso
             let mut p = passphrase;
             p.zeroize();
 
+            let recipients = match req.recipients.as_ref() {
+                Some(r) if !r.is_empty() => r.clone(),
+                _ => vec![gpg_id.clone()],
+            };
+
             let mut final_args = vec!["--batch", "--pinentry-mode", "loopback", "--passphrase-file", passphrase_file, "--trust-model", "always"];
             if req.mode == "decrypt" {
                 final_args.push("-d");
             } else {
-                final_args.extend(["-e", "-r", &gpg_id, "--armor"]);
+                final_args.push("-e");
+                for recipient in &recipients {
+                    final_args.extend(["-r", recipient.as_str()]);
+                }
+                if binary {
+                    final_args.extend(["--quiet", "--yes", "--compress-algo=none", "--no-encrypt-to"]);
+                } else {
+                    final_args.push("--armor");
+                }
             }
 
             // Spawn GPG with stdin piped
@@ -298,7 +380,7 @@ pub async fn process_gpg(State(_state): State<AppState>, headers: HeaderMap, Jso
 
             // Write the payload to encrypt/decrypt
             if let Some(mut stdin) = child.stdin.take() {
-                if let Err(_) = stdin.write_all(&_decoded_input).await {
+                if stdin.write_all(&_decoded_input).await.is_err() {
                     let _ = fs::remove_file(passphrase_file);
                     return Json(CryptResponse { result: "ERROR_WRITE_PAYLOAD".to_string(), signature: None });
                 }
@@ -311,9 +393,16 @@ pub async fn process_gpg(State(_state): State<AppState>, headers: HeaderMap, Jso
             let _ = fs::remove_file(passphrase_file);
             
             match output {
+                Ok(o) if binary && (!o.status.success() || o.stdout.is_empty()) => {
+                    log_audit_event(&format!("gpg_{}", req.mode), "failed", &String::from_utf8_lossy(&o.stderr));
+                    Json(CryptResponse { result: "ERROR_GPG_EXEC".to_string(), signature: None })
+                },
                 Ok(o) => {
-                    let stdout = String::from_utf8_lossy(&o.stdout).to_string();
-                    let stderr = String::from_utf8_lossy(&o.stderr).to_string();
+                    let stdout = if binary {
+                        general_purpose::STANDARD.encode(&o.stdout)
+                    } else {
+                        String::from_utf8_lossy(&o.stdout).to_string()
+                    };
                     log_audit_event(&format!("gpg_{}", req.mode), "success", "operation completed");
                     let result = stdout;
                     let signature = sign_response(&result);
@@ -326,6 +415,96 @@ pub async fn process_gpg(State(_state): State<AppState>, headers: HeaderMap, Jso
             }
         },
 
+        "fingerprint" => match key_fingerprint(&gpg_id).await {
+            Some(result) => {
+                let signature = sign_response(&result);
+                Json(CryptResponse { result, signature: Some(signature) })
+            },
+            None => Json(CryptResponse { result: "ERROR_NO_KEY".to_string(), signature: None }),
+        },
+
+        "sign" => {
+            log_audit_event("gpg_sign", "started", &format!("detached signature for {}", gpg_id));
+
+            let mut passphrase = match VAULT_KEY.lock() {
+                Ok(guard) => match guard.as_ref() {
+                    Some(p) => p.clone(),
+                    None => return Json(CryptResponse { result: "ERROR_VAULT_SEALED".to_string(), signature: None }),
+                },
+                Err(_) => return Json(CryptResponse { result: "ERROR_LOCK_FAILED".to_string(), signature: None }),
+            };
+            let data = match general_purpose::STANDARD.decode(&req.payload) {
+                Ok(decoded) => decoded,
+                Err(_) => req.payload.into_bytes(),
+            };
+
+            let passphrase_file = format!("/tmp/gpg_passphrase_{}", uuid::Uuid::new_v4());
+            let written = fs::write(&passphrase_file, &passphrase);
+            passphrase.zeroize();
+            if written.is_err() {
+                return Json(CryptResponse { result: "ERROR_WRITE_PASSPHRASE_FILE".to_string(), signature: None });
+            }
+
+            let output = run_gpg(&[
+                "--batch", "--pinentry-mode", "loopback", "--passphrase-file", &passphrase_file,
+                "--local-user", &gpg_id, "--armor", "--detach-sign",
+            ], &data).await;
+            let _ = fs::remove_file(&passphrase_file);
+
+            match output {
+                Some(o) if o.status.success() => {
+                    log_audit_event("gpg_sign", "success", "signature created");
+                    let result = String::from_utf8_lossy(&o.stdout).to_string();
+                    let signature = sign_response(&result);
+                    Json(CryptResponse { result, signature: Some(signature) })
+                },
+                _ => {
+                    log_audit_event("gpg_sign", "failed", "gpg could not sign");
+                    Json(CryptResponse { result: "ERROR_GPG_EXEC".to_string(), signature: None })
+                },
+            }
+        },
+
+        "verify" => {
+            let Ok(task) = serde_json::from_str::<VerifyPayload>(&req.payload) else {
+                return Json(CryptResponse { result: "ERROR_INVALID_PAYLOAD".to_string(), signature: None });
+            };
+            let Ok(data) = general_purpose::STANDARD.decode(&task.data) else {
+                return Json(CryptResponse { result: "ERROR_INVALID_PAYLOAD".to_string(), signature: None });
+            };
+            let Some(expected) = key_fingerprint(&gpg_id).await else {
+                return Json(CryptResponse { result: "ERROR_NO_KEY".to_string(), signature: None });
+            };
+
+            let signature_file = format!("/tmp/talos_verify_{}.asc", uuid::Uuid::new_v4());
+            if fs::write(&signature_file, task.signature.as_bytes()).is_err() {
+                return Json(CryptResponse { result: "ERROR_WRITE_SIGNATURE_FILE".to_string(), signature: None });
+            }
+            let output = run_gpg(&["--batch", "--status-fd", "1", "--verify", &signature_file, "-"], &data).await;
+            let _ = fs::remove_file(&signature_file);
+
+            // Only a good signature made by our own key counts:
+            // VALIDSIG <fpr> ... [<primary-key-fpr>]
+            let valid = output.is_some_and(|o| {
+                o.status.success() && String::from_utf8_lossy(&o.stdout).lines().any(|l| {
+                    let mut fields = l.split_whitespace();
+                    fields.next() == Some("[GNUPG:]")
+                        && fields.next() == Some("VALIDSIG")
+                        && l.split_whitespace().skip(2).any(|f| f.eq_ignore_ascii_case(&expected))
+                })
+            });
+
+            if valid {
+                log_audit_event("gpg_verify", "success", "signature valid");
+                let result = "VALID".to_string();
+                let signature = sign_response(&result);
+                Json(CryptResponse { result, signature: Some(signature) })
+            } else {
+                log_audit_event("gpg_verify", "failed", "bad or foreign signature");
+                Json(CryptResponse { result: "ERROR_BAD_SIGNATURE".to_string(), signature: None })
+            }
+        },
+
         _ => Json(CryptResponse { result: "ERROR_INVALID_MODE".to_string(), signature: None }),
     }
 }
@@ -0,0 +1,1490 @@
+use axum::Json;
+use axum::extract::{Multipart, Query};
+use axum::extract::multipart::Field;
+use axum::http::{StatusCode, header};
+use axum::body::Body;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, env, fs, io::{self, Read, Seek, Write}, path::{Path as StdPath, PathBuf}};
+use once_cell::sync::Lazy;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use zip::write::SimpleFileOptions;
+use chrono::{DateTime, Utc};
+use base64::{Engine as _, engine::general_purpose};
+use sha2::{Sha256, Digest};
+use crate::config::{DEBUG_MODE, STORE_PATH};
+use crate::handlers::{bunker_call, commit_changes, is_git_path, log_audit_event, validate_path, EXTENSIONS_DIR, WRITE_LOCK};
+use crate::trash::TRASH_DIR;
+use crate::models::{BackupQuery, BunkerTask};
+use crate::search::schedule_rebuild;
+use crate::backup_stream::{ChannelWriter, CHUNK_SIZE, STREAM_CHUNKS};
+
+// Every backup carries a manifest of its entries and a detached signature of
+// that manifest made with the vault key. Restores verify the signature first
+// and then every archive entry against the manifest.
+pub const MANIFEST_FILE: &str = "MANIFEST.json";
+pub const MANIFEST_SIG_FILE: &str = "MANIFEST.json.asc";
+const MANIFEST_FORMAT: u32 = 1;
+// About 80,000 entries; anything larger is not a manifest this service wrote
+const MAX_MANIFEST_BYTES: u64 = 16 * 1024 * 1024;
+
+// Restores accept at most this much, uploaded or unpacked, so that neither a
+// huge archive nor a zip bomb can fill the disk
+pub static RESTORE_MAX_BYTES: Lazy<u64> = Lazy::new(|| {
+    env::var("RESTORE_MAX_BYTES").ok().and_then(|v| v.parse().ok()).unwrap_or(1024 * 1024 * 1024)
+});
+
+// Automatic snapshots taken before destructive restores
+pub static SNAPSHOT_DIR: Lazy<String> = Lazy::new(|| {
+    env::var("RESTORE_SNAPSHOT_DIR").unwrap_or_else(|_| "/home/talosuser/.talos-snapshots".to_string())
+});
+const SNAPSHOTS_KEPT: usize = 10;
+
+// Restores are assembled next to the live store and swapped in by renaming
+// top-level entries, with the replaced ones parked until the swap succeeded.
+// Both live inside STORE_PATH because the store root itself is a mount point.
+pub(crate) const STAGING_DIR: &str = ".restore-staging";
+pub(crate) const ROLLBACK_DIR: &str = ".restore-rollback";
+
+// commit_changes stages the whole store, so the work areas are excluded from
+// git before first use; a leftover from an interrupted restore would otherwise
+// be committed and pushed as a second copy of the store.
+pub(crate) fn exclude_work_dirs(store: &StdPath) -> io::Result<()> {
+    let git_dir = store.join(".git");
+    if !git_dir.is_dir() {
+        return Ok(());
+    }
+    let exclude = git_dir.join("info").join("exclude");
+    let current = fs::read_to_string(&exclude).unwrap_or_default();
+    let missing: Vec<String> = [STAGING_DIR, ROLLBACK_DIR].iter()
+        .map(|dir| format!("/{}/", dir))
+        .filter(|pattern| !current.lines().any(|l| l.trim() == pattern))
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+    fs::create_dir_all(git_dir.join("info"))?;
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(&exclude)?;
+    if !current.is_empty() && !current.ends_with('\n') {
+        writeln!(file)?;
+    }
+    writeln!(file, "# Talos restore work areas")?;
+    for pattern in missing {
+        writeln!(file, "{}", pattern)?;
+    }
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ManifestEntry {
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+    // Modification time of the file when the backup was taken (RFC 3339)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BackupManifest {
+    pub format: u32,
+    pub talos_version: String,
+    pub created_at: String,
+    pub gpg_id: String,
+    pub gpg_fingerprint: String,
+    pub files: Vec<ManifestEntry>,
+    // Files archived next to this one by the web tier (talos.db), listed here
+    // so that restores can check them against the signature too
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub external: Vec<ManifestEntry>,
+}
+
+fn sha256_hex(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+fn modified_time(path: &StdPath) -> Option<String> {
+    let modified = fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    Some(DateTime::<Utc>::from(modified).to_rfc3339())
+}
+
+// Files of the store that go into a backup, relative to the store root
+fn store_files(store_path: &str) -> Vec<(String, PathBuf)> {
+    let mut files = Vec::new();
+    for entry in walkdir::WalkDir::new(store_path).sort_by_file_name().into_iter().filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if !path.is_file() { continue; }
+        let Some(name) = path.strip_prefix(store_path).ok().and_then(|p| p.to_str()) else { continue };
+        // Ignore git folder and restore work areas
+        if is_git_path(name) || name.starts_with(STAGING_DIR) || name.starts_with(ROLLBACK_DIR) { continue; }
+        files.push((name.to_string(), path.to_path_buf()));
+    }
+    files
+}
+
+// Matches archive entries against a manifest one at a time: every entry must be
+// listed with the same size and hash, and every listed file must be present.
+struct EntryCheck<'a> {
+    expected: BTreeMap<&'a str, &'a ManifestEntry>,
+}
+
+impl<'a> EntryCheck<'a> {
+    fn new(files: &'a [ManifestEntry]) -> Result<Self, String> {
+        let mut expected = BTreeMap::new();
+        for file in files {
+            if expected.insert(file.path.as_str(), file).is_some() {
+                return Err(format!("Duplicate manifest entry: {}", file.path));
+            }
+        }
+        Ok(EntryCheck { expected })
+    }
+
+    fn check(&mut self, name: &str, content: &[u8]) -> Result<(), String> {
+        self.check_digest(name, content.len() as u64, &sha256_hex(content))
+    }
+
+    fn check_size(&mut self, name: &str, size: u64) -> Result<(), String> {
+        let Some(file) = self.expected.remove(name) else {
+            return Err(format!("Entry not in manifest: {}", name));
+        };
+        if file.size != size {
+            return Err(format!("Entry modified: {}", name));
+        }
+        Ok(())
+    }
+
+    fn check_digest(&mut self, name: &str, size: u64, sha256: &str) -> Result<(), String> {
+        let Some(file) = self.expected.remove(name) else {
+            return Err(format!("Entry not in manifest: {}", name));
+        };
+        if file.size != size || file.sha256 != sha256 {
+            return Err(format!("Entry modified: {}", name));
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<(), String> {
+        match self.expected.keys().next() {
+            Some(missing) => Err(format!("Entry missing from archive: {}", missing)),
+            None => Ok(()),
+        }
+    }
+}
+
+// Checks the entry names and sizes of an archive's central directory against
+// the manifest; the hashes are checked as each entry is extracted
+pub fn verify_entries(manifest: &BackupManifest, sizes: &BTreeMap<String, u64>) -> Result<(), String> {
+    let mut check = EntryCheck::new(&manifest.files)?;
+    for (name, size) in sizes {
+        check.check_size(name, *size)?;
+    }
+    check.finish()
+}
+
+// Checks the web tier's files, hashed by the caller, against the external
+// entries of a verified manifest
+pub fn verify_external(manifest: &BackupManifest, claimed: &[ManifestEntry]) -> Result<(), String> {
+    let mut check = EntryCheck::new(&manifest.external)?;
+    for file in claimed {
+        check.check_digest(&file.path, file.size, &file.sha256)?;
+    }
+    check.finish()
+}
+
+// Backups leaving the service are age archives (scrypt passphrase recipient,
+// ChaCha20-Poly1305 payload), the same format the web tier produces.
+pub fn decrypt_reader<R: Read>(input: R, passphrase: age::secrecy::SecretString) -> Result<age::stream::StreamReader<R>, String> {
+    let decryptor = age::Decryptor::new(input).map_err(|e| e.to_string())?;
+    let identity = age::scrypt::Identity::new(passphrase);
+    decryptor.decrypt(std::iter::once(&identity as &dyn age::Identity)).map_err(|e| e.to_string())
+}
+
+// Writes a signed backup archive of the store to `out` while reading the files.
+// Checksums are computed as entries are streamed, so the manifest and its
+// signature are the last entries of the archive.
+pub fn write_backup<W: Write>(
+    out: W,
+    store_path: &str,
+    gpg_fingerprint: String,
+    external: Vec<ManifestEntry>,
+    sign: impl FnOnce(&[u8]) -> Result<String, String>,
+) -> Result<(W, BackupManifest), String> {
+    let mut zip_writer = zip::ZipWriter::new_stream(out);
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+
+    let mut files = Vec::new();
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+    for (name, path) in store_files(store_path) {
+        // Files removed since the listing are left out
+        let Ok(mut file) = fs::File::open(&path) else { continue };
+        let large = file.metadata().map(|m| m.len() >= u32::MAX as u64).unwrap_or(true);
+        zip_writer.start_file(name.as_str(), options.large_file(large)).map_err(|e| e.to_string())?;
+
+        let mut hasher = Sha256::new();
+        let mut size = 0u64;
+        loop {
+            let n = file.read(&mut chunk).map_err(|e| format!("{}: {}", name, e))?;
+            if n == 0 { break; }
+            hasher.update(&chunk[..n]);
+            zip_writer.write_all(&chunk[..n]).map_err(|e| e.to_string())?;
+            size += n as u64;
+        }
+        files.push(ManifestEntry { path: name, size, sha256: format!("{:x}", hasher.finalize()), modified: modified_time(&path) });
+    }
+
+    let manifest = BackupManifest {
+        format: MANIFEST_FORMAT,
+        talos_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: Utc::now().to_rfc3339(),
+        gpg_id: env::var("GPG_ID").unwrap_or_default(),
+        gpg_fingerprint,
+        files,
+        external,
+    };
+    let manifest_json = serde_json::to_vec_pretty(&manifest).map_err(|e| e.to_string())?;
+    let manifest_sig = sign(&manifest_json)?;
+
+    zip_writer.start_file(MANIFEST_FILE, options).map_err(|e| e.to_string())?;
+    zip_writer.write_all(&manifest_json).map_err(|e| e.to_string())?;
+    zip_writer.start_file(MANIFEST_SIG_FILE, options).map_err(|e| e.to_string())?;
+    zip_writer.write_all(manifest_sig.as_bytes()).map_err(|e| e.to_string())?;
+
+    let mut out = zip_writer.finish().map_err(|e| e.to_string())?.into_inner();
+    out.flush().map_err(|e| e.to_string())?;
+    Ok((out, manifest))
+}
+
+async fn key_fingerprint(client: &reqwest::Client) -> Result<String, String> {
+    bunker_call(client, &BunkerTask {
+        payload: String::new(),
+        mode: "fingerprint".to_string(),
+        signature: None,
+        recipients: None,
+    }).await.map_err(|e| format!("key fingerprint unavailable: {}", e))
+}
+
+// Signing needs the unsealed vault key
+async fn sign_manifest(client: &reqwest::Client, manifest_json: &[u8]) -> Result<String, String> {
+    bunker_call(client, &BunkerTask {
+        payload: general_purpose::STANDARD.encode(manifest_json),
+        mode: "sign".to_string(),
+        signature: None,
+        recipients: None,
+    }).await.map_err(|e| format!("manifest signing failed: {}", e))
+}
+
+async fn verify_manifest(client: &reqwest::Client, manifest_json: &[u8], manifest_sig: &[u8]) -> Result<BackupManifest, String> {
+    let verify_payload = json!({
+        "data": general_purpose::STANDARD.encode(manifest_json),
+        "signature": String::from_utf8_lossy(manifest_sig),
+    });
+    bunker_call(client, &BunkerTask {
+        payload: verify_payload.to_string(),
+        mode: "verify".to_string(),
+        signature: None,
+        recipients: None,
+    }).await.map_err(|_| "Manifest signature verification failed".to_string())?;
+
+    let manifest: BackupManifest = serde_json::from_slice(manifest_json).map_err(|_| "Invalid manifest".to_string())?;
+    if manifest.format != MANIFEST_FORMAT {
+        return Err(format!("Unsupported manifest format: {}", manifest.format));
+    }
+    Ok(manifest)
+}
+
+// Streams a backup of the store into `out` on a blocking thread
+pub async fn backup_to<W: Write + Send + 'static>(client: &reqwest::Client, out: W, external: Vec<ManifestEntry>) -> Result<(W, BackupManifest), String> {
+    let gpg_fingerprint = key_fingerprint(client).await?;
+    let client = client.clone();
+    let runtime = tokio::runtime::Handle::current();
+    tokio::task::spawn_blocking(move || {
+        write_backup(out, STORE_PATH.as_str(), gpg_fingerprint, external, |manifest_json| runtime.block_on(sign_manifest(&client, manifest_json)))
+    }).await.map_err(|e| e.to_string())?
+}
+
+pub async fn download_backup(Query(query): Query<BackupQuery>) -> Response {
+    if *DEBUG_MODE { println!("--> [STORAGE] BACKUP request initiated"); }
+    let client = reqwest::Client::new();
+
+    let external = match (query.external_path, query.external_size, query.external_sha256) {
+        (Some(path), Some(size), Some(sha256)) => vec![ManifestEntry { path, size, sha256, modified: None }],
+        (None, None, None) => Vec::new(),
+        _ => return (StatusCode::BAD_REQUEST, Json(json!({"error": "External file needs a path, size and sha256"}))).into_response(),
+    };
+
+    // The status is sent before the archive is produced, so a vault that
+    // cannot sign the manifest is refused up front
+    let vault = bunker_call(&client, &BunkerTask {
+        payload: String::new(),
+        mode: "check".to_string(),
+        signature: None,
+        recipients: None,
+    }).await;
+    if vault.as_deref() != Ok("UNSEALED") {
+        log_audit_event("storage_backup", "failed", &format!("vault not available: {}", vault.unwrap_or_else(|e| e)));
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(json!({"error": "Could not sign backup manifest (vault sealed?)"}))).into_response();
+    }
+
+    let (tx, rx) = mpsc::channel(STREAM_CHUNKS);
+    tokio::spawn(async move {
+        let writer = io::BufWriter::with_capacity(CHUNK_SIZE, ChannelWriter(tx.clone()));
+        match backup_to(&client, writer, external).await {
+            Ok((_, manifest)) => {
+                log_audit_event("storage_backup", "success", &format!("backup created with {} files, manifest signed by {}", manifest.files.len(), manifest.gpg_fingerprint));
+            },
+            Err(e) => {
+                log_audit_event("storage_backup", "failed", &e);
+                // Abort the body so the client never mistakes a truncated archive for a backup
+                let _ = tx.send(Err(io::Error::other(e))).await;
+            },
+        }
+    });
+
+    (
+        [
+            (header::CONTENT_TYPE, "application/zip"),
+            (header::CONTENT_DISPOSITION, "attachment; filename=\"talos_backup.zip\""),
+        ],
+        Body::from_stream(ReceiverStream::new(rx)),
+    ).into_response()
+}
+
+// Saves a signed backup of the current store before it gets overwritten
+async fn take_snapshot(client: &reqwest::Client) -> Result<String, String> {
+    fs::create_dir_all(SNAPSHOT_DIR.as_str()).map_err(|e| e.to_string())?;
+    let name = format!("talos-snapshot-{}.zip", Utc::now().format("%Y%m%dT%H%M%S%3fZ"));
+    let path = StdPath::new(SNAPSHOT_DIR.as_str()).join(&name);
+    let file = fs::File::create(&path).map_err(|e| e.to_string())?;
+    if let Err(e) = backup_to(client, io::BufWriter::new(file), Vec::new()).await {
+        let _ = fs::remove_file(&path);
+        return Err(e);
+    }
+
+    // Keep only the most recent snapshots
+    let mut snapshots: Vec<PathBuf> = fs::read_dir(SNAPSHOT_DIR.as_str())
+        .map(|dir| dir.flatten().map(|e| e.path()).filter(|p| p.extension().is_some_and(|e| e == "zip")).collect())
+        .unwrap_or_default();
+    snapshots.sort();
+    let excess = snapshots.len().saturating_sub(SNAPSHOTS_KEPT);
+    for old in &snapshots[..excess] {
+        let _ = fs::remove_file(old);
+    }
+    Ok(name)
+}
+
+// Test-extracts an archive without loading it whole: the manifest signature is
+// checked first, then every entry is hashed and validated as it is read.
+pub fn check_archive<R: Read + io::Seek>(
+    reader: R,
+    verify: impl FnOnce(&[u8], &[u8]) -> Result<BackupManifest, String>,
+) -> Result<BackupManifest, String> {
+    let mut archive = zip::ZipArchive::new(reader).map_err(|_| "Invalid zip file".to_string())?;
+    let mut read_entry = |name: &str| -> Result<Vec<u8>, String> {
+        let mut file = archive.by_name(name).map_err(|_| "Backup has no signed manifest".to_string())?;
+        let mut content = Vec::new();
+        file.read_to_end(&mut content).map_err(|_| format!("Corrupted entry: {}", name))?;
+        Ok(content)
+    };
+    let manifest_json = read_entry(MANIFEST_FILE)?;
+    let manifest_sig = read_entry(MANIFEST_SIG_FILE)?;
+    let manifest = verify(&manifest_json, &manifest_sig)?;
+
+    let mut check = EntryCheck::new(&manifest.files)?;
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).map_err(|_| "Corrupted archive".to_string())?;
+        let name = file.name().map_err(|_| "Corrupted archive".to_string())?.to_string();
+        if file.is_dir() || name == MANIFEST_FILE || name == MANIFEST_SIG_FILE { continue; }
+        // One entry at a time, so memory is bounded by the largest secret
+        let mut content = Vec::new();
+        file.read_to_end(&mut content).map_err(|_| format!("Corrupted entry: {}", name))?;
+        check.check(&name, &content)?;
+        if name.ends_with(".gpg") {
+            check_openpgp(&content).map_err(|e| format!("{}: {}", name, e))?;
+        }
+    }
+    check.finish()?;
+    Ok(manifest)
+}
+
+// Runs check_archive on a blocking thread, verifying the signature with the bunker
+pub async fn verify_archive<R: Read + io::Seek + Send + 'static>(client: &reqwest::Client, reader: R) -> Result<BackupManifest, String> {
+    let client = client.clone();
+    let runtime = tokio::runtime::Handle::current();
+    tokio::task::spawn_blocking(move || {
+        check_archive(reader, |manifest_json, manifest_sig| runtime.block_on(verify_manifest(&client, manifest_json, manifest_sig)))
+    }).await.map_err(|e| e.to_string())?
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct EntryDigest {
+    pub size: u64,
+    pub sha256: String,
+}
+
+// A backup archive whose manifest has been authenticated; its entries are
+// still in the archive and only read when extracted
+pub struct OpenedBackup<R> {
+    archive: zip::ZipArchive<R>,
+    pub manifest: Option<BackupManifest>,
+    pub entries: BTreeMap<String, EntryDigest>,
+}
+
+fn too_large(limit: u64) -> String {
+    format!("Backup exceeds the restore limit of {} bytes", limit)
+}
+
+fn read_manifest_entry<R: Read + Seek>(archive: &mut zip::ZipArchive<R>, name: &str) -> Result<Option<Vec<u8>>, String> {
+    let file = match archive.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(_) => return Err("Corrupted archive".to_string()),
+    };
+    let mut content = Vec::new();
+    file.take(MAX_MANIFEST_BYTES + 1).read_to_end(&mut content).map_err(|_| format!("Corrupted entry: {}", name))?;
+    if content.len() as u64 > MAX_MANIFEST_BYTES {
+        return Err(format!("Entry too large: {}", name));
+    }
+    Ok(Some(content))
+}
+
+// Copies an entry to `out` in chunks, returning its size and hash. Reads at
+// most one byte past `size`, so a central directory that understates an
+// entry cannot make it unpack past the limit.
+fn copy_entry<R: Read + Seek>(archive: &mut zip::ZipArchive<R>, name: &str, size: u64, out: &mut impl Write) -> Result<EntryDigest, String> {
+    let file = archive.by_name(name).map_err(|_| format!("Corrupted entry: {}", name))?;
+    let mut reader = file.take(size + 1);
+    let mut hasher = Sha256::new();
+    let mut read = 0u64;
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+    loop {
+        let n = reader.read(&mut chunk).map_err(|_| format!("Corrupted entry: {}", name))?;
+        if n == 0 { break; }
+        hasher.update(&chunk[..n]);
+        out.write_all(&chunk[..n]).map_err(|e| format!("{}: {}", name, e))?;
+        read += n as u64;
+    }
+    Ok(EntryDigest { size: read, sha256: format!("{:x}", hasher.finalize()) })
+}
+
+// Opens a backup archive from its central directory and authenticates it
+// without unpacking the entries. Archives made before manifests existed have
+// none and are only accepted with `allow_legacy`, their entries hashed here
+// instead; an archive that does carry a manifest is always verified.
+pub fn read_backup<R: Read + Seek>(
+    data: R,
+    allow_legacy: bool,
+    limit: u64,
+    verify: impl FnOnce(&[u8], &[u8]) -> Result<BackupManifest, String>,
+) -> Result<OpenedBackup<R>, String> {
+    let mut archive = zip::ZipArchive::new(data).map_err(|_| "Invalid zip file".to_string())?;
+
+    let mut sizes = BTreeMap::new();
+    let mut total = 0u64;
+    for i in 0..archive.len() {
+        let file = archive.by_index_raw(i).map_err(|_| "Corrupted archive".to_string())?;
+        if file.is_dir() { continue; }
+        let name = file.name().map_err(|_| "Corrupted archive".to_string())?.to_string();
+        total = total.saturating_add(file.size());
+        if total > limit {
+            return Err(too_large(limit));
+        }
+        if sizes.insert(name.clone(), file.size()).is_some() {
+            return Err(format!("Duplicate entry: {}", name));
+        }
+    }
+
+    let manifest_json = read_manifest_entry(&mut archive, MANIFEST_FILE)?;
+    let manifest_sig = read_manifest_entry(&mut archive, MANIFEST_SIG_FILE)?;
+    let (manifest_json, manifest_sig) = match (manifest_json, manifest_sig) {
+        (Some(json), Some(sig)) => (json, sig),
+        (None, None) if allow_legacy => {
+            // Legacy archives also carried the old, never-valid checksum file
+            sizes.remove("SHA256_CHECKSUM.txt");
+            let mut entries = BTreeMap::new();
+            for (name, size) in sizes {
+                let digest = copy_entry(&mut archive, &name, size, &mut io::sink())?;
+                if digest.size != size {
+                    return Err(format!("Corrupted entry: {}", name));
+                }
+                entries.insert(name, digest);
+            }
+            return Ok(OpenedBackup { archive, manifest: None, entries });
+        },
+        _ => return Err("Backup has no signed manifest".to_string()),
+    };
+
+    let manifest = verify(&manifest_json, &manifest_sig)?;
+    sizes.remove(MANIFEST_FILE);
+    sizes.remove(MANIFEST_SIG_FILE);
+    verify_entries(&manifest, &sizes)?;
+    let entries = manifest.files.iter()
+        .map(|f| (f.path.clone(), EntryDigest { size: f.size, sha256: f.sha256.clone() }))
+        .collect();
+    Ok(OpenedBackup { archive, manifest: Some(manifest), entries })
+}
+
+// Runs read_backup on a blocking thread, verifying the signature with the bunker
+pub async fn open_backup<R: Read + Seek + Send + 'static>(client: &reqwest::Client, data: R, allow_legacy: bool) -> Result<OpenedBackup<R>, String> {
+    let client = client.clone();
+    let runtime = tokio::runtime::Handle::current();
+    tokio::task::spawn_blocking(move || {
+        read_backup(data, allow_legacy, *RESTORE_MAX_BYTES, |manifest_json, manifest_sig| {
+            runtime.block_on(verify_manifest(&client, manifest_json, manifest_sig))
+        })
+    }).await.map_err(|e| e.to_string())?
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum MergePolicy {
+    /// Backup version replaces the current file
+    #[default]
+    Overwrite,
+    /// Backup version only replaces files modified before the backup was taken
+    KeepNewer,
+    /// Current secret stays, the backup version is written next to it
+    KeepBoth,
+}
+
+struct RestoreForm {
+    data: Option<fs::File>,
+    legacy: bool,
+    // Archive paths (or directory prefixes) to restore; everything when absent
+    paths: Option<Vec<String>>,
+    policy: MergePolicy,
+    // The web tier's own files from the same backup, for verify_external
+    external: Vec<ManifestEntry>,
+}
+
+// Uploads go to an unnamed temporary file as they arrive instead of being
+// buffered, so a restore is bound by RESTORE_MAX_BYTES rather than by memory
+// or the request body limit
+async fn spool_upload(mut field: Field<'_>, limit: u64) -> Result<fs::File, String> {
+    let file = tempfile::tempfile().map_err(|e| format!("Could not spool upload: {}", e))?;
+    let mut file = tokio::fs::File::from_std(file);
+    let mut size = 0u64;
+    while let Some(chunk) = field.chunk().await.map_err(|_| "Failed to read backup data".to_string())? {
+        size += chunk.len() as u64;
+        if size > limit {
+            return Err(too_large(limit));
+        }
+        tokio::io::AsyncWriteExt::write_all(&mut file, &chunk).await.map_err(|e| format!("Could not spool upload: {}", e))?;
+    }
+    tokio::io::AsyncWriteExt::flush(&mut file).await.map_err(|e| format!("Could not spool upload: {}", e))?;
+    let mut file = file.into_std().await;
+    file.rewind().map_err(|e| format!("Could not spool upload: {}", e))?;
+    Ok(file)
+}
+
+async fn read_restore_form(mut multipart: Multipart) -> Result<RestoreForm, String> {
+    let mut form = RestoreForm { data: None, legacy: false, paths: None, policy: MergePolicy::default(), external: Vec::new() };
+    while let Some(field) = multipart.next_field().await.unwrap_or(None) {
+        match field.name() {
+            Some("backup") => form.data = Some(spool_upload(field, *RESTORE_MAX_BYTES).await?),
+            Some("legacy") => form.legacy = field.text().await.map(|v| v == "true").unwrap_or(false),
+            Some("paths") => {
+                let text = field.text().await.unwrap_or_default();
+                form.paths = Some(serde_json::from_str(&text).map_err(|_| "Invalid paths list".to_string())?);
+            },
+            Some("policy") => {
+                let text = field.text().await.unwrap_or_default();
+                form.policy = serde_json::from_value(Value::String(text)).map_err(|_| "Invalid merge policy".to_string())?;
+            },
+            Some("external") => {
+                let text = field.text().await.unwrap_or_default();
+                form.external = serde_json::from_str(&text).map_err(|_| "Invalid external entries".to_string())?;
+            },
+            _ => {},
+        }
+    }
+    Ok(form)
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct PlanEntry {
+    pub path: String,
+    /// add | overwrite | unchanged | untouched (only in the current store)
+    pub action: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub backup_modified: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_modified: Option<String>,
+}
+
+// Where an archive entry lands in the store; None for anything escaping it
+fn restore_target(store_path: &str, name: &str) -> Option<PathBuf> {
+    if name.split('/').any(|c| c == ".." || c.is_empty()) || is_git_path(name) {
+        return None;
+    }
+    let path = StdPath::new(store_path).join(name);
+    path.starts_with(store_path).then_some(path)
+}
+
+fn is_selected(name: &str, paths: Option<&[String]>) -> bool {
+    paths.is_none_or(|paths| paths.iter().any(|p| {
+        let p = p.trim_matches('/');
+        name == p || name.starts_with(&format!("{}/", p))
+    }))
+}
+
+fn file_digest(path: &StdPath) -> Option<EntryDigest> {
+    let mut file = fs::File::open(path).ok()?;
+    let mut hasher = Sha256::new();
+    let size = io::copy(&mut file, &mut hasher).ok()?;
+    Some(EntryDigest { size, sha256: format!("{:x}", hasher.finalize()) })
+}
+
+// Compares a backup with the current store, entry by entry
+pub fn plan_restore(manifest: Option<&BackupManifest>, entries: &BTreeMap<String, EntryDigest>, store_path: &str) -> Vec<PlanEntry> {
+    let backup_times: BTreeMap<&str, Option<String>> = manifest
+        .map(|m| m.files.iter().map(|f| (f.path.as_str(), f.modified.clone())).collect())
+        .unwrap_or_default();
+    let mut current: BTreeMap<String, PathBuf> = store_files(store_path).into_iter().collect();
+
+    let mut plan = Vec::new();
+    for (name, digest) in entries {
+        let Some(target) = restore_target(store_path, name) else { continue };
+        let existing = current.remove(name).or_else(|| target.is_file().then(|| target.clone()));
+        let action = match &existing {
+            None => "add",
+            // Only files of the same size are hashed
+            Some(path) if fs::metadata(path).is_ok_and(|m| m.len() == digest.size)
+                && file_digest(path).is_some_and(|d| d == *digest) => "unchanged",
+            Some(_) => "overwrite",
+        };
+        plan.push(PlanEntry {
+            path: name.clone(),
+            action,
+            backup_modified: backup_times.get(name.as_str()).cloned().flatten(),
+            current_modified: existing.as_deref().and_then(modified_time),
+        });
+    }
+    for (name, path) in current {
+        plan.push(PlanEntry { current_modified: modified_time(&path), path: name, action: "untouched", backup_modified: None });
+    }
+    plan.sort_by(|a, b| a.path.cmp(&b.path));
+    plan
+}
+
+#[derive(Debug, PartialEq)]
+enum Decision {
+    Write,
+    WriteAs(String),
+    Skip(&'static str),
+}
+
+fn decide(entry: &PlanEntry, policy: MergePolicy, stamp: &str) -> Decision {
+    match (entry.action, policy) {
+        ("add", _) => Decision::Write,
+        ("overwrite", MergePolicy::Overwrite) => Decision::Write,
+        ("overwrite", MergePolicy::KeepNewer) => {
+            let parse = |t: &Option<String>| t.as_deref().and_then(|t| DateTime::parse_from_rfc3339(t).ok());
+            // Backups without timestamps (legacy) never count as newer
+            match (parse(&entry.backup_modified), parse(&entry.current_modified)) {
+                (Some(backup), Some(current)) if backup > current => Decision::Write,
+                _ => Decision::Skip("current is newer"),
+            }
+        },
+        ("overwrite", MergePolicy::KeepBoth) => match entry.path.strip_suffix(".gpg") {
+            Some(stem) => Decision::WriteAs(format!("{}.restored-{}.gpg", stem, stamp)),
+            None => Decision::Skip("kept current"),
+        },
+        _ => Decision::Skip("unchanged"),
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct RestoreIssue {
+    pub path: String,
+    pub error: String,
+}
+
+// Structured report returned when a restore is refused; the store is unchanged
+// unless `rolled_back` is false at the swap stage.
+#[derive(Serialize, Debug)]
+pub struct RestoreReport {
+    /// verify | validate | staging | swap
+    pub stage: &'static str,
+    pub errors: Vec<RestoreIssue>,
+    pub rolled_back: bool,
+}
+
+fn restore_failure(status: StatusCode, report: RestoreReport) -> (StatusCode, Json<Value>) {
+    let summary = report.errors.iter().map(|e| format!("{}: {}", e.path, e.error)).collect::<Vec<_>>().join("; ");
+    log_audit_event("storage_restore", "failed", &format!("{} stage: {}", report.stage, summary));
+    let error = match report.errors.first() {
+        Some(first) if report.errors.len() == 1 => format!("Restore failed: {}", first.error),
+        _ => format!("Restore failed at {} stage ({} errors)", report.stage, report.errors.len()),
+    };
+    (status, Json(json!({"error": error, "report": report})))
+}
+
+fn issue(path: &str, error: impl ToString) -> RestoreIssue {
+    RestoreIssue { path: path.to_string(), error: error.to_string() }
+}
+
+// Archive entries follow the same path rules as API writes; trashed entries
+// are checked below their `.trash/<id>/` slot.
+pub fn validate_entry_path(name: &str) -> Result<(), String> {
+    if is_git_path(name) || name.starts_with(STAGING_DIR) || name.starts_with(ROLLBACK_DIR) {
+        return Err("Reserved path".to_string());
+    }
+    let relative = name.strip_prefix(&format!("{}/", TRASH_DIR))
+        .or_else(|| name.strip_prefix(&format!("{}/", EXTENSIONS_DIR)))
+        .unwrap_or(name);
+    validate_path(relative)?;
+    if name.split('/').any(|c| c.is_empty()) {
+        return Err("Empty path component".to_string());
+    }
+    Ok(())
+}
+
+// Checks that the content is a well-formed OpenPGP message: armored, or a
+// binary packet stream starting with an encryption (or literal) packet and
+// whose packet lengths add up to the file size.
+pub fn check_openpgp(content: &[u8]) -> Result<(), String> {
+    check_openpgp_from(content)
+}
+
+const ARMOR_HEADER: &[u8] = b"-----BEGIN PGP MESSAGE-----";
+
+// check_openpgp over a reader: packet bodies are skipped, not buffered, so a
+// large attachment is checked in constant memory. Armored messages are
+// decoded whole; gpg only writes those for small secrets.
+pub fn check_openpgp_from<R: Read>(mut reader: R) -> Result<(), String> {
+    let mut head = Vec::with_capacity(ARMOR_HEADER.len());
+    reader.by_ref().take(ARMOR_HEADER.len() as u64).read_to_end(&mut head).map_err(|e| e.to_string())?;
+    if head == ARMOR_HEADER {
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).map_err(|e| e.to_string())?;
+        let text = std::str::from_utf8(&rest).map_err(|_| "Armor is not valid UTF-8".to_string())?;
+        if !text.contains("-----END PGP MESSAGE-----") {
+            return Err("Truncated armored message".to_string());
+        }
+        let body: String = text.lines()
+            .skip(1)
+            .skip_while(|l| !l.trim().is_empty())
+            .take_while(|l| !l.starts_with('=') && !l.starts_with("-----END"))
+            .collect::<Vec<_>>()
+            .concat();
+        let binary = general_purpose::STANDARD.decode(body.trim()).map_err(|_| "Invalid armor encoding".to_string())?;
+        return check_packets(binary.as_slice());
+    }
+    check_packets(head.as_slice().chain(reader))
+}
+
+// Reads packet headers and skips packet bodies, keeping count of the position
+struct Packets<R> {
+    reader: R,
+    pos: u64,
+}
+
+impl<R: Read> Packets<R> {
+    fn byte(&mut self) -> Result<Option<u8>, String> {
+        let mut byte = [0u8; 1];
+        match self.reader.read_exact(&mut byte) {
+            Ok(()) => {
+                self.pos += 1;
+                Ok(Some(byte[0]))
+            },
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    fn length(&mut self, octets: usize) -> Result<u64, String> {
+        let mut length = 0u64;
+        for _ in 0..octets {
+            length = (length << 8) | self.byte()?.ok_or("Truncated packet length")? as u64;
+        }
+        Ok(length)
+    }
+
+    fn skip(&mut self, length: u64) -> Result<(), String> {
+        let skipped = io::copy(&mut self.reader.by_ref().take(length), &mut io::sink()).map_err(|e| e.to_string())?;
+        self.pos += skipped;
+        if skipped < length {
+            return Err("Truncated packet".to_string());
+        }
+        Ok(())
+    }
+
+    fn skip_rest(&mut self) -> Result<(), String> {
+        self.pos += io::copy(&mut self.reader, &mut io::sink()).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
+
+fn check_packets<R: Read>(reader: R) -> Result<(), String> {
+    let mut packets = Packets { reader, pos: 0 };
+    let mut first = true;
+    while let Some(header) = packets.byte()? {
+        if header & 0x80 == 0 {
+            return Err(format!("Invalid packet header at byte {}", packets.pos - 1));
+        }
+        let (tag, length) = if header & 0x40 != 0 {
+            // New format: partial body lengths chain until a final length
+            let tag = header & 0x3f;
+            loop {
+                let b0 = packets.length(1)?;
+                match b0 {
+                    0..=191 => break (tag, Some(b0)),
+                    192..=223 => break (tag, Some(((b0 - 192) << 8) + packets.length(1)? + 192)),
+                    255 => break (tag, Some(packets.length(4)?)),
+                    _ => packets.skip(1 << (b0 & 0x1f))?,
+                }
+            }
+        } else {
+            let tag = (header >> 2) & 0x0f;
+            let length = match header & 0x03 {
+                0 => Some(packets.length(1)?),
+                1 => Some(packets.length(2)?),
+                2 => Some(packets.length(4)?),
+                // Indeterminate length runs to the end of the file
+                _ => None,
+            };
+            (tag, length)
+        };
+
+        // PKESK, SKESK, compressed, marker, literal, SEIPD, AEAD
+        if first && !matches!(tag, 1 | 3 | 8 | 10 | 11 | 18 | 20) {
+            return Err(format!("Unexpected first packet (tag {})", tag));
+        }
+        first = false;
+        match length {
+            Some(length) => packets.skip(length)?,
+            None => packets.skip_rest()?,
+        }
+    }
+    if first {
+        return Err("Empty message".to_string());
+    }
+    Ok(())
+}
+
+// Mirrors the live store into the staging area (hard links, so nothing is
+// copied) and has `write` fill in each restored file, given as its path in
+// the store and the archive entry it comes from.
+fn build_staging(
+    store: &StdPath,
+    staging: &StdPath,
+    writes: &[(String, String)],
+    mut write: impl FnMut(&str, &mut fs::File) -> Result<(), String>,
+) -> Result<(), RestoreIssue> {
+    if staging.exists() {
+        fs::remove_dir_all(staging).map_err(|e| issue(STAGING_DIR, e))?;
+    }
+    fs::create_dir_all(staging).map_err(|e| issue(STAGING_DIR, e))?;
+
+    let walker = walkdir::WalkDir::new(store).min_depth(1).into_iter().filter_entry(|e| {
+        let name = e.file_name().to_string_lossy();
+        e.depth() > 1 || !(name == ".git" || name == STAGING_DIR || name == ROLLBACK_DIR)
+    });
+    for entry in walker {
+        let entry = entry.map_err(|e| issue("store", e))?;
+        let Ok(relative) = entry.path().strip_prefix(store) else { continue };
+        let target = staging.join(relative);
+        let name = relative.to_string_lossy();
+        if entry.file_type().is_dir() {
+            fs::create_dir_all(&target).map_err(|e| issue(&name, e))?;
+        } else if fs::hard_link(entry.path(), &target).is_err() {
+            fs::copy(entry.path(), &target).map_err(|e| issue(&name, e))?;
+        }
+    }
+
+    for (name, entry) in writes {
+        let target = staging.join(name);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|e| issue(name, e))?;
+        }
+        // Never write through a hard link into the live store
+        if target.exists() {
+            fs::remove_file(&target).map_err(|e| issue(name, e))?;
+        }
+        let mut file = fs::OpenOptions::new().read(true).write(true).create_new(true).open(&target).map_err(|e| issue(name, e))?;
+        write(entry, &mut file).map_err(|e| issue(name, e))?;
+    }
+    Ok(())
+}
+
+impl<R: Read + Seek> OpenedBackup<R> {
+    // Unpacks entries into a fresh staging area one chunk at a time, checking
+    // each against its hash and every secret for well-formed OpenPGP data.
+    // Mismatches and malformed secrets are all reported together, and the
+    // staging area is removed on any failure.
+    fn stage(&mut self, store: &StdPath, staging: &StdPath, writes: &[(String, String)]) -> Result<(), (StatusCode, RestoreReport)> {
+        let (archive, entries) = (&mut self.archive, &self.entries);
+        let mut modified = Vec::new();
+        let mut invalid = Vec::new();
+        let staged = build_staging(store, staging, writes, |entry, file| {
+            let expected = entries.get(entry).ok_or_else(|| format!("Entry missing from archive: {}", entry))?;
+            let mut out = io::BufWriter::new(&mut *file);
+            let digest = copy_entry(archive, entry, expected.size, &mut out)?;
+            out.flush().map_err(|e| e.to_string())?;
+            drop(out);
+            if digest != *expected {
+                modified.push(issue(entry, format!("Entry modified: {}", entry)));
+            } else if entry.ends_with(".gpg") {
+                file.rewind().map_err(|e| e.to_string())?;
+                if let Err(e) = check_openpgp_from(io::BufReader::new(&mut *file)) {
+                    invalid.push(issue(entry, e));
+                }
+            }
+            Ok(())
+        });
+
+        let failure = match staged {
+            Err(e) => Some((StatusCode::INTERNAL_SERVER_ERROR, "staging", vec![e])),
+            Ok(()) if !modified.is_empty() => Some((StatusCode::BAD_REQUEST, "verify", modified)),
+            Ok(()) if !invalid.is_empty() => Some((StatusCode::UNPROCESSABLE_ENTITY, "validate", invalid)),
+            Ok(()) => None,
+        };
+        match failure {
+            Some((status, stage, errors)) => {
+                let _ = fs::remove_dir_all(staging);
+                Err((status, RestoreReport { stage, errors, rolled_back: true }))
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+fn top_level_entries(dir: &StdPath) -> Result<Vec<std::ffi::OsString>, String> {
+    let entries = fs::read_dir(dir).map_err(|e| e.to_string())?;
+    Ok(entries.flatten()
+        .map(|e| e.file_name())
+        .filter(|n| n != ".git" && n != STAGING_DIR && n != ROLLBACK_DIR)
+        .collect())
+}
+
+// Moves the live top-level entries into the rollback area and the staged ones
+// into the store. Any failed rename undoes the ones already made.
+fn swap_in(store: &StdPath, staging: &StdPath, rollback: &StdPath) -> Result<(), (String, bool)> {
+    if rollback.exists() {
+        return Err((format!("{} exists from an interrupted restore; inspect it before restoring again", ROLLBACK_DIR), true));
+    }
+    fs::create_dir(rollback).map_err(|e| (e.to_string(), true))?;
+
+    let undo = |moved_out: &[std::ffi::OsString], moved_in: &[std::ffi::OsString]| -> bool {
+        let mut ok = true;
+        for name in moved_in {
+            ok &= fs::rename(store.join(name), staging.join(name)).is_ok();
+        }
+        for name in moved_out {
+            ok &= fs::rename(rollback.join(name), store.join(name)).is_ok();
+        }
+        if ok {
+            let _ = fs::remove_dir_all(rollback);
+        }
+        ok
+    };
+
+    let live = top_level_entries(store).map_err(|e| (e, true))?;
+    let mut moved_out = Vec::new();
+    for name in live {
+        if let Err(e) = fs::rename(store.join(&name), rollback.join(&name)) {
+            let rolled_back = undo(&moved_out, &[]);
+            return Err((format!("{}: {}", name.to_string_lossy(), e), rolled_back));
+        }
+        moved_out.push(name);
+    }
+
+    let staged = match top_level_entries(staging) {
+        Ok(staged) => staged,
+        Err(e) => {
+            let rolled_back = undo(&moved_out, &[]);
+            return Err((e, rolled_back));
+        }
+    };
+    let mut moved_in = Vec::new();
+    for name in staged {
+        if let Err(e) = fs::rename(staging.join(&name), store.join(&name)) {
+            let rolled_back = undo(&moved_out, &moved_in);
+            return Err((format!("{}: {}", name.to_string_lossy(), e), rolled_back));
+        }
+        moved_in.push(name);
+    }
+
+    let _ = fs::remove_dir_all(staging);
+    let _ = fs::remove_dir_all(rollback);
+    Ok(())
+}
+
+pub async fn preview_restore(multipart: Multipart) -> (StatusCode, Json<Value>) {
+    if *DEBUG_MODE { println!("--> [STORAGE] RESTORE PREVIEW request"); }
+
+    let form = match read_restore_form(multipart).await {
+        Ok(form) => form,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))),
+    };
+    let Some(data) = form.data else {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": "No backup file provided"})));
+    };
+
+    let client = reqwest::Client::new();
+    let backup = match open_backup(&client, data, form.legacy).await {
+        Ok(backup) => backup,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))),
+    };
+    let manifest = &backup.manifest;
+    if let Some(manifest) = manifest
+        && let Err(e) = verify_external(manifest, &form.external)
+    {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    }
+
+    let plan = plan_restore(manifest.as_ref(), &backup.entries, STORE_PATH.as_str());
+    (StatusCode::OK, Json(json!({
+        "legacy": manifest.is_none(),
+        "created_at": manifest.as_ref().map(|m| m.created_at.clone()),
+        "talos_version": manifest.as_ref().map(|m| m.talos_version.clone()),
+        "entries": plan,
+    })))
+}
+
+pub async fn restore_backup(multipart: Multipart) -> (StatusCode, Json<Value>) {
+    log_audit_event("storage_restore", "started", "restoring from backup");
+
+    if *DEBUG_MODE { println!("--> [STORAGE] RESTORE request initiated"); }
+
+    let form = match read_restore_form(multipart).await {
+        Ok(form) => form,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))),
+    };
+    let Some(data) = form.data else {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": "No backup file provided"})));
+    };
+    let client = reqwest::Client::new();
+
+    let mut backup = match open_backup(&client, data, form.legacy).await {
+        Ok(backup) => backup,
+        Err(e) => return restore_failure(StatusCode::BAD_REQUEST, RestoreReport {
+            stage: "verify",
+            errors: vec![issue("archive", e)],
+            rolled_back: true,
+        }),
+    };
+    if let Some(manifest) = &backup.manifest
+        && let Err(e) = verify_external(manifest, &form.external)
+    {
+        return restore_failure(StatusCode::BAD_REQUEST, RestoreReport { stage: "verify", errors: vec![issue("archive", e)], rolled_back: true });
+    }
+    match &backup.manifest {
+        Some(manifest) => log_audit_event("storage_restore", "success", &format!(
+            "manifest verified: {} files, created {} by Talos {}", manifest.files.len(), manifest.created_at, manifest.talos_version
+        )),
+        None => log_audit_event("storage_restore", "warning", "legacy backup without manifest, restoring unverified"),
+    }
+
+    // Validate every entry path up front, reporting every problem at once;
+    // contents are checked as they are unpacked
+    let errors: Vec<RestoreIssue> = backup.entries.keys()
+        .filter_map(|name| validate_entry_path(name).err().map(|e| issue(name, e)))
+        .collect();
+    if !errors.is_empty() {
+        return restore_failure(StatusCode::UNPROCESSABLE_ENTITY, RestoreReport { stage: "validate", errors, rolled_back: true });
+    }
+
+    let store_path = STORE_PATH.as_str();
+    let _write_guard = WRITE_LOCK.lock().await;
+
+    let stamp = Utc::now().format("%Y%m%dT%H%M%S").to_string();
+    let decisions: Vec<(PlanEntry, Decision)> = plan_restore(backup.manifest.as_ref(), &backup.entries, store_path)
+        .into_iter()
+        .filter(|e| e.action != "untouched" && is_selected(&e.path, form.paths.as_deref()))
+        .map(|e| {
+            let decision = decide(&e, form.policy, &stamp);
+            (e, decision)
+        })
+        .collect();
+
+    let mut restored = Vec::new();
+    let mut kept_both = Vec::new();
+    let mut skipped = Vec::new();
+    let mut writes: Vec<(String, String)> = Vec::new();
+    for (entry, decision) in &decisions {
+        match decision {
+            Decision::Write => {
+                writes.push((entry.path.clone(), entry.path.clone()));
+                restored.push(entry.path.clone());
+            },
+            Decision::WriteAs(name) => {
+                writes.push((name.clone(), entry.path.clone()));
+                kept_both.push(json!({"path": entry.path, "restored_as": name}));
+            },
+            Decision::Skip(reason) => skipped.push(json!({"path": entry.path, "reason": reason})),
+        }
+    }
+
+    let store = StdPath::new(store_path);
+    let staging = store.join(STAGING_DIR);
+    let staged = !writes.is_empty();
+    if staged {
+        if let Err(e) = exclude_work_dirs(store) {
+            return restore_failure(StatusCode::INTERNAL_SERVER_ERROR, RestoreReport { stage: "staging", errors: vec![issue(".git/info/exclude", e)], rolled_back: true });
+        }
+        let staging = staging.clone();
+        let result = tokio::task::spawn_blocking(move || backup.stage(store, &staging, &writes)).await
+            .unwrap_or_else(|e| Err((StatusCode::INTERNAL_SERVER_ERROR, RestoreReport { stage: "staging", errors: vec![issue(STAGING_DIR, e)], rolled_back: true })));
+        if let Err((status, report)) = result {
+            return restore_failure(status, report);
+        }
+    }
+
+    // Snapshot the current state before replacing anything in it
+    let destructive = decisions.iter().any(|(e, d)| e.action == "overwrite" && *d == Decision::Write);
+    let snapshot = if destructive {
+        match take_snapshot(&client).await {
+            Ok(name) => {
+                log_audit_event("storage_restore", "snapshot", &format!("pre-restore snapshot {}", name));
+                Some(name)
+            },
+            Err(e) => {
+                let _ = fs::remove_dir_all(&staging);
+                log_audit_event("storage_restore", "failed", &format!("pre-restore snapshot failed: {}", e));
+                return (StatusCode::SERVICE_UNAVAILABLE, Json(json!({"error": "Could not snapshot the current store before restoring (vault sealed?)"})));
+            }
+        }
+    } else {
+        None
+    };
+
+    if staged
+        && let Err((e, rolled_back)) = swap_in(store, &staging, &store.join(ROLLBACK_DIR))
+    {
+        if rolled_back {
+            let _ = fs::remove_dir_all(&staging);
+        }
+        return restore_failure(StatusCode::INTERNAL_SERVER_ERROR, RestoreReport { stage: "swap", errors: vec![issue("store", e)], rolled_back });
+    }
+
+    commit_changes("Restored from backup");
+    schedule_rebuild();
+    log_audit_event("storage_restore", "success", &format!(
+        "policy {:?}: {} restored, {} kept both, {} skipped", form.policy, restored.len(), kept_both.len(), skipped.len()
+    ));
+    (StatusCode::OK, Json(json!({
+        "status": "Restored successfully",
+        "restored": restored,
+        "kept_both": kept_both,
+        "skipped": skipped,
+        "snapshot": snapshot,
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peak_alloc::peak_since;
+
+    fn manifest_for(entries: &BTreeMap<String, Vec<u8>>) -> BackupManifest {
+        BackupManifest {
+            format: MANIFEST_FORMAT,
+            talos_version: "test".to_string(),
+            created_at: String::new(),
+            gpg_id: String::new(),
+            gpg_fingerprint: String::new(),
+            files: entries.iter().map(|(n, c)| ManifestEntry { path: n.clone(), size: c.len() as u64, sha256: sha256_hex(c), modified: None }).collect(),
+            external: Vec::new(),
+        }
+    }
+
+    fn digests(entries: &BTreeMap<String, Vec<u8>>) -> BTreeMap<String, EntryDigest> {
+        entries.iter().map(|(n, c)| (n.clone(), EntryDigest { size: c.len() as u64, sha256: sha256_hex(c) })).collect()
+    }
+
+    // A zip archive of the given entries, deflated like third-party tools do
+    fn archive_of(entries: &[(&str, &[u8])]) -> io::Cursor<Vec<u8>> {
+        let mut zip_writer = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            zip_writer.start_file(*name, SimpleFileOptions::default()).unwrap();
+            zip_writer.write_all(content).unwrap();
+        }
+        let mut archive = zip_writer.finish().unwrap();
+        archive.rewind().unwrap();
+        archive
+    }
+
+    fn parse_manifest(json: &[u8], _sig: &[u8]) -> Result<BackupManifest, String> {
+        serde_json::from_slice(json).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn verify_entries_rejects_modified_extra_and_missing_entries() {
+        let mut entries = BTreeMap::new();
+        entries.insert("web/github.gpg".to_string(), b"cipher".to_vec());
+        entries.insert(".gpg-id".to_string(), b"admin@talos.local\n".to_vec());
+        let manifest = manifest_for(&entries);
+        let sizes: BTreeMap<String, u64> = entries.iter().map(|(n, c)| (n.clone(), c.len() as u64)).collect();
+        assert!(verify_entries(&manifest, &sizes).is_ok());
+
+        let mut modified = sizes.clone();
+        modified.insert("web/github.gpg".to_string(), 8);
+        assert!(verify_entries(&manifest, &modified).unwrap_err().starts_with("Entry modified"));
+
+        let mut extra = sizes.clone();
+        extra.insert("injected.gpg".to_string(), 0);
+        assert!(verify_entries(&manifest, &extra).unwrap_err().starts_with("Entry not in manifest"));
+
+        let mut missing = sizes.clone();
+        missing.remove(".gpg-id");
+        assert!(verify_entries(&manifest, &missing).unwrap_err().starts_with("Entry missing"));
+    }
+
+    #[test]
+    fn archives_are_checked_as_they_are_unpacked() {
+        let secret = [0xC1, 0x02, 0x03, 0x00, 0xD2, 0x01, 0x01];
+        let mut contents = BTreeMap::new();
+        contents.insert("web/github.gpg".to_string(), secret.to_vec());
+        contents.insert("web/notes.gpg".to_string(), b"not openpgp".to_vec());
+        let manifest = serde_json::to_vec(&manifest_for(&contents)).unwrap();
+        let store = tempfile::tempdir().unwrap();
+        let staging = store.path().join(STAGING_DIR);
+        let write = |name: &str| vec![(name.to_string(), name.to_string())];
+
+        let mut backup = read_backup(
+            archive_of(&[("web/github.gpg", &secret), ("web/notes.gpg", b"not openpgp"), (MANIFEST_FILE, &manifest), (MANIFEST_SIG_FILE, b"sig")]),
+            false, 1024, parse_manifest,
+        ).unwrap();
+        assert_eq!(backup.entries, digests(&contents));
+        backup.stage(store.path(), &staging, &write("web/github.gpg")).unwrap();
+        assert_eq!(fs::read(staging.join("web/github.gpg")).unwrap(), secret);
+        let (status, report) = backup.stage(store.path(), &staging, &write("web/notes.gpg")).unwrap_err();
+        assert_eq!((status, report.stage), (StatusCode::UNPROCESSABLE_ENTITY, "validate"));
+        assert!(!staging.exists());
+
+        // Same size, different bytes: only the hash taken while unpacking tells
+        let mut backup = read_backup(
+            archive_of(&[("web/github.gpg", &[0xC1, 0x02, 0x03, 0x00, 0xD2, 0x01, 0x02]), ("web/notes.gpg", b"not openpgp"), (MANIFEST_FILE, &manifest), (MANIFEST_SIG_FILE, b"sig")]),
+            false, 1024, parse_manifest,
+        ).unwrap();
+        let (status, report) = backup.stage(store.path(), &staging, &write("web/github.gpg")).unwrap_err();
+        assert_eq!((status, report.stage), (StatusCode::BAD_REQUEST, "verify"));
+
+        // Sizes are summed from the central directory before anything is unpacked
+        let bomb = vec![0u8; 4096];
+        let error = read_backup(archive_of(&[("bomb.bin", &bomb)]), true, 1024, parse_manifest).err().unwrap();
+        assert!(error.starts_with("Backup exceeds the restore limit"), "{}", error);
+        let legacy = read_backup(archive_of(&[("a.gpg", &secret), ("SHA256_CHECKSUM.txt", b"")]), true, 1024, parse_manifest).unwrap();
+        assert!(legacy.manifest.is_none());
+        assert_eq!(legacy.entries.keys().collect::<Vec<_>>(), ["a.gpg"]);
+        assert_eq!(read_backup(archive_of(&[("a.gpg", &secret)]), false, 1024, parse_manifest).err().unwrap(), "Backup has no signed manifest");
+    }
+
+    #[test]
+    fn verify_external_checks_the_web_tier_files() {
+        let db = ManifestEntry { path: "talos.db".to_string(), size: 9, sha256: sha256_hex(b"sqlite db"), modified: None };
+        let mut manifest = manifest_for(&BTreeMap::new());
+        manifest.external.push(db.clone());
+        assert!(verify_external(&manifest, std::slice::from_ref(&db)).is_ok());
+
+        let tampered = ManifestEntry { sha256: sha256_hex(b"sqlite DB"), ..db.clone() };
+        assert!(verify_external(&manifest, &[tampered]).unwrap_err().starts_with("Entry modified"));
+        assert!(verify_external(&manifest, &[]).unwrap_err().starts_with("Entry missing"));
+        // Manifests written before external entries existed do not cover talos.db
+        assert!(verify_external(&manifest_for(&BTreeMap::new()), &[db]).unwrap_err().starts_with("Entry not in manifest"));
+    }
+
+    fn plan_entry(action: &'static str, backup: Option<&str>, current: Option<&str>) -> PlanEntry {
+        PlanEntry {
+            path: "web/github.gpg".to_string(),
+            action,
+            backup_modified: backup.map(String::from),
+            current_modified: current.map(String::from),
+        }
+    }
+
+    #[test]
+    fn merge_policies_decide_per_entry() {
+        let older = Some("2026-01-01T00:00:00+00:00");
+        let newer = Some("2026-02-01T00:00:00+00:00");
+        let stamp = "20260301T000000";
+
+        assert_eq!(decide(&plan_entry("add", None, None), MergePolicy::KeepNewer, stamp), Decision::Write);
+        assert_eq!(decide(&plan_entry("unchanged", older, older), MergePolicy::Overwrite, stamp), Decision::Skip("unchanged"));
+        assert_eq!(decide(&plan_entry("overwrite", older, newer), MergePolicy::Overwrite, stamp), Decision::Write);
+        assert_eq!(decide(&plan_entry("overwrite", newer, older), MergePolicy::KeepNewer, stamp), Decision::Write);
+        assert_eq!(decide(&plan_entry("overwrite", older, newer), MergePolicy::KeepNewer, stamp), Decision::Skip("current is newer"));
+        assert_eq!(decide(&plan_entry("overwrite", None, older), MergePolicy::KeepNewer, stamp), Decision::Skip("current is newer"));
+        assert_eq!(
+            decide(&plan_entry("overwrite", older, newer), MergePolicy::KeepBoth, stamp),
+            Decision::WriteAs("web/github.restored-20260301T000000.gpg".to_string())
+        );
+    }
+
+    #[test]
+    fn plan_restore_classifies_against_the_store() {
+        let store = tempfile::tempdir().unwrap();
+        let store_path = store.path().to_str().unwrap();
+        fs::create_dir_all(store.path().join("web")).unwrap();
+        fs::write(store.path().join("web/github.gpg"), b"same").unwrap();
+        fs::write(store.path().join("web/gitlab.gpg"), b"current").unwrap();
+        fs::write(store.path().join("local.gpg"), b"only here").unwrap();
+
+        let mut entries = BTreeMap::new();
+        entries.insert("web/github.gpg".to_string(), b"same".to_vec());
+        entries.insert("web/gitlab.gpg".to_string(), b"from backup".to_vec());
+        entries.insert("new.gpg".to_string(), b"new".to_vec());
+        entries.insert("../escape.gpg".to_string(), b"evil".to_vec());
+
+        let actions: Vec<(String, &str)> = plan_restore(None, &digests(&entries), store_path)
+            .into_iter()
+            .map(|e| (e.path, e.action))
+            .collect();
+        assert_eq!(actions, vec![
+            ("local.gpg".to_string(), "untouched"),
+            ("new.gpg".to_string(), "add"),
+            ("web/github.gpg".to_string(), "unchanged"),
+            ("web/gitlab.gpg".to_string(), "overwrite"),
+        ]);
+        assert!(is_selected("web/gitlab.gpg", Some(&["web/".to_string()])));
+        assert!(!is_selected("webmail.gpg", Some(&["web".to_string()])));
+    }
+
+    #[test]
+    fn check_openpgp_accepts_packet_streams_only() {
+        // PKESK (tag 1) followed by SEIPD (tag 18), new format headers
+        let message = [0xC1, 0x02, 0x03, 0x00, 0xD2, 0x01, 0x01];
+        assert!(check_openpgp(&message).is_ok());
+        // Old format PKESK with one-byte length
+        assert!(check_openpgp(&[0x84, 0x01, 0x03]).is_ok());
+
+        let armored = format!(
+            "-----BEGIN PGP MESSAGE-----\n\n{}\n=abcd\n-----END PGP MESSAGE-----\n",
+            general_purpose::STANDARD.encode(message)
+        );
+        assert!(check_openpgp(armored.as_bytes()).is_ok());
+
+        assert!(check_openpgp(b"hunter2\n").is_err());
+        assert!(check_openpgp(&message[..5]).is_err());
+        assert!(check_openpgp(&[]).is_err());
+        // Public key packet is not a message
+        assert!(check_openpgp(&[0xC6, 0x01, 0x04]).is_err());
+        // Partial body lengths chain until the final length
+        assert!(check_openpgp(&[0xD2, 0xE1, 0x01, 0x02, 0x01, 0x03]).is_ok());
+        assert!(check_openpgp(&[0xD2, 0xE1, 0x01, 0x02, 0x02, 0x03]).is_err());
+        // Old format indeterminate length runs to the end
+        assert!(check_openpgp(&[0xAF, 0x01, 0x02, 0x03]).is_ok());
+    }
+
+    #[test]
+    fn validate_entry_path_applies_api_rules() {
+        assert!(validate_entry_path("web/github.gpg").is_ok());
+        assert!(validate_entry_path(".trash/20260101T000000Z/meta.json").is_ok());
+        assert!(validate_entry_path(".git/config").is_err());
+        assert!(validate_entry_path(".gitattributes").is_ok());
+        assert!(validate_entry_path(".extensions/otp.bash").is_ok());
+        assert!(validate_entry_path("web/../../etc/passwd").is_err());
+        assert!(validate_entry_path("web/$(reboot).gpg").is_err());
+    }
+
+    #[test]
+    fn staging_leaves_store_untouched_until_swap() {
+        let store = tempfile::tempdir().unwrap();
+        fs::create_dir_all(store.path().join("web")).unwrap();
+        fs::create_dir_all(store.path().join("empty")).unwrap();
+        fs::write(store.path().join("web/github.gpg"), b"current").unwrap();
+        fs::write(store.path().join("local.gpg"), b"local").unwrap();
+
+        let staging = store.path().join(STAGING_DIR);
+        let writes = vec![
+            ("web/github.gpg".to_string(), "restored".to_string()),
+            ("new/secret.gpg".to_string(), "new".to_string()),
+        ];
+        build_staging(store.path(), &staging, &writes, |entry, file| file.write_all(entry.as_bytes()).map_err(|e| e.to_string())).unwrap();
+        assert_eq!(fs::read(store.path().join("web/github.gpg")).unwrap(), b"current");
+        assert!(!store.path().join("new").exists());
+
+        swap_in(store.path(), &staging, &store.path().join(ROLLBACK_DIR)).unwrap();
+        assert_eq!(fs::read(store.path().join("web/github.gpg")).unwrap(), b"restored");
+        assert_eq!(fs::read(store.path().join("new/secret.gpg")).unwrap(), b"new");
+        assert_eq!(fs::read(store.path().join("local.gpg")).unwrap(), b"local");
+        assert!(store.path().join("empty").is_dir());
+        assert!(!staging.exists());
+        assert!(!store.path().join(ROLLBACK_DIR).exists());
+    }
+
+    #[test]
+    fn work_dirs_are_excluded_from_git_once() {
+        let store = tempfile::tempdir().unwrap();
+        // Without a repository there is nothing to exclude from
+        exclude_work_dirs(store.path()).unwrap();
+        assert!(!store.path().join(".git").exists());
+
+        fs::create_dir_all(store.path().join(".git/info")).unwrap();
+        fs::write(store.path().join(".git/info/exclude"), "*.swp").unwrap();
+        exclude_work_dirs(store.path()).unwrap();
+        exclude_work_dirs(store.path()).unwrap();
+        let exclude = fs::read_to_string(store.path().join(".git/info/exclude")).unwrap();
+        assert_eq!(exclude, "*.swp\n# Talos restore work areas\n/.restore-staging/\n/.restore-rollback/\n");
+    }
+
+    #[test]
+    fn swap_refuses_leftover_rollback() {
+        let store = tempfile::tempdir().unwrap();
+        fs::write(store.path().join("a.gpg"), b"current").unwrap();
+        fs::create_dir_all(store.path().join(ROLLBACK_DIR)).unwrap();
+        let staging = store.path().join(STAGING_DIR);
+        build_staging(store.path(), &staging, &[("a.gpg".to_string(), "restored".to_string())], |entry, file| {
+            file.write_all(entry.as_bytes()).map_err(|e| e.to_string())
+        }).unwrap();
+
+        let (_, rolled_back) = swap_in(store.path(), &staging, &store.path().join(ROLLBACK_DIR)).unwrap_err();
+        assert!(rolled_back);
+        assert_eq!(fs::read(store.path().join("a.gpg")).unwrap(), b"current");
+    }
+
+    #[test]
+    fn backup_streams_large_store_with_bounded_memory() {
+        const MIB: usize = 1024 * 1024;
+        let store = tempfile::tempdir().unwrap();
+        // 64 MiB store of 1 MiB literal-data packets (new-format header, 4-byte length)
+        for i in 0..64 {
+            let dir = store.path().join(format!("category{}", i % 8));
+            fs::create_dir_all(&dir).unwrap();
+            let mut packet = vec![0xCB, 0xFF];
+            packet.extend_from_slice(&((MIB - 6) as u32).to_be_bytes());
+            packet.extend((0..MIB - 6).map(|n| (n * 31 + i) as u8));
+            fs::write(dir.join(format!("attachment{}.gpg", i)), packet).unwrap();
+        }
+
+        let target = tempfile::tempdir().unwrap();
+        let archive_path = target.path().join("backup.zip");
+        let mut manifest = None;
+        let peak = peak_since(|| {
+            let out = io::BufWriter::new(fs::File::create(&archive_path).unwrap());
+            manifest = Some(write_backup(out, store.path().to_str().unwrap(), "fp".to_string(), Vec::new(), |_| Ok("sig".to_string())).unwrap().1);
+        });
+        let manifest = manifest.unwrap();
+        assert_eq!(manifest.files.len(), 64);
+        assert!(fs::metadata(&archive_path).unwrap().len() > 64 * MIB as u64);
+        assert!(peak < 4 * MIB, "backup peaked at {} bytes", peak);
+
+        // Test extraction reads one entry at a time
+        let peak = peak_since(|| {
+            let checked = check_archive(fs::File::open(&archive_path).unwrap(), |json, sig| {
+                assert_eq!(sig, b"sig");
+                serde_json::from_slice(json).map_err(|e| e.to_string())
+            }).unwrap();
+            assert_eq!(checked.files.len(), 64);
+        });
+        assert!(peak < 8 * MIB, "verification peaked at {} bytes", peak);
+
+        // So does a restore, from the central directory to the staging area
+        let restore = tempfile::tempdir().unwrap();
+        let staging = restore.path().join(STAGING_DIR);
+        let peak = peak_since(|| {
+            let mut backup = read_backup(fs::File::open(&archive_path).unwrap(), false, 128 * MIB as u64, parse_manifest).unwrap();
+            let writes: Vec<(String, String)> = backup.entries.keys().map(|n| (n.clone(), n.clone())).collect();
+            let plan = plan_restore(backup.manifest.as_ref(), &backup.entries, store.path().to_str().unwrap());
+            assert!(plan.iter().all(|e| e.action == "unchanged"));
+            backup.stage(restore.path(), &staging, &writes).map_err(|(_, report)| format!("{:?}", report)).unwrap();
+        });
+        assert!(peak < 8 * MIB, "restore peaked at {} bytes", peak);
+        assert_eq!(fs::read(staging.join("category3/attachment3.gpg")).unwrap(), fs::read(store.path().join("category3/attachment3.gpg")).unwrap());
+        let error = read_backup(fs::File::open(&archive_path).unwrap(), false, 32 * MIB as u64, parse_manifest).err().unwrap();
+        assert!(error.starts_with("Backup exceeds the restore limit"), "{}", error);
+    }
+}
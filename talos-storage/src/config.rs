@@ -5,6 +5,13 @@ use std::fs;
 #[derive(Deserialize, Debug)]
 pub struct Config {
     pub backend: Backend,
+    #[serde(default)]
+    pub trash: TrashConfig,
+    // Automatic backups; disabled when absent
+    #[serde(default)]
+    pub backup_schedule: Option<BackupScheduleConfig>,
+    #[serde(default)]
+    pub export: ExportConfig,
 }
 
 #[derive(Deserialize, Debug)]
@@ -14,6 +21,53 @@ pub struct Backend {
     pub ssh_key_path: Option<String>,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct TrashConfig {
+    // Days a deleted entry stays in .trash before it is purged (0 = keep forever)
+    #[serde(default = "default_trash_retention_days")]
+    pub retention_days: u64,
+}
+
+fn default_trash_retention_days() -> u64 { 30 }
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        TrashConfig { retention_days: default_trash_retention_days() }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+pub struct ExportConfig {
+    // Unencrypted CSV exports are refused unless explicitly enabled
+    #[serde(default)]
+    pub allow_plaintext_csv: bool,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct BackupScheduleConfig {
+    // Standard 5-field cron expression, evaluated in UTC
+    pub cron: String,
+    pub target: BackupTarget,
+    // Generations kept: newest backup of each of the last N days/weeks/months
+    #[serde(default = "default_keep_daily")]
+    pub keep_daily: usize,
+    #[serde(default = "default_keep_weekly")]
+    pub keep_weekly: usize,
+    #[serde(default = "default_keep_monthly")]
+    pub keep_monthly: usize,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BackupTarget {
+    // Any mounted filesystem, including object stores mounted via s3fs/rclone
+    Directory { path: String },
+}
+
+fn default_keep_daily() -> usize { 7 }
+fn default_keep_weekly() -> usize { 4 }
+fn default_keep_monthly() -> usize { 6 }
+
 pub static CONFIG: Lazy<Config> = Lazy::new(|| {
     let config_path = "/app/config/storage.json";
     match fs::read_to_string(config_path) {
@@ -44,7 +98,10 @@ impl Default for Config {
                 r#type: "local".to_string(),
                 repository_url: None,
                 ssh_key_path: None,
-            }
+            },
+            trash: TrashConfig::default(),
+            backup_schedule: None,
+            export: ExportConfig::default(),
         }
     }
 }
\ No newline at end of file
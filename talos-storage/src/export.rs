@@ -0,0 +1,174 @@
+use axum::Json;
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use chrono::Utc;
+use ::keepass::config::KdfConfig;
+use serde::Deserialize;
+use serde_json::json;
+use crate::config::{CONFIG, DEBUG_MODE};
+use crate::handlers::{bunker_call, log_audit_event};
+use crate::import::{bitwarden, keepass, ImportRecord};
+use crate::metadata;
+use crate::models::BunkerTask;
+use crate::search::{decrypt_file, scan_store};
+
+const MIN_PASSPHRASE_LEN: usize = 8;
+
+// Argon2id at KeePassXC's default cost; the database leaves the host, so it
+// has to stand up to offline guessing on its own.
+const KDBX_KDF: KdfConfig = KdfConfig::Argon2id {
+    iterations: 10,
+    memory: 64 * 1024 * 1024,
+    parallelism: 2,
+    version: argon2::Version::Version13,
+};
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// KeePass 2.x database (KDBX 4)
+    Kdbx,
+    /// Bitwarden password protected JSON
+    Bitwarden,
+    /// Bitwarden CSV, plaintext; only with `export.allow_plaintext_csv`
+    Csv,
+}
+
+impl ExportFormat {
+    fn file(&self) -> (&'static str, &'static str) {
+        match self {
+            ExportFormat::Kdbx => ("kdbx", "application/octet-stream"),
+            ExportFormat::Bitwarden => ("json", "application/json"),
+            ExportFormat::Csv => ("csv", "text/csv; charset=utf-8"),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ExportRequest {
+    pub format: ExportFormat,
+    #[serde(default)]
+    pub passphrase: String,
+}
+
+// Bitwarden's CSV layout, which most password managers can import
+fn write_csv(records: &[ImportRecord]) -> Result<Vec<u8>, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    writer.write_record(["folder", "favorite", "type", "name", "notes", "fields", "reprompt", "login_uri", "login_username", "login_password", "login_totp"])
+        .map_err(|e| e.to_string())?;
+    for record in records {
+        let fields = record.fields.iter().map(|(key, value)| format!("{}: {}", key, value)).collect::<Vec<_>>().join("\n");
+        writer.write_record([
+            record.folder.join("/").as_str(), "", "login", &record.title, &record.notes, &fields, "0",
+            &record.url, &record.username, &record.password, record.totp.as_deref().unwrap_or(""),
+        ]).map_err(|e| e.to_string())?;
+    }
+    writer.into_inner().map_err(|e| e.to_string())
+}
+
+// Decrypts every secret in the store; one failure aborts the export rather
+// than producing an incomplete copy.
+async fn collect_records(client: &reqwest::Client) -> Result<Vec<ImportRecord>, String> {
+    let mut meta = metadata::load();
+    let mut paths: Vec<String> = scan_store().into_iter().map(|(path, _)| path).collect();
+    paths.sort();
+    let mut records = Vec::with_capacity(paths.len());
+    for path in paths {
+        let content = decrypt_file(client, &path).await.map_err(|e| format!("Failed to decrypt {}: {}", path, e))?;
+        let tags = meta.remove(&path).map(|m| m.tags).unwrap_or_default();
+        records.push(ImportRecord::from_secret(&path, &content, tags));
+    }
+    Ok(records)
+}
+
+// Writes the whole store in a format other password managers can import.
+// KDBX and Bitwarden JSON are encrypted with the passphrase from the request.
+pub async fn export_secrets(Json(req): Json<ExportRequest>) -> Response {
+    if *DEBUG_MODE { println!("--> [STORAGE] EXPORT request initiated ({:?})", req.format); }
+
+    if req.format == ExportFormat::Csv && !CONFIG.export.allow_plaintext_csv {
+        log_audit_event("storage_export", "denied", "plaintext CSV export is disabled");
+        return (StatusCode::FORBIDDEN, Json(json!({"error": "Plaintext CSV export is disabled (export.allow_plaintext_csv)"}))).into_response();
+    }
+    if req.format != ExportFormat::Csv && req.passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": format!("Export passphrase must be at least {} characters", MIN_PASSPHRASE_LEN)}))).into_response();
+    }
+
+    let client = reqwest::Client::new();
+    let vault = bunker_call(&client, &BunkerTask {
+        payload: String::new(),
+        mode: "check".to_string(),
+        signature: None,
+        recipients: None,
+    }).await;
+    if vault.as_deref() != Ok("UNSEALED") {
+        log_audit_event("storage_export", "failed", "vault not available");
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(json!({"error": "Vault is sealed"}))).into_response();
+    }
+
+    let records = match collect_records(&client).await {
+        Ok(records) => records,
+        Err(e) => {
+            log_audit_event("storage_export", "failed", &e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e}))).into_response();
+        }
+    };
+    let count = records.len();
+    let format = req.format;
+    let built = tokio::task::spawn_blocking(move || match format {
+        ExportFormat::Kdbx => keepass::write_kdbx(&records, &req.passphrase, KDBX_KDF),
+        ExportFormat::Bitwarden => Ok(bitwarden::write_encrypted(&records, &req.passphrase, bitwarden::EXPORT_KDF_ITERATIONS)),
+        ExportFormat::Csv => write_csv(&records),
+    }).await.unwrap_or_else(|e| Err(e.to_string()));
+    let data = match built {
+        Ok(data) => data,
+        Err(e) => {
+            log_audit_event("storage_export", "failed", &e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": e}))).into_response();
+        }
+    };
+
+    log_audit_event("storage_export", "success", &format!("{:?} export of {} secrets", format, count));
+    let (extension, content_type) = format.file();
+    let disposition = format!("attachment; filename=\"talos-export-{}.{}\"", Utc::now().format("%Y%m%dT%H%M%SZ"), extension);
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+            (header::HeaderName::from_static("x-export-count"), count.to_string()),
+        ],
+        data,
+    ).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exports_round_trip_through_the_importers() {
+        let content = "hunter2\nUser: alice\nURL: https://mail.example\notpauth://totp/Mail?secret=JBSWY3DP\nPIN: 1234\nbackup codes";
+        let record = ImportRecord::from_secret("Internet/Mail", content, vec!["work".to_string()]);
+        assert_eq!(record, ImportRecord {
+            folder: vec!["Internet".to_string()],
+            title: "Mail".to_string(),
+            username: "alice".to_string(),
+            password: "hunter2".to_string(),
+            url: "https://mail.example".to_string(),
+            notes: "PIN: 1234\nbackup codes".to_string(),
+            totp: Some("otpauth://totp/Mail?secret=JBSWY3DP".to_string()),
+            tags: vec!["work".to_string()],
+            ..Default::default()
+        });
+        assert_eq!(record.content(), content);
+
+        let weak = KdfConfig::Argon2id { iterations: 1, memory: 64 * 1024, parallelism: 1, version: argon2::Version::Version13 };
+        let kdbx = keepass::write_kdbx(std::slice::from_ref(&record), "export passphrase", weak).unwrap();
+        assert_eq!(keepass::parse(&kdbx, "export passphrase").unwrap(), vec![record.clone()]);
+
+        let csv = write_csv(std::slice::from_ref(&record)).unwrap();
+        let mut from_csv = crate::import::browser::parse(&csv).unwrap();
+        from_csv[0].tags = record.tags.clone();
+        assert_eq!(from_csv, vec![record]);
+    }
+}
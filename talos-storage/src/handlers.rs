@@ -1,22 +1,30 @@
 use axum::Json;
-use axum::extract::Multipart;
+use axum::extract::Query;
 use axum::http::{StatusCode, header};
 use axum::response::IntoResponse;
 use serde::Serialize;
 use serde_json::{json, Value};
-use std::{env, fs, io::{self, Cursor, Write}, path::Path as StdPath, sync::atomic::{AtomicBool, Ordering}};
-use crate::models::{ActionRequest, BunkerTask};
+use std::{env, fs, path::Path as StdPath, sync::atomic::{AtomicBool, Ordering}};
+use once_cell::sync::Lazy;
+use tokio::sync::Mutex;
+use crate::models::{ActionRequest, BunkerTask, HistoryQuery, MoveRequest, TreeQuery};
+use crate::metadata::{self, MetaStore, META_FILE};
+use crate::backup::{ROLLBACK_DIR, STAGING_DIR};
 use crate::config::{CONFIG, DEBUG_MODE, STORE_PATH};
-use zip::write::FileOptions;
+use crate::trash::{move_to_trash, TRASH_DIR};
+use crate::search::index_saved_secret;
 use chrono::Utc;
 use base64::{Engine as _, engine::general_purpose};
 use sha2::{Sha256, Digest};
 use hmac::{Hmac, Mac};
-use hex;
 
 type HmacSha256 = Hmac<Sha256>;
 
-fn log_audit_event(action: &str, status: &str, details: &str) {
+// Serializes the version check and the write in `encrypt_and_save` so two
+// concurrent saves cannot both pass the same If-Match check.
+pub(crate) static WRITE_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+pub(crate) fn log_audit_event(action: &str, status: &str, details: &str) {
     let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S UTC");
     eprintln!("[AUDIT {}] ACTION={} STATUS={} DETAILS={}", timestamp, action, status, details);
 }
@@ -29,8 +37,22 @@ fn verify_signature(result: &str, signature: &str) -> bool {
     expected_signature == signature
 }
 
+// `pass` extension scripts; kept with the store but never written through the API
+pub(crate) const EXTENSIONS_DIR: &str = ".extensions";
+
+// The repository itself, but not `pass`'s .gitattributes next to it
+pub(crate) fn is_git_path(name: &str) -> bool {
+    name == ".git" || name.starts_with(".git/")
+}
+
+// What the store keeps next to the secrets. Anything else is listed, dot-named
+// secrets and categories such as `.env` or `.ssh/` included.
+pub(crate) fn is_internal(name: &str) -> bool {
+    matches!(name, ".git" | ".gpg-id" | ".gitattributes" | EXTENSIONS_DIR | TRASH_DIR | META_FILE | STAGING_DIR | ROLLBACK_DIR)
+}
+
 // Validate and sanitize path to prevent path traversal attacks
-fn validate_path(path: &str) -> Result<(), String> {
+pub(crate) fn validate_path(path: &str) -> Result<(), String> {
     // Prevent null bytes
     if path.contains('\0') {
         return Err("Path contains null byte".to_string());
@@ -46,6 +68,11 @@ fn validate_path(path: &str) -> Result<(), String> {
         return Err("Path traversal not allowed".to_string());
     }
 
+    // Prevent writing into internal directories and `pass` extensions
+    if matches!(path.trim_start_matches('/').split('/').next(), Some(".git") | Some(EXTENSIONS_DIR) | Some(TRASH_DIR)) {
+        return Err("Reserved path".to_string());
+    }
+
     // Prevent shell metacharacters
     if path.chars().any(|c| matches!(c, '*' | '?' | '[' | ']' | '{' | '}' | '$' | '`' | '|' | ';' | '&' | '>' | '<')) {
         return Err("Invalid characters in path".to_string());
@@ -59,38 +86,232 @@ fn validate_path(path: &str) -> Result<(), String> {
     Ok(())
 }
 
+// Version token of a secret: SHA-256 of the ciphertext on disk.
+// Any re-encryption produces a new token, so it changes on every save.
+pub(crate) fn secret_version(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+fn current_version(path: &str) -> Option<String> {
+    fs::read(format!("{}/{}.gpg", &*STORE_PATH, path)).ok().map(|b| secret_version(&b))
+}
+
+// Recipients for a secret, taken from the nearest .gpg-id walking up towards
+// the store root (same lookup as `pass`). Falls back to GPG_ID.
+pub fn recipients_for(path: &str) -> Vec<String> {
+    recipients_in(StdPath::new(&*STORE_PATH), path)
+}
+
+// Recipients listed in a directory's own .gpg-id, if it has a non-empty one
+pub(crate) fn gpg_id_in(dir: &StdPath) -> Option<Vec<String>> {
+    let content = fs::read_to_string(dir.join(".gpg-id")).ok()?;
+    let ids: Vec<String> = content.lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .map(String::from)
+        .collect();
+    (!ids.is_empty()).then_some(ids)
+}
+
+fn recipients_in(store: &StdPath, path: &str) -> Vec<String> {
+    let mut dir = StdPath::new(path).parent().map(|p| p.to_path_buf()).unwrap_or_default();
+    loop {
+        if let Some(ids) = gpg_id_in(&store.join(&dir)) {
+            return ids;
+        }
+        if !dir.pop() {
+            break;
+        }
+    }
+    vec![env::var("GPG_ID").unwrap_or_else(|_| "admin@talos.local".to_string())]
+}
+
+pub(crate) struct ReencryptFailure {
+    pub status: StatusCode,
+    // For the client
+    pub error: String,
+    // For the audit log
+    pub detail: String,
+}
+
+// Re-encrypts secrets that were just moved, given as (new path, recipients
+// before the move), wherever the effective .gpg-id at the new path differs.
+// All ciphertexts are produced before anything is written so a Bunker failure
+// leaves the store as it was. New ciphertexts are written next to the old
+// ones and only renamed over them once all are on disk, so a failed write can
+// be undone the same way: on any failure the caller reverts its move.
+pub(crate) async fn reencrypt_moved(store: &StdPath, moved: &[(String, Vec<String>)]) -> Result<usize, ReencryptFailure> {
+    let client = reqwest::Client::new();
+    let mut rewrites = Vec::new();
+    for (new, old_r) in moved {
+        let new_r = recipients_for(new);
+        if same_recipients(old_r, &new_r) {
+            continue;
+        }
+        let file = store.join(format!("{}.gpg", new));
+        match reencrypt(&client, &file, new_r).await {
+            Ok(ciphertext) => rewrites.push((file, ciphertext)),
+            Err(e) => return Err(ReencryptFailure {
+                status: StatusCode::BAD_GATEWAY,
+                error: format!("Re-encryption failed for {}", new),
+                detail: format!("re-encryption of {} failed: {}", new, e),
+            }),
+        }
+    }
+    let mut staged = Vec::new();
+    for (file, ciphertext) in &rewrites {
+        let tmp = file.with_extension("gpg.tmp");
+        if let Err(e) = write_secret_file(&tmp, ciphertext) {
+            println!("❌ [STORAGE] Error writing file: {}", e);
+            for tmp in staged.iter().chain(std::iter::once(&tmp)) {
+                let _ = fs::remove_file(tmp);
+            }
+            return Err(ReencryptFailure {
+                status: StatusCode::INTERNAL_SERVER_ERROR,
+                error: "Could not write re-encrypted secret".to_string(),
+                detail: format!("writing re-encrypted {} failed: {}", file.display(), e),
+            });
+        }
+        staged.push(tmp);
+    }
+    for (tmp, (file, _)) in staged.iter().zip(&rewrites) {
+        if let Err(e) = fs::rename(tmp, file) {
+            println!("❌ [STORAGE] Error replacing {}: {}", file.display(), e);
+        }
+    }
+    Ok(rewrites.len())
+}
+
+fn same_recipients(a: &[String], b: &[String]) -> bool {
+    let mut a = a.to_vec();
+    let mut b = b.to_vec();
+    a.sort();
+    b.sort();
+    a == b
+}
+
+// Secret paths (without .gpg) below `dir`, prefixed with its store-relative path
+pub(crate) fn list_secrets(dir: &StdPath, prefix: &str) -> Vec<String> {
+    let mut secrets: Vec<String> = walkdir::WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file())
+        .filter_map(|e| {
+            let rel = e.path().strip_prefix(dir).ok()?.to_str()?.to_string();
+            let name = rel.strip_suffix(".gpg")?;
+            Some(format!("{}/{}", prefix, name))
+        })
+        .collect();
+    secrets.sort();
+    secrets
+}
+
+// Sends a task to the Bunker and returns its verified result
+pub(crate) async fn bunker_call(client: &reqwest::Client, task: &BunkerTask) -> Result<String, String> {
+    let bunker_url = env::var("BUNKER_URL").unwrap_or_else(|_| "http://talos-bunker:5000".to_string());
+    let shared_secret = env::var("SHARED_SECRET").unwrap_or_default();
+
+    let res = client.post(format!("{}/process", bunker_url))
+        .header("X-Talos-Auth", &shared_secret)
+        .json(task)
+        .send().await
+        .map_err(|_| "Bunker unavailable".to_string())?;
+    if !res.status().is_success() {
+        return Err("Bunker unavailable".to_string());
+    }
+
+    let data: Value = res.json().await.map_err(|_| "Invalid bunker response".to_string())?;
+    let result = data["result"].as_str().unwrap_or("").to_string();
+    match data["signature"].as_str() {
+        Some(signature) if !verify_signature(&result, signature) => Err("Signature verification failed".to_string()),
+        None if result.is_empty() || result.starts_with("ERROR_") => Err(format!("Bunker error: {}", result)),
+        _ => Ok(result),
+    }
+}
+
+// Encrypts a secret the way `pass insert` does, so the `pass` CLI and Talos
+// can share a store: a binary OpenPGP message of the plaintext ending in a
+// newline, for the recipients of the nearest .gpg-id.
+pub(crate) async fn encrypt_secret(client: &reqwest::Client, content: &str, recipients: Vec<String>) -> Result<Vec<u8>, String> {
+    let mut plaintext = content.to_string();
+    if !plaintext.ends_with('\n') {
+        plaintext.push('\n');
+    }
+    let encrypted = bunker_call(client, &BunkerTask {
+        payload: plaintext,
+        mode: "encrypt_binary".to_string(),
+        signature: None,
+        recipients: Some(recipients),
+    }).await?;
+    general_purpose::STANDARD.decode(encrypted).map_err(|_| "Invalid bunker response".to_string())
+}
+
+// Writes a secret file with the permissions `pass` uses (umask 077)
+pub(crate) fn write_secret_file(file: &StdPath, data: &[u8]) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+    if let Some(parent) = file.parent() {
+        fs::DirBuilder::new().recursive(true).mode(0o700).create(parent)?;
+    }
+    fs::OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(file)?.write_all(data)
+}
+
+// Decrypts a .gpg file and encrypts it again for a new recipient set
+async fn reencrypt(client: &reqwest::Client, file: &StdPath, recipients: Vec<String>) -> Result<Vec<u8>, String> {
+    let encrypted = fs::read(file).map_err(|e| format!("read failed: {}", e))?;
+    let plaintext = bunker_call(client, &BunkerTask {
+        payload: general_purpose::STANDARD.encode(&encrypted),
+        mode: "decrypt".to_string(),
+        signature: None,
+        recipients: None,
+    }).await?;
+    encrypt_secret(client, &plaintext, recipients).await
+}
+
 #[derive(Serialize)]
 pub struct TreeNode {
     name: String,
     path: String,
     is_dir: bool,
     children: Option<Vec<TreeNode>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    favourite: bool,
 }
 
-fn build_tree_recursive(base_path: &str, current_path: &str) -> Vec<TreeNode> {
+fn build_tree_recursive(base_path: &str, current_path: &str, meta: &MetaStore, user: Option<&str>) -> Vec<TreeNode> {
     let full_path = StdPath::new(base_path).join(current_path);
     let mut nodes = Vec::new();
 
     if let Ok(read_dir) = fs::read_dir(full_path) {
         for entry in read_dir.flatten() {
             let file_name = entry.file_name().into_string().unwrap();
-            // Filter out git and config files, but allow files that are just ".gpg"
-            if file_name == ".git" || file_name == ".gpg-id" || file_name == ".gitkeep" { continue; }
+            if is_internal(&file_name) { continue; }
 
             let is_dir = entry.path().is_dir();
+            // Anything else kept in a `pass` store (READMEs, scripts) is not a secret
+            if !is_dir && !file_name.ends_with(".gpg") { continue; }
             let path_str = StdPath::new(current_path).join(&file_name).to_str().unwrap().to_string();
             
-            let children = if is_dir {
-                Some(build_tree_recursive(base_path, &path_str))
+            let (children, version) = if is_dir {
+                (Some(build_tree_recursive(base_path, &path_str, meta, user)), None)
             } else {
-                None
+                (None, fs::read(entry.path()).ok().map(|b| secret_version(&b)))
             };
 
+            let path = if is_dir { path_str } else { path_str.trim_end_matches(".gpg").to_string() };
+            let entry_meta = if is_dir { None } else { meta.get(&path) };
             nodes.push(TreeNode {
-                name: file_name.replace(".gpg", ""),
-                path: path_str.replace(".gpg", ""),
+                name: if is_dir { file_name } else { file_name.trim_end_matches(".gpg").to_string() },
+                tags: entry_meta.map(|m| m.tags.clone()).unwrap_or_default(),
+                favourite: entry_meta.zip(user).is_some_and(|(m, u)| m.favourites.iter().any(|f| f == u)),
+                path,
                 is_dir,
                 children,
+                version,
             });
         }
     }
@@ -98,21 +319,42 @@ fn build_tree_recursive(base_path: &str, current_path: &str) -> Vec<TreeNode> {
     nodes
 }
 
-pub async fn list_tree() -> Json<Vec<TreeNode>> {
+// Keeps secrets tagged with `tag` and the categories that lead to them
+fn filter_by_tag(nodes: Vec<TreeNode>, tag: &str) -> Vec<TreeNode> {
+    nodes.into_iter()
+        .filter_map(|mut node| {
+            if node.is_dir {
+                let children = filter_by_tag(node.children.take().unwrap_or_default(), tag);
+                (!children.is_empty()).then_some(TreeNode { children: Some(children), ..node })
+            } else {
+                node.tags.iter().any(|t| t == tag).then_some(node)
+            }
+        })
+        .collect()
+}
+
+pub async fn list_tree(Query(query): Query<TreeQuery>) -> Json<Vec<TreeNode>> {
     if *DEBUG_MODE { println!("--> [STORAGE] LIST TREE request"); }
     let root_path = STORE_PATH.as_str();
-    let nodes = build_tree_recursive(root_path, "");
-    Json(nodes)
+    let meta = metadata::load();
+    let nodes = build_tree_recursive(root_path, "", &meta, query.user.as_deref());
+    match query.tag.as_deref().filter(|t| !t.is_empty()) {
+        Some(tag) => Json(filter_by_tag(nodes, tag)),
+        None => Json(nodes),
+    }
 }
 
-pub async fn decrypt_secret(Json(req): Json<ActionRequest>) -> (StatusCode, Json<Value>) {
+pub async fn decrypt_secret(Json(req): Json<ActionRequest>) -> impl IntoResponse {
     // Validate path to prevent traversal attacks
     if let Err(e) = validate_path(&req.path) {
-        return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))).into_response();
     }
 
     let file_path = format!("{}/{}.gpg", &*STORE_PATH, req.path);
-    let encrypted_bytes = fs::read(file_path).unwrap_or_else(|_| vec![]);
+    let Ok(encrypted_bytes) = fs::read(file_path) else {
+        return (StatusCode::NOT_FOUND, Json(json!({"error": "Secret not found"}))).into_response();
+    };
+    let version = secret_version(&encrypted_bytes);
     let encrypted_content = general_purpose::STANDARD.encode(&encrypted_bytes);
     let bunker_url = env::var("BUNKER_URL").unwrap_or_else(|_| "http://talos-bunker:5000".to_string());
     let shared_secret = env::var("SHARED_SECRET").unwrap_or_default();
@@ -124,12 +366,17 @@ pub async fn decrypt_secret(Json(req): Json<ActionRequest>) -> (StatusCode, Json
             payload: encrypted_content,
             mode: "decrypt".to_string(),
             signature: None,
+            recipients: None,
         })
         .send().await 
     {
         Ok(res) if res.status().is_success() => {
             let data: Value = res.json().await.unwrap_or(json!({"result": "Data error"}));
             let mut decrypted = data["result"].as_str().unwrap_or("").to_string();
+            // `pass` terminates secrets with a newline; editors get the text without it
+            if decrypted.ends_with('\n') {
+                decrypted.pop();
+            }
 
             // If revealing the secret is not explicitly requested, we obfuscate it.
             if !req.reveal.unwrap_or(false) {
@@ -139,9 +386,9 @@ pub async fn decrypt_secret(Json(req): Json<ActionRequest>) -> (StatusCode, Json
                     decrypted = "__TALOS_HIDDEN_SECRET__".to_string();
                 }
             }
-            (StatusCode::OK, Json(json!(decrypted)))
+            (StatusCode::OK, [(header::ETAG, format!("\"{}\"", version))], Json(json!(decrypted))).into_response()
         },
-        _ => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!("Error: Bunker unavailable or decryption failed")))
+        _ => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!("Error: Bunker unavailable or decryption failed"))).into_response()
     }
 }
 
@@ -156,9 +403,23 @@ pub async fn encrypt_and_save(Json(req): Json<ActionRequest>) -> (StatusCode, Js
         return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
     }
 
-    if let Some(ref original_path) = req.original_path {
-        if let Err(e) = validate_path(original_path) {
-            return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    if let Some(ref original_path) = req.original_path
+        && let Err(e) = validate_path(original_path)
+    {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    }
+
+    // Optimistic concurrency: reject the save if the secret changed since the client read it
+    let _write_guard = WRITE_LOCK.lock().await;
+    if let Some(expected) = req.expected_version.as_deref() {
+        let edited_path = req.original_path.as_deref().unwrap_or(&req.path);
+        let current = current_version(edited_path);
+        if current.as_deref() != Some(expected.trim_matches('"')) {
+            log_audit_event("storage_save", "conflict", &format!("stale version for path: {}", edited_path));
+            return (StatusCode::CONFLICT, Json(json!({
+                "error": "Secret was modified by someone else. Reload it before saving.",
+                "current_version": current
+            })));
         }
     }
 
@@ -171,7 +432,7 @@ pub async fn encrypt_and_save(Json(req): Json<ActionRequest>) -> (StatusCode, Js
     
     if payload.starts_with("__TALOS_KEEP_SECRET__") {
         let file_path = format!("{}/{}.gpg", &*STORE_PATH, req.path);
-        let encrypted_content = fs::read_to_string(&file_path).unwrap_or_default();
+        let encrypted_content = general_purpose::STANDARD.encode(fs::read(&file_path).unwrap_or_default());
         
         let decrypt_res = client.post(format!("{}/process", bunker_url))
             .header("X-Talos-Auth", &shared_secret)
@@ -179,92 +440,64 @@ pub async fn encrypt_and_save(Json(req): Json<ActionRequest>) -> (StatusCode, Js
                 payload: encrypted_content,
                 mode: "decrypt".to_string(),
                 signature: None,
+                recipients: None,
             })
             .send().await;
             
-        if let Ok(res) = decrypt_res {
-            if res.status().is_success() {
-                let data: serde_json::Value = match res.json().await {
-                    Ok(d) => d,
-                    Err(_) => json!({"result": ""}),
-                };
-                let full_text = data["result"].as_str().unwrap_or("").to_string();
-                
-                // Verify signature if present
-                if let Some(signature) = data["signature"].as_str() {
-                    if !verify_signature(&full_text, signature) {
-                        log_audit_event("storage_save", "failed", "signature verification failed during decrypt");
-                        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Signature verification failed"})));
-                    }
-                }
-                let old_pass = full_text.split('\n').next().unwrap_or("");
-                
-                // Replace marker with the old password
-                payload = payload.replace("__TALOS_KEEP_SECRET__", old_pass);
-            }
-        }
-    }
-
-    let res_result = client.post(format!("{}/process", bunker_url))
-        .header("X-Talos-Auth", &shared_secret)
-        .json(&BunkerTask {
-            payload: payload,
-            mode: "encrypt".to_string(),
-            signature: None,
-        })
-        .send().await;
-
-    match res_result {
-        Ok(res) if res.status().is_success() => {
+        if let Ok(res) = decrypt_res
+            && res.status().is_success()
+        {
             let data: serde_json::Value = match res.json().await {
                 Ok(d) => d,
                 Err(_) => json!({"result": ""}),
             };
-            let encrypted = data["result"].as_str().unwrap_or("").to_string();
-            
+            let full_text = data["result"].as_str().unwrap_or("").to_string();
+
             // Verify signature if present
-            if let Some(signature) = data["signature"].as_str() {
-                if !verify_signature(&encrypted, signature) {
-                    log_audit_event("storage_save", "failed", "signature verification failed during encrypt");
-                    return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Signature verification failed"})));
-                }
-            }
-            
-            let armored_gpg = encrypted;
-            
-            if armored_gpg.is_empty() {
-                return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Encryption failed"})));
+            if let Some(signature) = data["signature"].as_str()
+                && !verify_signature(&full_text, signature)
+            {
+                log_audit_event("storage_save", "failed", "signature verification failed during decrypt");
+                return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Signature verification failed"})));
             }
+            let old_pass = full_text.split('\n').next().unwrap_or("");
+
+            // Replace marker with the old password
+            payload = payload.replace("__TALOS_KEEP_SECRET__", old_pass);
+        }
+    }
 
+    match encrypt_secret(&client, &payload, recipients_for(&req.path)).await {
+        Ok(encrypted) => {
             let file_path = format!("{}/{}.gpg", &*STORE_PATH, req.path);
-            if let Some(parent) = std::path::Path::new(&file_path).parent() {
-                if let Err(e) = fs::create_dir_all(parent) {
-                    println!("❌ [STORAGE] Error creating directory: {}", e);
-                    return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Could not create directory"})));
-                }
-            }
-            if let Err(e) = fs::write(&file_path, armored_gpg) {
+            let version = secret_version(&encrypted);
+            if let Err(e) = write_secret_file(StdPath::new(&file_path), &encrypted) {
                 println!("❌ [STORAGE] Error writing file: {}", e);
                 return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Could not write secret to disk"})));
             }
             
             let mut commit_msg = format!("Update secret: {}", req.path);
 
-            if let Some(original_path) = &req.original_path {
-                if &req.path != original_path {
-                    // This is a move operation
-                    let old_file_path = format!("{}/{}.gpg", &*STORE_PATH, original_path);
-                    if fs::remove_file(old_file_path).is_ok() {
-                        if *DEBUG_MODE { println!("--> [STORAGE] Removed old file for move: {}", original_path); }
-                        commit_msg = format!("Move secret from {} to {}", original_path, req.path);
-                    }
+            if let Some(original_path) = &req.original_path
+                && &req.path != original_path
+            {
+                // This is a move operation
+                let old_file_path = format!("{}/{}.gpg", &*STORE_PATH, original_path);
+                if fs::remove_file(old_file_path).is_ok() {
+                    if *DEBUG_MODE { println!("--> [STORAGE] Removed old file for move: {}", original_path); }
+                    metadata::rename_prefix(original_path, &req.path, false);
+                    commit_msg = format!("Move secret from {} to {}", original_path, req.path);
                 }
             }
             
             commit_changes(&commit_msg);
-            (StatusCode::OK, Json(json!({"status": "OK"})))
+            index_saved_secret(&req.path, &payload, &version).await;
+            (StatusCode::OK, Json(json!({"status": "OK", "version": version})))
         },
-        _ => (StatusCode::SERVICE_UNAVAILABLE, Json(json!({"error": "Bunker unavailable"})))
+        Err(e) => {
+            log_audit_event("storage_save", "failed", &format!("encryption failed: {}", e));
+            (StatusCode::SERVICE_UNAVAILABLE, Json(json!({"error": "Bunker unavailable"})))
+        }
     }
 }
 
@@ -283,13 +516,21 @@ pub async fn delete_entry(Json(req): Json<ActionRequest>) -> (StatusCode, Json<V
     let path_as_dir = StdPath::new(store_path).join(&req.path);
     let path_as_file = StdPath::new(store_path).join(format!("{}.gpg", req.path));
 
+    let actor = req.actor.as_deref().unwrap_or("unknown");
+    let _write_guard = WRITE_LOCK.lock().await;
+
     if path_as_file.is_file() {
-        // Attempt to delete it as a file
-        if fs::remove_file(&path_as_file).is_ok() {
-            commit_changes(&format!("Delete secret: {}", req.path));
-            (StatusCode::OK, Json(json!({"status": "OK"})))
-        } else {
-            (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Could not delete file"})))
+        // Soft delete: the secret goes to the trash and can be restored
+        match move_to_trash(&req.path, false, actor) {
+            Ok(entry) => {
+                commit_changes(&format!("Move secret to trash: {}", req.path));
+                log_audit_event("storage_delete", "success", &format!("{} moved to trash by {} (id {})", req.path, actor, entry.id));
+                (StatusCode::OK, Json(json!({"status": "OK", "trash_id": entry.id})))
+            },
+            Err(e) => {
+                println!("❌ [STORAGE] {}", e);
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Could not delete file"})))
+            }
         }
     } else if path_as_dir.is_dir() {
         // Attempt to delete it as a directory
@@ -307,6 +548,27 @@ pub async fn delete_entry(Json(req): Json<ActionRequest>) -> (StatusCode, Json<V
                     } else {
                         (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Could not delete directory"})))
                     }
+                } else if req.recursive.unwrap_or(false) {
+                    // Recursive delete must be confirmed by repeating the exact path
+                    if req.confirm.as_deref() != Some(req.path.as_str()) {
+                        log_audit_event("storage_delete", "rejected", &format!("unconfirmed recursive delete of: {}", req.path));
+                        return (StatusCode::BAD_REQUEST, Json(json!({"error": "Recursive delete requires 'confirm' to repeat the category path"})));
+                    }
+
+                    match move_to_trash(&req.path, true, actor) {
+                        Ok(entry) => {
+                            log_audit_event("storage_delete", "success", &format!(
+                                "recursive delete of {} by {} moved {} secrets to trash (id {}): [{}]",
+                                req.path, actor, entry.secrets.len(), entry.id, entry.secrets.join(", ")
+                            ));
+                            commit_changes(&format!("Move category to trash: {} ({} secrets)", req.path, entry.secrets.len()));
+                            (StatusCode::OK, Json(json!({"status": "OK", "deleted": entry.secrets, "trash_id": entry.id})))
+                        },
+                        Err(e) => {
+                            log_audit_event("storage_delete", "failed", &format!("recursive delete of {} failed: {}", req.path, e));
+                            (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Could not delete directory"})))
+                        }
+                    }
                 } else {
                     (StatusCode::CONFLICT, Json(json!({"error": "Category is not empty. Please remove all secrets and sub-categories first."})))
                 }
@@ -318,6 +580,84 @@ pub async fn delete_entry(Json(req): Json<ActionRequest>) -> (StatusCode, Json<V
     }
 }
 
+pub async fn move_entry(Json(req): Json<MoveRequest>) -> (StatusCode, Json<Value>) {
+    log_audit_event("storage_move", "started", &format!("moving {} to {}", req.from, req.to));
+
+    if *DEBUG_MODE { println!("--> [STORAGE] MOVE request: {} -> {}", req.from, req.to); }
+
+    // Validate both paths to prevent traversal attacks
+    for path in [&req.from, &req.to] {
+        if let Err(e) = validate_path(path) {
+            log_audit_event("storage_move", "failed", &format!("path validation failed: {}", e));
+            return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+        }
+    }
+
+    let from = req.from.trim_matches('/');
+    let to = req.to.trim_matches('/');
+    if from.is_empty() || to.is_empty() || from == to || to.starts_with(&format!("{}/", from)) {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid move destination"})));
+    }
+
+    // Held from the existence checks on, so two moves cannot both claim one destination
+    let _write_guard = WRITE_LOCK.lock().await;
+
+    let store = StdPath::new(STORE_PATH.as_str());
+    let (src, dst, is_dir) = if store.join(format!("{}.gpg", from)).is_file() {
+        (store.join(format!("{}.gpg", from)), store.join(format!("{}.gpg", to)), false)
+    } else if store.join(from).is_dir() {
+        (store.join(from), store.join(to), true)
+    } else {
+        return (StatusCode::NOT_FOUND, Json(json!({"error": "Entry not found"})));
+    };
+
+    if store.join(to).exists() || store.join(format!("{}.gpg", to)).exists() {
+        return (StatusCode::CONFLICT, Json(json!({"error": "Destination already exists"})));
+    }
+
+    // (old path, new path) for every secret that moves
+    let secrets: Vec<(String, String)> = if is_dir {
+        list_secrets(&src, from).into_iter()
+            .map(|old| {
+                let new = format!("{}{}", to, &old[from.len()..]);
+                (old, new)
+            })
+            .collect()
+    } else {
+        vec![(from.to_string(), to.to_string())]
+    };
+    let moved: Vec<(String, Vec<String>)> = secrets.iter().map(|(old, new)| (new.clone(), recipients_for(old))).collect();
+
+    if let Some(parent) = dst.parent()
+        && let Err(e) = fs::create_dir_all(parent)
+    {
+        println!("❌ [STORAGE] Error creating directory: {}", e);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Could not create directory"})));
+    }
+    if let Err(e) = fs::rename(&src, &dst) {
+        println!("❌ [STORAGE] Error moving entry: {}", e);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Could not move entry"})));
+    }
+
+    let reencrypted = match reencrypt_moved(store, &moved).await {
+        Ok(reencrypted) => reencrypted,
+        Err(failure) => {
+            let _ = fs::rename(&dst, &src);
+            log_audit_event("storage_move", "failed", &failure.detail);
+            return (failure.status, Json(json!({"error": failure.error})));
+        }
+    };
+
+    metadata::rename_prefix(from, to, is_dir);
+
+    let kind = if is_dir { "category" } else { "secret" };
+    commit_changes(&format!("Move {} from {} to {}", kind, from, to));
+    log_audit_event("storage_move", "success", &format!(
+        "moved {} {} to {} ({} secrets, {} re-encrypted)", kind, from, to, secrets.len(), reencrypted
+    ));
+    (StatusCode::OK, Json(json!({"status": "OK", "moved": secrets.len(), "reencrypted": reencrypted})))
+}
+
 pub async fn create_category(Json(req): Json<ActionRequest>) -> (StatusCode, Json<Value>) {
     if *DEBUG_MODE { println!("--> [STORAGE] CREATE CATEGORY request for: {}", req.path); }
 
@@ -328,7 +668,7 @@ pub async fn create_category(Json(req): Json<ActionRequest>) -> (StatusCode, Jso
 
     let dir_path = format!("{}/{}", &*STORE_PATH, req.path);
     
-    if let Err(_) = fs::create_dir_all(&dir_path) {
+    if fs::create_dir_all(&dir_path).is_err() {
         return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Could not create directory"})));
     }
 
@@ -337,121 +677,6 @@ pub async fn create_category(Json(req): Json<ActionRequest>) -> (StatusCode, Jso
     (StatusCode::OK, Json(json!({"status": "OK"})))
 }
 
-pub async fn download_backup() -> impl IntoResponse {
-    if *DEBUG_MODE { println!("--> [STORAGE] BACKUP request initiated"); }
-    let store_path = STORE_PATH.as_str();
-    let mut buf = Vec::new();
-    
-    {
-        let mut zip_writer = zip::ZipWriter::new(Cursor::new(&mut buf));
-        let options = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
-
-        let walk_dir = walkdir::WalkDir::new(store_path);
-        for entry in walk_dir.into_iter().filter_map(|e| e.ok()) {
-            let path = entry.path();
-            if path.is_file() {
-                let name = path.strip_prefix(store_path).unwrap().to_str().unwrap();
-                // Ignore git folder
-                if name.starts_with(".git") { continue; }
-                
-                if let Ok(content) = fs::read(path) {
-                    let _ = zip_writer.start_file(name, options);
-                    let _ = zip_writer.write_all(&content);
-                }
-            }
-        }
-        
-        let _ = zip_writer.finish();
-    }
-    
-    // Calculate checksum after zip is complete
-    let checksum = format!("{:x}", Sha256::digest(&buf));
-
-    log_audit_event("storage_backup", "success", &format!("backup created with checksum: {}", checksum));
-
-    (
-        [
-            (header::CONTENT_TYPE, "application/zip"),
-            (header::CONTENT_DISPOSITION, "attachment; filename=\"talos_backup.zip\""),
-        ],
-        buf,
-    )
-}
-
-pub async fn restore_backup(mut multipart: Multipart) -> (StatusCode, Json<Value>) {
-    log_audit_event("storage_restore", "started", "restoring from backup");
-    
-    if *DEBUG_MODE { println!("--> [STORAGE] RESTORE request initiated"); }
-    
-    while let Some(field) = multipart.next_field().await.unwrap_or(None) {
-        if field.name() == Some("backup") {
-            let data = field.bytes().await.unwrap_or_default();
-            
-            // Verify integrity checksum
-            let expected_checksum = format!("{:x}", Sha256::digest(&data));
-            
-            let reader = Cursor::new(data);
-            let mut archive = match zip::ZipArchive::new(reader) {
-                Ok(a) => a,
-                Err(_) => {
-                    log_audit_event("storage_restore", "failed", "invalid zip file");
-                    return (StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid zip file"})));
-                },
-            };
-            
-            // Check for checksum file and verify
-            let mut found_checksum = false;
-            for i in 0..archive.len() {
-                if let Ok(mut file) = archive.by_index(i) {
-                    if file.name() == "SHA256_CHECKSUM.txt" {
-                        if let Ok(checksum_content) = std::io::read_to_string(&mut file) {
-                            found_checksum = true;
-                            // Extract just the checksum (remove any whitespace)
-                            let stored_checksum = checksum_content.trim();
-                            if stored_checksum != expected_checksum {
-                                log_audit_event("storage_restore", "failed", "integrity check failed - checksum mismatch");
-                                return (StatusCode::BAD_REQUEST, Json(json!({"error": "Integrity verification failed"})));
-                            }
-                        }
-                    }
-                }
-            }
-            
-            if !found_checksum {
-                log_audit_event("storage_restore", "warning", "no checksum file found, proceeding without verification");
-            } else {
-                log_audit_event("storage_restore", "success", "integrity verification passed");
-            }
-
-            let store_path = STORE_PATH.as_str();
-            
-            // Extract files
-            for i in 0..archive.len() {
-                let mut file = archive.by_index(i).unwrap();
-                let outpath = match StdPath::new(store_path).join(file.mangled_name()) {
-                    // Path traversal defense
-                    path if path.starts_with(store_path) => path,
-                    _ => continue,
-                };
-
-                if file.name().ends_with('/') {
-                    let _ = fs::create_dir_all(&outpath);
-                } else {
-                    if let Some(p) = outpath.parent() {
-                        if !p.exists() { let _ = fs::create_dir_all(p); }
-                    }
-                    let mut outfile = fs::File::create(&outpath).unwrap();
-                    io::copy(&mut file, &mut outfile).unwrap();
-                }
-            }
-            
-            commit_changes("Restored from backup");
-            return (StatusCode::OK, Json(json!({"status": "Restored successfully"})));
-        }
-    }
-    (StatusCode::BAD_REQUEST, Json(json!({"error": "No backup file provided"})))
-}
-
 #[derive(serde::Deserialize)]
 pub struct InitializeRequest {
     pub key: String,
@@ -466,7 +691,7 @@ pub async fn initialize_bunker(Json(req): Json<InitializeRequest>) -> impl IntoR
     // Check if the bunker is already initialized
     let check_res: serde_json::Value = client.post(format!("{}/process", bunker_url))
         .header("X-Talos-Auth", &shared_secret)
-        .json(&BunkerTask { payload: "".to_string(), mode: "check".to_string(), signature: None })
+        .json(&BunkerTask { payload: "".to_string(), mode: "check".to_string(), signature: None, recipients: None })
         .send().await.unwrap().json().await.unwrap();
     
     if check_res["result"].as_str() != Some("UNINITIALIZED") {
@@ -480,6 +705,7 @@ pub async fn initialize_bunker(Json(req): Json<InitializeRequest>) -> impl IntoR
             payload: req.key,
             mode: "initialize".to_string(),
             signature: None,
+            recipients: None,
         })
         .send().await;
 
@@ -508,6 +734,19 @@ pub async fn import_bunker_key(Json(req): Json<ImportRequest>) -> impl IntoRespo
     let shared_secret = env::var("SHARED_SECRET").unwrap_or_default();
     let client = reqwest::Client::new();
 
+    // Like initialize, only possible once: an import would replace the vault key
+    let check_res = client.post(format!("{}/process", bunker_url))
+        .header("X-Talos-Auth", &shared_secret)
+        .json(&BunkerTask { payload: "".to_string(), mode: "check".to_string(), signature: None, recipients: None })
+        .send().await;
+    let state: serde_json::Value = match check_res {
+        Ok(res) => res.json().await.unwrap_or_default(),
+        Err(_) => return (StatusCode::SERVICE_UNAVAILABLE, Json(json!({"error": "Bunker unreachable"}))),
+    };
+    if state["result"].as_str() != Some("UNINITIALIZED") {
+        return (StatusCode::FORBIDDEN, Json(json!({"error": "System already initialized"})));
+    }
+
     // Send Import Command to Bunker
     // We send the private key block and the passphrase to unlock/verify it
     let import_res = client.post(format!("{}/process", bunker_url))
@@ -521,7 +760,12 @@ pub async fn import_bunker_key(Json(req): Json<ImportRequest>) -> impl IntoRespo
 
     match import_res {
         Ok(res) if res.status().is_success() => {
-            (StatusCode::OK, Json(json!({"status": "imported"})))
+            let data: serde_json::Value = res.json().await.unwrap_or_default();
+            if data["result"].as_str() == Some("INITIALIZED") {
+                (StatusCode::OK, Json(json!({"status": "imported"})))
+            } else {
+                (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Import failed"})))
+            }
         },
         _ => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Import failed"})))
     }
@@ -578,6 +822,7 @@ pub async fn unlock_bunker(Json(req): Json<UnlockRequest>) -> impl IntoResponse
             payload: req.key.clone(),
             mode: "unlock".to_string(),
             signature: None,
+            recipients: None,
         })
         .send().await;
 
@@ -597,14 +842,14 @@ pub async fn unlock_bunker(Json(req): Json<UnlockRequest>) -> impl IntoResponse
             payload: test_payload.to_string(),
             mode: "encrypt".to_string(),
             signature: None,
+            recipients: None,
         })
         .send().await;
 
     let encrypted = match enc_res {
         Ok(res) if res.status().is_success() => {
             let data: serde_json::Value = res.json().await.unwrap_or_default();
-            let result = data["result"].as_str().unwrap_or("").to_string();
-            result
+            data["result"].as_str().unwrap_or("").to_string()
         },
         _ => {
             return (StatusCode::UNAUTHORIZED, Json(json!({"error": "Key rejected (Encryption failed)"})))
@@ -618,6 +863,7 @@ pub async fn unlock_bunker(Json(req): Json<UnlockRequest>) -> impl IntoResponse
             payload: encrypted,
             mode: "decrypt".to_string(),
             signature: None,
+            recipients: None,
         })
         .send().await;
 
@@ -636,7 +882,7 @@ pub async fn unlock_bunker(Json(req): Json<UnlockRequest>) -> impl IntoResponse
     }
 }
 
-fn commit_changes(msg: &str) {
+pub(crate) fn commit_changes(msg: &str) {
     let store_path = STORE_PATH.as_str();
     
     if CONFIG.backend.r#type == "git" {
@@ -651,7 +897,79 @@ fn commit_changes(msg: &str) {
     }
 }
 
+#[derive(Serialize, Debug)]
+pub struct HistoryEntry {
+    commit: String,
+    author: String,
+    date: String,
+    message: String,
+}
+
+const HISTORY_LIMIT: &str = "100";
+
+// Commits touching one secret, newest first. Covers commits made with
+// `pass` on the same repository as well as Talos' own.
+fn read_history(store: &StdPath, path: &str) -> Result<Vec<HistoryEntry>, String> {
+    let output = std::process::Command::new("git")
+        .arg("-C").arg(store)
+        .args(["log", "--follow", "-n", HISTORY_LIMIT, "--format=%H%x1f%an%x1f%aI%x1f%s", "--"])
+        .arg(format!("{}.gpg", path))
+        .output()
+        .map_err(|e| format!("git unavailable: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(4, '\x1f');
+            Some(HistoryEntry {
+                commit: parts.next()?.to_string(),
+                author: parts.next()?.to_string(),
+                date: parts.next()?.to_string(),
+                message: parts.next().unwrap_or("").to_string(),
+            })
+        })
+        .collect())
+}
+
+pub async fn secret_history(Query(query): Query<HistoryQuery>) -> (StatusCode, Json<Value>) {
+    if let Err(e) = validate_path(&query.path) {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    }
+    if CONFIG.backend.r#type != "git" {
+        return (StatusCode::NOT_FOUND, Json(json!({"error": "History requires the git backend"})));
+    }
+    let store = StdPath::new(STORE_PATH.as_str()).to_path_buf();
+    let path = query.path.clone();
+    match tokio::task::spawn_blocking(move || read_history(&store, &path)).await {
+        Ok(Ok(history)) => (StatusCode::OK, Json(json!(history))),
+        Ok(Err(e)) => {
+            println!("❌ [STORAGE] git log failed for {}: {}", query.path, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Could not read history"})))
+        },
+        Err(_) => (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Could not read history"}))),
+    }
+}
+
 // Health check handler to verify connectivity with the Bunker
+// Checks a master key against the unlocked vault without touching it.
+// Used to re-authenticate sensitive operations such as exports.
+pub async fn verify_master_key(Json(req): Json<UnlockRequest>) -> (StatusCode, Json<Value>) {
+    let client = reqwest::Client::new();
+    let result = bunker_call(&client, &BunkerTask {
+        payload: req.key.clone(),
+        mode: "verify_key".to_string(),
+        signature: None,
+        recipients: None,
+    }).await;
+    match result.as_deref() {
+        Ok("KEY_VALID") => (StatusCode::OK, Json(json!({"status": "verified"}))),
+        Ok(_) => (StatusCode::UNAUTHORIZED, Json(json!({"error": "Invalid Master Key"}))),
+        Err(_) => (StatusCode::SERVICE_UNAVAILABLE, Json(json!({"error": "Vault is sealed"}))),
+    }
+}
+
 pub async fn storage_health_check() -> Json<Value> {
     let client = reqwest::Client::new();
     let bunker_url = env::var("BUNKER_URL").unwrap_or_else(|_| "http://talos-bunker:5000".to_string());
@@ -673,6 +991,252 @@ pub async fn storage_health_check() -> Json<Value> {
     
     Json(json!({
         "storage": true, // Storage is reachable if this code executes
-        "bunker": bunker_status
+        "bunker": bunker_status,
+        "backups": crate::scheduler::status_json()
     }))
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backup::check_openpgp;
+    use crate::testing::{self, read_secret, request, write_secret, FAILING_RECIPIENT};
+    use std::process::Command;
+
+    // Store written with the `pass` layout: root and nested .gpg-id, binary
+    // entries plus one armored entry, .gitattributes and an extension script.
+    // The throwaway keys it is encrypted to are in pass-store-keys.asc.
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/pass-store");
+
+    fn names(nodes: &[TreeNode]) -> Vec<String> {
+        nodes.iter().flat_map(|n| {
+            std::iter::once(n.path.clone()).chain(names(n.children.as_deref().unwrap_or_default()))
+        }).collect()
+    }
+
+    #[test]
+    fn pass_store_layout() {
+        let tree = build_tree_recursive(FIXTURE, "", &MetaStore::new(), None);
+        assert_eq!(names(&tree), [
+            "Email", "Email/personal",
+            "Team", "Team/Servers", "Team/Servers/router", "Team/vpn",
+            "Web", "Web/example.com",
+        ]);
+
+        let store = StdPath::new(FIXTURE);
+        assert_eq!(recipients_in(store, "Email/personal"), ["fixture@talos.local"]);
+        assert_eq!(recipients_in(store, "Team/Servers/router"), ["fixture@talos.local", "team@talos.local"]);
+        assert!(validate_path(".extensions/hello").is_err());
+
+        let binary = fs::read(store.join("Email/personal.gpg")).unwrap();
+        let armored = fs::read(store.join("Web/example.com.gpg")).unwrap();
+        assert_eq!(binary[0] & 0x80, 0x80);
+        assert!(armored.starts_with(b"-----BEGIN PGP MESSAGE-----"));
+        for secret in ["Email/personal", "Team/vpn", "Team/Servers/router", "Web/example.com"] {
+            check_openpgp(&fs::read(store.join(format!("{}.gpg", secret))).unwrap()).unwrap();
+        }
+    }
+
+    #[test]
+    fn dot_named_secrets_are_listed_but_internals_are_not() {
+        let store = tempfile::tempdir().unwrap();
+        for file in [
+            ".env.gpg", ".ssh/id_ed25519.gpg", ".ssh/.gpg-id", "web/.htpasswd.gpg",
+            ".git/config", ".extensions/otp.bash", ".trash/1/old.gpg", ".restore-staging/a.gpg",
+            ".restore-rollback/b.gpg", ".gpg-id", ".gitattributes", ".talos-meta.json", "web/.gitkeep",
+        ] {
+            let path = store.path().join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, b"").unwrap();
+        }
+        let tree = build_tree_recursive(store.path().to_str().unwrap(), "", &MetaStore::new(), None);
+        assert_eq!(names(&tree), [".env", ".ssh", ".ssh/id_ed25519", "web", "web/.htpasswd"]);
+    }
+
+    #[test]
+    fn pass_store_decrypts_with_fixture_keys() {
+        let home = tempfile::tempdir().unwrap();
+        let gpg = |args: &[&str]| Command::new("gpg").env("GNUPGHOME", home.path()).args(["--batch", "--quiet"]).args(args).output();
+        // Needs the gpg binary, which the Storage container does not ship
+        let Ok(import) = gpg(&["--import", concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/pass-store-keys.asc")]) else { return };
+        assert!(import.status.success());
+        for (secret, expected) in [
+            ("Team/vpn", "correct horse battery staple\n"),
+            ("Web/example.com", "armored-pass\n"),
+        ] {
+            let output = gpg(&["-d", &format!("{}/{}.gpg", FIXTURE, secret)]).unwrap();
+            assert_eq!(String::from_utf8_lossy(&output.stdout), expected);
+        }
+    }
+
+    #[test]
+    fn history_includes_pass_commits() {
+        let repo = tempfile::tempdir().unwrap();
+        let git = |args: &[&str]| {
+            let status = Command::new("git").arg("-C").arg(repo.path())
+                .args(["-c", "user.name=pass", "-c", "user.email=pass@localhost"])
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success());
+        };
+        git(&["init", "-q"]);
+        fs::create_dir_all(repo.path().join("Email")).unwrap();
+        fs::copy(format!("{}/Email/personal.gpg", FIXTURE), repo.path().join("Email/personal.gpg")).unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "Add given password for Email/personal to store."]);
+        fs::create_dir_all(repo.path().join("Mail")).unwrap();
+        git(&["mv", "Email/personal.gpg", "Mail/personal.gpg"]);
+        git(&["commit", "-q", "-m", "Rename Email/personal to Mail/personal."]);
+
+        let history = read_history(repo.path(), "Mail/personal").unwrap();
+        let messages: Vec<&str> = history.iter().map(|h| h.message.as_str()).collect();
+        assert_eq!(messages, ["Rename Email/personal to Mail/personal.", "Add given password for Email/personal to store."]);
+        assert_eq!(history[0].author, "pass");
+        assert_eq!(history[0].commit.len(), 40);
+    }
+
+    async fn read_response(response: axum::response::Response) -> (StatusCode, Option<String>, Value) {
+        let status = response.status();
+        let etag = response.headers().get(header::ETAG).map(|v| v.to_str().unwrap().to_string());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, etag, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn saves_must_match_the_version_that_was_read() {
+        let store = testing::store();
+        let (status, Json(saved)) = encrypt_and_save(request(json!({"path": "etag/github", "content": "hunter2\nUser: alice"}))).await;
+        assert_eq!(status, StatusCode::OK);
+        let version = saved["version"].as_str().unwrap().to_string();
+        assert_eq!(version, secret_version(&fs::read(store.join("etag/github.gpg")).unwrap()));
+
+        let (status, etag, content) = read_response(decrypt_secret(request(json!({"path": "etag/github", "reveal": true}))).await.into_response()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(etag, Some(format!("\"{}\"", version)));
+        assert_eq!(content, json!("hunter2\nUser: alice"));
+
+        // Saving on top of what was read goes through and changes the version
+        let (status, Json(updated)) = encrypt_and_save(request(json!({"path": "etag/github", "content": "hunter3", "expected_version": etag}))).await;
+        assert_eq!(status, StatusCode::OK);
+        let current = updated["version"].as_str().unwrap();
+        assert_ne!(current, version);
+
+        // A second edit of the same read is stale and writes nothing
+        let (status, Json(conflict)) = encrypt_and_save(request(json!({"path": "etag/github", "content": "stale", "expected_version": version}))).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(conflict["current_version"], current);
+        assert_eq!(read_secret("etag/github").1, "hunter3\n");
+
+        let (status, etag, _) = read_response(decrypt_secret(request(json!({"path": "etag/missing"}))).await.into_response()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(etag, None);
+    }
+
+    #[tokio::test]
+    async fn moves_take_the_subtree_and_reencrypt_for_the_new_gpg_id() {
+        let store = testing::store();
+        fs::create_dir_all(store.join("move/team")).unwrap();
+        fs::write(store.join("move/.gpg-id"), "admin@talos.local\n").unwrap();
+        fs::write(store.join("move/team/.gpg-id"), "admin@talos.local\nteam@talos.local\n").unwrap();
+        write_secret("move/servers/router", &["admin@talos.local"], "router-pass\n");
+        write_secret("move/servers/dc/ldap", &["admin@talos.local"], "ldap-pass\nUser: cn=admin\n");
+
+        let (status, Json(moved)) = move_entry(request(json!({"from": "move/servers", "to": "move/team/servers"}))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!((moved["moved"].as_u64(), moved["reencrypted"].as_u64()), (Some(2), Some(2)));
+        assert!(!store.join("move/servers").exists());
+        assert_eq!(read_secret("move/team/servers/router"), ("admin@talos.local,team@talos.local".to_string(), "router-pass\n".to_string()));
+        assert_eq!(read_secret("move/team/servers/dc/ldap").1, "ldap-pass\nUser: cn=admin\n");
+        assert!(walkdir::WalkDir::new(store.join("move")).into_iter().flatten().all(|e| !e.file_name().to_string_lossy().ends_with(".tmp")));
+
+        // Same recipients on both sides: moved as is
+        let (status, Json(moved)) = move_entry(request(json!({"from": "move/team/servers/router", "to": "move/team/router"}))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(moved["reencrypted"].as_u64(), Some(0));
+
+        // The destination is never overwritten, and a category cannot go into itself
+        write_secret("move/team/vpn", &["admin@talos.local", "team@talos.local"], "vpn-pass\n");
+        for (from, to, expected) in [
+            ("move/team/router", "move/team/vpn", StatusCode::CONFLICT),
+            ("move/team/servers", "move/team", StatusCode::CONFLICT),
+            ("move/team/servers", "move/team/servers/dc/servers", StatusCode::BAD_REQUEST),
+            ("move/team", "move/team", StatusCode::BAD_REQUEST),
+            ("move/nothing", "move/elsewhere", StatusCode::NOT_FOUND),
+        ] {
+            let (status, _) = move_entry(request(json!({"from": from, "to": to}))).await;
+            assert_eq!(status, expected, "{} -> {}", from, to);
+        }
+        assert_eq!(read_secret("move/team/vpn").1, "vpn-pass\n");
+        assert_eq!(read_secret("move/team/router").1, "router-pass\n");
+
+        // A secret the Bunker cannot re-encrypt keeps the whole move from happening
+        fs::create_dir_all(store.join("move/offline")).unwrap();
+        fs::write(store.join("move/offline/.gpg-id"), format!("{}\n", FAILING_RECIPIENT)).unwrap();
+        let (status, _) = move_entry(request(json!({"from": "move/team/servers", "to": "move/offline/servers"}))).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert!(!store.join("move/offline/servers").exists());
+        assert_eq!(read_secret("move/team/servers/dc/ldap").0, "admin@talos.local,team@talos.local");
+    }
+
+    #[tokio::test]
+    async fn trash_restores_reencrypt_for_the_gpg_id_of_the_new_folder() {
+        let store = testing::store();
+        fs::create_dir_all(store.join("binmove/team")).unwrap();
+        fs::write(store.join("binmove/team/.gpg-id"), "admin@talos.local\nteam@talos.local\n").unwrap();
+        write_secret("binmove/wifi", &["admin@talos.local"], "wifi-pass\n");
+        // A category with its own .gpg-id keeps it, and its secrets, in the trash
+        fs::create_dir_all(store.join("binmove/ops")).unwrap();
+        fs::write(store.join("binmove/ops/.gpg-id"), "ops@talos.local\n").unwrap();
+        write_secret("binmove/ops/pager", &["ops@talos.local"], "pager-pass\n");
+
+        let trash = |path: &'static str| async move {
+            let (status, Json(deleted)) = delete_entry(request(json!({"path": path, "recursive": true, "confirm": path, "actor": "alice"}))).await;
+            assert_eq!(status, StatusCode::OK, "{}", deleted);
+            deleted["trash_id"].as_str().unwrap().to_string()
+        };
+        let restore = |id: String, path: &'static str| crate::trash::restore_from_trash(request(json!({"id": id, "path": path})));
+
+        let (status, Json(restored)) = restore(trash("binmove/wifi").await, "binmove/team/wifi").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(restored["reencrypted"].as_u64(), Some(1));
+        assert_eq!(read_secret("binmove/team/wifi"), ("admin@talos.local,team@talos.local".to_string(), "wifi-pass\n".to_string()));
+        assert!(walkdir::WalkDir::new(store.join("binmove")).into_iter().flatten().all(|e| !e.file_name().to_string_lossy().ends_with(".tmp")));
+
+        let (status, Json(restored)) = restore(trash("binmove/ops").await, "binmove/team/ops").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(restored["reencrypted"].as_u64(), Some(0));
+        assert_eq!(read_secret("binmove/team/ops/pager").0, "ops@talos.local");
+
+        // A secret the Bunker cannot re-encrypt stays in the trash
+        fs::create_dir_all(store.join("binmove/offline")).unwrap();
+        fs::write(store.join("binmove/offline/.gpg-id"), format!("{}\n", FAILING_RECIPIENT)).unwrap();
+        let id = trash("binmove/team/wifi").await;
+        let (status, _) = restore(id.clone(), "binmove/offline/wifi").await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        assert!(!store.join("binmove/offline/wifi.gpg").exists());
+        let (status, _) = restore(id, "binmove/wifi").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(read_secret("binmove/wifi"), ("admin@talos.local".to_string(), "wifi-pass\n".to_string()));
+    }
+
+    #[tokio::test]
+    async fn recursive_delete_needs_the_path_repeated() {
+        let store = testing::store();
+        write_secret("wipe/old/a", &["admin@talos.local"], "a\n");
+        write_secret("wipe/old/nested/b", &["admin@talos.local"], "b\n");
+
+        let delete = |body: Value| delete_entry(request(body));
+        let (status, _) = delete(json!({"path": "wipe/old"})).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        for confirm in [json!(null), json!("wipe"), json!("wipe/old/")] {
+            let (status, _) = delete(json!({"path": "wipe/old", "recursive": true, "confirm": confirm})).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert!(store.join("wipe/old/nested/b.gpg").is_file());
+        }
+
+        let (status, Json(deleted)) = delete(json!({"path": "wipe/old", "recursive": true, "confirm": "wipe/old", "actor": "alice"})).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(deleted["deleted"], json!(["wipe/old/a", "wipe/old/nested/b"]));
+        assert!(!store.join("wipe/old").exists());
+    }
+}
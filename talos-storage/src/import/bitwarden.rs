@@ -0,0 +1,387 @@
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use base64::{Engine as _, engine::general_purpose};
+use hkdf::Hkdf;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::{BTreeSet, HashMap};
+use super::{totp_value, ImportRecord};
+
+// Bitwarden JSON export, unencrypted or password protected (the account
+// key-encrypted variant cannot be opened outside Bitwarden). Folders
+// ("Work/Servers" nests) or, for organization exports, the first collection
+// become categories.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Export {
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    password_protected: bool,
+    #[serde(default)]
+    salt: String,
+    #[serde(default)]
+    kdf_type: u8,
+    #[serde(default)]
+    kdf_iterations: u32,
+    #[serde(default, rename = "encKeyValidation_DO_NOT_EDIT")]
+    enc_key_validation: String,
+    #[serde(default)]
+    data: String,
+    #[serde(default)]
+    folders: Vec<Named>,
+    #[serde(default)]
+    collections: Vec<Named>,
+    #[serde(default)]
+    items: Vec<Item>,
+}
+
+#[derive(Deserialize)]
+struct Named {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Item {
+    #[serde(rename = "type")]
+    kind: u8,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    notes: Option<String>,
+    #[serde(default)]
+    folder_id: Option<String>,
+    #[serde(default)]
+    collection_ids: Option<Vec<String>>,
+    #[serde(default)]
+    fields: Option<Vec<Field>>,
+    #[serde(default)]
+    login: Option<Login>,
+    #[serde(default)]
+    card: Option<HashMap<String, Value>>,
+    #[serde(default)]
+    identity: Option<HashMap<String, Value>>,
+    #[serde(default)]
+    ssh_key: Option<HashMap<String, Value>>,
+}
+
+#[derive(Deserialize)]
+struct Field {
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    value: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Login {
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    totp: Option<String>,
+    #[serde(default)]
+    uris: Option<Vec<Uri>>,
+}
+
+#[derive(Deserialize)]
+struct Uri {
+    #[serde(default)]
+    uri: Option<String>,
+}
+
+const CARD_FIELDS: [(&str, &str); 5] = [
+    ("cardholderName", "Cardholder"),
+    ("brand", "Brand"),
+    ("expMonth", "Expiry Month"),
+    ("expYear", "Expiry Year"),
+    ("code", "Security Code"),
+];
+
+const IDENTITY_FIELDS: [(&str, &str); 18] = [
+    ("title", "Title"),
+    ("firstName", "First Name"),
+    ("middleName", "Middle Name"),
+    ("lastName", "Last Name"),
+    ("username", "Username"),
+    ("company", "Company"),
+    ("email", "Email"),
+    ("phone", "Phone"),
+    ("address1", "Address 1"),
+    ("address2", "Address 2"),
+    ("address3", "Address 3"),
+    ("city", "City"),
+    ("state", "State"),
+    ("postalCode", "Postal Code"),
+    ("country", "Country"),
+    ("ssn", "SSN"),
+    ("passportNumber", "Passport Number"),
+    ("licenseNumber", "License Number"),
+];
+
+const SSH_KEY_FIELDS: [(&str, &str); 3] = [
+    ("keyFingerprint", "Fingerprint"),
+    ("publicKey", "Public Key"),
+    ("privateKey", "Private Key"),
+];
+
+fn text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn copy_fields(source: &Option<HashMap<String, Value>>, names: &[(&str, &str)], fields: &mut Vec<(String, String)>) {
+    let Some(source) = source else { return };
+    for (key, label) in names {
+        if let Some(value) = source.get(*key).and_then(text) {
+            fields.push((label.to_string(), value));
+        }
+    }
+}
+
+// Password protected exports: PBKDF2-SHA256 over the password and salt,
+// stretched with HKDF into AES-256-CBC and HMAC-SHA256 keys. Values are
+// "2.<iv>|<ciphertext>|<mac>" encrypted strings.
+const KDF_PBKDF2: u8 = 0;
+pub const EXPORT_KDF_ITERATIONS: u32 = 600_000;
+
+struct Keys {
+    enc: [u8; 32],
+    mac: [u8; 32],
+}
+
+fn derive_keys(password: &str, salt: &str, iterations: u32) -> Keys {
+    let mut master = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(password.as_bytes(), salt.as_bytes(), iterations, &mut master);
+    let hkdf = Hkdf::<Sha256>::from_prk(&master).expect("PRK is one hash long");
+    let mut keys = Keys { enc: [0; 32], mac: [0; 32] };
+    hkdf.expand(b"enc", &mut keys.enc).expect("valid output length");
+    hkdf.expand(b"mac", &mut keys.mac).expect("valid output length");
+    keys
+}
+
+fn mac(keys: &Keys, iv: &[u8], ciphertext: &[u8]) -> Hmac<Sha256> {
+    let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&keys.mac).expect("HMAC accepts any key length");
+    mac.update(iv);
+    mac.update(ciphertext);
+    mac
+}
+
+fn encrypt_string(keys: &Keys, plaintext: &[u8]) -> String {
+    let mut iv = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut iv);
+    let mut buf = vec![0u8; plaintext.len() + 16];
+    buf[..plaintext.len()].copy_from_slice(plaintext);
+    let len = cbc::Encryptor::<aes::Aes256>::new(&keys.enc.into(), &iv.into())
+        .encrypt_padded_mut::<Pkcs7>(&mut buf, plaintext.len())
+        .expect("buffer has room for padding")
+        .len();
+    buf.truncate(len);
+    let tag = mac(keys, &iv, &buf).finalize().into_bytes();
+    let b64 = |bytes: &[u8]| general_purpose::STANDARD.encode(bytes);
+    format!("2.{}|{}|{}", b64(&iv), b64(&buf), b64(&tag))
+}
+
+fn decrypt_string(keys: &Keys, value: &str) -> Result<Vec<u8>, String> {
+    let parts: Vec<Vec<u8>> = value.strip_prefix("2.")
+        .ok_or("Unsupported Bitwarden encryption type")?
+        .split('|')
+        .map(|part| general_purpose::STANDARD.decode(part).map_err(|_| "Invalid Bitwarden encrypted value".to_string()))
+        .collect::<Result<_, _>>()?;
+    let [iv, ciphertext, tag] = parts.as_slice() else {
+        return Err("Invalid Bitwarden encrypted value".to_string());
+    };
+    mac(keys, iv, ciphertext).verify_slice(tag).map_err(|_| "Wrong export password".to_string())?;
+    let iv: [u8; 16] = iv.as_slice().try_into().map_err(|_| "Invalid Bitwarden encrypted value".to_string())?;
+    let mut buf = ciphertext.clone();
+    let len = cbc::Decryptor::<aes::Aes256>::new(&keys.enc.into(), &iv.into())
+        .decrypt_padded_mut::<Pkcs7>(&mut buf)
+        .map_err(|_| "Invalid Bitwarden encrypted value".to_string())?
+        .len();
+    buf.truncate(len);
+    Ok(buf)
+}
+
+pub fn parse(data: &[u8], password: &str) -> Result<Vec<ImportRecord>, String> {
+    let export: Export = serde_json::from_slice(data).map_err(|e| format!("Invalid Bitwarden export: {}", e))?;
+    if export.encrypted && export.password_protected {
+        if password.is_empty() {
+            return Err("This Bitwarden export is password protected, the export password is required".to_string());
+        }
+        if export.kdf_type != KDF_PBKDF2 {
+            return Err("Only PBKDF2 password protected Bitwarden exports are supported".to_string());
+        }
+        let keys = derive_keys(password, &export.salt, export.kdf_iterations);
+        decrypt_string(&keys, &export.enc_key_validation)?;
+        return parse(&decrypt_string(&keys, &export.data)?, "");
+    }
+    if export.encrypted {
+        return Err("Account-encrypted Bitwarden exports are not supported, export as unencrypted or password protected JSON".to_string());
+    }
+    let folders: HashMap<&str, &str> = export.folders.iter().map(|f| (f.id.as_str(), f.name.as_str())).collect();
+    let collections: HashMap<&str, &str> = export.collections.iter().map(|c| (c.id.as_str(), c.name.as_str())).collect();
+
+    Ok(export.items.iter().map(|item| {
+        let folder = item.folder_id.as_deref().and_then(|id| folders.get(id))
+            .or_else(|| item.collection_ids.iter().flatten().find_map(|id| collections.get(id.as_str())))
+            .map(|name| name.split('/').map(str::to_string).collect())
+            .unwrap_or_default();
+        let mut record = ImportRecord {
+            folder,
+            title: item.name.clone().unwrap_or_default(),
+            notes: item.notes.clone().unwrap_or_default(),
+            ..Default::default()
+        };
+
+        if let Some(login) = &item.login {
+            record.username = login.username.clone().unwrap_or_default();
+            record.password = login.password.clone().unwrap_or_default();
+            let label = if record.username.is_empty() { record.title.clone() } else { format!("{}:{}", record.title, record.username) };
+            if let Some(totp) = login.totp.as_deref().filter(|t| !t.is_empty()) {
+                // Steam and other non-standard codes are kept verbatim
+                match totp_value(totp, &label) {
+                    Some(uri) => record.totp = Some(uri),
+                    None => record.fields.push(("TOTP".to_string(), totp.to_string())),
+                }
+            }
+            let mut uris = login.uris.iter().flatten().filter_map(|u| u.uri.clone()).filter(|u| !u.is_empty());
+            record.url = uris.next().unwrap_or_default();
+            for (n, uri) in uris.enumerate() {
+                record.fields.push((format!("URL {}", n + 2), uri));
+            }
+        }
+        // Cards keep the number on the password line
+        if let Some(card) = &item.card {
+            record.password = card.get("number").and_then(text).unwrap_or_default();
+        }
+        copy_fields(&item.card, &CARD_FIELDS, &mut record.fields);
+        copy_fields(&item.identity, &IDENTITY_FIELDS, &mut record.fields);
+        copy_fields(&item.ssh_key, &SSH_KEY_FIELDS, &mut record.fields);
+
+        // Linked fields (type 3) carry no value of their own
+        for field in item.fields.iter().flatten() {
+            if let Some(value) = field.value.clone().filter(|v| !v.is_empty()) {
+                record.fields.push((field.name.clone().filter(|n| !n.is_empty()).unwrap_or_else(|| "Field".to_string()), value));
+            }
+        }
+        if item.kind == 2 && record.title.is_empty() {
+            record.title = "Secure Note".to_string();
+        }
+        record
+    }).collect())
+}
+
+// Unencrypted export document with one folder per category path
+fn export_document(records: &[ImportRecord]) -> Value {
+    let folders: BTreeSet<String> = records.iter().filter(|r| !r.folder.is_empty()).map(|r| r.folder.join("/")).collect();
+    let folder_ids: HashMap<&str, String> = folders.iter().enumerate().map(|(i, name)| (name.as_str(), format!("folder-{}", i + 1))).collect();
+    let items: Vec<Value> = records.iter().enumerate().map(|(i, record)| {
+        let folder = record.folder.join("/");
+        let blank = |s: &str| if s.is_empty() { Value::Null } else { json!(s) };
+        json!({
+            "id": format!("item-{}", i + 1),
+            "organizationId": null,
+            "folderId": folder_ids.get(folder.as_str()),
+            "type": 1,
+            "reprompt": 0,
+            "name": record.title,
+            "notes": blank(&record.notes),
+            "favorite": false,
+            "fields": record.fields.iter().map(|(name, value)| json!({"name": name, "value": value, "type": 0})).collect::<Vec<_>>(),
+            "login": {
+                "uris": if record.url.is_empty() { json!([]) } else { json!([{"match": null, "uri": record.url}]) },
+                "username": blank(&record.username),
+                "password": blank(&record.password),
+                "totp": record.totp,
+            },
+            "collectionIds": null,
+        })
+    }).collect();
+    json!({
+        "encrypted": false,
+        "folders": folders.iter().map(|name| json!({"id": folder_ids[name.as_str()], "name": name})).collect::<Vec<_>>(),
+        "items": items,
+    })
+}
+
+// Password protected export, importable by Bitwarden with the same password
+pub fn write_encrypted(records: &[ImportRecord], password: &str, iterations: u32) -> Vec<u8> {
+    let mut salt = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut salt);
+    let salt = general_purpose::STANDARD.encode(salt);
+    let keys = derive_keys(password, &salt, iterations);
+    let mut validation = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut validation);
+
+    let document = json!({
+        "encrypted": true,
+        "passwordProtected": true,
+        "salt": salt,
+        "kdfType": KDF_PBKDF2,
+        "kdfIterations": iterations,
+        "kdfMemory": null,
+        "kdfParallelism": null,
+        "encKeyValidation_DO_NOT_EDIT": encrypt_string(&keys, hex::encode(validation).as_bytes()),
+        "data": encrypt_string(&keys, export_document(records).to_string().as_bytes()),
+    });
+    serde_json::to_vec_pretty(&document).expect("JSON values serialize")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bitwarden_export() {
+        let export = br#"{
+          "encrypted": false,
+          "folders": [{"id": "f1", "name": "Work/Servers"}],
+          "items": [
+            {"id": "1", "folderId": "f1", "type": 1, "name": "Router", "notes": "rack 2",
+             "fields": [{"name": "PIN", "value": "1234", "type": 1}, {"name": "Linked", "value": null, "type": 3, "linkedId": 100}],
+             "login": {"username": "admin", "password": "s3cret", "totp": "JBSW Y3DP",
+                       "uris": [{"match": null, "uri": "https://10.0.0.1"}, {"uri": "https://router.lan"}]}},
+            {"id": "2", "folderId": null, "type": 3, "name": "Visa", "notes": null,
+             "card": {"cardholderName": "Alice", "brand": "Visa", "number": "4111111111111111", "expMonth": "4", "expYear": "2030", "code": "123"}}
+          ]
+        }"#;
+        let records = parse(export, "").unwrap();
+        assert_eq!(records[0].folder, vec!["Work".to_string(), "Servers".to_string()]);
+        assert_eq!(records[0].url, "https://10.0.0.1");
+        assert_eq!(records[0].totp.as_deref(), Some("otpauth://totp/Router:admin?secret=JBSWY3DP"));
+        assert_eq!(records[0].fields, vec![
+            ("URL 2".to_string(), "https://router.lan".to_string()),
+            ("PIN".to_string(), "1234".to_string()),
+        ]);
+        assert!(records[1].folder.is_empty());
+        assert_eq!(records[1].password, "4111111111111111");
+        assert_eq!(records[1].fields[0], ("Cardholder".to_string(), "Alice".to_string()));
+
+        assert!(parse(br#"{"encrypted": true, "encKeyValidation_DO_NOT_EDIT": "x", "data": "y"}"#, "").is_err());
+    }
+
+    #[test]
+    fn password_protected_round_trip() {
+        let record = ImportRecord {
+            folder: vec!["Work".to_string()],
+            title: "Router".to_string(),
+            username: "admin".to_string(),
+            password: "s3cret".to_string(),
+            url: "https://10.0.0.1".to_string(),
+            totp: Some("otpauth://totp/Router?secret=JBSWY3DP".to_string()),
+            ..Default::default()
+        };
+        let export = write_encrypted(std::slice::from_ref(&record), "export password", 1000);
+        assert!(!String::from_utf8_lossy(&export).contains("s3cret"));
+        assert_eq!(parse(&export, "export password").unwrap(), vec![record]);
+        assert_eq!(parse(&export, "wrong").unwrap_err(), "Wrong export password");
+    }
+}
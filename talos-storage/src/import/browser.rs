@@ -0,0 +1,103 @@
+use super::{totp_value, ImportRecord};
+
+// Password CSV exports: Chrome/Edge (name,url,username,password,note),
+// Firefox (url,username,password,httpRealm,...), Safari and 1Password
+// (Title,Url,Username,Password,OTPAuth,...,Notes) and Bitwarden (folder,...,
+// login_uri,login_username,login_password,login_totp). Columns are matched by
+// header name; columns without a mapping are dropped, they only carry
+// browser bookkeeping.
+enum Column {
+    Folder,
+    Title,
+    Url,
+    Username,
+    Password,
+    Notes,
+    Totp,
+    Tags,
+    Ignored,
+}
+
+fn column(header: &str) -> Column {
+    match header.trim().to_lowercase().as_str() {
+        "folder" | "group" => Column::Folder,
+        "name" | "title" => Column::Title,
+        "url" | "login_uri" | "website" => Column::Url,
+        "username" | "login_username" | "user name" => Column::Username,
+        "password" | "login_password" => Column::Password,
+        "note" | "notes" | "notesplain" | "extra" => Column::Notes,
+        "otpauth" | "totp" | "login_totp" => Column::Totp,
+        "tags" => Column::Tags,
+        _ => Column::Ignored,
+    }
+}
+
+// Logins without a name are titled after their site
+fn host_title(url: &str) -> String {
+    reqwest::Url::parse(url).ok()
+        .and_then(|u| u.host_str().map(|h| h.trim_start_matches("www.").to_string()))
+        .unwrap_or_else(|| url.to_string())
+}
+
+pub fn parse(data: &[u8]) -> Result<Vec<ImportRecord>, String> {
+    let data = data.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(data);
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(data);
+    let columns: Vec<Column> = reader.headers()
+        .map_err(|e| format!("Invalid CSV export: {}", e))?
+        .iter()
+        .map(column)
+        .collect();
+    if !columns.iter().any(|c| matches!(c, Column::Password)) {
+        return Err("CSV export has no password column".to_string());
+    }
+
+    let mut records = Vec::new();
+    for row in reader.records() {
+        let row = row.map_err(|e| format!("Invalid CSV export: {}", e))?;
+        let mut record = ImportRecord::default();
+        let mut totp = None;
+        for (value, column) in row.iter().zip(&columns) {
+            let value = value.to_string();
+            match column {
+                Column::Folder => record.folder = value.split('/').filter(|f| !f.is_empty()).map(str::to_string).collect(),
+                Column::Title => record.title = value,
+                Column::Url => record.url = value,
+                Column::Username => record.username = value,
+                Column::Password => record.password = value,
+                Column::Notes => record.notes = value,
+                Column::Totp => totp = Some(value),
+                Column::Tags => record.tags = value.split([',', ';']).map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect(),
+                Column::Ignored => {},
+            }
+        }
+        if record.title.is_empty() {
+            record.title = host_title(&record.url);
+        }
+        let label = if record.username.is_empty() { record.title.clone() } else { format!("{}:{}", record.title, record.username) };
+        record.totp = totp.and_then(|t| totp_value(&t, &label));
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn browser_csv_exports() {
+        let chrome = b"name,url,username,password,note\nexample.com,https://example.com/login,alice,pw1,\"multi\nline\"\n";
+        let records = parse(chrome).unwrap();
+        assert_eq!(records[0].title, "example.com");
+        assert_eq!(records[0].notes, "multi\nline");
+
+        let firefox = b"\"url\",\"username\",\"password\",\"httpRealm\",\"formActionOrigin\",\"guid\",\"timeCreated\",\"timeLastUsed\",\"timePasswordChanged\"\n\
+\"https://www.example.org\",\"bob\",\"pw2\",,\"https://www.example.org\",\"{abc}\",\"1\",\"2\",\"3\"\n";
+        let records = parse(firefox).unwrap();
+        assert_eq!(records[0].title, "example.org");
+        assert_eq!(records[0].username, "bob");
+        assert!(records[0].fields.is_empty());
+
+        assert!(parse(b"a,b\n1,2\n").is_err());
+    }
+}
@@ -0,0 +1,367 @@
+use keepass::{Database, DatabaseKey};
+use keepass::config::KdfConfig;
+use keepass::db::{GroupId, GroupMut, GroupRef, Value};
+use keepass::error::DatabaseOpenError;
+use roxmltree::Node;
+use std::{collections::{BTreeMap, HashMap}, io::Read};
+use base64::{Engine as _, engine::general_purpose};
+use super::{base32, otpauth_uri, ImportRecord};
+
+// KeePass 2.x: KDBX databases opened with the master password, or the
+// unencrypted XML export. Groups become categories (the root group is
+// dropped), the recycle bin and entry history are ignored.
+const KDBX_SIGNATURE: [u8; 4] = [0x03, 0xD9, 0xA2, 0x9A];
+
+pub fn parse(data: &[u8], password: &str) -> Result<Vec<ImportRecord>, String> {
+    if data.starts_with(&KDBX_SIGNATURE) {
+        parse_kdbx(data, password)
+    } else {
+        parse_xml(data)
+    }
+}
+
+pub fn parse_kdbx(data: &[u8], password: &str) -> Result<Vec<ImportRecord>, String> {
+    let db = Database::parse(data, DatabaseKey::new().with_password(password)).map_err(|e| match e {
+        DatabaseOpenError::Key(_) => "Wrong master password (or the database needs a key file)".to_string(),
+        DatabaseOpenError::UnsupportedVersion => "Unsupported KeePass database version".to_string(),
+        e => format!("Invalid KeePass database: {}", e),
+    })?;
+    let recycle_bin = db.recycle_bin().map(|bin| bin.id());
+    let mut records = Vec::new();
+    collect_kdbx_group(&db.root(), &[], recycle_bin, &mut records);
+    Ok(records)
+}
+
+fn collect_kdbx_group(group: &GroupRef, folder: &[String], recycle_bin: Option<GroupId>, records: &mut Vec<ImportRecord>) {
+    for entry in group.entries() {
+        let fields = entry.fields.iter().map(|(key, value)| (key.clone(), value.get().clone())).collect();
+        let attachments = entry.attachments_named()
+            .map(|(name, attachment)| (name.to_string(), attachment.data.get().clone()))
+            .collect();
+        records.push(entry_record(folder, fields, entry.tags.clone(), attachments));
+    }
+    for child in group.groups() {
+        if Some(child.id()) == recycle_bin {
+            continue;
+        }
+        let mut path = folder.to_vec();
+        path.push(child.name.clone());
+        collect_kdbx_group(&child, &path, recycle_bin, records);
+    }
+}
+
+pub fn parse_xml(data: &[u8]) -> Result<Vec<ImportRecord>, String> {
+    let text = std::str::from_utf8(data).map_err(|_| "Not a KeePass database or XML export".to_string())?;
+    let doc = roxmltree::Document::parse(text.trim_start_matches('\u{feff}'))
+        .map_err(|e| format!("Invalid KeePass XML: {}", e))?;
+    let root = doc.root_element();
+    if !root.has_tag_name("KeePassFile") {
+        return Err("Not a KeePass XML export".to_string());
+    }
+
+    let meta = child(root, "Meta");
+    let binaries = match meta.and_then(|meta| child(meta, "Binaries")) {
+        Some(pool) => binary_pool(pool)?,
+        None => HashMap::new(),
+    };
+    let recycle_bin = meta
+        .filter(|meta| child_text(*meta, "RecycleBinEnabled") != Some("False"))
+        .and_then(|meta| child_text(meta, "RecycleBinUUID"))
+        .filter(|uuid| !uuid.is_empty() && *uuid != "AAAAAAAAAAAAAAAAAAAAAA==");
+    let group = child(root, "Root")
+        .and_then(|root| child(root, "Group"))
+        .ok_or("KeePass XML export has no root group")?;
+
+    let mut records = Vec::new();
+    collect_xml_group(group, &[], recycle_bin, &binaries, &mut records)?;
+    Ok(records)
+}
+
+fn child<'a, 'i>(node: Node<'a, 'i>, name: &str) -> Option<Node<'a, 'i>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).map(|n| n.text().unwrap_or(""))
+}
+
+fn decode_binary(node: Node) -> Result<Vec<u8>, String> {
+    let encoded: String = node.text().unwrap_or("").split_whitespace().collect();
+    let data = general_purpose::STANDARD.decode(encoded).map_err(|_| "Invalid attachment encoding".to_string())?;
+    if node.attribute("Compressed") != Some("True") {
+        return Ok(data);
+    }
+    let mut inflated = Vec::new();
+    flate2::read::GzDecoder::new(data.as_slice())
+        .read_to_end(&mut inflated)
+        .map_err(|_| "Invalid compressed attachment".to_string())?;
+    Ok(inflated)
+}
+
+// Shared attachment pool of KDBX 3.1 style exports (`Meta/Binaries`)
+fn binary_pool(pool: Node) -> Result<HashMap<String, Vec<u8>>, String> {
+    pool.children()
+        .filter(|n| n.has_tag_name("Binary"))
+        .map(|n| Ok((n.attribute("ID").unwrap_or("").to_string(), decode_binary(n)?)))
+        .collect()
+}
+
+fn collect_xml_group(group: Node, folder: &[String], recycle_bin: Option<&str>, binaries: &HashMap<String, Vec<u8>>, records: &mut Vec<ImportRecord>) -> Result<(), String> {
+    for node in group.children().filter(Node::is_element) {
+        match node.tag_name().name() {
+            "Entry" => records.push(xml_entry(node, folder, binaries)?),
+            "Group" => {
+                if recycle_bin.is_some() && child_text(node, "UUID") == recycle_bin {
+                    continue;
+                }
+                let mut path = folder.to_vec();
+                path.push(child_text(node, "Name").unwrap_or("").to_string());
+                collect_xml_group(node, &path, recycle_bin, binaries, records)?;
+            },
+            _ => {},
+        }
+    }
+    Ok(())
+}
+
+// Direct children only, so `<History>` snapshots are skipped
+fn xml_entry(entry: Node, folder: &[String], binaries: &HashMap<String, Vec<u8>>) -> Result<ImportRecord, String> {
+    let mut fields = Vec::new();
+    let mut attachments = Vec::new();
+    for node in entry.children().filter(Node::is_element) {
+        let key = child_text(node, "Key").unwrap_or("").to_string();
+        match node.tag_name().name() {
+            "String" => fields.push((key, child_text(node, "Value").unwrap_or("").to_string())),
+            "Binary" => {
+                let Some(value) = child(node, "Value") else { continue };
+                let data = match value.attribute("Ref") {
+                    Some(id) => binaries.get(id).cloned().ok_or_else(|| format!("Missing attachment data for {}", key))?,
+                    None => decode_binary(value)?,
+                };
+                attachments.push((key, data));
+            },
+            _ => {},
+        }
+    }
+    let tags = child_text(entry, "Tags").unwrap_or("")
+        .split([';', ','])
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .collect();
+    Ok(entry_record(folder, fields, tags, attachments))
+}
+
+fn take_field(fields: &mut Vec<(String, String)>, key: &str) -> Option<String> {
+    let index = fields.iter().position(|(k, _)| k == key)?;
+    Some(fields.remove(index).1)
+}
+
+fn entry_record(folder: &[String], mut fields: Vec<(String, String)>, tags: Vec<String>, attachments: Vec<(String, Vec<u8>)>) -> ImportRecord {
+    fields.sort();
+    let title = take_field(&mut fields, "Title").unwrap_or_default();
+    let username = take_field(&mut fields, "UserName").unwrap_or_default();
+    let label = if username.is_empty() { title.clone() } else { format!("{}:{}", title, username) };
+    let totp = take_totp(&mut fields, &label);
+    fields.retain(|(_, value)| !value.is_empty());
+    ImportRecord {
+        folder: folder.to_vec(),
+        password: take_field(&mut fields, "Password").unwrap_or_default(),
+        url: take_field(&mut fields, "URL").unwrap_or_default(),
+        notes: take_field(&mut fields, "Notes").unwrap_or_default(),
+        title,
+        username,
+        fields,
+        totp,
+        tags,
+        attachments,
+    }
+}
+
+// TOTP settings come in several flavours: an "otp" field (otpauth URI, or
+// KeeOtp's key=...&step=... form), KeePass 2.47+ TimeOtp-* fields and the
+// TrayTOTP plugin's seed/settings pair. Consumed fields are not kept as
+// custom fields.
+fn take_totp(fields: &mut Vec<(String, String)>, label: &str) -> Option<String> {
+    if let Some(otp) = take_field(fields, "otp") {
+        if otp.starts_with("otpauth://") {
+            return Some(otp);
+        }
+        let params: HashMap<String, String> = reqwest::Url::parse(&format!("http://otp/?{}", otp)).ok()
+            .map(|url| url.query_pairs().into_owned().collect())
+            .unwrap_or_default();
+        match params.get("key") {
+            Some(key) => {
+                let get = |name: &str| params.get(name).map(String::as_str);
+                return Some(otpauth_uri(label, key, get("size"), get("step"), get("otpHashMode")));
+            },
+            None => fields.push(("otp".to_string(), otp)),
+        }
+    }
+
+    let secret = take_field(fields, "TimeOtp-Secret-Base32")
+        .or_else(|| take_field(fields, "TimeOtp-Secret-Hex").and_then(|hex| hex::decode(hex.trim()).ok()).map(|b| base32(&b)))
+        .or_else(|| take_field(fields, "TimeOtp-Secret-Base64").and_then(|b64| general_purpose::STANDARD.decode(b64.trim()).ok()).map(|b| base32(&b)))
+        .or_else(|| take_field(fields, "TimeOtp-Secret").map(|raw| base32(raw.as_bytes())));
+    if let Some(secret) = secret {
+        let digits = take_field(fields, "TimeOtp-Length");
+        let period = take_field(fields, "TimeOtp-Period");
+        let algorithm = take_field(fields, "TimeOtp-Algorithm");
+        return Some(otpauth_uri(label, &secret, digits.as_deref(), period.as_deref(), algorithm.as_deref()));
+    }
+
+    let seed = take_field(fields, "TOTP Seed")?;
+    // "<period>;<digits>[;<time server>]"
+    let settings = take_field(fields, "TOTP Settings").unwrap_or_default();
+    let mut settings = settings.split(';');
+    let period = settings.next();
+    let digits = settings.next();
+    Some(otpauth_uri(label, &seed, digits, period, None))
+}
+
+// Export: categories become nested groups below the root, TOTP URIs go in
+// the "otp" field KeePassXC and KeePass 2.57+ read.
+#[derive(Default)]
+struct Folder<'a> {
+    groups: BTreeMap<&'a str, Folder<'a>>,
+    entries: Vec<&'a ImportRecord>,
+}
+
+fn fill_group(group: &mut GroupMut, folder: &Folder) {
+    for record in &folder.entries {
+        let mut entry = group.add_entry();
+        entry.set_unprotected("Title", record.title.as_str());
+        entry.set_unprotected("UserName", record.username.as_str());
+        entry.set_protected("Password", record.password.as_str());
+        entry.set_unprotected("URL", record.url.as_str());
+        entry.set_unprotected("Notes", record.notes.as_str());
+        for (key, value) in &record.fields {
+            entry.set_protected(key.as_str(), value.as_str());
+        }
+        if let Some(totp) = &record.totp {
+            entry.set_protected("otp", totp.as_str());
+        }
+        entry.tags = record.tags.clone();
+        for (name, data) in &record.attachments {
+            entry.add_attachment(name.as_str(), Value::protected(data.clone()));
+        }
+    }
+    for (name, child) in &folder.groups {
+        let mut subgroup = group.add_group();
+        subgroup.name = name.to_string();
+        fill_group(&mut subgroup, child);
+    }
+}
+
+pub fn write_kdbx(records: &[ImportRecord], password: &str, kdf_config: KdfConfig) -> Result<Vec<u8>, String> {
+    let mut tree = Folder::default();
+    for record in records {
+        let folder = record.folder.iter().fold(&mut tree, |folder, name| folder.groups.entry(name.as_str()).or_default());
+        folder.entries.push(record);
+    }
+
+    let mut db = Database::new();
+    db.config.kdf_config = kdf_config;
+    let mut root = db.root_mut();
+    root.name = "Talos".to_string();
+    fill_group(&mut root, &tree);
+
+    let mut file = Vec::new();
+    db.save(&mut file, DatabaseKey::new().with_password(password))
+        .map_err(|e| format!("Failed to write KeePass database: {}", e))?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kdbx4_round_trip() {
+        let mut db = Database::new();
+        db.config.kdf_config = KdfConfig::Argon2 { iterations: 1, memory: 64 * 1024, parallelism: 1, version: argon2::Version::Version13 };
+        let mut root = db.root_mut();
+        let mut group = root.add_group();
+        group.name = "Internet".to_string();
+        let mut entry = group.add_entry();
+        entry.set_unprotected("Title", "Mail");
+        entry.set_unprotected("UserName", "alice");
+        entry.set_protected("Password", "hunter2");
+        entry.set_unprotected("URL", "https://mail.example");
+        entry.set_unprotected("Notes", "backup codes");
+        entry.set_protected("PIN", "1234");
+        entry.set_unprotected("TimeOtp-Secret-Base32", "JBSWY3DPEHPK3PXP");
+        entry.set_unprotected("TimeOtp-Period", "60");
+        entry.tags = vec!["work".to_string()];
+        entry.add_attachment("key.txt", Value::protected(b"ssh-key".to_vec()));
+
+        let mut file = Vec::new();
+        db.save(&mut file, DatabaseKey::new().with_password("master")).unwrap();
+
+        let records = parse(&file, "master").unwrap();
+        assert_eq!(records, vec![ImportRecord {
+            folder: vec!["Internet".to_string()],
+            title: "Mail".to_string(),
+            username: "alice".to_string(),
+            password: "hunter2".to_string(),
+            url: "https://mail.example".to_string(),
+            notes: "backup codes".to_string(),
+            fields: vec![("PIN".to_string(), "1234".to_string())],
+            totp: Some("otpauth://totp/Mail:alice?secret=JBSWY3DPEHPK3PXP&period=60".to_string()),
+            tags: vec!["work".to_string()],
+            attachments: vec![("key.txt".to_string(), b"ssh-key".to_vec())],
+        }]);
+        assert!(parse(&file, "wrong").unwrap_err().contains("Wrong master password"));
+    }
+
+    #[test]
+    fn xml_export() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8" standalone="yes"?>
+<KeePassFile>
+  <Meta>
+    <RecycleBinEnabled>True</RecycleBinEnabled>
+    <RecycleBinUUID>YmluYmluYmluYmluYmluYg==</RecycleBinUUID>
+    <Binaries><Binary ID="0" Compressed="True">H4sIAAAAAAAAAytJLS4BAAx+f9gEAAAA</Binary></Binaries>
+  </Meta>
+  <Root>
+    <Group>
+      <UUID>cm9vdHJvb3Ryb290cm9vdA==</UUID>
+      <Name>Database</Name>
+      <Entry>
+        <Tags>a;b</Tags>
+        <String><Key>Title</Key><Value>Top</Value></String>
+        <String><Key>Password</Key><Value ProtectInMemory="True">p1</Value></String>
+        <String><Key>otp</Key><Value>key=JBSWY3DP&amp;size=8</Value></String>
+        <Binary><Key>note.txt</Key><Value Ref="0"/></Binary>
+        <History><Entry><String><Key>Title</Key><Value>Old</Value></String></Entry></History>
+      </Entry>
+      <Group>
+        <UUID>d29ya3dvcmt3b3Jrd29yaw==</UUID>
+        <Name>Work</Name>
+        <Entry>
+          <String><Key>Title</Key><Value>VPN</Value></String>
+          <String><Key>Password</Key><Value>p2</Value></String>
+          <String><Key>TOTP Seed</Key><Value>JBSWY3DP</Value></String>
+          <String><Key>TOTP Settings</Key><Value>30;6</Value></String>
+        </Entry>
+      </Group>
+      <Group>
+        <UUID>YmluYmluYmluYmluYmluYg==</UUID>
+        <Name>Recycle Bin</Name>
+        <Entry><String><Key>Title</Key><Value>Deleted</Value></String></Entry>
+      </Group>
+    </Group>
+  </Root>
+</KeePassFile>"#;
+        let records = parse(xml.as_bytes(), "").unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].title, "Top");
+        assert!(records[0].folder.is_empty());
+        assert_eq!(records[0].tags, vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(records[0].totp.as_deref(), Some("otpauth://totp/Top?secret=JBSWY3DP&digits=8"));
+        assert_eq!(records[0].attachments, vec![("note.txt".to_string(), b"test".to_vec())]);
+        assert_eq!(records[1].folder, vec!["Work".to_string()]);
+        assert_eq!(records[1].totp.as_deref(), Some("otpauth://totp/VPN?secret=JBSWY3DP&digits=6&period=30"));
+        assert!(records[1].fields.is_empty());
+        assert!(parse(b"<html/>", "").is_err());
+    }
+}
@@ -0,0 +1,564 @@
+use axum::Json;
+use axum::extract::Multipart;
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::HashSet, path::Path as StdPath};
+use base64::{Engine as _, engine::general_purpose};
+use crate::config::{DEBUG_MODE, STORE_PATH};
+use crate::handlers::{bunker_call, commit_changes, encrypt_secret, log_audit_event, recipients_for, secret_version, validate_path, write_secret_file, WRITE_LOCK};
+use crate::metadata;
+use crate::models::BunkerTask;
+use crate::search::index_saved_secret;
+
+pub mod bitwarden;
+pub mod browser;
+pub mod keepass;
+pub mod onepassword;
+
+// Every importer turns its source format into these records; planning,
+// conflict handling and writing are shared.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ImportRecord {
+    // Folder path in the source, outermost first
+    pub folder: Vec<String>,
+    pub title: String,
+    pub username: String,
+    pub password: String,
+    pub url: String,
+    pub notes: String,
+    // Custom fields in source order
+    pub fields: Vec<(String, String)>,
+    // otpauth:// URI
+    pub totp: Option<String>,
+    pub tags: Vec<String>,
+    // File name and content; each becomes its own secret below the entry
+    pub attachments: Vec<(String, Vec<u8>)>,
+}
+
+impl ImportRecord {
+    // Secret text in the layout the UI edits: password first, then the
+    // User/URL lines, TOTP, single-line custom fields and finally the notes.
+    pub fn content(&self) -> String {
+        let mut content = self.password.clone();
+        if !self.username.is_empty() {
+            content.push_str(&format!("\nUser: {}", self.username));
+        }
+        if !self.url.is_empty() {
+            content.push_str(&format!("\nURL: {}", self.url));
+        }
+        if let Some(totp) = &self.totp {
+            content.push_str(&format!("\n{}", totp));
+        }
+        let (single, multi): (Vec<_>, Vec<_>) = self.fields.iter().partition(|(_, value)| !value.contains('\n'));
+        for (key, value) in single {
+            content.push_str(&format!("\n{}: {}", key, value));
+        }
+        if !self.notes.is_empty() {
+            content.push_str(&format!("\n{}", self.notes));
+        }
+        for (key, value) in multi {
+            content.push_str(&format!("\n{}:\n{}", key, value));
+        }
+        content
+    }
+
+    // Inverse of `content` for exports. Custom fields cannot be told apart
+    // from notes once written, so they come back as part of the notes.
+    pub fn from_secret(path: &str, content: &str, tags: Vec<String>) -> ImportRecord {
+        let mut folder: Vec<String> = path.split('/').map(str::to_string).collect();
+        let title = folder.pop().unwrap_or_default();
+        let mut lines = content.lines();
+        let mut record = ImportRecord {
+            folder,
+            title,
+            password: lines.next().unwrap_or("").to_string(),
+            tags,
+            ..Default::default()
+        };
+        let mut notes = Vec::new();
+        for line in lines {
+            if let Some(user) = line.strip_prefix("User: ").filter(|_| record.username.is_empty()) {
+                record.username = user.trim().to_string();
+            } else if let Some(url) = line.strip_prefix("URL: ").filter(|_| record.url.is_empty()) {
+                record.url = url.trim().to_string();
+            } else if line.starts_with("otpauth://") && record.totp.is_none() {
+                record.totp = Some(line.trim().to_string());
+            } else {
+                notes.push(line);
+            }
+        }
+        record.notes = notes.join("\n").trim().to_string();
+        record
+    }
+
+    fn source(&self) -> String {
+        let mut source = self.folder.clone();
+        source.push(self.title.clone());
+        source.join("/")
+    }
+}
+
+fn attachment_content(name: &str, data: &[u8]) -> String {
+    match std::str::from_utf8(data) {
+        Ok(text) if !text.contains('\0') => format!("\nAttachment: {}\n{}", name, text),
+        _ => format!("\nAttachment: {}\nEncoding: base64\n{}", name, general_purpose::STANDARD.encode(data)),
+    }
+}
+
+const MAX_COMPONENT_LEN: usize = 64;
+
+// Turns a folder, title or file name from a foreign format into a single
+// component that passes `validate_path`.
+pub fn path_component(name: &str) -> String {
+    let mut clean: String = name.chars()
+        .map(|c| match c {
+            '/' | '\\' => '-',
+            '*' | '?' | '[' | ']' | '{' | '}' | '$' | '`' | '|' | ';' | '&' | '>' | '<' => '_',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect();
+    while clean.contains("..") {
+        clean = clean.replace("..", ".");
+    }
+    let clean = clean.trim().trim_start_matches('.').trim();
+    let clean: String = clean.chars().take(MAX_COMPONENT_LEN).collect();
+    match clean.trim_end() {
+        "" => "Untitled".to_string(),
+        clean => clean.to_string(),
+    }
+}
+
+// The target folder of an import. Web authorizes it as sent, so a folder that
+// path_component would rewrite (`.ssh` into `ssh`, `a*` into `a_`) is refused
+// rather than imported somewhere the caller was never allowed to write.
+fn parse_prefix(text: &str) -> Result<Vec<String>, String> {
+    let text = text.trim_matches('/');
+    let prefix: Vec<String> = text.split('/').filter(|c| !c.is_empty()).map(path_component).collect();
+    if prefix.join("/") != text {
+        return Err(format!("Invalid import folder: {}", text));
+    }
+    Ok(prefix)
+}
+
+fn record_path(prefix: &[String], record: &ImportRecord) -> String {
+    prefix.iter().cloned()
+        .chain(record.folder.iter().map(|f| path_component(f)))
+        .chain(std::iter::once(path_component(&record.title)))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ConflictPolicy {
+    /// Existing secrets stay, the imported entry is reported and dropped
+    #[default]
+    Skip,
+    /// Imported entry replaces the existing secret
+    Overwrite,
+    /// Imported entry is written next to it as "<name> (2)"
+    Rename,
+}
+
+#[derive(Serialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStatus {
+    Created,
+    Overwritten,
+    Renamed,
+    Skipped,
+    Failed,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ImportResult {
+    // Entry location in the source, e.g. "Internet/Mail"
+    pub source: String,
+    pub path: String,
+    pub status: ImportStatus,
+    /// existing | duplicate (same path earlier in the import)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conflict: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_path: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip)]
+    writes: Vec<(String, String)>,
+    #[serde(skip)]
+    tags: Vec<String>,
+}
+
+const MAX_RENAMES: usize = 1000;
+
+// Decides where every record goes. `exists` reports secrets already in the
+// store and is subject to the policy; paths claimed earlier in the same import
+// (e.g. two browser logins for one site) are always renamed.
+fn plan_import(records: Vec<ImportRecord>, prefix: &[String], policy: ConflictPolicy, exists: impl Fn(&str) -> bool) -> Vec<ImportResult> {
+    let mut claimed: HashSet<String> = HashSet::new();
+    let mut results = Vec::new();
+
+    for record in records {
+        let path = record_path(prefix, &record);
+        let attachment_names: Vec<String> = record.attachments.iter().map(|(name, _)| path_component(name)).collect();
+        let targets = |base: &str| -> Vec<String> {
+            std::iter::once(base.to_string())
+                .chain(attachment_names.iter().map(|name| format!("{}/{}", base, name)))
+                .collect()
+        };
+        let mut result = ImportResult {
+            source: record.source(),
+            path: path.clone(),
+            status: ImportStatus::Created,
+            conflict: None,
+            original_path: None,
+            attachments: Vec::new(),
+            error: None,
+            writes: Vec::new(),
+            tags: record.tags.clone(),
+        };
+
+        if let Err(e) = targets(&path).iter().try_for_each(|p| validate_path(p)) {
+            result.status = ImportStatus::Failed;
+            result.error = Some(e);
+            results.push(result);
+            continue;
+        }
+
+        let duplicate = targets(&path).iter().any(|p| claimed.contains(p));
+        let existing = targets(&path).iter().any(|p| exists(p));
+        let mut target = path.clone();
+        if duplicate || existing {
+            result.conflict = Some(if duplicate { "duplicate" } else { "existing" });
+            match (policy, duplicate) {
+                (ConflictPolicy::Skip, false) => result.status = ImportStatus::Skipped,
+                (ConflictPolicy::Overwrite, false) => result.status = ImportStatus::Overwritten,
+                _ => {
+                    let free = (2..MAX_RENAMES)
+                        .map(|n| format!("{} ({})", path, n))
+                        .find(|candidate| targets(candidate).iter().all(|p| !claimed.contains(p) && !exists(p) && validate_path(p).is_ok()));
+                    match free {
+                        Some(free) => {
+                            result.status = ImportStatus::Renamed;
+                            result.original_path = Some(path.clone());
+                            result.path = free.clone();
+                            target = free;
+                        },
+                        None => {
+                            result.status = ImportStatus::Failed;
+                            result.error = Some("No free name to rename to".to_string());
+                        },
+                    }
+                },
+            }
+        }
+        if matches!(result.status, ImportStatus::Skipped | ImportStatus::Failed) {
+            results.push(result);
+            continue;
+        }
+
+        let paths = targets(&target);
+        claimed.extend(paths.iter().cloned());
+        result.writes.push((target.clone(), record.content()));
+        for (path, (name, data)) in paths.iter().skip(1).zip(&record.attachments) {
+            result.attachments.push(path.clone());
+            result.writes.push((path.clone(), attachment_content(name, data)));
+        }
+        results.push(result);
+    }
+    results
+}
+
+fn summary(results: &[ImportResult]) -> Value {
+    let count = |status: ImportStatus| results.iter().filter(|r| r.status == status).count();
+    json!({
+        "total": results.len(),
+        "created": count(ImportStatus::Created),
+        "overwritten": count(ImportStatus::Overwritten),
+        "renamed": count(ImportStatus::Renamed),
+        "skipped": count(ImportStatus::Skipped),
+        "failed": count(ImportStatus::Failed),
+        "conflicts": results.iter().filter(|r| r.conflict.is_some()).count(),
+    })
+}
+
+// Encrypts through the Bunker for the path's recipients and writes the file
+async fn write_secret(client: &reqwest::Client, path: &str, content: &str) -> Result<(), String> {
+    let encrypted = encrypt_secret(client, content, recipients_for(path)).await?;
+    let file = StdPath::new(STORE_PATH.as_str()).join(format!("{}.gpg", path));
+    let version = secret_version(&encrypted);
+    write_secret_file(&file, &encrypted).map_err(|e| format!("Could not write secret: {}", e))?;
+    index_saved_secret(path, content, &version).await;
+    Ok(())
+}
+
+// Parses an upload into records; returns the source name used in commit
+// messages and audit events.
+fn parse(format: &str, data: &[u8], password: &str) -> Result<(Vec<ImportRecord>, &'static str), String> {
+    match format {
+        "keepass" | "kdbx" | "keepass_xml" => Ok((keepass::parse(data, password)?, "KeePass")),
+        "bitwarden" => Ok((bitwarden::parse(data, password)?, "Bitwarden")),
+        "1password" => Ok((onepassword::parse(data)?, "1Password")),
+        "chrome" => Ok((browser::parse(data)?, "Chrome")),
+        "firefox" => Ok((browser::parse(data)?, "Firefox")),
+        other => Err(format!("Unsupported import format: {}", other)),
+    }
+}
+
+struct ImportForm {
+    format: String,
+    data: Option<axum::body::Bytes>,
+    password: String,
+    dry_run: bool,
+    policy: ConflictPolicy,
+    prefix: Vec<String>,
+}
+
+async fn read_import_form(mut multipart: Multipart) -> Result<ImportForm, String> {
+    let mut form = ImportForm {
+        format: "keepass".to_string(),
+        data: None,
+        password: String::new(),
+        dry_run: false,
+        policy: ConflictPolicy::default(),
+        prefix: Vec::new(),
+    };
+    while let Some(field) = multipart.next_field().await.unwrap_or(None) {
+        match field.name() {
+            Some("file") => form.data = Some(field.bytes().await.unwrap_or_default()),
+            Some("format") => form.format = field.text().await.unwrap_or_default(),
+            Some("password") => form.password = field.text().await.unwrap_or_default(),
+            Some("dry_run") => form.dry_run = field.text().await.map(|v| v == "true").unwrap_or(false),
+            Some("policy") => {
+                let text = field.text().await.unwrap_or_default();
+                form.policy = serde_json::from_value(Value::String(text)).map_err(|_| "Invalid conflict policy".to_string())?;
+            },
+            Some("prefix") => form.prefix = parse_prefix(&field.text().await.unwrap_or_default())?,
+            _ => {},
+        }
+    }
+    Ok(form)
+}
+
+// Imports secrets exported from another password manager. Every entry is
+// encrypted through the Bunker like a regular save; a dry run returns the
+// same per-entry report without writing anything.
+pub async fn import_secrets(multipart: Multipart) -> (StatusCode, Json<Value>) {
+    let form = match read_import_form(multipart).await {
+        Ok(form) => form,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))),
+    };
+    let Some(data) = form.data else {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": "No import file uploaded"})));
+    };
+    if *DEBUG_MODE { println!("--> [STORAGE] IMPORT request: {} ({} bytes, dry run: {})", form.format, data.len(), form.dry_run); }
+
+    // KDBX key derivation is deliberately expensive
+    let format = form.format.clone();
+    let password = form.password;
+    let parsed = tokio::task::spawn_blocking(move || parse(&format, &data, &password)).await
+        .unwrap_or_else(|_| Err("Import parser crashed".to_string()));
+    let (records, source) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            log_audit_event("storage_import", "failed", &format!("{} import: {}", form.format, e));
+            return (StatusCode::UNPROCESSABLE_ENTITY, Json(json!({"error": e})));
+        },
+    };
+
+    let client = reqwest::Client::new();
+    if !form.dry_run {
+        let vault = bunker_call(&client, &BunkerTask {
+            payload: String::new(),
+            mode: "check".to_string(),
+            signature: None,
+            recipients: None,
+        }).await;
+        if vault.as_deref() != Ok("UNSEALED") {
+            log_audit_event("storage_import", "failed", &format!("{} import: vault not available", source));
+            return (StatusCode::SERVICE_UNAVAILABLE, Json(json!({"error": "Vault is sealed"})));
+        }
+    }
+
+    let _write_guard = WRITE_LOCK.lock().await;
+    let store = StdPath::new(STORE_PATH.as_str());
+    let mut results = plan_import(records, &form.prefix, form.policy, |path| store.join(format!("{}.gpg", path)).is_file());
+
+    if !form.dry_run {
+        let mut tagged = Vec::new();
+        for result in results.iter_mut() {
+            for (path, content) in &result.writes {
+                if let Err(e) = write_secret(&client, path, content).await {
+                    println!("❌ [STORAGE] Import of {} failed: {}", path, e);
+                    result.status = ImportStatus::Failed;
+                    result.error = Some(e);
+                    break;
+                }
+            }
+            if result.status != ImportStatus::Failed && !result.writes.is_empty() {
+                tagged.push((result.path.clone(), std::mem::take(&mut result.tags)));
+            }
+        }
+        let written = tagged.len();
+        metadata::import_tags(tagged);
+        if written > 0 {
+            commit_changes(&format!("Import {} secrets from {}", written, source));
+        }
+    }
+
+    let summary = summary(&results);
+    let status = if form.dry_run { "dry_run" } else if summary["failed"] == 0 { "success" } else { "partial" };
+    log_audit_event("storage_import", status, &format!("{} import: {}", source, summary));
+    (StatusCode::OK, Json(json!({
+        "dry_run": form.dry_run,
+        "source": source,
+        "summary": summary,
+        "entries": results,
+    })))
+}
+
+// RFC 4648 base32 without padding, the secret encoding of otpauth URIs
+pub fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut out = String::new();
+    for chunk in bytes.chunks(5) {
+        let mut block = [0u8; 8];
+        block[3..3 + chunk.len()].copy_from_slice(chunk);
+        let bits = u64::from_be_bytes(block);
+        for i in 0..(chunk.len() * 8).div_ceil(5) {
+            out.push(ALPHABET[((bits >> (35 - i * 5)) & 31) as usize] as char);
+        }
+    }
+    out
+}
+
+// Builds an otpauth://totp URI; unset parameters fall back to the
+// authenticator defaults (SHA1, 6 digits, 30 seconds).
+pub fn otpauth_uri(label: &str, secret: &str, digits: Option<&str>, period: Option<&str>, algorithm: Option<&str>) -> String {
+    let secret: String = secret.chars()
+        .filter(|c| !c.is_whitespace() && *c != '=' && *c != '-')
+        .collect::<String>()
+        .to_uppercase();
+    let mut uri = reqwest::Url::parse("otpauth://totp/").expect("static URI");
+    uri.set_path(&format!("/{}", label));
+    {
+        let mut query = uri.query_pairs_mut();
+        query.append_pair("secret", &secret);
+        if let Some(algorithm) = algorithm.map(|a| a.to_uppercase().replace("HMAC-", "").replace('-', "")).filter(|a| !a.is_empty()) {
+            query.append_pair("algorithm", &algorithm);
+        }
+        if let Some(digits) = digits.map(str::trim).filter(|d| d.parse::<u32>().is_ok()) {
+            query.append_pair("digits", digits);
+        }
+        if let Some(period) = period.map(str::trim).filter(|p| p.parse::<u32>().is_ok()) {
+            query.append_pair("period", period);
+        }
+    }
+    uri.to_string()
+}
+
+// Exports store TOTP either as an otpauth URI or as the bare base32 secret
+pub fn totp_value(value: &str, label: &str) -> Option<String> {
+    let value = value.trim();
+    if value.starts_with("otpauth://") {
+        return Some(value.to_string());
+    }
+    let secret: String = value.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_uppercase();
+    let is_base32 = !secret.is_empty() && secret.trim_end_matches('=').chars().all(|c| c.is_ascii_uppercase() || ('2'..='7').contains(&c));
+    is_base32.then(|| otpauth_uri(label, &secret, None, None, None))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(folder: &[&str], title: &str) -> ImportRecord {
+        ImportRecord {
+            folder: folder.iter().map(|f| f.to_string()).collect(),
+            title: title.to_string(),
+            password: "pw".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn content_matches_the_secret_layout() {
+        let record = ImportRecord {
+            password: "hunter2".into(),
+            username: "alice".into(),
+            url: "https://mail.example".into(),
+            notes: "recovery codes\nin the safe".into(),
+            fields: vec![("PIN".into(), "1234".into()), ("Key".into(), "a\nb".into())],
+            totp: Some("otpauth://totp/Mail?secret=ABC".into()),
+            ..Default::default()
+        };
+        assert_eq!(record.content(), "hunter2\nUser: alice\nURL: https://mail.example\notpauth://totp/Mail?secret=ABC\nPIN: 1234\nrecovery codes\nin the safe\nKey:\na\nb");
+        assert_eq!(attachment_content("a.txt", b"hello"), "\nAttachment: a.txt\nhello");
+        assert_eq!(attachment_content("a.bin", &[0, 1]), "\nAttachment: a.bin\nEncoding: base64\nAAE=");
+    }
+
+    #[test]
+    fn path_components_pass_validation() {
+        for name in ["a/b", "..hidden", "Wait...", "rm *; echo", "  ", ".git", "tab\there", &"x".repeat(300)] {
+            let component = path_component(name);
+            assert!(validate_path(&component).is_ok(), "{:?} -> {:?}", name, component);
+        }
+        assert_eq!(path_component("a/b"), "a-b");
+        assert_eq!(path_component(".."), "Untitled");
+    }
+
+    #[test]
+    fn prefixes_are_taken_as_sent_or_refused() {
+        assert_eq!(parse_prefix("Imported/KeePass").unwrap(), ["Imported", "KeePass"]);
+        assert_eq!(parse_prefix("/Imported/").unwrap(), ["Imported"]);
+        assert!(parse_prefix("").unwrap().is_empty());
+        for prefix in [".ssh", "team/.ssh", "a*", "a$b", "a//b", " a", "a/../b"] {
+            assert!(parse_prefix(prefix).is_err(), "{:?} was accepted", prefix);
+        }
+    }
+
+    #[test]
+    fn conflicts_follow_the_policy() {
+        let existing = |path: &str| path == "Mail/Gmail" || path == "Mail/Gmail (2)";
+        let records = || {
+            let mut with_file = record(&["Web"], "Site");
+            with_file.attachments.push(("key.pem".into(), b"-----".to_vec()));
+            vec![record(&["Mail"], "Gmail"), record(&["Web"], "Site"), with_file, record(&[], "..")]
+        };
+
+        let skip = plan_import(records(), &[], ConflictPolicy::Skip, existing);
+        let statuses: Vec<_> = skip.iter().map(|r| (r.status, r.conflict)).collect();
+        assert_eq!(statuses, vec![
+            (ImportStatus::Skipped, Some("existing")),
+            (ImportStatus::Created, None),
+            (ImportStatus::Renamed, Some("duplicate")),
+            (ImportStatus::Created, None),
+        ]);
+        assert_eq!(skip[2].path, "Web/Site (2)");
+        assert_eq!(skip[3].path, "Untitled");
+
+        let overwrite = plan_import(records(), &["Imported".to_string()], ConflictPolicy::Overwrite, |p| p == "Imported/Mail/Gmail");
+        assert_eq!(overwrite[0].status, ImportStatus::Overwritten);
+        assert_eq!(overwrite[2].status, ImportStatus::Renamed);
+        assert_eq!(overwrite[2].path, "Imported/Web/Site (2)");
+        assert_eq!(overwrite[2].attachments, vec!["Imported/Web/Site (2)/key.pem".to_string()]);
+        assert_eq!(overwrite[2].writes.len(), 2);
+
+        let rename = plan_import(records(), &[], ConflictPolicy::Rename, existing);
+        assert_eq!(rename[0].path, "Mail/Gmail (3)");
+        assert_eq!(rename[0].original_path.as_deref(), Some("Mail/Gmail"));
+    }
+
+    #[test]
+    fn totp_uris() {
+        assert_eq!(base32(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(
+            otpauth_uri("Mail:alice", "jbsw y3dp", Some("8"), Some("60"), Some("HMAC-SHA-256")),
+            "otpauth://totp/Mail:alice?secret=JBSWY3DP&algorithm=SHA256&digits=8&period=60"
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use serde_json::Value;
+use std::io::{Cursor, Read};
+use super::{browser, totp_value, ImportRecord};
+
+// 1Password: 1PUX archives (a zip holding `export.data` JSON and the
+// attachments under `files/`), or the CSV export. Vaults become categories.
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const EXPORT_DATA: &str = "export.data";
+
+pub fn parse(data: &[u8]) -> Result<Vec<ImportRecord>, String> {
+    if data.starts_with(ZIP_MAGIC) {
+        parse_1pux(data)
+    } else {
+        browser::parse(data)
+    }
+}
+
+fn read_entry(archive: &mut zip::ZipArchive<Cursor<&[u8]>>, name: &str) -> Option<Vec<u8>> {
+    let mut file = archive.by_name(name).ok()?;
+    let mut buf = Vec::new();
+    file.read_to_end(&mut buf).ok().map(|_| buf)
+}
+
+fn str_at<'a>(value: &'a Value, pointer: &str) -> &'a str {
+    value.pointer(pointer).and_then(Value::as_str).unwrap_or("")
+}
+
+pub fn parse_1pux(data: &[u8]) -> Result<Vec<ImportRecord>, String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).map_err(|_| "Invalid 1PUX archive".to_string())?;
+    let export = read_entry(&mut archive, EXPORT_DATA).ok_or("1PUX archive has no export.data")?;
+    let export: Value = serde_json::from_slice(&export).map_err(|e| format!("Invalid 1PUX export data: {}", e))?;
+
+    let mut records = Vec::new();
+    for account in export["accounts"].as_array().into_iter().flatten() {
+        for vault in account["vaults"].as_array().into_iter().flatten() {
+            let folder = vec![str_at(vault, "/attrs/name").to_string()];
+            for item in vault["items"].as_array().into_iter().flatten() {
+                records.push(item_record(item, &folder, &mut archive));
+            }
+        }
+    }
+    Ok(records)
+}
+
+fn attachment(archive: &mut zip::ZipArchive<Cursor<&[u8]>>, file: &Value) -> Option<(String, Vec<u8>)> {
+    let name = file["fileName"].as_str()?;
+    let data = read_entry(archive, &format!("files/{}__{}", file["documentId"].as_str()?, name))?;
+    Some((name.to_string(), data))
+}
+
+// Section field values are single-key objects naming their type
+fn field_text(value: &Value) -> Option<String> {
+    let (kind, inner) = value.as_object()?.iter().next()?;
+    let text = match (kind.as_str(), inner) {
+        (_, Value::String(s)) => s.clone(),
+        ("date", Value::Number(n)) => chrono::DateTime::from_timestamp(n.as_i64()?, 0)?.format("%Y-%m-%d").to_string(),
+        ("monthYear", Value::Number(n)) => {
+            let n = n.as_u64()?;
+            format!("{:02}/{}", n % 100, n / 100)
+        },
+        ("email", inner) => inner["email_address"].as_str()?.to_string(),
+        ("address", Value::Object(parts)) => ["street", "city", "state", "zip", "country"].iter()
+            .filter_map(|k| parts.get(*k).and_then(Value::as_str).filter(|s| !s.is_empty()))
+            .collect::<Vec<_>>()
+            .join(", "),
+        ("sshKey", inner) => inner["privateKey"].as_str()?.to_string(),
+        (_, Value::Number(n)) => n.to_string(),
+        _ => return None,
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+fn item_record(item: &Value, folder: &[String], archive: &mut zip::ZipArchive<Cursor<&[u8]>>) -> ImportRecord {
+    let details = &item["details"];
+    let overview = &item["overview"];
+    let mut record = ImportRecord {
+        folder: folder.to_vec(),
+        title: str_at(overview, "/title").to_string(),
+        url: str_at(overview, "/url").to_string(),
+        notes: str_at(details, "/notesPlain").to_string(),
+        password: str_at(details, "/password").to_string(),
+        tags: overview["tags"].as_array().into_iter().flatten().filter_map(|t| t.as_str().map(str::to_string)).collect(),
+        ..Default::default()
+    };
+
+    for field in details["loginFields"].as_array().into_iter().flatten() {
+        let value = str_at(field, "/value").to_string();
+        match str_at(field, "/designation") {
+            "username" => record.username = value,
+            "password" => record.password = value,
+            // Other form fields remembered from the login page
+            _ if !value.is_empty() && !field["name"].as_str().unwrap_or("").is_empty() => {
+                record.fields.push((str_at(field, "/name").to_string(), value));
+            },
+            _ => {},
+        }
+    }
+    for url in overview["urls"].as_array().into_iter().flatten().skip(1) {
+        let label = str_at(url, "/label");
+        record.fields.push((if label.is_empty() { "URL" } else { label }.to_string(), str_at(url, "/url").to_string()));
+    }
+
+    let label = if record.username.is_empty() { record.title.clone() } else { format!("{}:{}", record.title, record.username) };
+    for section in details["sections"].as_array().into_iter().flatten() {
+        for field in section["fields"].as_array().into_iter().flatten() {
+            let value = &field["value"];
+            let title = [str_at(field, "/title"), str_at(field, "/id")].into_iter().find(|t| !t.is_empty()).unwrap_or("Field").to_string();
+            if let Some(file) = value.get("file") {
+                record.attachments.extend(attachment(archive, file));
+            } else if let Some(totp) = value["totp"].as_str().filter(|t| !t.is_empty()) {
+                match totp_value(totp, &label) {
+                    Some(uri) if record.totp.is_none() => record.totp = Some(uri),
+                    _ => record.fields.push((title, totp.to_string())),
+                }
+            } else if let Some(text) = field_text(value) {
+                record.fields.push((title, text));
+            }
+        }
+    }
+    // Document items carry their file in the details
+    if let Some(document) = details.get("documentAttributes") {
+        record.attachments.extend(attachment(archive, document));
+    }
+    record
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    #[test]
+    fn onepassword_1pux_and_csv() {
+        let export = r#"{"accounts": [{"attrs": {"name": "Alice"}, "vaults": [{"attrs": {"uuid": "v1", "name": "Private"}, "items": [
+          {"uuid": "i1", "categoryUuid": "001", "state": "active",
+           "overview": {"title": "Shop", "url": "https://shop.example", "tags": ["retail"],
+                        "urls": [{"label": "", "url": "https://shop.example"}, {"label": "Mobile", "url": "https://m.shop.example"}]},
+           "details": {"notesPlain": "note",
+                       "loginFields": [{"value": "alice", "designation": "username", "name": "email"},
+                                       {"value": "pw", "designation": "password", "name": "password"}],
+                       "sections": [{"title": "", "fields": [
+                         {"title": "one-time password", "id": "TOTP_1", "value": {"totp": "otpauth://totp/Shop?secret=JBSWY3DP"}},
+                         {"title": "PIN", "id": "pin", "value": {"concealed": "9876"}},
+                         {"title": "expires", "id": "exp", "value": {"monthYear": 202807}},
+                         {"title": "receipt", "id": "f", "value": {"file": {"fileName": "r.txt", "documentId": "d1", "decryptedSize": 2}}}
+                       ]}]}}
+        ]}]}]}"#;
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file(EXPORT_DATA, SimpleFileOptions::default()).unwrap();
+        zip.write_all(export.as_bytes()).unwrap();
+        zip.start_file("files/d1__r.txt", SimpleFileOptions::default()).unwrap();
+        zip.write_all(b"ok").unwrap();
+        let archive = zip.finish().unwrap().into_inner();
+
+        let records = parse(&archive).unwrap();
+        assert_eq!(records, vec![ImportRecord {
+            folder: vec!["Private".to_string()],
+            title: "Shop".to_string(),
+            username: "alice".to_string(),
+            password: "pw".to_string(),
+            url: "https://shop.example".to_string(),
+            notes: "note".to_string(),
+            fields: vec![
+                ("Mobile".to_string(), "https://m.shop.example".to_string()),
+                ("PIN".to_string(), "9876".to_string()),
+                ("expires".to_string(), "07/2028".to_string()),
+            ],
+            totp: Some("otpauth://totp/Shop?secret=JBSWY3DP".to_string()),
+            tags: vec!["retail".to_string()],
+            attachments: vec![("r.txt".to_string(), b"ok".to_vec())],
+        }]);
+
+        let csv = b"Title,Url,Username,Password,OTPAuth,Favorite,Archived,Tags,Notes\nShop,https://shop.example,alice,pw,otpauth://totp/Shop?secret=JBSWY3DP,false,false,\"a,b\",note\n";
+        let records = parse(csv).unwrap();
+        assert_eq!(records[0].totp.as_deref(), Some("otpauth://totp/Shop?secret=JBSWY3DP"));
+        assert_eq!(records[0].tags, vec!["a".to_string(), "b".to_string()]);
+    }
+}
@@ -20,10 +20,7 @@ pub async fn init_storage() {
         if CONFIG.backend.r#type == "git" { // Initialize Git repository only if configured
             if !std::path::Path::new(&git_dir).exists() {
                 println!("📦 Git repository missing. Initializing...");
-                std::process::Command::new("git").args(["config", "--global", "--add", "safe.directory", store_path]).status().unwrap();
                 std::process::Command::new("git").args(["init", store_path]).status().unwrap();
-                std::process::Command::new("git").args(["-C", store_path, "config", "user.email", "talos@system.local"]).status().unwrap();
-                std::process::Command::new("git").args(["-C", store_path, "config", "user.name", "Talos Storage"]).status().unwrap();
             }
 
             if let (Some(repo_url), Some(ssh_key_path)) = (&CONFIG.backend.repository_url, &CONFIG.backend.ssh_key_path) {
@@ -36,7 +33,9 @@ pub async fn init_storage() {
 
                 // Try to pull to check connection and get the latest changes
                 println!("📦 Performing initial pull from remote...");
-                std::process::Command::new("git").args(["-C", store_path, "pull", "origin", "main", "--rebase"]).status(); // Ignore error if 'main' doesn't exist
+                let _ = std::process::Command::new("git").args(["-C", store_path, "pull", "origin", "main", "--rebase"]).status(); // Ignore error if 'main' doesn't exist
+                // Pulled secrets are picked up by the search index once the vault is unsealed
+                crate::search::schedule_rebuild();
             } else {
                 panic!("'git' backend type requires 'repository_url' and 'ssh_key_path' in config.");
             }
@@ -46,4 +45,31 @@ pub async fn init_storage() {
             println!("ℹ️  Backup available via Web UI or manual volume copy.");
         }
     }
+
+    if CONFIG.backend.r#type == "git" && std::path::Path::new(&git_dir).exists() {
+        prepare_repository(store_path);
+        if let Err(e) = crate::backup::exclude_work_dirs(std::path::Path::new(store_path)) {
+            println!("⚠️ [STORAGE] Could not exclude restore work areas from git: {}", e);
+        }
+    }
+}
+
+fn git_output(args: &[&str]) -> Option<String> {
+    let output = std::process::Command::new("git").args(args).output().ok()?;
+    output.status.success().then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+// Makes an existing repository usable, including one created by `pass git
+// init` on the host: it is usually owned by another uid and has no committer
+// identity configured for the container user.
+fn prepare_repository(store_path: &str) {
+    let trusted = git_output(&["config", "--global", "--get-all", "safe.directory"])
+        .is_some_and(|dirs| dirs.lines().any(|d| d == store_path || d == "*"));
+    if !trusted {
+        let _ = std::process::Command::new("git").args(["config", "--global", "--add", "safe.directory", store_path]).status();
+    }
+    if git_output(&["-C", store_path, "config", "user.email"]).is_none() {
+        let _ = std::process::Command::new("git").args(["-C", store_path, "config", "user.email", "talos@system.local"]).status();
+        let _ = std::process::Command::new("git").args(["-C", store_path, "config", "user.name", "Talos Storage"]).status();
+    }
 }
\ No newline at end of file
@@ -2,12 +2,33 @@ mod models;
 mod handlers;
 mod init;
 mod config;
+mod trash;
+mod search;
+mod metadata;
+mod backup;
+mod scheduler;
+mod import;
+mod export;
+#[path = "../../shared/backup_stream.rs"]
+mod backup_stream;
+#[cfg(test)]
+mod testing;
+#[cfg(test)]
+#[path = "../../test-support/peak_alloc.rs"]
+mod peak_alloc;
 
-use axum::{routing::{get, post}, Router};
+use axum::{extract::DefaultBodyLimit, routing::{get, post}, Router};
 use std::env;
 use tower_http::limit::RequestBodyLimitLayer;
-use crate::handlers::{list_tree, decrypt_secret, encrypt_and_save, delete_entry, storage_health_check, download_backup, restore_backup, create_category, unlock_bunker, initialize_bunker, import_bunker_key, backup_bunker_key};
+use crate::handlers::{list_tree, secret_history, decrypt_secret, encrypt_and_save, delete_entry, move_entry, storage_health_check, create_category, unlock_bunker, verify_master_key, initialize_bunker, import_bunker_key, backup_bunker_key};
+use crate::backup::{download_backup, preview_restore, restore_backup};
+use crate::import::import_secrets;
+use crate::export::export_secrets;
 use crate::init::init_storage;
+use crate::metadata::update_metadata;
+use crate::scheduler::spawn_backup_scheduler;
+use crate::search::{search_secrets, rebuild_search_index};
+use crate::trash::{list_trash, restore_from_trash, purge_trash, purge_expired_trash};
 
 #[tokio::main]
 async fn main() {
@@ -32,24 +53,120 @@ async fn main() {
     println!("🌉 Initializing TALOS Storage...");
     init_storage().await;
 
-    let app = Router::new()
+    // Trash retention sweeper
+    tokio::spawn(async {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            purge_expired_trash().await;
+        }
+    });
+
+    // Scheduled backups (no-op unless configured)
+    spawn_backup_scheduler();
+
+    let app = app();
+
+    let port = env::var("PORT").unwrap_or_else(|_| "4000".to_string());
+    let addr = format!("0.0.0.0:{}", port);
+    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
+    println!("🌉 Storage Bridge active on port {}", port);
+    axum::serve(listener, app).await.unwrap();
+}
+
+fn app() -> Router {
+    Router::new()
         .route("/api/tree", get(list_tree))
         .route("/api/decrypt", post(decrypt_secret))
+        .route("/api/history", get(secret_history))
         .route("/api/save", post(encrypt_and_save))
         .route("/api/delete", post(delete_entry))
+        .route("/api/move", post(move_entry))
+        .route("/api/meta", post(update_metadata))
+        .route("/api/search", get(search_secrets))
+        .route("/api/search/rebuild", post(rebuild_search_index))
+        .route("/api/trash", get(list_trash))
+        .route("/api/trash/restore", post(restore_from_trash))
+        .route("/api/trash/purge", post(purge_trash))
         .route("/api/backup", get(download_backup))
-        .route("/api/restore", post(restore_backup))
+        .route("/api/import", post(import_secrets))
+        .route("/api/export", post(export_secrets))
         .route("/api/create_category", post(create_category))
         .route("/api/initialize", post(initialize_bunker))
         .route("/api/initialize/import", post(import_bunker_key))
         .route("/api/backup/key", get(backup_bunker_key))
         .route("/api/unlock", post(unlock_bunker))
+        .route("/api/unlock/verify", post(verify_master_key))
         .route("/api/health", get(storage_health_check))
-        .layer(RequestBodyLimitLayer::new(10 * 1024 * 1024)); // 10MB limit
+        .layer(RequestBodyLimitLayer::new(10 * 1024 * 1024)) // 10MB limit
+        // Added after the limit: restore uploads are spooled to disk as they arrive
+        .route("/api/restore", post(restore_backup).layer(DefaultBodyLimit::disable()))
+        .route("/api/restore/preview", post(preview_restore).layer(DefaultBodyLimit::disable()))
+}
 
-    let port = env::var("PORT").unwrap_or_else(|_| "4000".to_string());
-    let addr = format!("0.0.0.0:{}", port);
-    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    println!("🌉 Storage Bridge active on port {}", port);
-    axum::serve(listener, app).await.unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{header, Request, StatusCode};
+    use http_body_util::BodyExt;
+    use serde_json::Value;
+    use tower::ServiceExt;
+    use crate::testing;
+
+    const MIB: usize = 1024 * 1024;
+    const BOUNDARY: &str = "talos-test-boundary";
+
+    fn multipart_request(uri: &str, fields: &[(&str, &[u8])]) -> Request<Body> {
+        let mut body = Vec::new();
+        for (name, content) in fields {
+            body.extend_from_slice(format!("--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\r\n", BOUNDARY, name, name).as_bytes());
+            body.extend_from_slice(content);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+        Request::post(uri)
+            .header(header::CONTENT_TYPE, format!("multipart/form-data; boundary={}", BOUNDARY))
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    async fn json_body(response: axum::response::Response) -> Value {
+        serde_json::from_slice(&response.into_body().collect().await.unwrap().to_bytes()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn backups_over_the_body_limit_round_trip_through_restore() {
+        let store = testing::store();
+        let large: Vec<u8> = (0..12 * MIB).map(|i| (i % 251) as u8).collect();
+        std::fs::create_dir_all(store.join("roundtrip")).unwrap();
+        std::fs::write(store.join("roundtrip/large.bin"), &large).unwrap();
+
+        let response = app().oneshot(Request::get("/api/backup").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let backup = response.into_body().collect().await.unwrap().to_bytes();
+        assert!(backup.len() > 12 * MIB);
+
+        // The upload is verified against the signed manifest, entry by entry
+        let response = app().oneshot(multipart_request("/api/restore/preview", &[("backup", &backup)])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let preview = json_body(response).await;
+        let entry = preview["entries"].as_array().unwrap().iter().find(|e| e["path"] == "roundtrip/large.bin").unwrap();
+        assert_eq!(entry["action"], "unchanged");
+
+        let paths = serde_json::to_vec(&["roundtrip"]).unwrap();
+        let response = app().oneshot(multipart_request("/api/restore", &[("backup", &backup), ("paths", &paths)])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        // Only entries that are written get unpacked, so other tests' stand-in
+        // ciphertexts elsewhere in the backup are not checked here
+        let restore = json_body(response).await;
+        assert_eq!(restore["skipped"][0]["path"], "roundtrip/large.bin", "{}", restore);
+        assert_eq!(std::fs::read(store.join("roundtrip/large.bin")).unwrap(), large);
+        std::fs::remove_dir_all(store.join("roundtrip")).unwrap();
+
+        // Everything else keeps the limit
+        let response = app().oneshot(multipart_request("/api/import", &[("file", &large)])).await.unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
 }
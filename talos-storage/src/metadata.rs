@@ -0,0 +1,232 @@
+use axum::Json;
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::BTreeMap, fs, path::Path as StdPath};
+use crate::config::{DEBUG_MODE, STORE_PATH};
+use crate::handlers::{commit_changes, log_audit_event, validate_path, WRITE_LOCK};
+use crate::models::MetadataRequest;
+
+// Sidecar file at the store root holding plaintext, non-sensitive metadata per
+// secret path. It lives inside the store so it is versioned, backed up and
+// restored together with the secrets, and it can be listed without the Bunker.
+pub const META_FILE: &str = ".talos-meta.json";
+
+const MAX_TAGS: usize = 32;
+const MAX_TAG_LEN: usize = 48;
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct SecretMeta {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    // Users who marked the secret as favourite
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub favourites: Vec<String>,
+}
+
+impl SecretMeta {
+    fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.favourites.is_empty()
+    }
+}
+
+pub type MetaStore = BTreeMap<String, SecretMeta>;
+
+pub fn load() -> MetaStore {
+    fs::read_to_string(StdPath::new(STORE_PATH.as_str()).join(META_FILE))
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn save(meta: &MetaStore) -> Result<(), String> {
+    let content = serde_json::to_string_pretty(meta).map_err(|e| e.to_string())?;
+    fs::write(StdPath::new(STORE_PATH.as_str()).join(META_FILE), content).map_err(|e| e.to_string())
+}
+
+// A secret is only its own key; a category is everything below it, but not a
+// secret of the same name next to it
+fn under(key: &str, prefix: &str, is_dir: bool) -> bool {
+    if is_dir {
+        key.starts_with(&format!("{}/", prefix))
+    } else {
+        key == prefix
+    }
+}
+
+// Re-keys metadata after a move of a secret or a whole category.
+// Callers must hold WRITE_LOCK.
+pub fn rename_prefix(from: &str, to: &str, is_dir: bool) {
+    let mut meta = load();
+    let moved: Vec<String> = meta.keys().filter(|k| under(k, from, is_dir)).cloned().collect();
+    if moved.is_empty() {
+        return;
+    }
+    for key in moved {
+        if let Some(entry) = meta.remove(&key) {
+            meta.insert(format!("{}{}", to, &key[from.len()..]), entry);
+        }
+    }
+    if let Err(e) = save(&meta) {
+        println!("❌ [STORAGE] Error writing metadata: {}", e);
+    }
+}
+
+// Removes and returns the metadata of a secret or of everything in a category
+// (keys relative to `prefix`), e.g. to keep it with a trashed entry.
+pub fn take_prefix(prefix: &str, is_dir: bool) -> MetaStore {
+    let mut meta = load();
+    let keys: Vec<String> = meta.keys().filter(|k| under(k, prefix, is_dir)).cloned().collect();
+    let taken: MetaStore = keys.iter()
+        .filter_map(|k| meta.remove(k).map(|v| (k[prefix.len()..].to_string(), v)))
+        .collect();
+    if !taken.is_empty() && let Err(e) = save(&meta) {
+        println!("❌ [STORAGE] Error writing metadata: {}", e);
+    }
+    taken
+}
+
+// Inverse of `take_prefix`: re-attaches metadata below a (possibly new) prefix
+pub fn put_prefix(prefix: &str, entries: MetaStore) {
+    if entries.is_empty() {
+        return;
+    }
+    let mut meta = load();
+    for (suffix, entry) in entries {
+        meta.insert(format!("{}{}", prefix, suffix), entry);
+    }
+    if let Err(e) = save(&meta) {
+        println!("❌ [STORAGE] Error writing metadata: {}", e);
+    }
+}
+
+// Tags imported from other password managers; tags this store would reject
+// are dropped instead of failing the import. Callers must hold WRITE_LOCK.
+pub fn import_tags(tagged: Vec<(String, Vec<String>)>) {
+    let mut meta = load();
+    let mut changed = false;
+    for (path, tags) in tagged {
+        let mut tags: Vec<String> = tags.into_iter()
+            .filter_map(|tag| normalize_tags(vec![tag]).ok())
+            .flatten()
+            .collect();
+        tags.sort();
+        tags.dedup();
+        tags.truncate(MAX_TAGS);
+        if !tags.is_empty() {
+            meta.entry(path).or_default().tags = tags;
+            changed = true;
+        }
+    }
+    if changed && let Err(e) = save(&meta) {
+        println!("❌ [STORAGE] Error writing metadata: {}", e);
+    }
+}
+
+fn normalize_tags(tags: Vec<String>) -> Result<Vec<String>, String> {
+    let mut clean: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = tag.trim().to_string();
+        if tag.is_empty() {
+            continue;
+        }
+        if tag.len() > MAX_TAG_LEN || tag.chars().any(|c| c.is_control() || c == ',') {
+            return Err(format!("Invalid tag: {}", tag));
+        }
+        if !clean.contains(&tag) {
+            clean.push(tag);
+        }
+    }
+    if clean.len() > MAX_TAGS {
+        return Err("Too many tags".to_string());
+    }
+    clean.sort();
+    Ok(clean)
+}
+
+pub async fn update_metadata(Json(req): Json<MetadataRequest>) -> (StatusCode, Json<Value>) {
+    if *DEBUG_MODE { println!("--> [STORAGE] METADATA request for: {}", req.path); }
+
+    if let Err(e) = validate_path(&req.path) {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    }
+
+    let _write_guard = WRITE_LOCK.lock().await;
+    let store = StdPath::new(STORE_PATH.as_str());
+    if !store.join(format!("{}.gpg", req.path)).is_file() {
+        return (StatusCode::NOT_FOUND, Json(json!({"error": "Secret not found"})));
+    }
+
+    let mut meta = load();
+    let mut entry = meta.remove(&req.path).unwrap_or_default();
+
+    if let Some(tags) = req.tags {
+        match normalize_tags(tags) {
+            Ok(tags) => entry.tags = tags,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))),
+        }
+    }
+    if let Some(favourite) = req.favourite {
+        let Some(user) = req.actor.filter(|a| !a.is_empty()) else {
+            return (StatusCode::BAD_REQUEST, Json(json!({"error": "Favourites require a user"})));
+        };
+        entry.favourites.retain(|u| u != &user);
+        if favourite {
+            entry.favourites.push(user);
+            entry.favourites.sort();
+        }
+    }
+
+    let response = json!({"status": "OK", "tags": entry.tags});
+    if !entry.is_empty() {
+        meta.insert(req.path.clone(), entry);
+    }
+    if let Err(e) = save(&meta) {
+        println!("❌ [STORAGE] Error writing metadata: {}", e);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Could not write metadata"})));
+    }
+
+    commit_changes(&format!("Update metadata: {}", req.path));
+    log_audit_event("storage_metadata", "success", &format!("metadata updated for {}", req.path));
+    (StatusCode::OK, Json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn normalize_tags_trims_dedupes_and_sorts() {
+        let tags = normalize_tags(vec![" prod ".into(), "db".into(), "prod".into(), "".into()]).unwrap();
+        assert_eq!(tags, vec!["db".to_string(), "prod".to_string()]);
+        assert!(normalize_tags(vec!["a,b".into()]).is_err());
+    }
+
+    fn tags(tag: &str) -> SecretMeta {
+        SecretMeta { tags: vec![tag.to_string()], favourites: Vec::new() }
+    }
+
+    #[tokio::test]
+    async fn secrets_and_categories_of_the_same_name_keep_their_own_metadata() {
+        testing::store();
+        let _write_guard = WRITE_LOCK.lock().await;
+        let mut meta = load();
+        meta.insert("meta/a".into(), tags("secret"));
+        meta.insert("meta/a/child".into(), tags("child"));
+        meta.insert("meta/ab".into(), tags("sibling"));
+        save(&meta).unwrap();
+
+        rename_prefix("meta/a", "meta/moved", false);
+        rename_prefix("meta/a", "meta/folder", true);
+        let meta = load();
+        assert_eq!(meta["meta/moved"], tags("secret"));
+        assert_eq!(meta["meta/folder/child"], tags("child"));
+        assert_eq!(meta["meta/ab"], tags("sibling"));
+        assert!(!meta.contains_key("meta/a") && !meta.contains_key("meta/a/child"));
+
+        assert_eq!(take_prefix("meta/moved", false), MetaStore::from([("".to_string(), tags("secret"))]));
+        assert_eq!(take_prefix("meta/folder", true), MetaStore::from([("/child".to_string(), tags("child"))]));
+        assert_eq!(load()["meta/ab"], tags("sibling"));
+    }
+}
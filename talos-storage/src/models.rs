@@ -6,6 +6,20 @@ pub struct ActionRequest {
     pub content: Option<String>,
     pub original_path: Option<String>,
     pub reveal: Option<bool>,
+    /// Version token the client last saw (If-Match semantics on save)
+    pub expected_version: Option<String>,
+    /// Delete a non-empty category together with everything below it
+    pub recursive: Option<bool>,
+    /// Must repeat `path` to confirm a recursive delete
+    pub confirm: Option<String>,
+    /// Identity of the user behind the request, filled in by talos-web
+    pub actor: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct MoveRequest {
+    pub from: String,
+    pub to: String,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -14,4 +28,47 @@ pub struct BunkerTask {
     pub mode: String,
     #[serde(default)]
     pub signature: Option<String>,
-}
\ No newline at end of file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recipients: Option<Vec<String>>,
+}
+
+#[derive(Deserialize)]
+pub struct MetadataRequest {
+    pub path: String,
+    /// Replaces the tag list when present
+    pub tags: Option<Vec<String>>,
+    /// Sets or clears the favourite flag of `actor`
+    pub favourite: Option<bool>,
+    pub actor: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TreeQuery {
+    /// User whose favourites are reported
+    pub user: Option<String>,
+    /// Only keep secrets carrying this tag (and the categories leading to them)
+    pub tag: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct HistoryQuery {
+    pub path: String,
+}
+
+// A file the caller archives next to the backup (the web tier's talos.db),
+// to be listed in the signed manifest
+#[derive(Deserialize)]
+pub struct BackupQuery {
+    pub external_path: Option<String>,
+    pub external_size: Option<u64>,
+    pub external_sha256: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TrashRequest {
+    pub id: Option<String>,
+    /// Restore to a different location than the original path
+    pub path: Option<String>,
+    /// Purge every entry in the trash
+    pub all: Option<bool>,
+}
@@ -0,0 +1,336 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDateTime, TimeZone, Timelike, Utc};
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::{collections::HashSet, fs, io::{self, Write}, path::{Path, PathBuf}, sync::Mutex};
+use crate::backup::{backup_to, decrypt_reader, verify_archive};
+use crate::backup_stream::{backup_passphrase, encrypt_writer};
+use crate::config::{BackupScheduleConfig, BackupTarget, CONFIG, DEBUG_MODE};
+use crate::handlers::{log_audit_event, WRITE_LOCK};
+
+const BACKUP_PREFIX: &str = "talos-backup-";
+const BACKUP_SUFFIX: &str = ".zip.age";
+const STAMP_FORMAT: &str = "%Y%m%dT%H%M%SZ";
+
+// Minimal 5-field cron expression (minute hour day-of-month month day-of-week).
+// Supports `*`, lists, ranges and steps; day-of-week 0 and 7 are both Sunday.
+#[derive(Debug, PartialEq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // Cron semantics: when both day fields are restricted, either may match
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let mut mask = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().map_err(|_| format!("Invalid step: {}", part))?),
+            None => (part, 1),
+        };
+        if step == 0 {
+            return Err(format!("Invalid step: {}", part));
+        }
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (a.parse().map_err(|_| format!("Invalid range: {}", part))?, b.parse().map_err(|_| format!("Invalid range: {}", part))?)
+        } else {
+            let value = range.parse().map_err(|_| format!("Invalid value: {}", part))?;
+            // `5/15` means "from 5 to the end, every 15"
+            (value, if part.contains('/') { max } else { value })
+        };
+        if start < min || end > max || start > end {
+            return Err(format!("Out of range: {}", part));
+        }
+        for value in (start..=end).step_by(step as usize) {
+            mask |= 1 << value;
+        }
+    }
+    Ok(mask)
+}
+
+impl CronSchedule {
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        if fields.len() != 5 {
+            return Err("Cron expression must have 5 fields".to_string());
+        }
+        let mut weekdays = parse_field(fields[4], 0, 7)?;
+        if weekdays & (1 << 7) != 0 {
+            weekdays = (weekdays | 1) & !(1 << 7);
+        }
+        Ok(CronSchedule {
+            minutes: parse_field(fields[0], 0, 59)?,
+            hours: parse_field(fields[1], 0, 23)?,
+            days: parse_field(fields[2], 1, 31)?,
+            months: parse_field(fields[3], 1, 12)?,
+            weekdays,
+            days_restricted: fields[2] != "*",
+            weekdays_restricted: fields[4] != "*",
+        })
+    }
+
+    fn day_matches(&self, t: &NaiveDateTime) -> bool {
+        let day = self.days & (1 << t.day()) != 0;
+        let weekday = self.weekdays & (1 << t.weekday().num_days_from_sunday()) != 0;
+        match (self.days_restricted, self.weekdays_restricted) {
+            (true, true) => day || weekday,
+            (true, false) => day,
+            (false, true) => weekday,
+            (false, false) => true,
+        }
+    }
+
+    // First matching minute strictly after `after`
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let start = after.naive_utc().with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let mut t = start;
+        // Impossible expressions (e.g. 30 February) never match; give up after a few years
+        while t < start + Duration::days(366 * 5) {
+            if self.months & (1 << t.month()) == 0 {
+                let (year, month) = if t.month() == 12 { (t.year() + 1, 1) } else { (t.year(), t.month() + 1) };
+                t = chrono::NaiveDate::from_ymd_opt(year, month, 1)?.and_hms_opt(0, 0, 0)?;
+            } else if !self.day_matches(&t) {
+                t = t.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if self.hours & (1 << t.hour()) == 0 {
+                t = t.with_minute(0)? + Duration::hours(1);
+            } else if self.minutes & (1 << t.minute()) == 0 {
+                t += Duration::minutes(1);
+            } else {
+                return Some(Utc.from_utc_datetime(&t));
+            }
+        }
+        None
+    }
+}
+
+// Calendar period a backup falls into (year, day/week/month number)
+type Bucket = fn(&DateTime<Utc>) -> (i32, u32);
+
+// Picks the generations to keep: the newest backup of each of the last
+// `keep_daily` days, `keep_weekly` ISO weeks and `keep_monthly` months.
+// The newest backup is always kept.
+pub fn select_retained(backups: &[DateTime<Utc>], keep_daily: usize, keep_weekly: usize, keep_monthly: usize) -> HashSet<DateTime<Utc>> {
+    let mut newest_first = backups.to_vec();
+    newest_first.sort_by(|a, b| b.cmp(a));
+
+    let mut keep = HashSet::new();
+    if let Some(newest) = newest_first.first() {
+        keep.insert(*newest);
+    }
+    let buckets: [(usize, Bucket); 3] = [
+        (keep_daily, |t| (t.year(), t.ordinal())),
+        (keep_weekly, |t| (t.iso_week().year(), t.iso_week().week())),
+        (keep_monthly, |t| (t.year(), t.month())),
+    ];
+    for (limit, bucket) in buckets {
+        let mut seen = HashSet::new();
+        for t in &newest_first {
+            if seen.len() >= limit { break; }
+            if seen.insert(bucket(t)) {
+                keep.insert(*t);
+            }
+        }
+    }
+    keep
+}
+
+#[derive(Default, Clone)]
+pub struct ScheduleStatus {
+    pub last_success: Option<String>,
+    pub last_backup: Option<String>,
+    pub last_attempt: Option<String>,
+    pub last_error: Option<String>,
+    pub next_run: Option<String>,
+}
+
+static STATUS: Lazy<Mutex<ScheduleStatus>> = Lazy::new(|| Mutex::new(ScheduleStatus::default()));
+
+// Scheduled backup state as reported by /api/health
+pub fn status_json() -> Value {
+    let Some(schedule) = CONFIG.backup_schedule.as_ref() else {
+        return json!({ "enabled": false });
+    };
+    let status = STATUS.lock().unwrap().clone();
+    json!({
+        "enabled": true,
+        "cron": schedule.cron,
+        "ok": status.last_error.is_none() && status.last_success.is_some(),
+        "last_success": status.last_success,
+        "last_backup": status.last_backup,
+        "last_attempt": status.last_attempt,
+        "last_error": status.last_error,
+        "next_run": status.next_run,
+    })
+}
+
+fn target_dir(schedule: &BackupScheduleConfig) -> PathBuf {
+    match &schedule.target {
+        BackupTarget::Directory { path } => PathBuf::from(path),
+    }
+}
+
+fn backup_time(name: &str) -> Option<DateTime<Utc>> {
+    let stamp = name.strip_prefix(BACKUP_PREFIX)?.strip_suffix(BACKUP_SUFFIX)?;
+    NaiveDateTime::parse_from_str(stamp, STAMP_FORMAT).ok().map(|t| Utc.from_utc_datetime(&t))
+}
+
+fn existing_backups(dir: &Path) -> Vec<(DateTime<Utc>, PathBuf)> {
+    fs::read_dir(dir)
+        .map(|entries| entries.flatten()
+            .filter_map(|e| backup_time(&e.file_name().to_string_lossy()).map(|t| (t, e.path())))
+            .collect())
+        .unwrap_or_default()
+}
+
+// Reads the written generation back and test-extracts it like a restore would
+async fn verify_backup(client: &reqwest::Client, path: &Path, passphrase: age::secrecy::SecretString) -> Result<(), String> {
+    let file = fs::File::open(path).map_err(|e| format!("Read-back failed: {}", e))?;
+    let plain = decrypt_reader(io::BufReader::new(file), passphrase).map_err(|e| format!("Decryption failed: {}", e))?;
+    verify_archive(client, plain).await.map(|_| ())
+}
+
+async fn write_generation(client: &reqwest::Client, schedule: &BackupScheduleConfig) -> Result<String, String> {
+    let passphrase = backup_passphrase().ok_or("BACKUP_PASSPHRASE is not configured")?;
+    let dir = target_dir(schedule);
+    fs::create_dir_all(&dir).map_err(|e| format!("Target unavailable: {}", e))?;
+
+    // Only verified backups get their final name, so retention never counts a bad one
+    let name = format!("{}{}{}", BACKUP_PREFIX, Utc::now().format(STAMP_FORMAT), BACKUP_SUFFIX);
+    let partial = dir.join(format!("{}.partial", name));
+    let written = async {
+        let file = fs::File::create(&partial).map_err(|e| format!("Write failed: {}", e))?;
+        let encrypted = encrypt_writer(io::BufWriter::new(file), passphrase.clone())?;
+        let (encrypted, _) = {
+            // Hold the write lock so a generation never captures a half-finished change
+            let _write_guard = WRITE_LOCK.lock().await;
+            backup_to(client, encrypted, Vec::new()).await?
+        };
+        encrypted.finish().and_then(|mut file| file.flush()).map_err(|e| format!("Write failed: {}", e))?;
+        verify_backup(client, &partial, passphrase).await.map_err(|e| format!("Verification failed: {}", e))
+    }.await;
+    if let Err(e) = written {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+    fs::rename(&partial, dir.join(&name)).map_err(|e| format!("Write failed: {}", e))?;
+
+    let backups = existing_backups(&dir);
+    let times: Vec<DateTime<Utc>> = backups.iter().map(|(t, _)| *t).collect();
+    let keep = select_retained(&times, schedule.keep_daily, schedule.keep_weekly, schedule.keep_monthly);
+    for (t, path) in &backups {
+        if !keep.contains(t) {
+            if *DEBUG_MODE { println!("--> [STORAGE] Pruning backup generation {:?}", path); }
+            let _ = fs::remove_file(path);
+        }
+    }
+    Ok(name)
+}
+
+async fn run_scheduled_backup(client: &reqwest::Client, schedule: &BackupScheduleConfig) {
+    let attempt = Utc::now().to_rfc3339();
+    let result = write_generation(client, schedule).await;
+
+    let mut status = STATUS.lock().unwrap();
+    status.last_attempt = Some(attempt.clone());
+    match result {
+        Ok(name) => {
+            println!("💾 [STORAGE] Scheduled backup written: {}", name);
+            log_audit_event("storage_scheduled_backup", "success", &name);
+            status.last_success = Some(attempt);
+            status.last_backup = Some(name);
+            status.last_error = None;
+        },
+        Err(e) => {
+            println!("❌ [STORAGE] Scheduled backup failed: {}", e);
+            log_audit_event("storage_scheduled_backup", "failed", &e);
+            status.last_error = Some(e);
+        },
+    }
+}
+
+pub fn spawn_backup_scheduler() {
+    let Some(schedule) = CONFIG.backup_schedule.as_ref() else { return };
+    let cron = match CronSchedule::parse(&schedule.cron) {
+        Ok(cron) => cron,
+        Err(e) => {
+            println!("❌ [STORAGE] Invalid backup schedule '{}': {}", schedule.cron, e);
+            STATUS.lock().unwrap().last_error = Some(format!("Invalid schedule: {}", e));
+            return;
+        },
+    };
+
+    // Last success survives restarts through the newest generation on disk
+    if let Some((t, path)) = existing_backups(&target_dir(schedule)).into_iter().max_by_key(|(t, _)| *t) {
+        let mut status = STATUS.lock().unwrap();
+        status.last_success = Some(t.to_rfc3339());
+        status.last_backup = path.file_name().map(|n| n.to_string_lossy().to_string());
+    }
+    println!("💾 [STORAGE] Backup schedule '{}' -> {:?}", schedule.cron, target_dir(schedule));
+
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        while let Some(next) = cron.next_after(Utc::now()) {
+            STATUS.lock().unwrap().next_run = Some(next.to_rfc3339());
+            let wait = (next - Utc::now()).to_std().unwrap_or_default();
+            tokio::time::sleep(wait).await;
+            run_scheduled_backup(&client, schedule).await;
+        }
+        STATUS.lock().unwrap().next_run = None;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn cron_next_run() {
+        let daily = CronSchedule::parse("0 3 * * *").unwrap();
+        assert_eq!(daily.next_after(at("2024-05-01T02:59:30Z")), Some(at("2024-05-01T03:00:00Z")));
+        assert_eq!(daily.next_after(at("2024-05-01T03:00:00Z")), Some(at("2024-05-02T03:00:00Z")));
+
+        let steps = CronSchedule::parse("*/15 9-17 * * 1-5").unwrap();
+        // Friday evening rolls over to Monday morning
+        assert_eq!(steps.next_after(at("2024-05-03T17:50:00Z")), Some(at("2024-05-06T09:00:00Z")));
+
+        // Day-of-month and day-of-week restricted together match either
+        let either = CronSchedule::parse("30 1 1 * 0").unwrap();
+        assert_eq!(either.next_after(at("2024-05-02T00:00:00Z")), Some(at("2024-05-05T01:30:00Z")));
+
+        assert_eq!(CronSchedule::parse("0 0 30 2 *").unwrap().next_after(at("2024-01-01T00:00:00Z")), None);
+        assert!(CronSchedule::parse("60 * * * *").is_err());
+        assert!(CronSchedule::parse("* * * *").is_err());
+        assert!(CronSchedule::parse("*/0 * * * *").is_err());
+    }
+
+    #[test]
+    fn retention_keeps_daily_weekly_monthly_generations() {
+        let backups: Vec<DateTime<Utc>> = (0..120)
+            .map(|d| at("2024-06-30T03:00:00Z") - Duration::days(d))
+            .chain([at("2024-06-30T15:00:00Z")])
+            .collect();
+        let keep = select_retained(&backups, 3, 2, 3);
+
+        let mut kept: Vec<String> = keep.iter().map(|t| t.format("%Y-%m-%dT%H").to_string()).collect();
+        kept.sort();
+        assert_eq!(kept, vec![
+            "2024-04-30T03", // April
+            "2024-05-31T03", // May
+            "2024-06-23T03", // ISO week 25
+            "2024-06-28T03",
+            "2024-06-29T03",
+            "2024-06-30T15", // newest of the day, week and month
+        ]);
+        assert!(select_retained(&backups[..1], 0, 0, 0).contains(&backups[0]));
+    }
+}
@@ -0,0 +1,419 @@
+use axum::Json;
+use axum::extract::Query;
+use axum::http::StatusCode;
+use base64::{Engine as _, engine::general_purpose};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{collections::HashMap, env, fs, os::unix::fs::MetadataExt, path::Path as StdPath, time::SystemTime};
+use tokio::sync::Mutex;
+use crate::config::{DEBUG_MODE, STORE_PATH};
+use crate::handlers::{bunker_call, is_internal, log_audit_event, secret_version};
+use crate::metadata;
+use crate::models::BunkerTask;
+
+// Search index over the non-sensitive fields of every secret. The password line
+// is never parsed into it. On disk it is sealed with the Bunker key, so it can
+// only be loaded while the vault is unsealed.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct IndexEntry {
+    pub path: String,
+    pub username: String,
+    pub url: String,
+    pub title: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // Ciphertext version the entry was built from; a mismatch triggers re-indexing
+    #[serde(default)]
+    version: String,
+}
+
+// Bumped when the indexed fields change, so older sealed indexes are rebuilt
+// instead of keeping what they used to hold
+const INDEX_FORMAT: u32 = 2;
+
+#[derive(Serialize, Deserialize)]
+struct SearchIndex {
+    #[serde(default)]
+    format: u32,
+    entries: HashMap<String, IndexEntry>,
+    #[serde(skip)]
+    loaded: bool,
+}
+
+impl Default for SearchIndex {
+    fn default() -> Self {
+        SearchIndex { format: INDEX_FORMAT, entries: HashMap::new(), loaded: false }
+    }
+}
+
+static INDEX: Lazy<Mutex<SearchIndex>> = Lazy::new(|| Mutex::new(SearchIndex::default()));
+
+fn index_path() -> String {
+    env::var("SEARCH_INDEX_PATH").unwrap_or_else(|_| "/home/talosuser/.talos-search-index.gpg".to_string())
+}
+
+// Extracts the indexable fields from a decrypted secret. Only the labelled
+// User, URL and Title lines are read: the password line, notes, custom fields
+// and otpauth:// seeds never reach the index. Without a Title line the title
+// is the secret's name.
+pub fn parse_entry(path: &str, content: &str) -> IndexEntry {
+    let mut entry = IndexEntry { path: path.to_string(), ..Default::default() };
+    for line in content.lines().skip(1) {
+        let Some((label, value)) = line.split_once(": ") else { continue };
+        let value = value.trim();
+        if value.starts_with("otpauth://") {
+            continue;
+        }
+        match label {
+            "User" => entry.username = value.to_string(),
+            "URL" => entry.url = value.to_string(),
+            "Title" if entry.title.is_empty() => entry.title = value.to_string(),
+            _ => {},
+        }
+    }
+    if entry.title.is_empty() {
+        entry.title = path.rsplit('/').next().unwrap_or(path).to_string();
+    }
+    entry
+}
+
+// File identity a cached version was computed for. Saves write a new file and
+// rename it into place, so the inode changes even within one mtime tick.
+#[derive(PartialEq)]
+struct FileStamp {
+    modified: SystemTime,
+    len: u64,
+    inode: u64,
+}
+
+// Ciphertext versions from the previous scan, so a search only hashes the
+// secrets that changed since
+static VERSIONS: Lazy<std::sync::Mutex<HashMap<String, (FileStamp, String)>>> = Lazy::new(Default::default);
+
+// (secret path, ciphertext version) for every secret in the store
+pub(crate) fn scan_store() -> Vec<(String, String)> {
+    let root = STORE_PATH.as_str();
+    let mut cache = VERSIONS.lock().unwrap_or_else(|e| e.into_inner());
+    let mut previous = std::mem::take(&mut *cache);
+    walkdir::WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|e| {
+            let name = e.file_name().to_str().unwrap_or("");
+            // .git, .trash and restore staging never hold live secrets
+            e.depth() == 0 || !is_internal(name)
+        })
+        .filter_map(|e| e.ok())
+        .filter(|e| e.path().is_file())
+        .filter_map(|e| {
+            let rel = e.path().strip_prefix(root).ok()?.to_str()?.trim_start_matches('/');
+            let path = rel.strip_suffix(".gpg")?.to_string();
+            let metadata = e.metadata().ok()?;
+            let stamp = FileStamp { modified: metadata.modified().ok()?, len: metadata.len(), inode: metadata.ino() };
+            let version = match previous.remove(&path) {
+                Some((cached, version)) if cached == stamp => version,
+                _ => secret_version(&fs::read(e.path()).ok()?),
+            };
+            cache.insert(path.clone(), (stamp, version.clone()));
+            Some((path, version))
+        })
+        .collect()
+}
+
+pub(crate) async fn decrypt_file(client: &reqwest::Client, path: &str) -> Result<String, String> {
+    let bytes = fs::read(StdPath::new(STORE_PATH.as_str()).join(format!("{}.gpg", path)))
+        .map_err(|e| e.to_string())?;
+    bunker_call(client, &BunkerTask {
+        payload: general_purpose::STANDARD.encode(&bytes),
+        mode: "decrypt".to_string(),
+        signature: None,
+        recipients: None,
+    }).await
+}
+
+async fn load_sealed(client: &reqwest::Client) -> Result<HashMap<String, IndexEntry>, String> {
+    let Ok(sealed) = fs::read(index_path()) else {
+        return Ok(HashMap::new());
+    };
+    let json = bunker_call(client, &BunkerTask {
+        payload: general_purpose::STANDARD.encode(&sealed),
+        mode: "decrypt".to_string(),
+        signature: None,
+        recipients: None,
+    }).await?;
+    // A corrupted or outdated index is simply rebuilt
+    Ok(serde_json::from_str::<SearchIndex>(&json).ok()
+        .filter(|i| i.format == INDEX_FORMAT)
+        .map(|i| i.entries)
+        .unwrap_or_default())
+}
+
+async fn persist_sealed(client: &reqwest::Client, index: &SearchIndex) -> Result<(), String> {
+    let json = serde_json::to_string(index).map_err(|e| e.to_string())?;
+    let sealed = bunker_call(client, &BunkerTask {
+        payload: json,
+        mode: "encrypt".to_string(),
+        signature: None,
+        recipients: None,
+    }).await?;
+    fs::write(index_path(), sealed).map_err(|e| e.to_string())
+}
+
+// Brings the index in line with the store: secrets whose ciphertext changed
+// (saves, moves, restores, git pulls) are re-indexed, vanished ones dropped.
+async fn sync_index(index: &mut SearchIndex) -> Result<(), String> {
+    let client = reqwest::Client::new();
+    if !index.loaded {
+        index.entries = load_sealed(&client).await?;
+        index.loaded = true;
+    }
+
+    let current = scan_store();
+    let mut changed = false;
+
+    let present: std::collections::HashSet<&str> = current.iter().map(|(p, _)| p.as_str()).collect();
+    let before = index.entries.len();
+    index.entries.retain(|path, _| present.contains(path.as_str()));
+    changed |= index.entries.len() != before;
+
+    for (path, version) in &current {
+        if index.entries.get(path).map(|e| &e.version) == Some(version) {
+            continue;
+        }
+        // Secrets the vault key cannot open, e.g. in a mounted `pass` folder
+        // whose .gpg-id names other people, are indexed by name only and not
+        // tried again until their ciphertext changes
+        let content = match decrypt_file(&client, path).await {
+            Ok(content) => content,
+            Err(e) if e == "Bunker unavailable" || e.ends_with("ERROR_VAULT_SEALED") => return Err(e),
+            Err(e) => {
+                println!("⚠️ [STORAGE] Search index skips {}: {}", path, e);
+                String::new()
+            }
+        };
+        let mut entry = parse_entry(path, &content);
+        entry.version = version.clone();
+        index.entries.insert(path.clone(), entry);
+        changed = true;
+    }
+
+    if changed {
+        persist_sealed(&client, index).await?;
+        if *DEBUG_MODE { println!("--> [STORAGE] Search index synced ({} entries)", index.entries.len()); }
+    }
+    Ok(())
+}
+
+// Updates the entry of a just-saved secret from its plaintext, avoiding a
+// Bunker round trip on the next search
+pub async fn index_saved_secret(path: &str, content: &str, version: &str) {
+    let mut index = INDEX.lock().await;
+    if !index.loaded {
+        return;
+    }
+    let mut entry = parse_entry(path, content);
+    entry.version = version.to_string();
+    index.entries.insert(path.to_string(), entry);
+}
+
+// Re-indexes in the background, e.g. after a restore or a git pull
+pub fn schedule_rebuild() {
+    tokio::spawn(async {
+        let mut index = INDEX.lock().await;
+        if let Err(e) = sync_index(&mut index).await {
+            log_audit_event("storage_search_index", "deferred", &format!("rebuild postponed: {}", e));
+        }
+    });
+}
+
+#[derive(Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    /// Comma separated subset of: path, username, url, title, tags
+    pub fields: Option<String>,
+    /// "substring" (default) or "fuzzy"
+    pub mode: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Serialize)]
+pub struct SearchHit {
+    #[serde(flatten)]
+    entry: IndexEntry,
+    score: f32,
+    matched: Vec<&'static str>,
+}
+
+const SEARCH_FIELDS: [&str; 5] = ["path", "username", "url", "title", "tags"];
+
+fn field_value(entry: &IndexEntry, field: &str) -> String {
+    match field {
+        "path" => entry.path.clone(),
+        "username" => entry.username.clone(),
+        "url" => entry.url.clone(),
+        "title" => entry.title.clone(),
+        "tags" => entry.tags.join(" "),
+        _ => String::new(),
+    }
+}
+
+// Scores `query` as an in-order subsequence of `text`: 1.0 for a contiguous
+// match, lower the more spread out the characters are.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<f32> {
+    let text: Vec<char> = text.to_lowercase().chars().collect();
+    let query: Vec<char> = query.to_lowercase().chars().collect();
+    if query.is_empty() {
+        return None;
+    }
+
+    let (mut qi, mut first, mut last) = (0, None, 0);
+    for (ti, c) in text.iter().enumerate() {
+        if qi < query.len() && *c == query[qi] {
+            first.get_or_insert(ti);
+            last = ti;
+            qi += 1;
+        }
+    }
+    if qi < query.len() {
+        return None;
+    }
+    let span = (last - first.unwrap_or(0) + 1) as f32;
+    Some(query.len() as f32 / span)
+}
+
+pub async fn search_secrets(Query(params): Query<SearchQuery>) -> (StatusCode, Json<Value>) {
+    if *DEBUG_MODE { println!("--> [STORAGE] SEARCH request"); }
+
+    let query = params.q.trim().to_lowercase();
+    if query.is_empty() {
+        return (StatusCode::OK, Json(json!([])));
+    }
+
+    let fields: Vec<&'static str> = match params.fields.as_deref() {
+        Some(f) => SEARCH_FIELDS.iter().copied().filter(|name| f.split(',').any(|x| x.trim() == *name)).collect(),
+        None => SEARCH_FIELDS.to_vec(),
+    };
+    if fields.is_empty() {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": "Unknown search field"})));
+    }
+    let fuzzy = params.mode.as_deref() == Some("fuzzy");
+
+    let mut index = INDEX.lock().await;
+    if let Err(e) = sync_index(&mut index).await {
+        log_audit_event("storage_search", "failed", &format!("index unavailable: {}", e));
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(json!({"error": "Search index unavailable (vault sealed?)"})));
+    }
+
+    // Tags change without touching the ciphertext, so they come from the sidecar at query time
+    let meta = metadata::load();
+    let mut hits: Vec<SearchHit> = index.entries.values()
+        .map(|entry| {
+            let mut entry = entry.clone();
+            entry.tags = meta.get(&entry.path).map(|m| m.tags.clone()).unwrap_or_default();
+            entry
+        })
+        .filter_map(|entry| {
+            let mut score = 0.0f32;
+            let mut matched = Vec::new();
+            for field in &fields {
+                let value = field_value(&entry, field).to_lowercase();
+                let field_score = if value.contains(&query) {
+                    Some(1.0)
+                } else if fuzzy {
+                    fuzzy_score(&query, &value).filter(|s| *s >= 0.3)
+                } else {
+                    None
+                };
+                if let Some(s) = field_score {
+                    score = score.max(s);
+                    matched.push(*field);
+                }
+            }
+            (!matched.is_empty()).then_some(SearchHit { entry, score, matched })
+        })
+        .collect();
+
+    hits.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.entry.path.cmp(&b.entry.path)));
+    hits.truncate(params.limit.unwrap_or(100));
+    (StatusCode::OK, Json(json!(hits)))
+}
+
+pub async fn rebuild_search_index() -> (StatusCode, Json<Value>) {
+    let mut index = INDEX.lock().await;
+    index.entries.clear();
+    index.loaded = true;
+    match sync_index(&mut index).await {
+        Ok(()) => {
+            log_audit_event("storage_search_index", "success", &format!("rebuilt with {} entries", index.entries.len()));
+            (StatusCode::OK, Json(json!({"status": "OK", "entries": index.entries.len()})))
+        },
+        Err(e) => {
+            index.loaded = false;
+            log_audit_event("storage_search_index", "failed", &format!("rebuild failed: {}", e));
+            (StatusCode::SERVICE_UNAVAILABLE, Json(json!({"error": "Search index rebuild failed (vault sealed?)"})))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_entry_never_indexes_the_password_line() {
+        let entry = parse_entry("Social/twitter", "hunter2\nUser: alice\nURL: https://x.com\nTitle: Personal account\nmore notes");
+        assert_eq!(entry.username, "alice");
+        assert_eq!(entry.url, "https://x.com");
+        assert_eq!(entry.title, "Personal account");
+        assert!(!serde_json::to_string(&entry).unwrap().contains("hunter2"));
+    }
+
+    #[test]
+    fn parse_entry_skips_totp_seeds_and_notes() {
+        // Layout of imported entries, see `ImportRecord::content`
+        let content = "hunter2\nUser: alice\nURL: https://mail.example\notpauth://totp/Mail?secret=JBSWY3DPEHPK3PXP\nPIN: 1234\nrecovery codes in the safe";
+        let entry = parse_entry("Mail/work", content);
+        assert_eq!(entry.username, "alice");
+        assert_eq!(entry.title, "work");
+        let indexed = serde_json::to_string(&entry).unwrap();
+        for secret in ["hunter2", "JBSWY3DPEHPK3PXP", "1234", "recovery"] {
+            assert!(!indexed.contains(secret), "{} was indexed", secret);
+        }
+
+        let entry = parse_entry("Mail/work", "hunter2\nTitle: otpauth://totp/Mail?secret=JBSWY3DPEHPK3PXP");
+        assert_eq!(entry.title, "work");
+    }
+
+    #[tokio::test]
+    async fn secrets_the_vault_cannot_open_do_not_stop_the_index() {
+        let store = crate::testing::store();
+        crate::testing::write_secret("search-sync/readable", &["admin@talos.local"], "hunter2\nUser: alice");
+        // Not a ciphertext of the stand-in Bunker, so it cannot be decrypted
+        fs::write(store.join("search-sync/foreign.gpg"), b"encrypted for someone else").unwrap();
+
+        let search = || search_secrets(Query(SearchQuery { q: "search-sync".to_string(), fields: Some("path".to_string()), mode: None, limit: None }));
+        let (status, Json(hits)) = search().await;
+        assert_eq!(status, StatusCode::OK);
+        let paths: Vec<&str> = hits.as_array().unwrap().iter().map(|h| h["path"].as_str().unwrap()).collect();
+        assert_eq!(paths, ["search-sync/foreign", "search-sync/readable"]);
+        assert_eq!(hits[1]["username"], "alice");
+
+        // The placeholder keeps the version it was tried at, so it is not retried
+        let version = secret_version(&fs::read(store.join("search-sync/foreign.gpg")).unwrap());
+        assert_eq!(INDEX.lock().await.entries["search-sync/foreign"].version, version);
+        assert!(scan_store().contains(&("search-sync/foreign".to_string(), version.clone())));
+
+        // A rewritten secret gets a new version from the next scan
+        crate::testing::write_secret("search-sync/foreign", &["admin@talos.local"], "hunter2\nUser: bob");
+        assert_eq!(search().await.0, StatusCode::OK);
+        assert_eq!(INDEX.lock().await.entries["search-sync/foreign"].username, "bob");
+        fs::remove_dir_all(store.join("search-sync")).unwrap();
+    }
+
+    #[test]
+    fn fuzzy_score_prefers_compact_matches() {
+        assert_eq!(fuzzy_score("git", "github"), Some(1.0));
+        assert!(fuzzy_score("gthb", "github").unwrap() < 1.0);
+        assert_eq!(fuzzy_score("xyz", "github"), None);
+    }
+}
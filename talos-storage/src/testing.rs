@@ -0,0 +1,104 @@
+use axum::{routing::post, Json, Router};
+use base64::{Engine as _, engine::general_purpose};
+use once_cell::sync::Lazy;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::path::Path;
+use crate::config::STORE_PATH;
+use crate::models::BunkerTask;
+
+// Shared environment for handler tests. STORE_PATH and BUNKER_URL are read
+// once per process, so every test in the binary uses the same store and the
+// same stand-in Bunker, and keeps to top-level paths of its own.
+static STORE: Lazy<tempfile::TempDir> = Lazy::new(|| {
+    let store = tempfile::tempdir().unwrap();
+    let bunker = spawn_bunker();
+    // SAFETY: set once, before anything in the test binary reads them
+    unsafe {
+        std::env::set_var("PASSWORD_STORE_DIR", store.path());
+        std::env::set_var("BUNKER_URL", bunker);
+        std::env::set_var("SEARCH_INDEX_PATH", std::env::temp_dir().join(format!("talos-search-index-{}.gpg", std::process::id())));
+    }
+    assert_eq!(STORE_PATH.as_str(), store.path().to_str().unwrap(), "STORE_PATH was read before the test store was set up");
+    store
+});
+
+pub fn store() -> &'static Path {
+    STORE.path()
+}
+
+// Encrypting for this recipient fails, like a Bunker missing the key
+pub const FAILING_RECIPIENT: &str = "offline@talos.local";
+
+// The stand-in's "ciphertext": the recipients on the first line, then the plaintext
+pub fn ciphertext(recipients: &[&str], plaintext: &str) -> Vec<u8> {
+    format!("{}\n{}", recipients.join(","), plaintext).into_bytes()
+}
+
+// Recipients and plaintext of a secret written through the stand-in
+pub fn read_secret(path: &str) -> (String, String) {
+    let content = std::fs::read_to_string(store().join(format!("{}.gpg", path))).unwrap();
+    let (recipients, plaintext) = content.split_once('\n').unwrap();
+    (recipients.to_string(), plaintext.to_string())
+}
+
+pub fn write_secret(path: &str, recipients: &[&str], plaintext: &str) {
+    let file = store().join(format!("{}.gpg", path));
+    std::fs::create_dir_all(file.parent().unwrap()).unwrap();
+    std::fs::write(file, ciphertext(recipients, plaintext)).unwrap();
+}
+
+// Handler request bodies from JSON, so tests only spell out the fields they use
+pub fn request<T: DeserializeOwned>(body: Value) -> Json<T> {
+    Json(serde_json::from_value(body).unwrap())
+}
+
+// The stand-in's "signature": a digest of the signed data
+fn signature(data: &[u8]) -> String {
+    format!("signed:{:x}", Sha256::digest(data))
+}
+
+async fn process(Json(task): Json<BunkerTask>) -> Json<Value> {
+    let result = match task.mode.as_str() {
+        "encrypt_binary" => {
+            let recipients = task.recipients.unwrap_or_default();
+            if recipients.iter().any(|r| r == FAILING_RECIPIENT) {
+                "ERROR_ENCRYPT".to_string()
+            } else {
+                let recipients: Vec<&str> = recipients.iter().map(String::as_str).collect();
+                general_purpose::STANDARD.encode(ciphertext(&recipients, &task.payload))
+            }
+        },
+        "decrypt" => general_purpose::STANDARD.decode(&task.payload).ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())
+            .and_then(|content| content.split_once('\n').map(|(_, plaintext)| plaintext.to_string()))
+            .unwrap_or_else(|| "ERROR_DECRYPT".to_string()),
+        "encrypt" => String::from_utf8(ciphertext(&["index"], &task.payload)).unwrap(),
+        "check" => "UNSEALED".to_string(),
+        "fingerprint" => "0000TESTFINGERPRINT".to_string(),
+        "sign" => general_purpose::STANDARD.decode(&task.payload).map(|data| signature(&data)).unwrap_or_else(|_| "ERROR_SIGN".to_string()),
+        "verify" => {
+            let request: Value = serde_json::from_str(&task.payload).unwrap_or_default();
+            let data = general_purpose::STANDARD.decode(request["data"].as_str().unwrap_or_default()).unwrap_or_default();
+            if request["signature"].as_str() == Some(signature(&data).as_str()) { "VALID" } else { "ERROR_BAD_SIGNATURE" }.to_string()
+        },
+        _ => "ERROR_MODE".to_string(),
+    };
+    Json(json!({"result": result}))
+}
+
+// Runs on its own thread and runtime so it outlives the runtime of any one test
+fn spawn_bunker() -> String {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    listener.set_nonblocking(true).unwrap();
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+        runtime.block_on(async move {
+            let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+            axum::serve(listener, Router::new().route("/process", post(process))).await.unwrap();
+        });
+    });
+    url
+}
@@ -0,0 +1,320 @@
+use axum::Json;
+use axum::http::StatusCode;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::{fs, path::{Path as StdPath, PathBuf}};
+use crate::config::{CONFIG, DEBUG_MODE, STORE_PATH};
+use crate::handlers::{commit_changes, gpg_id_in, list_secrets, log_audit_event, recipients_for, reencrypt_moved, validate_path, WRITE_LOCK};
+use crate::metadata::{self, MetaStore};
+use crate::models::TrashRequest;
+
+// Hidden directory inside the store holding soft-deleted entries:
+// .trash/<id>/meta.json plus the deleted file or directory itself.
+pub const TRASH_DIR: &str = ".trash";
+const META_FILE: &str = "meta.json";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct TrashEntry {
+    pub id: String,
+    pub original_path: String,
+    pub is_dir: bool,
+    pub deleted_at: String,
+    pub deleted_by: String,
+    pub secrets: Vec<String>,
+    // Tags and favourites travel with the entry, keyed relative to original_path
+    #[serde(default)]
+    pub metadata: MetaStore,
+}
+
+fn trash_root() -> PathBuf {
+    StdPath::new(STORE_PATH.as_str()).join(TRASH_DIR)
+}
+
+fn read_entry(slot: &StdPath) -> Option<TrashEntry> {
+    let content = fs::read_to_string(slot.join(META_FILE)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+fn read_entries() -> Vec<TrashEntry> {
+    let mut entries: Vec<TrashEntry> = fs::read_dir(trash_root())
+        .map(|dir| dir.flatten().filter_map(|e| read_entry(&e.path())).collect())
+        .unwrap_or_default();
+    entries.sort_by(|a, b| b.deleted_at.cmp(&a.deleted_at));
+    entries
+}
+
+// Trash ids are generated by us; anything else is rejected before touching the disk
+fn validate_id(id: &str) -> Result<(), String> {
+    if id.is_empty() || !id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err("Invalid trash id".to_string());
+    }
+    Ok(())
+}
+
+// Moves a secret (or a whole category) into the trash and records who deleted it.
+// Callers must hold WRITE_LOCK.
+pub fn move_to_trash(path: &str, is_dir: bool, actor: &str) -> Result<TrashEntry, String> {
+    let store = StdPath::new(STORE_PATH.as_str());
+    let (src, secrets) = if is_dir {
+        let dir = store.join(path);
+        let secrets = list_secrets(&dir, path);
+        (dir, secrets)
+    } else {
+        (store.join(format!("{}.gpg", path)), vec![path.to_string()])
+    };
+    let name = src.file_name().ok_or("Invalid path")?.to_owned();
+
+    let id = Utc::now().format("%Y%m%dT%H%M%S%9fZ").to_string();
+    let slot = trash_root().join(&id);
+    fs::create_dir_all(&slot).map_err(|e| format!("Could not create trash slot: {}", e))?;
+
+    let entry = TrashEntry {
+        id,
+        original_path: path.to_string(),
+        is_dir,
+        deleted_at: Utc::now().to_rfc3339(),
+        deleted_by: actor.to_string(),
+        secrets,
+        metadata: metadata::take_prefix(path, is_dir),
+    };
+    let meta = serde_json::to_string_pretty(&entry).map_err(|e| e.to_string())?;
+    if let Err(e) = fs::write(slot.join(META_FILE), meta) {
+        metadata::put_prefix(path, entry.metadata);
+        let _ = fs::remove_dir_all(&slot);
+        return Err(format!("Could not write trash metadata: {}", e));
+    }
+
+    if let Err(e) = fs::rename(&src, slot.join(name)) {
+        metadata::put_prefix(path, entry.metadata);
+        let _ = fs::remove_dir_all(&slot);
+        return Err(format!("Could not move entry to trash: {}", e));
+    }
+    Ok(entry)
+}
+
+// Recipients a trashed secret was encrypted for: those of a .gpg-id that went
+// to the trash inside its category, or else of the one above where it was
+fn trashed_recipients(entry: &TrashEntry, trashed: &StdPath, secret: &str) -> Vec<String> {
+    if entry.is_dir {
+        let relative = secret[entry.original_path.len()..].trim_start_matches('/');
+        let mut dir = trashed.join(relative);
+        while dir.pop() && dir.starts_with(trashed) {
+            if let Some(ids) = gpg_id_in(&dir) {
+                return ids;
+            }
+        }
+    }
+    recipients_for(secret)
+}
+
+pub async fn list_trash() -> Json<Vec<TrashEntry>> {
+    if *DEBUG_MODE { println!("--> [STORAGE] LIST TRASH request"); }
+    Json(read_entries())
+}
+
+pub async fn restore_from_trash(Json(req): Json<TrashRequest>) -> (StatusCode, Json<Value>) {
+    let id = req.id.unwrap_or_default();
+    if let Err(e) = validate_id(&id) {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    }
+
+    let _write_guard = WRITE_LOCK.lock().await;
+    let slot = trash_root().join(&id);
+    let Some(entry) = read_entry(&slot) else {
+        return (StatusCode::NOT_FOUND, Json(json!({"error": "Trash entry not found"})));
+    };
+
+    let target = req.path.unwrap_or_else(|| entry.original_path.clone());
+    if let Err(e) = validate_path(&target) {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    }
+
+    let store = StdPath::new(STORE_PATH.as_str());
+    let (src_name, dst) = if entry.is_dir {
+        (StdPath::new(&entry.original_path).file_name().map(|n| n.to_owned()), store.join(&target))
+    } else {
+        (StdPath::new(&format!("{}.gpg", entry.original_path)).file_name().map(|n| n.to_owned()), store.join(format!("{}.gpg", target)))
+    };
+    let Some(src_name) = src_name else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Corrupted trash entry"})));
+    };
+    if dst.exists() {
+        return (StatusCode::CONFLICT, Json(json!({"error": "An entry already exists at the restore path"})));
+    }
+
+    // (new path, recipients before) of every secret, to re-encrypt those
+    // landing under a different .gpg-id like a move does
+    let trashed = slot.join(&src_name);
+    let moved: Vec<(String, Vec<String>)> = entry.secrets.iter()
+        .map(|secret| {
+            let new = format!("{}{}", target, &secret[entry.original_path.len()..]);
+            (new, trashed_recipients(&entry, &trashed, secret))
+        })
+        .collect();
+
+    if let Some(parent) = dst.parent()
+        && fs::create_dir_all(parent).is_err()
+    {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Could not create directory"})));
+    }
+    if let Err(e) = fs::rename(&trashed, &dst) {
+        println!("❌ [STORAGE] Error restoring from trash: {}", e);
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Could not restore entry"})));
+    }
+    let reencrypted = match reencrypt_moved(store, &moved).await {
+        Ok(reencrypted) => reencrypted,
+        Err(failure) => {
+            let _ = fs::rename(&dst, &trashed);
+            log_audit_event("storage_trash_restore", "failed", &failure.detail);
+            return (failure.status, Json(json!({"error": failure.error})));
+        }
+    };
+    let _ = fs::remove_dir_all(&slot);
+    metadata::put_prefix(&target, entry.metadata.clone());
+
+    commit_changes(&format!("Restore from trash: {}", target));
+    log_audit_event("storage_trash_restore", "success", &format!(
+        "restored {} (deleted by {}) to {} ({} re-encrypted)", entry.original_path, entry.deleted_by, target, reencrypted
+    ));
+    (StatusCode::OK, Json(json!({"status": "OK", "path": target, "reencrypted": reencrypted})))
+}
+
+pub async fn purge_trash(Json(req): Json<TrashRequest>) -> (StatusCode, Json<Value>) {
+    let _write_guard = WRITE_LOCK.lock().await;
+
+    let ids: Vec<String> = if req.all.unwrap_or(false) {
+        read_entries().into_iter().map(|e| e.id).collect()
+    } else {
+        let id = req.id.unwrap_or_default();
+        if let Err(e) = validate_id(&id) {
+            return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+        }
+        if !trash_root().join(&id).is_dir() {
+            return (StatusCode::NOT_FOUND, Json(json!({"error": "Trash entry not found"})));
+        }
+        vec![id]
+    };
+
+    let purged = purge_ids(&ids);
+    if purged > 0 {
+        commit_changes(&format!("Purge {} entries from trash", purged));
+    }
+    log_audit_event("storage_trash_purge", "success", &format!("purged {} entries: [{}]", purged, ids.join(", ")));
+    (StatusCode::OK, Json(json!({"status": "OK", "purged": purged})))
+}
+
+fn purge_ids(ids: &[String]) -> usize {
+    ids.iter()
+        .filter(|id| fs::remove_dir_all(trash_root().join(id)).is_ok())
+        .count()
+}
+
+// Retention sweep: permanently removes trash entries older than trash.retention_days
+pub async fn purge_expired_trash() {
+    let retention_days = CONFIG.trash.retention_days;
+    if retention_days == 0 {
+        return;
+    }
+
+    let _write_guard = WRITE_LOCK.lock().await;
+    let cutoff = Utc::now() - Duration::days(retention_days as i64);
+    let expired: Vec<String> = read_entries().into_iter()
+        .filter(|e| DateTime::parse_from_rfc3339(&e.deleted_at).map(|t| t < cutoff).unwrap_or(false))
+        .map(|e| e.id)
+        .collect();
+    if expired.is_empty() {
+        return;
+    }
+
+    let purged = purge_ids(&expired);
+    commit_changes(&format!("Auto-purge {} expired entries from trash", purged));
+    log_audit_event("storage_trash_purge", "success", &format!("retention ({} days) purged {} entries: [{}]", retention_days, purged, expired.join(", ")));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::delete_entry;
+    use crate::metadata::update_metadata;
+    use crate::testing::{self, read_secret, request, write_secret};
+
+    async fn trash(path: &str) -> TrashEntry {
+        let (status, Json(deleted)) = delete_entry(request(json!({"path": path, "actor": "alice"}))).await;
+        assert_eq!(status, StatusCode::OK);
+        let id = deleted["trash_id"].as_str().unwrap();
+        read_entries().into_iter().find(|e| e.id == id).unwrap()
+    }
+
+    #[tokio::test]
+    async fn deleted_secrets_can_be_restored_with_their_tags() {
+        let store = testing::store();
+        write_secret("bin/github", &["admin@talos.local"], "hunter2\n");
+        let (status, _) = update_metadata(request(json!({"path": "bin/github", "tags": ["work"]}))).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let entry = trash("bin/github").await;
+        assert_eq!((entry.original_path.as_str(), entry.deleted_by.as_str(), entry.is_dir), ("bin/github", "alice", false));
+        assert_eq!(entry.secrets, ["bin/github"]);
+        assert_eq!(entry.metadata[""].tags, ["work"]);
+        assert!(!store.join("bin/github.gpg").exists());
+        assert!(!metadata::load().contains_key("bin/github"));
+
+        let (status, _) = restore_from_trash(request(json!({"id": entry.id}))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(read_secret("bin/github").1, "hunter2\n");
+        assert_eq!(metadata::load()["bin/github"].tags, ["work"]);
+        assert!(!trash_root().join(&entry.id).exists());
+
+        // A new secret took the old path: the trashed one goes elsewhere or nowhere
+        let entry = trash("bin/github").await;
+        write_secret("bin/github", &["admin@talos.local"], "replacement\n");
+        let (status, _) = restore_from_trash(request(json!({"id": entry.id}))).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(read_secret("bin/github").1, "replacement\n");
+        let (status, _) = restore_from_trash(request(json!({"id": entry.id, "path": "bin/old/github"}))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(read_secret("bin/old/github").1, "hunter2\n");
+
+        for (id, expected) in [("../bin", StatusCode::BAD_REQUEST), ("20000101T000000000000000Z", StatusCode::NOT_FOUND)] {
+            let (status, _) = restore_from_trash(request(json!({"id": id}))).await;
+            assert_eq!(status, expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn purged_entries_are_gone_for_good() {
+        write_secret("purge/a", &["admin@talos.local"], "a\n");
+        write_secret("purge/b", &["admin@talos.local"], "b\n");
+        let purged = trash("purge/a").await;
+        let kept = trash("purge/b").await;
+
+        let (status, Json(result)) = purge_trash(request(json!({"id": purged.id}))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(result["purged"], 1);
+        assert!(!trash_root().join(&purged.id).exists());
+        assert!(trash_root().join(&kept.id).exists());
+
+        let (status, _) = purge_trash(request(json!({"id": purged.id}))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = restore_from_trash(request(json!({"id": purged.id}))).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = purge_trash(request(json!({"id": "../purge"}))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn retention_sweep_purges_only_expired_entries() {
+        write_secret("sweep/old", &["admin@talos.local"], "old\n");
+        write_secret("sweep/recent", &["admin@talos.local"], "recent\n");
+        let mut expired = trash("sweep/old").await;
+        let recent = trash("sweep/recent").await;
+
+        expired.deleted_at = (Utc::now() - Duration::days(CONFIG.trash.retention_days as i64 + 1)).to_rfc3339();
+        fs::write(trash_root().join(&expired.id).join(META_FILE), serde_json::to_string(&expired).unwrap()).unwrap();
+
+        purge_expired_trash().await;
+        assert!(!trash_root().join(&expired.id).exists());
+        assert!(trash_root().join(&recent.id).exists());
+    }
+}
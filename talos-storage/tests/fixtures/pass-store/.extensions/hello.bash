@@ -0,0 +1,3 @@
+#!/usr/bin/env bash
+# pass extension, ignored by Talos
+echo "hello from $PREFIX"
@@ -1,6 +1,6 @@
 use axum::{
-    extract::{ConnectInfo, Request, State},
-    http::{HeaderMap, StatusCode, header},
+    extract::{ConnectInfo, Extension, Request, State},
+    http::{HeaderMap, Method, StatusCode, header},
     Json,
     response::{IntoResponse, Response},
     middleware::Next,
@@ -8,69 +8,102 @@ use axum::{
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::env;
-use std::net::{IpAddr, SocketAddr};
-use std::time::{Duration, Instant};
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use data_encoding::BASE64URL_NOPAD;
+use subtle::ConstantTimeEq;
 use tower_sessions::Session;
 use zeroize::Zeroize;
-use crate::state::{AppState, RateLimiter, RateLimitEntry};
-use crate::handlers::log_audit;
+use crate::state::AppState;
+use crate::handlers::{is_debug, log_audit, log_audit_with_severity};
+use crate::policy::{Policy, Role};
+use crate::bootstrap;
+use crate::throttle;
+use crate::tokens;
+use crate::totp::{self, Factor};
+use crate::users::{self, current_user, start_session, User};
 
-const MAX_LOGIN_ATTEMPTS: u32 = 5;
-const RATE_LIMIT_WINDOW_SECONDS: u64 = 60;
 const CSRF_TOKEN_KEY: &str = "csrf_token";
+// A password login waiting for its second factor
+const PENDING_USER_KEY: &str = "pending_user_id";
+const PENDING_SINCE_KEY: &str = "pending_since";
+const PENDING_ATTEMPTS_KEY: &str = "pending_attempts";
+const PENDING_TIMEOUT_SECONDS: u64 = 300;
+const MAX_SECOND_FACTOR_ATTEMPTS: u32 = 5;
 
-async fn generate_csrf_token(session: &Session) -> Result<String, StatusCode> {
-    if let Some(token) = session.get::<String>(CSRF_TOKEN_KEY).await.unwrap_or(None) {
-        return Ok(token);
-    }
-    
-    // Generate simple random token
-    use std::time::{SystemTime, UNIX_EPOCH};
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_nanos();
-    let token = format!("csrf_{:x}", timestamp);
-    
-    session.insert(CSRF_TOKEN_KEY, &token).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+// A random token per session, rotated at every sign-in. The frontend sends it
+// back in this header on every state-changing request.
+pub(crate) const CSRF_HEADER: &str = "x-csrf-token";
+
+pub(crate) async fn issue_csrf_token(session: &Session) -> Result<String, tower_sessions::session::Error> {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    let token = BASE64URL_NOPAD.encode(&bytes);
+    session.insert(CSRF_TOKEN_KEY, &token).await?;
     Ok(token)
 }
 
-pub async fn validate_csrf_token(session: &Session, token: &str) -> Result<bool, StatusCode> {
-    let stored_token = session.get::<String>(CSRF_TOKEN_KEY).await
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?
-        .ok_or(StatusCode::UNAUTHORIZED)?;
-    Ok(stored_token == token)
+async fn csrf_token(session: &Session) -> Option<String> {
+    match session.get::<String>(CSRF_TOKEN_KEY).await.ok()? {
+        Some(token) => Some(token),
+        None => issue_csrf_token(session).await.ok(),
+    }
+}
+
+async fn validate_csrf_token(session: &Session, token: &str) -> bool {
+    let Ok(Some(stored_token)) = session.get::<String>(CSRF_TOKEN_KEY).await else {
+        return false;
+    };
+    bool::from(stored_token.as_bytes().ct_eq(token.as_bytes()))
+}
+
+// Every request that is not a plain read must carry the session's CSRF token.
+// Bearer tokens are exempt: browsers never attach them on their own, and a
+// cross-site page cannot set the header without a CORS preflight, which
+// Talos never allows. `require_auth` still checks the token itself.
+pub async fn require_csrf(session: Session, request: Request, next: Next) -> Response {
+    if matches!(*request.method(), Method::GET | Method::HEAD | Method::OPTIONS)
+        || tokens::bearer_token(request.headers()).is_some() {
+        return next.run(request).await;
+    }
+    let token = request.headers().get(CSRF_HEADER).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    if token.is_empty() || !validate_csrf_token(&session, token).await {
+        if is_debug() {
+            println!("--> [WEB] CSRF check failed: {} {}", request.method(), request.uri().path());
+        }
+        return (StatusCode::FORBIDDEN, Json(json!({"error": "CSRF token missing or invalid"}))).into_response();
+    }
+    next.run(request).await
 }
 
 #[derive(Deserialize, Zeroize)]
 #[zeroize(drop)]
 pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Deserialize, Zeroize)]
+#[zeroize(drop)]
+pub struct SecondFactorRequest {
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Deserialize, Zeroize)]
+#[zeroize(drop)]
+pub struct SetupRequest {
+    // Master key, proving the caller controls the vault
     pub key: String,
+    pub username: String,
+    pub password: String,
 }
 
-fn check_rate_limit(ip: IpAddr, rate_limiter: &RateLimiter) -> bool {
-    let mut limiter = rate_limiter.lock().unwrap();
-    let now = Instant::now();
-    
-    if let Some(entry) = limiter.get_mut(&ip) {
-        if now.duration_since(entry.window_start) > Duration::from_secs(RATE_LIMIT_WINDOW_SECONDS) {
-            // Reset window
-            entry.attempts = 1;
-            entry.window_start = now;
-            true
-        } else {
-            entry.attempts += 1;
-            entry.attempts <= MAX_LOGIN_ATTEMPTS
-        }
-    } else {
-        limiter.insert(ip, RateLimitEntry {
-            attempts: 1,
-            window_start: now,
-        });
-        true
-    }
+#[derive(Deserialize, Zeroize)]
+#[zeroize(drop)]
+pub struct UnsealRequest {
+    pub key: String,
 }
 
 #[derive(Serialize)]
@@ -79,19 +112,28 @@ pub struct AuthStatus {
     pub authenticated: bool,
     pub auth_method: Option<String>,
     pub bunker: bool,
+    // False until the first administrator account has been created
+    pub users_configured: bool,
+    pub sealed: bool,
+    pub username: Option<String>,
+    pub role: Option<Role>,
+    // Sent back in the `X-CSRF-Token` header on state-changing requests
+    pub csrf_token: Option<String>,
+    // Single sign-on is configured
+    pub oidc: bool,
 }
 
 pub async fn get_auth_status(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     session: Session,
 ) -> Json<AuthStatus> {
-    let authenticated: bool = session.get("authenticated").await.unwrap_or_default().unwrap_or(false);
+    let user = current_user(&state, &session).await;
     let auth_method: Option<String> = session.get("auth_method").await.unwrap_or(None);
 
     // Check the actual system status through the health endpoint, which in turn queries the Bunker.
     let client = reqwest::Client::new();
     let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
-    let (initialized, bunker_ok) = match client.get(format!("{}/api/health", storage_url)).send().await {
+    let (initialized, bunker_ok, sealed) = match client.get(format!("{}/api/health", storage_url)).send().await {
         Ok(res) => {
             if let Ok(status) = res.json::<Value>().await {
                 let bunker_status = status["bunker"].as_str().unwrap_or("OFFLINE");
@@ -99,19 +141,25 @@ pub async fn get_auth_status(
                 // It can be "SEALED" or "INITIALIZED", both count as initialized.
                 let is_initialized = bunker_status != "UNINITIALIZED";
                 let is_bunker_ok = bunker_status != "OFFLINE";
-                (is_initialized, is_bunker_ok)
+                (is_initialized, is_bunker_ok, bunker_status == "SEALED")
             } else {
-                (false, false) // Assume worst case if the response is not valid JSON
+                (false, false, false) // Assume worst case if the response is not valid JSON
             }
         },
-        Err(_) => (false, false) // Assume worst case if Storage does not respond
+        Err(_) => (false, false, false) // Assume worst case if Storage does not respond
     };
     
     Json(AuthStatus {
         initialized,
-        authenticated,
-        auth_method,
+        authenticated: user.is_some(),
+        auth_method: user.as_ref().and(auth_method),
         bunker: bunker_ok,
+        users_configured: users::user_count(&state.pool).await > 0,
+        sealed,
+        role: user.as_ref().map(|u| u.role),
+        username: user.map(|u| u.username),
+        csrf_token: csrf_token(&session).await,
+        oidc: state.oidc.is_some(),
     })
 }
 
@@ -122,52 +170,247 @@ pub async fn login(
     headers: HeaderMap,
     Json(payload): Json<LoginRequest>,
 ) -> impl IntoResponse {
-    // Check rate limiting
-    if !check_rate_limit(addr.ip(), &state.rate_limiter) {
-        log_audit(&state, &session, Some(addr.ip()), headers.get(header::USER_AGENT), "LOGIN_RATE_LIMITED", "system").await;
-        return (StatusCode::TOO_MANY_REQUESTS, Json(json!({"error": "Too many login attempts. Please wait 60 seconds."})));
+    let ua_header = headers.get(header::USER_AGENT);
+    // The attempted name is recorded, it may not be an account
+    let target: String = payload.username.trim().chars().take(64).collect();
+    if let Err(retry_after) = throttle::check(&state.pool, addr.ip(), Some(&payload.username)).await {
+        log_audit(&state, &session, Some(addr.ip()), ua_header, "LOGIN_RATE_LIMITED", &target).await;
+        return throttle::too_many_attempts(retry_after);
     }
-    
+
+    let Some(user) = users::authenticate(&state.pool, &payload.username, &payload.password).await else {
+        throttle::fail(&state, &session, addr.ip(), ua_header, Some(&payload.username)).await;
+        log_audit(&state, &session, Some(addr.ip()), ua_header, "LOGIN_FAILURE", &target).await;
+        return (StatusCode::UNAUTHORIZED, Json(json!({"error": "Invalid username or password"})));
+    };
+
+    // The password alone does not sign in an account with a second factor;
+    // the session only remembers who is halfway through
+    if totp::is_enabled(&state.pool, user.id).await {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let pending = async {
+            // Whoever was signed in on this session before is signed out
+            session.clear().await;
+            session.cycle_id().await?;
+            session.insert(PENDING_USER_KEY, user.id).await?;
+            session.insert(PENDING_SINCE_KEY, now).await?;
+            session.insert(PENDING_ATTEMPTS_KEY, 0u32).await?;
+            issue_csrf_token(&session).await
+        };
+        let Ok(csrf_token) = pending.await else {
+            return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Failed to start session"})));
+        };
+        return (StatusCode::OK, Json(json!({"status": "Second factor required", "totp_required": true, "csrf_token": csrf_token})));
+    }
+
+    complete_login(&state, &session, addr, ua_header, &user, "password").await
+}
+
+async fn complete_login(state: &AppState, session: &Session, addr: SocketAddr, ua_header: Option<&header::HeaderValue>, user: &User, auth_method: &str) -> (StatusCode, Json<Value>) {
+    let Ok(csrf_token) = start_session(session, user, auth_method, addr, ua_header).await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Failed to start session"})));
+    };
+    throttle::record_success(&state.pool, &user.username).await;
+    log_audit(state, session, Some(addr.ip()), ua_header, "LOGIN_SUCCESS", &user.username).await;
+    (StatusCode::OK, Json(json!({
+        "status": "Logged in",
+        "username": user.username,
+        "csrf_token": csrf_token
+    })))
+}
+
+async fn clear_pending_login(session: &Session) {
+    let _ = session.remove_value(PENDING_USER_KEY).await;
+    let _ = session.remove_value(PENDING_SINCE_KEY).await;
+    let _ = session.remove_value(PENDING_ATTEMPTS_KEY).await;
+}
+
+// Second step of a login for accounts with two-factor authentication: an
+// authenticator code, or one of the recovery codes
+pub async fn login_totp(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<SecondFactorRequest>,
+) -> impl IntoResponse {
+    let ua_header = headers.get(header::USER_AGENT);
+    let pending_id: Option<i64> = session.get(PENDING_USER_KEY).await.unwrap_or(None);
+    let since: u64 = session.get(PENDING_SINCE_KEY).await.unwrap_or(None).unwrap_or(0);
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    let user = match pending_id {
+        Some(id) if now.saturating_sub(since) <= PENDING_TIMEOUT_SECONDS => users::find_user(&state.pool, id).await.filter(|u| !u.disabled),
+        _ => None,
+    };
+    let Some(user) = user else {
+        clear_pending_login(&session).await;
+        return (StatusCode::UNAUTHORIZED, Json(json!({"error": "Login expired, please sign in again"})));
+    };
+    if let Err(retry_after) = throttle::check(&state.pool, addr.ip(), Some(&user.username)).await {
+        log_audit(&state, &session, Some(addr.ip()), ua_header, "LOGIN_RATE_LIMITED", &user.username).await;
+        return throttle::too_many_attempts(retry_after);
+    }
+
+    let factor = totp::check_second_factor(&state.pool, user.id, payload.code.as_deref(), payload.recovery_code.as_deref()).await;
+    let Some(factor) = factor else {
+        let attempts = session.get::<u32>(PENDING_ATTEMPTS_KEY).await.unwrap_or(None).unwrap_or(0) + 1;
+        if attempts >= MAX_SECOND_FACTOR_ATTEMPTS {
+            clear_pending_login(&session).await;
+        } else {
+            let _ = session.insert(PENDING_ATTEMPTS_KEY, attempts).await;
+        }
+        throttle::fail(&state, &session, addr.ip(), ua_header, Some(&user.username)).await;
+        log_audit(&state, &session, Some(addr.ip()), ua_header, "LOGIN_TOTP_FAILURE", &user.username).await;
+        return (StatusCode::UNAUTHORIZED, Json(json!({"error": "Invalid code"})));
+    };
+
+    clear_pending_login(&session).await;
+    let auth_method = match factor {
+        Factor::Totp => "password+totp",
+        Factor::RecoveryCode => {
+            log_audit_with_severity(&state, &session, Some(addr.ip()), ua_header, "RECOVERY_CODE_USED", &user.username, "warning").await;
+            "password+recovery"
+        },
+    };
+    complete_login(&state, &session, addr, ua_header, &user, auth_method).await
+}
+
+// Proves knowledge of the master key: checked against the unlocked vault
+// when possible, otherwise by unsealing it
+async fn verify_master_key(client: &reqwest::Client, storage_url: &str, key: &str) -> bool {
+    match client.post(format!("{}/api/unlock/verify", storage_url)).json(&json!({ "key": key })).send().await {
+        Ok(res) if res.status().is_success() => true,
+        Ok(res) if res.status() == reqwest::StatusCode::SERVICE_UNAVAILABLE => {
+            client.post(format!("{}/api/unlock", storage_url))
+                .json(&json!({ "key": key }))
+                .send().await
+                .is_ok_and(|res| res.status().is_success())
+        },
+        _ => false,
+    }
+}
+
+// Creates the first administrator account. Only possible while no account
+// exists, and only for whoever knows the master key.
+pub async fn setup_account(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<SetupRequest>,
+) -> impl IntoResponse {
+    let ua_header = headers.get(header::USER_AGENT);
+    if users::user_count(&state.pool).await > 0 {
+        return (StatusCode::CONFLICT, Json(json!({"error": "Accounts are already configured"})));
+    }
+    if let Err(retry_after) = throttle::check(&state.pool, addr.ip(), None).await {
+        log_audit(&state, &session, Some(addr.ip()), ua_header, "ACCOUNT_SETUP_RATE_LIMITED", "system").await;
+        return throttle::too_many_attempts(retry_after);
+    }
+    let username = match users::normalize_username(&payload.username) {
+        Ok(username) => username,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))),
+    };
+    if let Err(e) = users::check_password_policy(&payload.password) {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": e})));
+    }
+
     let client = reqwest::Client::new();
-    let storage_url = std::env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
+    let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
+    if !verify_master_key(&client, &storage_url, &payload.key).await {
+        throttle::fail(&state, &session, addr.ip(), ua_header, None).await;
+        log_audit(&state, &session, Some(addr.ip()), ua_header, "ACCOUNT_SETUP_FAILURE", &username).await;
+        return (StatusCode::UNAUTHORIZED, Json(json!({"error": "Invalid Master Key"})));
+    }
 
-    // Verify key with Storage (Unlock attempt & Verification)
-    let res = client.post(format!("{}/api/unlock", storage_url))
-        .json(&json!({ "key": payload.key }))
-        .send().await;
+    let hash = match users::hash_blocking(&payload.password).await {
+        Ok(hash) => hash,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Failed to hash password"}))),
+    };
+    let user = match users::insert_first_admin(&state.pool, &username, &hash).await {
+        Ok(Some(id)) => users::find_user(&state.pool, id).await,
+        Ok(None) => return (StatusCode::CONFLICT, Json(json!({"error": "Accounts are already configured"}))),
+        Err(e) => {
+            println!("❌ [WEB] Account setup failed: {}", e);
+            None
+        }
+    };
+    let Some(user) = user else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Failed to create account"})));
+    };
 
+    let Ok(csrf_token) = start_session(&session, &user, "password", addr, ua_header).await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Failed to start session"})));
+    };
+    log_audit(&state, &session, Some(addr.ip()), ua_header, "ACCOUNT_SETUP", &user.username).await;
+    (StatusCode::CREATED, Json(json!({
+        "status": "Administrator created",
+        "username": user.username,
+        "csrf_token": csrf_token
+    })))
+}
+
+// Loads the master key into the Bunker after a restart. Separate from login:
+// only administrators do it, and only when the vault is sealed.
+pub async fn unseal(
+    State(state): State<AppState>,
+    session: Session,
+    Extension(policy): Extension<Policy>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(payload): Json<UnsealRequest>,
+) -> impl IntoResponse {
     let ua_header = headers.get(header::USER_AGENT);
+    let username = policy.user.username.as_str();
+    if let Err(retry_after) = throttle::check(&state.pool, addr.ip(), Some(username)).await {
+        log_audit(&state, &session, Some(addr.ip()), ua_header, "UNSEAL_RATE_LIMITED", "system").await;
+        return throttle::too_many_attempts(retry_after);
+    }
 
+    let client = reqwest::Client::new();
+    let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
+    let res = client.post(format!("{}/api/unlock", storage_url))
+        .json(&json!({ "key": payload.key }))
+        .send().await;
     match res {
         Ok(response) if response.status().is_success() => {
-            session.insert("authenticated", true).await.unwrap();
-            session.insert("auth_method", "password").await.unwrap();
-            let csrf_token = generate_csrf_token(&session).await.unwrap_or_default();
-            log_audit(&state, &session, Some(addr.ip()), ua_header, "LOGIN_SUCCESS", "system").await;
-            (StatusCode::OK, Json(json!({
-                "status": "Logged in",
-                "csrf_token": csrf_token
-            })))
+            log_audit(&state, &session, Some(addr.ip()), ua_header, "UNSEAL", "system").await;
+            (StatusCode::OK, Json(json!({"status": "Vault unsealed"})))
         },
         _ => {
-            log_audit(&state, &session, Some(addr.ip()), ua_header, "LOGIN_FAILURE", "system").await;
+            throttle::fail(&state, &session, addr.ip(), ua_header, Some(username)).await;
+            log_audit(&state, &session, Some(addr.ip()), ua_header, "UNSEAL_FAILURE", "system").await;
             (StatusCode::UNAUTHORIZED, Json(json!({"error": "Invalid Master Key"})))
         }
     }
 }
 
-pub async fn logout(session: Session) -> impl IntoResponse {
+pub async fn logout(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    if let Some(user) = current_user(&state, &session).await {
+        log_audit(&state, &session, Some(addr.ip()), headers.get(header::USER_AGENT), "LOGOUT", &user.username).await;
+    }
     session.flush().await.unwrap();
     (StatusCode::OK, Json(json!({"status": "Logged out"})))
 }
 
-pub async fn require_auth(session: Session, request: Request, next: Next) -> Result<Response, StatusCode> {
-    let authenticated: bool = session.get("authenticated").await.unwrap_or_default().unwrap_or(false);
-    if authenticated {
-        Ok(next.run(request).await)
-    } else {
-        Err(StatusCode::UNAUTHORIZED)
+// Sessions stop working as soon as their account is disabled. The caller's
+// role and grants are handed to the handlers as a `Policy` extension.
+// Scripts authenticate with an API token instead: `Authorization: Bearer`
+// replaces the session and its CSRF token, within the token's scope.
+pub async fn require_auth(State(state): State<AppState>, session: Session, mut request: Request, next: Next) -> Result<Response, StatusCode> {
+    if let Some(token) = tokens::bearer_token(request.headers()) {
+        let (identity, user, scope) = tokens::authenticate(&state.pool, token).await.ok_or(StatusCode::UNAUTHORIZED)?;
+        request.extensions_mut().insert(Policy::load(&state.pool, user).await.restricted_to(scope));
+        return Ok(tokens::with_identity(identity, next.run(request)).await);
     }
+    let user = current_user(&state, &session).await.ok_or(StatusCode::UNAUTHORIZED)?;
+    request.extensions_mut().insert(Policy::load(&state.pool, user).await);
+    Ok(next.run(request).await)
 }
 
 pub async fn proxy_import_key(
@@ -175,12 +418,16 @@ pub async fn proxy_import_key(
     session: Session,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
-    Json(body): Json<Value>
+    Json(mut body): Json<Value>
 ) -> impl IntoResponse {
     let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
     let client = reqwest::Client::new();
     
     let ua_header = headers.get(header::USER_AGENT);
+    if !bootstrap::is_valid(&state.bootstrap_token, &bootstrap::take_presented(&mut body)) {
+        log_audit_with_severity(&state, &session, Some(addr.ip()), ua_header, "IMPORT_SYSTEM_DENIED", "system", "warning").await;
+        return (StatusCode::FORBIDDEN, Json(json!({"error": "Invalid bootstrap token"})));
+    }
     log_audit(&state, &session, Some(addr.ip()), ua_header, "IMPORT_SYSTEM", "system").await;
 
     let res = client.post(format!("{}/api/initialize/import", storage_url))
@@ -192,36 +439,73 @@ pub async fn proxy_import_key(
             let status = response.status();
             let status_code = StatusCode::from_u16(status.as_u16()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
             let body = response.json::<Value>().await.unwrap_or_default();
+            if status_code.is_success() {
+                bootstrap::consume(&state.bootstrap_token);
+            }
             (status_code, Json(body))
         },
         Err(_) => (StatusCode::BAD_GATEWAY, Json(json!({"error": "Storage unreachable"})))
     }
 }
 
+#[derive(Deserialize, Zeroize)]
+#[zeroize(drop)]
+pub struct BackupKeyRequest {
+    // Master key, asked again even though an administrator is signed in
+    pub key: String,
+}
+
+// Downloads the vault's private key. Administrators only, and only after
+// proving the master key again; failed attempts count against the sign-in
+// rate limits.
 pub async fn proxy_backup_key(
     State(state): State<AppState>,
     session: Session,
+    Extension(policy): Extension<Policy>,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
+    Json(payload): Json<BackupKeyRequest>,
 ) -> impl IntoResponse {
     let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
     let ua_header = headers.get(header::USER_AGENT);
-    log_audit(&state, &session, Some(addr.ip()), ua_header, "BACKUP_KEY", "system").await;
+    let username = policy.user.username.as_str();
+    if let Err(retry_after) = throttle::check(&state.pool, addr.ip(), Some(username)).await {
+        log_audit(&state, &session, Some(addr.ip()), ua_header, "BACKUP_KEY_RATE_LIMITED", "system").await;
+        return throttle::too_many_attempts(retry_after).into_response();
+    }
 
-    // Proxy the download request
     let client = reqwest::Client::new();
+    let verified = client.post(format!("{}/api/unlock/verify", storage_url))
+        .json(&json!({ "key": payload.key }))
+        .send().await
+        .is_ok_and(|res| res.status().is_success());
+    if !verified {
+        throttle::fail(&state, &session, addr.ip(), ua_header, Some(username)).await;
+        log_audit_with_severity(&state, &session, Some(addr.ip()), ua_header, "BACKUP_KEY_REAUTH_FAILURE", "system", "high").await;
+        return (StatusCode::UNAUTHORIZED, Json(json!({"error": "Invalid Master Key"}))).into_response();
+    }
+
+    // Proxy the download request
     match client.get(format!("{}/api/backup/key", storage_url)).send().await {
-        Ok(res) => {
+        Ok(res) if res.status().is_success() => {
             let bytes = res.bytes().await.unwrap_or_default();
+            log_audit_with_severity(&state, &session, Some(addr.ip()), ua_header, "BACKUP_KEY", "system", "high").await;
             (
                 StatusCode::OK,
                 [
                     (header::CONTENT_TYPE, "application/pgp-keys"),
-                    (header::CONTENT_DISPOSITION, "attachment; filename=\"talos_master_private.key\"")
+                    (header::CONTENT_DISPOSITION, "attachment; filename=\"talos_master_private.key\""),
+                    (header::CACHE_CONTROL, "no-store"),
                 ],
-                axum::body::Bytes::from(bytes)
+                bytes
             ).into_response()
         },
+        Ok(res) => {
+            let status = StatusCode::from_u16(res.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+            let body = res.json::<Value>().await.unwrap_or_else(|_| json!({"error": "Failed to retrieve key"}));
+            log_audit_with_severity(&state, &session, Some(addr.ip()), ua_header, "BACKUP_KEY_FAILURE", "system", "high").await;
+            (status, Json(body)).into_response()
+        },
         Err(_) => (StatusCode::BAD_GATEWAY, Json(json!({"error": "Failed to retrieve key"}))).into_response()
     }
-}
\ No newline at end of file
+}
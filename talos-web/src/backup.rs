@@ -0,0 +1,728 @@
+use axum::Json;
+use axum::extract::{ConnectInfo, Multipart, State};
+use axum::extract::multipart::Field;
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::body::{Body, Bytes};
+use axum::response::IntoResponse;
+use age::secrecy::SecretString;
+use chrono::{DateTime, Utc};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use sqlx::{Connection, SqliteConnection, SqlitePool};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Seek, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use tower_sessions::Session;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use zip::write::SimpleFileOptions;
+use crate::state::AppState;
+use crate::backup_stream::{backup_passphrase, encrypt_writer, ChannelWriter, CHUNK_SIZE, STREAM_CHUNKS};
+use crate::handlers::{is_debug, log_audit};
+
+// Full backups are age archives (scrypt passphrase recipient, ChaCha20-Poly1305
+// payload), so neither secret names nor the audit log leak from a backup file.
+// They can also be opened offline with `age -d`.
+const AGE_MAGIC: &[u8] = b"age-encryption.org/v1";
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(AGE_MAGIC)
+}
+
+// Decrypts into `out` as it reads; a wrong passphrase fails before anything
+// is written, a damaged archive part way through
+pub fn decrypt_archive<R: Read, W: Write>(input: R, mut out: W, passphrase: SecretString) -> Result<W, String> {
+    let decryptor = age::Decryptor::new(input).map_err(|e| e.to_string())?;
+    if !decryptor.is_scrypt() {
+        return Err("Backup is not passphrase encrypted".to_string());
+    }
+    let identity = age::scrypt::Identity::new(passphrase);
+    let mut reader = decryptor.decrypt(std::iter::once(&identity as &dyn age::Identity)).map_err(|e| e.to_string())?;
+    io::copy(&mut reader, &mut out).map_err(|e| e.to_string())?;
+    Ok(out)
+}
+
+// Blocking reader over a Storage response body as it arrives
+struct ResponseReader {
+    response: reqwest::Response,
+    runtime: tokio::runtime::Handle,
+    pending: Bytes,
+}
+
+impl Read for ResponseReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pending.is_empty() {
+            match self.runtime.block_on(self.response.chunk()) {
+                Ok(Some(chunk)) => self.pending = chunk,
+                Ok(None) => return Ok(0),
+                Err(e) => return Err(io::Error::other(e)),
+            }
+        }
+        let n = buf.len().min(self.pending.len());
+        buf[..n].copy_from_slice(&self.pending.split_to(n));
+        Ok(n)
+    }
+}
+
+// Writes the full backup archive (the Storage archive as secrets.zip, plus
+// talos.db) to `out`, copying from both sources as it goes
+fn write_full_archive<W: Write>(out: W, mut secrets: impl Read, db: Option<impl Read>) -> Result<W, String> {
+    let mut zip_writer = zip::ZipWriter::new_stream(out);
+    // Sizes are not known up front, so entries always carry zip64 sizes
+    let options = SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored).large_file(true);
+
+    zip_writer.start_file("secrets.zip", options).map_err(|e| e.to_string())?;
+    io::copy(&mut secrets, &mut zip_writer).map_err(|e| format!("Storage archive: {}", e))?;
+    if let Some(mut db) = db {
+        zip_writer.start_file(DB_ENTRY, options).map_err(|e| e.to_string())?;
+        io::copy(&mut db, &mut zip_writer).map_err(|e| format!("{}: {}", DB_ENTRY, e))?;
+    }
+    Ok(zip_writer.finish().map_err(|e| e.to_string())?.into_inner())
+}
+
+pub async fn proxy_backup(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
+    if is_debug() { println!("--> [WEB] Proxying BACKUP download"); }
+
+    let Some(passphrase) = backup_passphrase() else {
+        println!("⚠️ [WEB] BACKUP_PASSPHRASE not set, refusing to produce a backup");
+        return (StatusCode::SERVICE_UNAVAILABLE, [(header::CONTENT_TYPE, "text/plain")], Bytes::from("Backup passphrase not configured (BACKUP_PASSPHRASE)")).into_response();
+    };
+
+    let client = reqwest::Client::new();
+
+    let ua_header = headers.get(header::USER_AGENT);
+    log_audit(&state, &session, Some(addr.ip()), ua_header, "BACKUP", "full_system").await;
+
+    // talos.db goes into the backup as a consistent copy, and Storage lists
+    // its digest in the signed manifest
+    let db_copy = db_work_file("backup");
+    let digest = match export_db(&state.pool, &db_copy).await.and_then(|_| File::open(&db_copy).and_then(|mut f| file_digest(&mut f)).map_err(|e| e.to_string())) {
+        Ok(digest) => digest,
+        Err(e) => {
+            let _ = std::fs::remove_file(&db_copy);
+            println!("❌ [WEB] Could not copy talos.db for backup: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, [(header::CONTENT_TYPE, "text/plain")], Bytes::from("Could not copy talos.db for backup")).into_response();
+        }
+    };
+    let query = [("external_path", DB_ENTRY.to_string()), ("external_size", digest.0.to_string()), ("external_sha256", digest.1)];
+
+    // 1. Obtener el backup de secretos (ZIP) del Storage
+    match client.get(format!("{}/api/backup", storage_url)).query(&query).send().await {
+        Ok(res) if !res.status().is_success() => {
+            let _ = std::fs::remove_file(&db_copy);
+            // e.g. the manifest cannot be signed while the vault is sealed
+            let status = StatusCode::from_u16(res.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+            let body = res.bytes().await.unwrap_or_default();
+            (status, [(header::CONTENT_TYPE, "application/json")], body).into_response()
+        },
+        Ok(res) => {
+            // 2. Stream the Storage archive into an encrypted master ZIP
+            let (tx, rx) = mpsc::channel(STREAM_CHUNKS);
+            let runtime = tokio::runtime::Handle::current();
+            tokio::task::spawn_blocking(move || {
+                let secrets = ResponseReader { response: res, runtime, pending: Bytes::new() };
+                let out = io::BufWriter::with_capacity(CHUNK_SIZE, ChannelWriter(tx.clone()));
+
+                let written = std::fs::File::open(&db_copy).map_err(|e| format!("{}: {}", DB_ENTRY, e))
+                    .and_then(|db| encrypt_writer(out, passphrase).map(|encrypted| (encrypted, db)))
+                    .and_then(|(encrypted, db)| write_full_archive(encrypted, secrets, Some(db)))
+                    .and_then(|encrypted| encrypted.finish().and_then(|mut out| out.flush()).map_err(|e| e.to_string()));
+                let _ = std::fs::remove_file(&db_copy);
+                if let Err(e) = written {
+                    println!("❌ [WEB] Backup failed: {}", e);
+                    // Abort the body so the client never keeps a truncated backup
+                    let _ = tx.blocking_send(Err(io::Error::other(e)));
+                }
+            });
+
+            (StatusCode::OK, [(header::CONTENT_TYPE, "application/octet-stream"), (header::CONTENT_DISPOSITION, "attachment; filename=\"talos_full_backup.zip.age\"")], Body::from_stream(ReceiverStream::new(rx))).into_response()
+        },
+        Err(_) => {
+            let _ = std::fs::remove_file(&db_copy);
+            (StatusCode::BAD_GATEWAY, [(header::CONTENT_TYPE, "text/plain")], Bytes::from("Error fetching backup")).into_response()
+        }
+    }
+}
+
+// The /data/talos.db path comes from docker-compose
+const DB_PATH: &str = "/data/talos.db";
+const DB_SNAPSHOT_DIR: &str = "/data/snapshots";
+const DB_SNAPSHOTS_KEPT: usize = 10;
+const DB_ENTRY: &str = "talos.db";
+
+struct RestoreUpload {
+    data: Option<File>,
+    // Passphrase of a backup made elsewhere; defaults to BACKUP_PASSPHRASE
+    passphrase: Option<SecretString>,
+    legacy: bool,
+    paths: Option<Vec<String>>,
+    policy: Option<String>,
+}
+
+fn spool_file() -> io::Result<File> {
+    tempfile::tempfile()
+}
+
+// Uploads go to an unnamed temporary file as they arrive instead of being
+// buffered, so a restore is not bound by memory or the request body limit
+async fn spool_upload(mut field: Field<'_>) -> io::Result<File> {
+    let mut file = tokio::fs::File::from_std(spool_file()?);
+    while let Some(chunk) = field.chunk().await.map_err(io::Error::other)? {
+        tokio::io::AsyncWriteExt::write_all(&mut file, &chunk).await?;
+    }
+    tokio::io::AsyncWriteExt::flush(&mut file).await?;
+    let mut file = file.into_std().await;
+    file.rewind()?;
+    Ok(file)
+}
+
+async fn read_upload(mut multipart: Multipart) -> Result<RestoreUpload, (StatusCode, Json<Value>)> {
+    let mut upload = RestoreUpload { data: None, passphrase: None, legacy: false, paths: None, policy: None };
+    while let Ok(Some(field)) = multipart.next_field().await {
+        match field.name() {
+            Some("passphrase") => upload.passphrase = field.text().await.ok().filter(|p| !p.is_empty()).map(SecretString::from),
+            Some("legacy") => upload.legacy = field.text().await.map(|v| v == "true").unwrap_or(false),
+            Some("paths") => {
+                let text = field.text().await.unwrap_or_default();
+                upload.paths = Some(serde_json::from_str(&text)
+                    .map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid paths list"}))))?);
+            },
+            Some("policy") => upload.policy = field.text().await.ok().filter(|p| !p.is_empty()),
+            Some("backup") => {
+                upload.data = Some(spool_upload(field).await
+                    .map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"error": "Failed to read backup data"}))))?);
+            },
+            _ => {},
+        }
+    }
+    Ok(upload)
+}
+
+// Decrypts the uploaded archive, or accepts it as plaintext when flagged legacy
+async fn open_upload(state: &AppState, session: &Session, addr: &SocketAddr, headers: &HeaderMap, upload: &mut RestoreUpload) -> Result<File, (StatusCode, Json<Value>)> {
+    let Some(mut data) = upload.data.take() else {
+        return Err((StatusCode::BAD_REQUEST, Json(json!({"error": "No backup file provided"}))));
+    };
+    let spool_failed = |e: io::Error| {
+        println!("❌ [WEB] Could not spool backup upload: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Could not read backup upload"})))
+    };
+    let mut magic = [0u8; AGE_MAGIC.len()];
+    let encrypted = data.read_exact(&mut magic).is_ok() && is_encrypted(&magic);
+    data.rewind().map_err(spool_failed)?;
+
+    let ua_header = headers.get(header::USER_AGENT);
+    if encrypted {
+        let Some(passphrase) = upload.passphrase.take().or_else(backup_passphrase) else {
+            return Err((StatusCode::BAD_REQUEST, Json(json!({"error": "Backup passphrase required", "passphrase": true}))));
+        };
+        let plain = spool_file().map_err(spool_failed)?;
+        let decrypted = tokio::task::spawn_blocking(move || {
+            decrypt_archive(io::BufReader::new(data), plain, passphrase)
+                .and_then(|mut plain| plain.rewind().map(|_| plain).map_err(|e| e.to_string()))
+        }).await.unwrap_or_else(|e| Err(e.to_string()));
+        match decrypted {
+            Ok(plain) => Ok(plain),
+            Err(e) => {
+                if is_debug() { println!("--> [WEB] Backup decryption failed: {}", e); }
+                log_audit(state, session, Some(addr.ip()), ua_header, "RESTORE_FAILURE", "backup decryption failed").await;
+                Err((StatusCode::BAD_REQUEST, Json(json!({"error": "Could not decrypt backup (wrong passphrase?)", "passphrase": true}))))
+            }
+        }
+    } else if upload.legacy {
+        log_audit(state, session, Some(addr.ip()), ua_header, "RESTORE_LEGACY", "plaintext backup").await;
+        Ok(data)
+    } else {
+        Err((StatusCode::BAD_REQUEST, Json(json!({"error": "Backup is not encrypted. Confirm to import it as a legacy plaintext backup.", "legacy": true}))))
+    }
+}
+
+struct FullBackup {
+    db: Option<File>,
+    secrets: Option<File>,
+}
+
+// Splits a full backup into talos.db and the Storage archive, each extracted
+// to a file of its own. Archives with neither entry are old secrets-only
+// backups and go to Storage as a whole.
+fn split_full_backup(mut data: File) -> Result<FullBackup, String> {
+    let mut archive = zip::ZipArchive::new(io::BufReader::new(&data)).map_err(|_| "Invalid zip file".to_string())?;
+
+    let mut extract = |name: &str| -> Result<Option<File>, String> {
+        let Ok(mut entry) = archive.by_name(name) else { return Ok(None) };
+        let mut file = spool_file().map_err(|e| e.to_string())?;
+        io::copy(&mut entry, &mut file).map_err(|_| format!("Corrupted entry: {}", name))?;
+        file.rewind().map_err(|e| e.to_string())?;
+        Ok(Some(file))
+    };
+    let db = extract(DB_ENTRY)?;
+    let secrets = extract("secrets.zip")?;
+    drop(archive);
+
+    if db.is_none() && secrets.is_none() {
+        data.rewind().map_err(|e| e.to_string())?;
+        return Ok(FullBackup { db: None, secrets: Some(data) });
+    }
+    Ok(FullBackup { db, secrets })
+}
+
+fn modified_time(path: &str) -> Option<String> {
+    let modified = std::fs::metadata(path).and_then(|m| m.modified()).ok()?;
+    Some(DateTime::<Utc>::from(modified).to_rfc3339())
+}
+
+// Scratch files for database copies, next to the live database
+fn db_work_file(kind: &str) -> PathBuf {
+    PathBuf::from(format!("{}.{}-{}", DB_PATH, kind, Utc::now().format("%Y%m%dT%H%M%S%6f")))
+}
+
+// Size and SHA-256 of a file, read from the start and rewound afterwards
+fn file_digest(file: &mut File) -> io::Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    file.rewind()?;
+    let size = io::copy(file, &mut hasher)?;
+    file.rewind()?;
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+// Consistent copy of the live database, taken by SQLite itself so that writes
+// in flight never leave a torn file
+async fn export_db(pool: &SqlitePool, target: &Path) -> Result<(), String> {
+    sqlx::query("VACUUM INTO ?")
+        .bind(target.to_string_lossy().into_owned())
+        .execute(pool)
+        .await
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+// Tables whose rows are not taken from a backup: sessions belong to the
+// accounts being replaced and are dropped, while sign-in throttling and
+// pending single sign-on logins describe the running instance
+const SESSION_TABLE: &str = "sessions";
+const KEPT_TABLES: [&str; 2] = ["login_failures", "oidc_logins"];
+
+// Replaces the contents of the live database with those of `source` in one
+// transaction, so the pool stays open and a failure leaves nothing changed
+async fn import_db(pool: &SqlitePool, source: &Path) -> Result<(), String> {
+    // Check the copy and bring it up to the current schema on its own connection
+    let options = SqliteConnectOptions::new().filename(source);
+    let restored = SqlitePoolOptions::new().max_connections(1).connect_with(options).await.map_err(|e| e.to_string())?;
+    let integrity: Result<String, _> = sqlx::query_scalar("PRAGMA integrity_check").fetch_one(&restored).await;
+    let checked = match integrity {
+        Ok(result) if result == "ok" => {
+            // migrate panics on a schema it cannot upgrade
+            let migrating = restored.clone();
+            tokio::spawn(async move { crate::db::migrate(&migrating).await }).await
+                .map_err(|_| "Could not upgrade the database schema".to_string())
+        },
+        Ok(result) => Err(format!("Integrity check failed: {}", result)),
+        Err(e) => Err(e.to_string()),
+    };
+    restored.close().await;
+    checked?;
+
+    let mut conn = pool.acquire().await.map_err(|e| e.to_string())?;
+    sqlx::query("ATTACH DATABASE ? AS restored")
+        .bind(source.to_string_lossy().into_owned())
+        .execute(&mut *conn)
+        .await
+        .map_err(|e| e.to_string())?;
+    let copied = copy_tables(&mut conn).await.map_err(|e| e.to_string());
+    let _ = sqlx::query("DETACH DATABASE restored").execute(&mut *conn).await;
+    copied
+}
+
+async fn table_columns(conn: &mut SqliteConnection, schema: &str, table: &str) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT name FROM pragma_table_info(?, ?)")
+        .bind(table)
+        .bind(schema)
+        .fetch_all(conn)
+        .await
+}
+
+async fn copy_tables(conn: &mut SqliteConnection) -> Result<(), sqlx::Error> {
+    let tables: Vec<String> = sqlx::query_scalar("SELECT name FROM main.sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'")
+        .fetch_all(&mut *conn)
+        .await?;
+    let tables: Vec<String> = tables.into_iter().filter(|t| !KEPT_TABLES.contains(&t.as_str())).collect();
+
+    let mut tx = conn.begin().await?;
+    // References are checked once every table is filled again
+    sqlx::query("PRAGMA defer_foreign_keys = ON").execute(&mut *tx).await?;
+    for table in &tables {
+        sqlx::query(&format!("DELETE FROM main.\"{}\"", table)).execute(&mut *tx).await?;
+    }
+    for table in tables.iter().filter(|t| *t != SESSION_TABLE) {
+        let available = table_columns(&mut tx, "restored", table).await?;
+        let columns: Vec<String> = table_columns(&mut tx, "main", table).await?
+            .into_iter()
+            .filter(|c| available.contains(c))
+            .map(|c| format!("\"{}\"", c))
+            .collect();
+        if columns.is_empty() { continue; }
+        let columns = columns.join(", ");
+        sqlx::query(&format!("INSERT INTO main.\"{table}\" ({columns}) SELECT {columns} FROM restored.\"{table}\""))
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await
+}
+
+// Puts an uploaded database in place of the live one
+async fn restore_db_file(pool: &SqlitePool, mut db: File) -> Result<(), String> {
+    let upload = db_work_file("restore");
+    let copied = File::create(&upload).and_then(|mut target| {
+        db.rewind()?;
+        io::copy(&mut db, &mut target)?;
+        target.sync_all()
+    });
+    if let Err(e) = copied {
+        let _ = std::fs::remove_file(&upload);
+        return Err(e.to_string());
+    }
+    let imported = import_db(pool, &upload).await;
+    let _ = std::fs::remove_file(&upload);
+    imported
+}
+
+// Copies the live database aside before it gets replaced
+async fn snapshot_db(pool: &SqlitePool) -> Result<String, String> {
+    std::fs::create_dir_all(DB_SNAPSHOT_DIR).map_err(|e| e.to_string())?;
+    let name = format!("talos-{}.db", Utc::now().format("%Y%m%dT%H%M%S%3fZ"));
+    export_db(pool, &Path::new(DB_SNAPSHOT_DIR).join(&name)).await?;
+
+    let mut snapshots: Vec<PathBuf> = std::fs::read_dir(DB_SNAPSHOT_DIR)
+        .map(|dir| dir.flatten().map(|e| e.path()).filter(|p| p.extension().is_some_and(|e| e == "db")).collect())
+        .unwrap_or_default();
+    snapshots.sort();
+    let excess = snapshots.len().saturating_sub(DB_SNAPSHOTS_KEPT);
+    for old in &snapshots[..excess] {
+        let _ = std::fs::remove_file(old);
+    }
+    Ok(name)
+}
+
+// `db` is the digest of the talos.db entry, which Storage checks against
+// the signed manifest
+fn storage_form(secrets: File, legacy: bool, db: Option<&(u64, String)>) -> reqwest::multipart::Form {
+    let mut form = reqwest::multipart::Form::new();
+    if legacy {
+        // Pre-manifest archives are only accepted when explicitly requested
+        form = form.text("legacy", "true");
+    }
+    if let Some((size, sha256)) = db {
+        form = form.text("external", json!([{"path": DB_ENTRY, "size": size, "sha256": sha256}]).to_string());
+    }
+    // Streamed from the spooled file
+    let length = secrets.metadata().map(|m| m.len()).unwrap_or_default();
+    let body = reqwest::Body::from(tokio::fs::File::from_std(secrets));
+    form.part("backup", reqwest::multipart::Part::stream_with_length(body, length).file_name("backup.zip"))
+}
+
+async fn storage_call(url: &str, form: reqwest::multipart::Form) -> Result<Value, (StatusCode, Json<Value>)> {
+    match reqwest::Client::new().post(url).multipart(form).send().await {
+        // Manifest or integrity failures are reported back as-is
+        Ok(res) if !res.status().is_success() => {
+            let status = StatusCode::from_u16(res.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+            let data = res.json::<Value>().await.unwrap_or_else(|_| json!({"error": "Restore failed"}));
+            println!("⚠️ [WEB] Storage Restore Rejected [{}]: {:?}", status, data);
+            Err((status, Json(data)))
+        },
+        Ok(res) => Ok(res.json::<Value>().await.unwrap_or_default()),
+        Err(e) => {
+            println!("❌ [WEB] Storage Restore Failed: {}", e);
+            Err((StatusCode::BAD_GATEWAY, Json(json!({"error": "Storage node unreachable"}))))
+        }
+    }
+}
+
+pub async fn proxy_restore_preview(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    multipart: Multipart
+) -> impl IntoResponse {
+    let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
+    if is_debug() { println!("--> [WEB] Proxying RESTORE PREVIEW"); }
+
+    let mut upload = match read_upload(multipart).await {
+        Ok(u) => u,
+        Err(e) => return e,
+    };
+    let data = match open_upload(&state, &session, &addr, &headers, &mut upload).await {
+        Ok(d) => d,
+        Err(e) => return e,
+    };
+    let FullBackup { db, secrets } = match split_full_backup(data) {
+        Ok(parts) => parts,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))),
+    };
+
+    let mut db = db;
+    let db_digest = match db.as_mut().map(file_digest).transpose() {
+        Ok(digest) => digest,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": format!("Could not read {}: {}", DB_ENTRY, e)}))),
+    };
+    let mut preview = match secrets {
+        Some(secrets) => match storage_call(&format!("{}/api/restore/preview", storage_url), storage_form(secrets, upload.legacy, db_digest.as_ref())).await {
+            Ok(p) => p,
+            Err(e) => return e,
+        },
+        None if db.is_some() && !upload.legacy => {
+            return (StatusCode::BAD_REQUEST, Json(json!({"error": "Backup has no signed manifest"})));
+        },
+        None => json!({"entries": []}),
+    };
+    if db.is_some()
+        && let Some(entries) = preview["entries"].as_array_mut()
+    {
+        entries.insert(0, json!({"path": DB_ENTRY, "action": "overwrite", "current_modified": modified_time(DB_PATH)}));
+    }
+    (StatusCode::OK, Json(preview))
+}
+
+pub async fn proxy_restore(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    multipart: Multipart
+) -> impl IntoResponse {
+    if is_debug() { println!("--> [WEB] RESTORE request received"); }
+
+    let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
+    if is_debug() { println!("--> [WEB] Processing RESTORE upload"); }
+    
+    let mut upload = match read_upload(multipart).await {
+        Ok(u) => u,
+        Err(e) => return e,
+    };
+
+    let data = match open_upload(&state, &session, &addr, &headers, &mut upload).await {
+        Ok(d) => d,
+        Err(e) => return e,
+    };
+    let FullBackup { db, secrets } = match split_full_backup(data) {
+        Ok(parts) => parts,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({"error": e}))),
+    };
+
+    let policy = upload.policy.clone().unwrap_or_else(|| "overwrite".to_string());
+    // talos.db (audit logs) has no merge semantics, it is only replaced wholesale
+    let restore_db = policy == "overwrite" && upload.paths.as_ref().is_none_or(|p| p.iter().any(|p| p == DB_ENTRY));
+    let secret_paths: Option<Vec<String>> = upload.paths.as_ref()
+        .map(|p| p.iter().filter(|p| *p != DB_ENTRY).cloned().collect());
+    let mut db = db;
+    let db_digest = match db.as_mut().map(file_digest).transpose() {
+        Ok(digest) => digest,
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": format!("Could not read {}: {}", DB_ENTRY, e)}))),
+    };
+
+    // 1. Secrets first: a rejected archive must not leave a replaced database behind.
+    // Storage also checks talos.db against the signed manifest.
+    let mut result = json!({"status": "System restored. Please refresh."});
+    let restore_secrets = secret_paths.as_ref().is_none_or(|p| !p.is_empty());
+    match secrets {
+        Some(secrets) if restore_secrets => {
+            let mut form = storage_form(secrets, upload.legacy, db_digest.as_ref()).text("policy", policy.clone());
+            if let Some(paths) = &secret_paths {
+                form = form.text("paths", json!(paths).to_string());
+            }
+            match storage_call(&format!("{}/api/restore", storage_url), form).await {
+                Ok(storage) => {
+                    for key in ["restored", "kept_both", "skipped", "snapshot"] {
+                        result[key] = storage[key].clone();
+                    }
+                },
+                Err(e) => return e,
+            }
+        },
+        // Only talos.db was selected; it is verified without restoring any secret
+        Some(secrets) if db.is_some() && restore_db => {
+            let form = storage_form(secrets, upload.legacy, db_digest.as_ref());
+            if let Err(e) = storage_call(&format!("{}/api/restore/preview", storage_url), form).await {
+                return e;
+            }
+        },
+        None if db.is_some() && restore_db && !upload.legacy => {
+            return (StatusCode::BAD_REQUEST, Json(json!({"error": "Backup has no signed manifest"})));
+        },
+        _ => {},
+    }
+
+    // 2. talos.db, through SQLite so the open pool never sees a half-written file
+    if let Some(db) = db {
+        if !restore_db {
+            result["database"] = json!("skipped");
+        } else {
+            match snapshot_db(&state.pool).await {
+                Ok(name) => result["database_snapshot"] = json!(name),
+                Err(e) => {
+                    println!("❌ [WEB] Could not snapshot talos.db: {}", e);
+                    result["error"] = json!("Could not snapshot talos.db before restoring");
+                    return (StatusCode::INTERNAL_SERVER_ERROR, Json(result));
+                }
+            }
+            match restore_db_file(&state.pool, db).await {
+                Ok(()) => {
+                    println!("--> [WEB] talos.db restored successfully");
+                    result["database"] = json!("restored");
+                },
+                Err(e) => {
+                    println!("❌ [WEB] Could not restore talos.db: {}", e);
+                    result["database"] = json!("failed");
+                    result["error"] = json!(format!("Could not restore talos.db: {}", e));
+                    return (StatusCode::INTERNAL_SERVER_ERROR, Json(result));
+                }
+            }
+        }
+    }
+
+    let ua_header = headers.get(header::USER_AGENT);
+    let target = format!("policy={} paths={}", policy, secret_paths.map(|p| p.join(", ")).unwrap_or_else(|| "all".to_string()));
+    log_audit(&state, &session, Some(addr.ip()), ua_header, "RESTORE", &target).await;
+
+    (StatusCode::OK, Json(result))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::peak_alloc::peak_since;
+
+    fn encrypt_archive(plain: &[u8], passphrase: SecretString) -> Vec<u8> {
+        let mut writer = encrypt_writer(Vec::new(), passphrase).unwrap();
+        writer.write_all(plain).unwrap();
+        writer.finish().unwrap()
+    }
+
+    #[test]
+    fn archive_round_trip_and_wrong_passphrase() {
+        let plain = b"PK\x03\x04 not really a zip".to_vec();
+        let encrypted = encrypt_archive(&plain, SecretString::from("correct horse".to_string()));
+        assert!(is_encrypted(&encrypted));
+        assert!(!is_encrypted(&plain));
+
+        let decrypted = decrypt_archive(encrypted.as_slice(), Vec::new(), SecretString::from("correct horse".to_string())).unwrap();
+        assert_eq!(decrypted, plain);
+        assert!(decrypt_archive(encrypted.as_slice(), Vec::new(), SecretString::from("wrong".to_string())).is_err());
+    }
+
+    #[test]
+    fn uploads_over_the_body_limit_are_decrypted_and_split_on_disk() {
+        const MIB: u64 = 1024 * 1024;
+        let passphrase = || SecretString::from("correct horse".to_string());
+        let mut upload = spool_file().unwrap();
+        let encrypted = encrypt_writer(&mut upload, passphrase()).unwrap();
+        write_full_archive(encrypted, io::repeat(0x5A).take(12 * MIB), Some(b"sqlite db".as_slice())).unwrap().finish().unwrap();
+        upload.rewind().unwrap();
+
+        let plain = decrypt_archive(io::BufReader::new(upload), spool_file().unwrap(), passphrase()).unwrap();
+        let FullBackup { db, secrets } = split_full_backup(plain).unwrap();
+        let (size, _) = file_digest(&mut secrets.unwrap()).unwrap();
+        assert_eq!(size, 12 * MIB);
+        let mut content = String::new();
+        db.unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content, "sqlite db");
+    }
+
+    async fn database(path: &Path) -> SqlitePool {
+        let options = SqliteConnectOptions::new().filename(path).create_if_missing(true);
+        let pool = SqlitePoolOptions::new().connect_with(options).await.unwrap();
+        crate::db::migrate(&pool).await;
+        pool
+    }
+
+    async fn usernames(pool: &SqlitePool) -> Vec<String> {
+        sqlx::query_scalar("SELECT username FROM users ORDER BY id").fetch_all(pool).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn database_restores_replace_rows_in_the_open_pool() {
+        let dir = tempfile::tempdir().unwrap();
+        let live = database(&dir.path().join("talos.db")).await;
+        sqlx::query("INSERT INTO users (username, password_hash) VALUES ('current', 'x')").execute(&live).await.unwrap();
+        sqlx::query("INSERT INTO sessions (session_hash, data, expires_at, user_id) VALUES ('h', '{}', 0, 1)").execute(&live).await.unwrap();
+        sqlx::query("INSERT INTO login_failures (scope, key, failures, last_failure_at) VALUES ('ip', '10.0.0.1', 3, 0)").execute(&live).await.unwrap();
+
+        // The backup side, exported the same way proxy_backup does
+        let source = database(&dir.path().join("source.db")).await;
+        sqlx::query("INSERT INTO users (username, password_hash, role) VALUES ('restored', 'y', 'admin')").execute(&source).await.unwrap();
+        sqlx::query("INSERT INTO audit_logs (action, target, user_id) VALUES ('LOGIN', 'restored', 1)").execute(&source).await.unwrap();
+        let exported = dir.path().join("exported.db");
+        export_db(&source, &exported).await.unwrap();
+        source.close().await;
+
+        import_db(&live, &exported).await.unwrap();
+        assert_eq!(usernames(&live).await, ["restored"]);
+        let audit: Vec<String> = sqlx::query_scalar("SELECT action FROM audit_logs").fetch_all(&live).await.unwrap();
+        assert_eq!(audit, ["LOGIN"]);
+        // Sessions of the replaced accounts are gone, throttling is kept
+        let sessions: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sessions").fetch_one(&live).await.unwrap();
+        assert_eq!(sessions, 0);
+        let failures: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM login_failures").fetch_one(&live).await.unwrap();
+        assert_eq!(failures, 1);
+
+        // A damaged upload is an error and changes nothing
+        let damaged = dir.path().join("damaged.db");
+        std::fs::write(&damaged, b"definitely not sqlite").unwrap();
+        assert!(import_db(&live, &damaged).await.is_err());
+        assert_eq!(usernames(&live).await, ["restored"]);
+        let attached: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM pragma_database_list WHERE name = 'restored'").fetch_one(&live).await.unwrap();
+        assert_eq!(attached, 0);
+    }
+
+    // Stands in for a large Storage archive arriving over the network
+    fn generated_archive(len: u64) -> impl Read {
+        io::repeat(0x5A).take(len)
+    }
+
+    #[test]
+    fn full_backup_streams_with_bounded_memory() {
+        const MIB: u64 = 1024 * 1024;
+        let target = tempfile::tempdir().unwrap();
+        let path = target.path().join("talos_full_backup.zip.age");
+        let passphrase = || SecretString::from("correct horse".to_string());
+
+        // Key derivation happens up front; only the streaming part is measured
+        let encrypted = encrypt_writer(io::BufWriter::new(std::fs::File::create(&path).unwrap()), passphrase()).unwrap();
+        let mut written = None;
+        let peak = peak_since(|| {
+            written = Some(write_full_archive(encrypted, generated_archive(96 * MIB), Some(b"sqlite db".as_slice())).unwrap());
+        });
+        written.unwrap().finish().unwrap().flush().unwrap();
+        assert!(peak < 4 * MIB as usize, "backup peaked at {} bytes", peak);
+
+        // The result is a regular age file holding both entries
+        let identity = age::scrypt::Identity::new(passphrase());
+        let decryptor = age::Decryptor::new(io::BufReader::new(std::fs::File::open(&path).unwrap())).unwrap();
+        let reader = decryptor.decrypt(std::iter::once(&identity as &dyn age::Identity)).unwrap();
+        let mut archive = zip::ZipArchive::new(reader).unwrap();
+
+        let mut secrets = archive.by_name("secrets.zip").unwrap();
+        let (mut chunk, mut copied) = (vec![0u8; 64 * 1024], 0u64);
+        loop {
+            let n = secrets.read(&mut chunk).unwrap();
+            if n == 0 { break; }
+            assert!(chunk[..n].iter().all(|b| *b == 0x5A));
+            copied += n as u64;
+        }
+        assert_eq!(copied, 96 * MIB);
+        drop(secrets);
+
+        let mut db = String::new();
+        archive.by_name(DB_ENTRY).unwrap().read_to_string(&mut db).unwrap();
+        assert_eq!(db, "sqlite db");
+    }
+}
@@ -0,0 +1,87 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use data_encoding::BASE64URL_NOPAD;
+use serde_json::Value;
+use std::env;
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+use crate::state::BootstrapToken;
+
+const POLL_INTERVAL_SECONDS: u64 = 5;
+
+// Genesis (creating or importing the master key) is only possible while the
+// Bunker is uninitialized, and anyone who reaches the port first could do it.
+// It therefore also needs a one-time token that only appears in the log: once
+// Storage answers and reports an uninitialized Bunker, one is printed.
+pub fn spawn_issuer(token: BootstrapToken) {
+    tokio::spawn(async move {
+        let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
+        let client = reqwest::Client::new();
+        let mut interval = tokio::time::interval(Duration::from_secs(POLL_INTERVAL_SECONDS));
+        loop {
+            interval.tick().await;
+            let Ok(res) = client.get(format!("{}/api/health", storage_url)).send().await else { continue };
+            let Ok(status) = res.json::<Value>().await else { continue };
+            match status["bunker"].as_str() {
+                Some("UNINITIALIZED") => {
+                    let issued = issue(&token);
+                    println!("🔑 [WEB] System uninitialized. Bootstrap token for genesis (single use):");
+                    println!("🔑 [WEB]     {}", issued);
+                    return;
+                },
+                Some("OFFLINE") | None => continue,
+                // Already initialized: there is nothing to protect
+                Some(_) => return,
+            }
+        }
+    });
+}
+
+fn issue(token: &BootstrapToken) -> String {
+    let mut bytes = [0u8; 24];
+    OsRng.fill_bytes(&mut bytes);
+    let issued = BASE64URL_NOPAD.encode(&bytes);
+    *token.lock().unwrap() = Some(issued.clone());
+    issued
+}
+
+// The token travels in the genesis request body and is not passed on to Storage
+pub fn take_presented(body: &mut Value) -> String {
+    body.as_object_mut()
+        .and_then(|fields| fields.remove("bootstrap_token"))
+        .and_then(|value| value.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+pub fn is_valid(token: &BootstrapToken, presented: &str) -> bool {
+    match token.lock().unwrap().as_deref() {
+        Some(expected) => bool::from(expected.as_bytes().ct_eq(presented.trim().as_bytes())),
+        None => false,
+    }
+}
+
+// After a successful genesis the token is worthless
+pub fn consume(token: &BootstrapToken) {
+    *token.lock().unwrap() = None;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn bootstrap_token_is_single_use() {
+        let token: BootstrapToken = Arc::new(Mutex::new(None));
+        assert!(!is_valid(&token, ""));
+
+        let issued = issue(&token);
+        assert_eq!(issued.len(), 32);
+        assert!(is_valid(&token, &issued));
+        assert!(is_valid(&token, &format!(" {}\n", issued)));
+        assert!(!is_valid(&token, &issued[1..]));
+        assert!(!is_valid(&token, ""));
+
+        consume(&token);
+        assert!(!is_valid(&token, &issued));
+    }
+}
@@ -36,6 +36,184 @@ pub async fn init_db() -> DbPool {
         }
     }
 
+    migrate(&pool).await;
+    pool
+}
+
+// Creates the schema, upgrading databases written by older versions
+pub async fn migrate(pool: &DbPool) {
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS users (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            username TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            password_hash TEXT NOT NULL,
+            role TEXT NOT NULL DEFAULT 'viewer',
+            disabled INTEGER NOT NULL DEFAULT 0,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_login DATETIME
+        )"
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to initialize user schema");
+
+    // Folder grants go to a single user or to every member of a team
+    for statement in [
+        "CREATE TABLE IF NOT EXISTS teams (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        "CREATE TABLE IF NOT EXISTS team_members (
+            team_id INTEGER NOT NULL REFERENCES teams(id) ON DELETE CASCADE,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            PRIMARY KEY (team_id, user_id)
+        )",
+        "CREATE TABLE IF NOT EXISTS grants (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER REFERENCES users(id) ON DELETE CASCADE,
+            team_id INTEGER REFERENCES teams(id) ON DELETE CASCADE,
+            pattern TEXT NOT NULL,
+            access TEXT NOT NULL CHECK (access IN ('read', 'write')),
+            CHECK ((user_id IS NULL) != (team_id IS NULL))
+        )",
+    ] {
+        sqlx::query(statement)
+            .execute(pool)
+            .await
+            .expect("Failed to initialize access schema");
+    }
+
+    // Accounts from before roles: administrators stay administrators, everyone
+    // else edits the whole store as they could before
+    if table_columns(pool, "users").await.iter().any(|c| c == "is_admin") {
+        for statement in [
+            "ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'viewer'",
+            "UPDATE users SET role = CASE WHEN is_admin = 1 THEN 'admin' ELSE 'editor' END",
+            "INSERT INTO grants (user_id, pattern, access) SELECT id, '*', 'write' FROM users WHERE is_admin = 0",
+            "ALTER TABLE users DROP COLUMN is_admin",
+        ] {
+            sqlx::query(statement)
+                .execute(pool)
+                .await
+                .expect("Failed to migrate user schema");
+        }
+    }
+
+    // Second factors: an authenticator secret with single-use recovery codes,
+    // and passkeys
+    for statement in [
+        "CREATE TABLE IF NOT EXISTS user_totp (
+            user_id INTEGER PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+            secret TEXT NOT NULL,
+            enabled INTEGER NOT NULL DEFAULT 0,
+            last_step INTEGER NOT NULL DEFAULT 0,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )",
+        "CREATE TABLE IF NOT EXISTS recovery_codes (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            code_hash TEXT NOT NULL,
+            used_at DATETIME
+        )",
+        // WebAuthn credentials, several per account
+        "CREATE TABLE IF NOT EXISTS webauthn_credentials (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            user_handle TEXT NOT NULL,
+            credential_id TEXT NOT NULL UNIQUE,
+            name TEXT NOT NULL,
+            passkey TEXT NOT NULL,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_used_at DATETIME
+        )",
+    ] {
+        sqlx::query(statement)
+            .execute(pool)
+            .await
+            .expect("Failed to initialize second factor schema");
+    }
+
+    // Personal API tokens, stored as SHA-256 hashes
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS api_tokens (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            name TEXT NOT NULL,
+            token_hash TEXT NOT NULL UNIQUE,
+            access TEXT NOT NULL CHECK (access IN ('read', 'write')),
+            path TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            expires_at DATETIME NOT NULL,
+            last_used_at DATETIME
+        )"
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to initialize token schema");
+
+    // Signed-in sessions, keyed by a hash of the session id
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS sessions (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            session_hash TEXT NOT NULL UNIQUE,
+            data TEXT NOT NULL,
+            expires_at INTEGER NOT NULL,
+            user_id INTEGER REFERENCES users(id) ON DELETE CASCADE,
+            auth_method TEXT,
+            ip_address TEXT,
+            user_agent TEXT,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            last_seen_at DATETIME DEFAULT CURRENT_TIMESTAMP
+        )"
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to initialize session schema");
+
+    // Failed sign-in attempts per client address, account and instance; see `throttle`
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS login_failures (
+            scope TEXT NOT NULL CHECK (scope IN ('ip', 'user', 'global')),
+            key TEXT NOT NULL,
+            failures INTEGER NOT NULL,
+            last_failure_at INTEGER NOT NULL,
+            blocked_until INTEGER NOT NULL DEFAULT 0,
+            PRIMARY KEY (scope, key)
+        )"
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to initialize rate limit schema");
+
+    // Accounts provisioned by single sign-on, by the provider's subject id
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS oidc_identities (
+            issuer TEXT NOT NULL,
+            subject TEXT NOT NULL,
+            user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+            created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (issuer, subject)
+        )"
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to initialize OIDC schema");
+
+    // Single sign-on logins waiting for the provider's redirect back
+    sqlx::query(
+        "CREATE TABLE IF NOT EXISTS oidc_logins (
+            flow_hash TEXT PRIMARY KEY,
+            state TEXT NOT NULL,
+            nonce TEXT NOT NULL,
+            code_verifier TEXT NOT NULL,
+            created_at INTEGER NOT NULL
+        )"
+    )
+    .execute(pool)
+    .await
+    .expect("Failed to initialize OIDC schema");
+
     // Create audit table
     sqlx::query(
         "CREATE TABLE IF NOT EXISTS audit_logs (
@@ -45,12 +223,33 @@ pub async fn init_db() -> DbPool {
             timestamp DATETIME DEFAULT CURRENT_TIMESTAMP,
             ip_address TEXT,
             user_agent TEXT,
-            auth_method TEXT
+            auth_method TEXT,
+            severity TEXT NOT NULL DEFAULT 'info',
+            user_id INTEGER REFERENCES users(id)
         )"
     )
-    .execute(&pool)
+    .execute(pool)
     .await
     .expect("Failed to initialize audit schema");
 
-    pool
-}
\ No newline at end of file
+    // Databases created before audit severities and user accounts existed
+    add_missing_column(pool, "audit_logs", "severity", "TEXT NOT NULL DEFAULT 'info'").await;
+    add_missing_column(pool, "audit_logs", "user_id", "INTEGER REFERENCES users(id)").await;
+}
+
+async fn table_columns(pool: &DbPool, table: &str) -> Vec<String> {
+    sqlx::query_scalar("SELECT name FROM pragma_table_info(?)")
+        .bind(table)
+        .fetch_all(pool)
+        .await
+        .expect("Failed to read schema")
+}
+
+async fn add_missing_column(pool: &DbPool, table: &str, column: &str, definition: &str) {
+    if !table_columns(pool, table).await.iter().any(|c| c == column) {
+        sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+            .execute(pool)
+            .await
+            .expect("Failed to migrate schema");
+    }
+}
@@ -0,0 +1,107 @@
+use axum::Json;
+use axum::body::Bytes;
+use axum::extract::{ConnectInfo, Extension, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use serde_json::json;
+use std::env;
+use std::net::SocketAddr;
+use tower_sessions::Session;
+use zeroize::Zeroize;
+use crate::state::AppState;
+use crate::handlers::{is_debug, log_audit, log_audit_with_severity};
+use crate::policy::Policy;
+use crate::throttle;
+
+// Formats produced by Storage's exporter; "csv" is plaintext and refused
+// unless Storage has `export.allow_plaintext_csv` set
+const FORMATS: [&str; 3] = ["kdbx", "bitwarden", "csv"];
+
+#[derive(Deserialize, Zeroize)]
+#[zeroize(drop)]
+pub struct ExportRequest {
+    // Master key, asked again even though the session is authenticated
+    key: String,
+    format: String,
+    // Protects the exported file
+    #[serde(default)]
+    passphrase: String,
+}
+
+fn error(status: StatusCode, message: &str) -> Response {
+    (status, Json(json!({"error": message}))).into_response()
+}
+
+// Exports every secret in a portable format. The master key is re-verified
+// first, failed attempts count against the sign-in rate limits.
+pub async fn proxy_export(
+    State(state): State<AppState>,
+    session: Session,
+    Extension(policy): Extension<Policy>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<ExportRequest>
+) -> Response {
+    let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
+    if is_debug() { println!("--> [WEB] Processing EXPORT request ({})", req.format); }
+    let ua_header = headers.get(header::USER_AGENT);
+
+    if !FORMATS.contains(&req.format.as_str()) {
+        return error(StatusCode::BAD_REQUEST, &format!("Format must be one of: {}", FORMATS.join(", ")));
+    }
+    let username = policy.user.username.as_str();
+    if let Err(retry_after) = throttle::check(&state.pool, addr.ip(), Some(username)).await {
+        log_audit(&state, &session, Some(addr.ip()), ua_header, "EXPORT_RATE_LIMITED", &req.format).await;
+        return throttle::too_many_attempts(retry_after).into_response();
+    }
+
+    let client = reqwest::Client::new();
+    let verified = client.post(format!("{}/api/unlock/verify", storage_url))
+        .json(&json!({ "key": req.key }))
+        .send().await
+        .is_ok_and(|res| res.status().is_success());
+    if !verified {
+        throttle::fail(&state, &session, addr.ip(), ua_header, Some(username)).await;
+        log_audit_with_severity(&state, &session, Some(addr.ip()), ua_header, "EXPORT_REAUTH_FAILURE", &req.format, "high").await;
+        return error(StatusCode::UNAUTHORIZED, "Invalid Master Key");
+    }
+
+    let response = client.post(format!("{}/api/export", storage_url))
+        .json(&json!({ "format": req.format, "passphrase": req.passphrase }))
+        .send().await;
+    match response {
+        Ok(res) if res.status().is_success() => {
+            let count = res.headers().get("x-export-count").and_then(|v| v.to_str().ok()).unwrap_or("?").to_string();
+            let header_text = |name: &str| res.headers().get(name).and_then(|v| v.to_str().ok()).unwrap_or("").to_string();
+            let content_type = header_text("content-type");
+            let disposition = header_text("content-disposition");
+            let Ok(data) = res.bytes().await else {
+                log_audit_with_severity(&state, &session, Some(addr.ip()), ua_header, "EXPORT_FAILURE", &format!("{}: transfer interrupted", req.format), "high").await;
+                return error(StatusCode::BAD_GATEWAY, "Export transfer interrupted");
+            };
+            log_audit_with_severity(&state, &session, Some(addr.ip()), ua_header, "EXPORT", &format!("{}: {} secrets", req.format, count), "high").await;
+            (
+                [
+                    (header::CONTENT_TYPE, content_type),
+                    (header::CONTENT_DISPOSITION, disposition),
+                    (header::CACHE_CONTROL, "no-store".to_string()),
+                ],
+                data,
+            ).into_response()
+        },
+        Ok(res) => {
+            let status = StatusCode::from_u16(res.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+            let body = res.bytes().await.unwrap_or_default();
+            let reason = serde_json::from_slice::<serde_json::Value>(&body).ok()
+                .and_then(|v| v["error"].as_str().map(str::to_string))
+                .unwrap_or_else(|| "unknown error".to_string());
+            log_audit_with_severity(&state, &session, Some(addr.ip()), ua_header, "EXPORT_FAILURE", &format!("{}: {}", req.format, reason), "high").await;
+            (status, [(header::CONTENT_TYPE, "application/json")], body).into_response()
+        },
+        Err(e) => {
+            println!("❌ [WEB] Storage Export Failed: {}", e);
+            (StatusCode::BAD_GATEWAY, [(header::CONTENT_TYPE, "application/json")], Bytes::from(json!({"error": "Storage node unreachable"}).to_string())).into_response()
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use axum::extract::{ConnectInfo, Request};
+use axum::http::HeaderMap;
+use axum::middleware::Next;
+use axum::response::Response;
+use std::env;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::LazyLock;
+
+// Reverse proxies whose X-Forwarded-For is believed, from TRUSTED_PROXIES
+// (comma separated addresses or CIDR ranges). Without it the header is ignored:
+// anyone can send one.
+static TRUSTED_PROXIES: LazyLock<Vec<Network>> = LazyLock::new(|| {
+    let networks = parse_networks(&env::var("TRUSTED_PROXIES").unwrap_or_default());
+    if !networks.is_empty() {
+        println!("🔒 [WEB] Trusting X-Forwarded-For from {} proxy range(s)", networks.len());
+    }
+    networks
+});
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Network {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl Network {
+    fn parse(value: &str) -> Option<Self> {
+        let (addr, prefix) = match value.split_once('/') {
+            Some((addr, prefix)) => (addr.parse::<IpAddr>().ok()?, Some(prefix.parse::<u8>().ok()?)),
+            None => (value.parse::<IpAddr>().ok()?, None),
+        };
+        let addr = addr.to_canonical();
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        (prefix <= max).then_some(Self { addr, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            },
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            },
+            _ => false,
+        }
+    }
+}
+
+pub fn parse_networks(value: &str) -> Vec<Network> {
+    value.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .filter_map(|entry| {
+            let network = Network::parse(entry);
+            if network.is_none() {
+                println!("⚠️ [WEB] Ignoring invalid TRUSTED_PROXIES entry: {}", entry);
+            }
+            network
+        })
+        .collect()
+}
+
+// Walks X-Forwarded-For from the right, past every trusted proxy, to the
+// first address one of them received the request from. Entries further left
+// were written by the client and prove nothing.
+pub fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted: &[Network]) -> IpAddr {
+    let is_trusted = |ip: IpAddr| trusted.iter().any(|network| network.contains(ip));
+    if !is_trusted(peer) {
+        return peer;
+    }
+    let hops: Vec<&str> = headers.get_all("x-forwarded-for").iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+    let mut client = peer;
+    for hop in hops.iter().rev() {
+        let Ok(ip) = hop.parse::<IpAddr>() else { break };
+        client = ip.to_canonical();
+        if !is_trusted(client) {
+            break;
+        }
+    }
+    client
+}
+
+// Replaces the connection address with the real client's, so rate limits,
+// sessions and the audit log see who is behind the proxy
+pub async fn resolve_client_addr(mut request: Request, next: Next) -> Response {
+    if let Some(ConnectInfo(peer)) = request.extensions().get::<ConnectInfo<SocketAddr>>().copied() {
+        let ip = client_ip(peer.ip(), request.headers(), &TRUSTED_PROXIES);
+        if ip != peer.ip() {
+            request.extensions_mut().insert(ConnectInfo(SocketAddr::new(ip, peer.port())));
+        }
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forwarded(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append("x-forwarded-for", value.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn forwarded_for_is_only_believed_from_trusted_proxies() {
+        let trusted = parse_networks("10.0.0.0/8, 192.0.2.10 ,fd00::/8, bogus, 10.0.0.0/33");
+        assert_eq!(trusted.len(), 3);
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+        // A direct client cannot pick its own address
+        let spoofed = forwarded(&["203.0.113.9"]);
+        assert_eq!(client_ip(ip("198.51.100.4"), &spoofed, &trusted), ip("198.51.100.4"));
+        assert_eq!(client_ip(ip("198.51.100.4"), &spoofed, &[]), ip("198.51.100.4"));
+
+        // Behind a chain of proxies, the first untrusted hop is the client
+        assert_eq!(client_ip(ip("10.1.2.3"), &forwarded(&["198.51.100.4"]), &trusted), ip("198.51.100.4"));
+        let chain = forwarded(&["203.0.113.9, 198.51.100.4", "192.0.2.10"]);
+        assert_eq!(client_ip(ip("::ffff:10.1.2.3"), &chain, &trusted), ip("198.51.100.4"));
+        assert_eq!(client_ip(ip("fd12::1"), &forwarded(&["2001:db8::7"]), &trusted), ip("2001:db8::7"));
+
+        // Without a usable header the proxy itself is all there is to go on
+        assert_eq!(client_ip(ip("10.1.2.3"), &HeaderMap::new(), &trusted), ip("10.1.2.3"));
+        assert_eq!(client_ip(ip("10.1.2.3"), &forwarded(&["unknown, 10.9.9.9"]), &trusted), ip("10.9.9.9"));
+        assert_eq!(client_ip(ip("10.1.2.3"), &forwarded(&["10.9.9.9"]), &trusted), ip("10.9.9.9"));
+
+        let all = parse_networks("0.0.0.0/0");
+        assert!(all[0].contains(ip("203.0.113.9")));
+        assert!(!all[0].contains(ip("2001:db8::7")));
+    }
+}
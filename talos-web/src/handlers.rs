@@ -1,19 +1,17 @@
 use axum::Json;
-use axum::extract::{ConnectInfo, Multipart, State};
+use axum::extract::{ConnectInfo, Extension, Query, RawQuery, State};
 use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
 use axum::response::IntoResponse;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::env;
-use std::io::{Cursor, Write};
 use std::net::SocketAddr;
-use std::sync::{Arc, Mutex};
 use tower_sessions::Session;
-use zip::write::FileOptions;
+use crate::policy::{Access, Policy, authorize, deny};
+use crate::bootstrap;
 use crate::state::AppState;
-use crate::auth::validate_csrf_token;
 
-fn is_debug() -> bool {
+pub(crate) fn is_debug() -> bool {
     env::var("DEBUG").unwrap_or_default() == "true"
 }
 
@@ -39,10 +37,75 @@ pub async fn health_check() -> Json<Value> {
     }
 }
 
-pub async fn proxy_list_tree() -> impl IntoResponse {
+#[derive(Deserialize)]
+pub struct TreeQuery {
+    tag: Option<String>,
+}
+
+// Only the parts of the tree the caller may read are returned
+pub async fn proxy_list_tree(session: Session, Extension(policy): Extension<Policy>, Query(query): Query<TreeQuery>) -> impl IntoResponse {
     let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
     if is_debug() { println!("--> [WEB] Proxying LIST TREE"); }
-    proxy_request(&format!("{}/api/tree", storage_url), None).await
+
+    // Favourites are per user, so the user always comes from the session
+    let mut params = vec![("user", session_actor(&session).await)];
+    if let Some(tag) = query.tag.filter(|t| !t.is_empty()) {
+        params.push(("tag", tag));
+    }
+    let url = match reqwest::Url::parse_with_params(&format!("{}/api/tree", storage_url), &params) {
+        Ok(url) => url,
+        Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({"error": "Invalid storage URL"}))),
+    };
+    match proxy_request(url.as_str(), None).await {
+        (status, Json(Value::Array(nodes))) if status.is_success() => (status, Json(Value::Array(policy.filter_tree(nodes)))),
+        response => response,
+    }
+}
+
+pub async fn proxy_search(Extension(policy): Extension<Policy>, RawQuery(query): RawQuery) -> impl IntoResponse {
+    let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
+    if is_debug() { println!("--> [WEB] Proxying SEARCH"); }
+    match proxy_request(&format!("{}/api/search?{}", storage_url, query.unwrap_or_default()), None).await {
+        (status, Json(Value::Array(hits))) if status.is_success() => {
+            let hits = hits.into_iter().filter(|hit| policy.allows(Access::Read, hit["path"].as_str().unwrap_or_default())).collect();
+            (status, Json(Value::Array(hits)))
+        },
+        response => response,
+    }
+}
+
+pub async fn proxy_history(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Extension(policy): Extension<Policy>,
+    RawQuery(query): RawQuery
+) -> impl IntoResponse {
+    let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
+    if is_debug() { println!("--> [WEB] Proxying HISTORY"); }
+    let query = query.unwrap_or_default();
+    let path = url::form_urlencoded::parse(query.as_bytes())
+        .find(|(key, _)| key == "path")
+        .map(|(_, value)| value.into_owned())
+        .unwrap_or_default();
+    if let Err(denied) = authorize(&state, &session, addr, &headers, &policy, Access::Read, "HISTORY", &[&path]).await {
+        return denied;
+    }
+    proxy_request(&format!("{}/api/history?{}", storage_url, query), None).await
+}
+
+pub async fn proxy_rebuild_search_index(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> impl IntoResponse {
+    let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
+    if is_debug() { println!("--> [WEB] Proxying SEARCH INDEX REBUILD"); }
+    let ua_header = headers.get(header::USER_AGENT);
+    log_audit(&state, &session, Some(addr.ip()), ua_header, "SEARCH_REBUILD", "search_index").await;
+    proxy_request(&format!("{}/api/search/rebuild", storage_url), Some(json!({}))).await
 }
 
 pub async fn log_audit(
@@ -53,22 +116,57 @@ pub async fn log_audit(
     action: &str,
     target: &str,
 ) {
-    let auth_method: Option<String> = session.get("auth_method").await.unwrap_or(None);
+    log_audit_with_severity(state, session, ip, user_agent, action, target, "info").await;
+}
+
+// Events that expose the whole vault (exports) are recorded as "high" so
+// they stand out in the audit log.
+pub async fn log_audit_with_severity(
+    state: &AppState,
+    session: &Session,
+    ip: Option<std::net::IpAddr>,
+    user_agent: Option<&HeaderValue>,
+    action: &str,
+    target: &str,
+    severity: &str,
+) {
+    let (auth_method, user_id): (Option<String>, Option<i64>) = match crate::tokens::current() {
+        Some(token) => (Some(format!("token:{}", token.name)), Some(token.user_id)),
+        None => (
+            session.get("auth_method").await.unwrap_or(None),
+            session.get(crate::users::SESSION_USER_ID).await.unwrap_or(None),
+        ),
+    };
     let ip_str = ip.map(|i| i.to_string());
     let ua_str = user_agent.and_then(|ua| ua.to_str().ok());
 
     let _ = sqlx::query(
-        "INSERT INTO audit_logs (action, target, ip_address, user_agent, auth_method) VALUES (?, ?, ?, ?, ?)",
+        "INSERT INTO audit_logs (action, target, ip_address, user_agent, auth_method, severity, user_id) VALUES (?, ?, ?, ?, ?, ?, ?)",
     )
         .bind(action)
         .bind(target)
         .bind(ip_str)
         .bind(ua_str)
         .bind(auth_method)
+        .bind(severity)
+        .bind(user_id)
         .execute(&state.pool)
         .await;
 }
 
+// Identity recorded by Storage for operations such as deletes
+pub async fn session_actor(session: &Session) -> String {
+    if let Some(token) = crate::tokens::current() {
+        return token.username;
+    }
+    if let Ok(Some(username)) = session.get::<String>(crate::users::SESSION_USERNAME).await {
+        return username;
+    }
+    session.get::<String>("auth_method").await
+        .unwrap_or(None)
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
 #[derive(Serialize, sqlx::FromRow)]
 pub struct AuditLogEntry {
     id: i64,
@@ -78,11 +176,15 @@ pub struct AuditLogEntry {
     ip_address: Option<String>,
     user_agent: Option<String>,
     auth_method: Option<String>,
+    severity: String,
+    user_id: Option<i64>,
+    username: Option<String>,
 }
 
 pub async fn get_audit_logs(State(state): State<AppState>) -> Json<Vec<AuditLogEntry>> {
     let logs = sqlx::query_as::<_, AuditLogEntry>(
-        "SELECT id, action, target, timestamp, ip_address, user_agent, auth_method FROM audit_logs ORDER BY id DESC LIMIT 100",
+        "SELECT a.id, a.action, a.target, a.timestamp, a.ip_address, a.user_agent, a.auth_method, a.severity, a.user_id, u.username
+         FROM audit_logs a LEFT JOIN users u ON u.id = a.user_id ORDER BY a.id DESC LIMIT 100",
     )
         .fetch_all(&state.pool)
         .await
@@ -95,16 +197,39 @@ pub async fn proxy_decrypt(
     session: Session,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
+    Extension(policy): Extension<Policy>,
     Json(body): Json<Value>
 ) -> impl IntoResponse {
     let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
     if is_debug() { println!("--> [WEB] Proxying DECRYPT"); }
     
     let path = body["path"].as_str().unwrap_or("unknown");
+    if let Err(denied) = authorize(&state, &session, addr, &headers, &policy, Access::Read, "DECRYPT", &[path]).await {
+        return denied.into_response();
+    }
     let ua_header = headers.get(header::USER_AGENT);
     log_audit(&state, &session, Some(addr.ip()), ua_header, "DECRYPT", path).await;
 
-    proxy_request(&format!("{}/api/decrypt", storage_url), Some(body)).await
+    // Forward the version token so the client can send it back as If-Match on save
+    let client = reqwest::Client::new();
+    match client.post(format!("{}/api/decrypt", storage_url)).json(&body).send().await {
+        Ok(res) => {
+            let status = StatusCode::from_u16(res.status().as_u16()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+            let etag = res.headers().get(reqwest::header::ETAG)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| HeaderValue::from_str(v).ok());
+            let data = res.json::<Value>().await.unwrap_or_else(|_| json!({"error": "Invalid node response"}));
+            let mut response = (status, Json(data)).into_response();
+            if let Some(etag) = etag {
+                response.headers_mut().insert(header::ETAG, etag);
+            }
+            response
+        },
+        Err(e) => {
+            println!("❌ [WEB] Node Unreachable: {}", e);
+            (StatusCode::BAD_GATEWAY, Json(json!({"error": "Node unreachable"}))).into_response()
+        }
+    }
 }
 
 pub async fn proxy_save(
@@ -112,10 +237,25 @@ pub async fn proxy_save(
     session: Session,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
-    Json(body): Json<Value>
+    Extension(policy): Extension<Policy>,
+    Json(mut body): Json<Value>
 ) -> impl IntoResponse {
     let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
     if is_debug() { println!("--> [WEB] Proxying SAVE"); }
+
+    // A rename writes the new path and removes the original one
+    let mut paths = vec![body["path"].as_str().unwrap_or("unknown")];
+    paths.extend(body["original_path"].as_str().filter(|p| !p.is_empty()));
+    if let Err(denied) = authorize(&state, &session, addr, &headers, &policy, Access::Write, "SAVE", &paths).await {
+        return denied;
+    }
+
+    // If-Match header takes the place of an explicit expected_version in the body
+    if body.get("expected_version").is_none()
+        && let Some(version) = headers.get(header::IF_MATCH).and_then(|v| v.to_str().ok())
+    {
+        body["expected_version"] = json!(version);
+    }
     
     let path = body["path"].as_str().unwrap_or("unknown");
     let ua_header = headers.get(header::USER_AGENT);
@@ -129,184 +269,220 @@ pub async fn proxy_delete(
     session: Session,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
-    Json(body): Json<Value>
+    Extension(policy): Extension<Policy>,
+    Json(mut body): Json<Value>
 ) -> impl IntoResponse {
     let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
     if is_debug() { println!("--> [WEB] Proxying DELETE"); }
+
+    body["actor"] = json!(session_actor(&session).await);
     
-    let path = body["path"].as_str().unwrap_or("unknown");
+    let path = body["path"].as_str().unwrap_or("unknown").to_string();
+    // For a folder this needs a grant covering the folder itself, i.e. everything in it
+    if let Err(denied) = authorize(&state, &session, addr, &headers, &policy, Access::Write, "DELETE", &[&path]).await {
+        return denied;
+    }
     let ua_header = headers.get(header::USER_AGENT);
-    log_audit(&state, &session, Some(addr.ip()), ua_header, "DELETE", path).await;
 
-    proxy_request(&format!("{}/api/delete", storage_url), Some(body)).await
+    if !body["recursive"].as_bool().unwrap_or(false) {
+        log_audit(&state, &session, Some(addr.ip()), ua_header, "DELETE", &path).await;
+        return proxy_request(&format!("{}/api/delete", storage_url), Some(body)).await;
+    }
+
+    // Recursive deletes record every secret that was removed
+    let (status, Json(data)) = proxy_request(&format!("{}/api/delete", storage_url), Some(body)).await;
+    if status.is_success() {
+        let deleted: Vec<&str> = data["deleted"].as_array()
+            .map(|a| a.iter().filter_map(|v| v.as_str()).collect())
+            .unwrap_or_default();
+        let target = format!("{} [{} secrets: {}]", path, deleted.len(), deleted.join(", "));
+        log_audit(&state, &session, Some(addr.ip()), ua_header, "DELETE_RECURSIVE", &target).await;
+    } else {
+        log_audit(&state, &session, Some(addr.ip()), ua_header, "DELETE_RECURSIVE_FAILURE", &path).await;
+    }
+    (status, Json(data))
 }
 
-pub async fn proxy_create_category(
+pub async fn proxy_move(
     State(state): State<AppState>,
     session: Session,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
+    Extension(policy): Extension<Policy>,
     Json(body): Json<Value>
 ) -> impl IntoResponse {
     let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
-    if is_debug() { println!("--> [WEB] Proxying CREATE CATEGORY"); }
+    if is_debug() { println!("--> [WEB] Proxying MOVE"); }
 
-    let path = body["path"].as_str().unwrap_or("unknown");
+    let paths = [body["from"].as_str().unwrap_or("unknown"), body["to"].as_str().unwrap_or("unknown")];
+    if let Err(denied) = authorize(&state, &session, addr, &headers, &policy, Access::Write, "MOVE", &paths).await {
+        return denied;
+    }
+
+    let target = format!(
+        "{} -> {}",
+        body["from"].as_str().unwrap_or("unknown"),
+        body["to"].as_str().unwrap_or("unknown")
+    );
     let ua_header = headers.get(header::USER_AGENT);
-    log_audit(&state, &session, Some(addr.ip()), ua_header, "CREATE_CATEGORY", path).await;
+    log_audit(&state, &session, Some(addr.ip()), ua_header, "MOVE", &target).await;
 
-    proxy_request(&format!("{}/api/create_category", storage_url), Some(body)).await
+    proxy_request(&format!("{}/api/move", storage_url), Some(body)).await
 }
 
-pub async fn proxy_initialize(
+pub async fn proxy_update_metadata(
     State(state): State<AppState>,
-    session: Session, // Empty session, but needed for signature
+    session: Session,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
-    Json(body): Json<Value>
+    Extension(policy): Extension<Policy>,
+    Json(mut body): Json<Value>
 ) -> impl IntoResponse {
     let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
-    if is_debug() { println!("--> [WEB] Proxying INITIALIZE"); }
+    if is_debug() { println!("--> [WEB] Proxying METADATA UPDATE"); }
+
+    body["actor"] = json!(session_actor(&session).await);
+
+    let path = body["path"].as_str().unwrap_or("unknown").to_string();
+    // Favourites are per user and only need read access, tags are shared
+    let access = if body["tags"].is_array() { Access::Write } else { Access::Read };
+    if let Err(denied) = authorize(&state, &session, addr, &headers, &policy, access, "META_UPDATE", &[&path]).await {
+        return denied;
+    }
+    let mut changes = Vec::new();
+    if let Some(tags) = body["tags"].as_array() {
+        let tags: Vec<&str> = tags.iter().filter_map(|t| t.as_str()).collect();
+        changes.push(format!("tags=[{}]", tags.join(", ")));
+    }
+    if let Some(favourite) = body["favourite"].as_bool() {
+        changes.push(format!("favourite={}", favourite));
+    }
+    let target = format!("{} {}", path, changes.join(" "));
     let ua_header = headers.get(header::USER_AGENT);
-    log_audit(&state, &session, Some(addr.ip()), ua_header, "INITIALIZE", "system").await;
-    proxy_request(&format!("{}/api/initialize", storage_url), Some(body)).await
+    log_audit(&state, &session, Some(addr.ip()), ua_header, "META_UPDATE", target.trim_end()).await;
+
+    proxy_request(&format!("{}/api/meta", storage_url), Some(body)).await
+}
+
+pub async fn proxy_list_trash(Extension(policy): Extension<Policy>) -> impl IntoResponse {
+    let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
+    if is_debug() { println!("--> [WEB] Proxying LIST TRASH"); }
+    match proxy_request(&format!("{}/api/trash", storage_url), None).await {
+        (status, Json(Value::Array(entries))) if status.is_success() => {
+            let entries = entries.into_iter().filter(|e| policy.allows(Access::Read, e["original_path"].as_str().unwrap_or_default())).collect();
+            (status, Json(Value::Array(entries)))
+        },
+        response => response,
+    }
+}
+
+// Where a trash entry was deleted from, which decides who may restore or purge it
+async fn trash_original_path(storage_url: &str, id: &str) -> Option<String> {
+    let (_, Json(entries)) = proxy_request(&format!("{}/api/trash", storage_url), None).await;
+    entries.as_array()?.iter()
+        .find(|e| e["id"].as_str() == Some(id))
+        .and_then(|e| e["original_path"].as_str().map(str::to_string))
 }
 
-pub async fn proxy_backup(
+pub async fn proxy_trash_restore(
     State(state): State<AppState>,
     session: Session,
     ConnectInfo(addr): ConnectInfo<SocketAddr>,
     headers: HeaderMap,
+    Extension(policy): Extension<Policy>,
+    Json(body): Json<Value>
 ) -> impl IntoResponse {
     let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
-    if is_debug() { println!("--> [WEB] Proxying BACKUP download"); }
-    
-    let client = reqwest::Client::new();
-
-    let ua_header = headers.get(header::USER_AGENT);
-    log_audit(&state, &session, Some(addr.ip()), ua_header, "BACKUP", "full_system").await;
-    
-    // 1. Obtener el backup de secretos (ZIP) del Storage
-    match client.get(format!("{}/api/backup", storage_url)).send().await {
-        Ok(res) => {
-            let secrets_zip_bytes = res.bytes().await.unwrap_or_default();
-            
-            // 2. Crear un nuevo ZIP maestro en memoria
-            let mut buf = Vec::new();
-            {
-                let mut zip_writer = zip::ZipWriter::new(Cursor::new(&mut buf));
-                let options = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
-
-                // A. Añadir secrets.zip
-                let _ = zip_writer.start_file("secrets.zip", options);
-                let _ = zip_writer.write_all(&secrets_zip_bytes);
-
-                // The /data/talos.db path comes from docker-compose
-                if let Ok(db_content) = std::fs::read("/data/talos.db") {
-                    let _ = zip_writer.start_file("talos.db", options);
-                    let _ = zip_writer.write_all(&db_content);
-                } else {
-                    println!("⚠️ [WEB] Could not read talos.db for backup");
-                }
-                
-                let _ = zip_writer.finish();
-            }
-
-            (StatusCode::OK, [(header::CONTENT_TYPE, "application/zip"), (header::CONTENT_DISPOSITION, "attachment; filename=\"talos_full_backup.zip\"")], axum::body::Bytes::from(buf)).into_response()
-        },
-        Err(_) => {
-            (StatusCode::BAD_GATEWAY, [(header::CONTENT_TYPE, "text/plain")], axum::body::Bytes::from("Error fetching backup")).into_response()
+    if is_debug() { println!("--> [WEB] Proxying TRASH RESTORE"); }
+
+    let target = body["id"].as_str().unwrap_or("unknown");
+    if !policy.covers_store(Access::Write) {
+        let Some(original) = trash_original_path(&storage_url, target).await else {
+            return (StatusCode::NOT_FOUND, Json(json!({"error": "Trash entry not found"})));
+        };
+        let mut paths = vec![original.as_str()];
+        paths.extend(body["path"].as_str().filter(|p| !p.is_empty()));
+        if let Err(denied) = authorize(&state, &session, addr, &headers, &policy, Access::Write, "TRASH_RESTORE", &paths).await {
+            return denied;
         }
     }
+    let ua_header = headers.get(header::USER_AGENT);
+    log_audit(&state, &session, Some(addr.ip()), ua_header, "TRASH_RESTORE", target).await;
+
+    proxy_request(&format!("{}/api/trash/restore", storage_url), Some(body)).await
 }
 
-pub async fn proxy_restore(
+pub async fn proxy_trash_purge(
+    State(state): State<AppState>,
     session: Session,
-    mut multipart: Multipart
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Extension(policy): Extension<Policy>,
+    Json(body): Json<Value>
 ) -> impl IntoResponse {
-    if is_debug() { println!("--> [WEB] RESTORE request received"); }
-
     let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
-    if is_debug() { println!("--> [WEB] Processing RESTORE upload"); }
-    
-    // Validate CSRF token for state-changing operation
-    let mut csrf_token = None;
-    let mut backup_data = None;
-    
-    while let Ok(Some(field)) = multipart.next_field().await {
-        if field.name() == Some("csrf_token") {
-            csrf_token = field.text().await.ok();
-        } else if field.name() == Some("backup") {
-            backup_data = Some(match field.bytes().await {
-                Ok(b) => b,
-                Err(_) => return (StatusCode::BAD_REQUEST, Json(json!({"error": "Failed to read backup data"}))),
-            });
-        }
-    }
-    
-    if let Some(token) = csrf_token.as_ref() {
-        if let Err(_) = validate_csrf_token(&session, token).await {
-            return (StatusCode::UNAUTHORIZED, Json(json!({"error": "CSRF token validation failed"})));
-        }
-    } else {
-        return (StatusCode::UNAUTHORIZED, Json(json!({"error": "CSRF token required"})));
-    }
-    
-    let data = match backup_data {
-        Some(d) => d,
-        None => return (StatusCode::BAD_REQUEST, Json(json!({"error": "No backup file provided"}))),
-    };
-    
-    // 1. Intentar abrir el ZIP
-    let reader = Cursor::new(&data);
-    let mut archive = match zip::ZipArchive::new(reader) {
-        Ok(a) => a,
-        Err(_) => return (StatusCode::BAD_REQUEST, Json(json!({"error": "Invalid zip file"}))),
-    };
+    if is_debug() { println!("--> [WEB] Proxying TRASH PURGE"); }
 
-    let mut secrets_payload: Option<Vec<u8>> = None;
-    let mut db_restored = false;
-
-    // 2. Find and restore talos.db (audit logs)
-    if let Ok(mut db_file) = archive.by_name("talos.db") {
-        let mut buf = Vec::new();
-        if std::io::copy(&mut db_file, &mut buf).is_ok() {
-            // Overwrite the local DB.
-            // NOTE: In a high-concurrency environment this is risky,
-            // but for a personal tool it is acceptable.
-            if std::fs::write("/data/talos.db", buf).is_ok() {
-                println!("--> [WEB] talos.db restored successfully");
-                db_restored = true;
-            }
+    let target = if body["all"].as_bool().unwrap_or(false) { "all" } else { body["id"].as_str().unwrap_or("unknown") };
+    // Emptying the whole trash is for administrators, single entries need write access
+    if !policy.covers_store(Access::Write) {
+        if target == "all" {
+            return deny(&state, &session, addr, &headers, "TRASH_PURGE", "all").await;
         }
-    }
-
-    // 3. Find secrets.zip (The Storage backup)
-    if let Ok(mut secrets_file) = archive.by_name("secrets.zip") {
-        let mut buf = Vec::new();
-        if std::io::copy(&mut secrets_file, &mut buf).is_ok() {
-            secrets_payload = Some(buf);
+        let Some(original) = trash_original_path(&storage_url, target).await else {
+            return (StatusCode::NOT_FOUND, Json(json!({"error": "Trash entry not found"})));
+        };
+        if let Err(denied) = authorize(&state, &session, addr, &headers, &policy, Access::Write, "TRASH_PURGE", &[&original]).await {
+            return denied;
         }
     }
+    let ua_header = headers.get(header::USER_AGENT);
+    log_audit(&state, &session, Some(addr.ip()), ua_header, "TRASH_PURGE", target).await;
 
-    // 4. Determine what to send to Storage
-    // If there's no secrets.zip or talos.db, we assume it's an old (Legacy) backup containing only secrets
-    let payload_to_send = secrets_payload.unwrap_or_else(|| {
-        if db_restored { Vec::new() } else { data.to_vec() }
-    });
+    proxy_request(&format!("{}/api/trash/purge", storage_url), Some(body)).await
+}
 
-    if !payload_to_send.is_empty() {
-        let client = reqwest::Client::new();
-        let part = reqwest::multipart::Part::bytes(payload_to_send).file_name("backup.zip");
-        let form = reqwest::multipart::Form::new().part("backup", part);
+pub async fn proxy_create_category(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Extension(policy): Extension<Policy>,
+    Json(body): Json<Value>
+) -> impl IntoResponse {
+    let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
+    if is_debug() { println!("--> [WEB] Proxying CREATE CATEGORY"); }
 
-        if let Err(e) = client.post(format!("{}/api/restore", storage_url)).multipart(form).send().await {
-             println!("❌ [WEB] Storage Restore Failed: {}", e);
-             return (StatusCode::BAD_GATEWAY, Json(json!({"error": "Storage node unreachable"})));
-        }
+    let path = body["path"].as_str().unwrap_or("unknown");
+    if let Err(denied) = authorize(&state, &session, addr, &headers, &policy, Access::Write, "CREATE_CATEGORY", &[path]).await {
+        return denied;
     }
+    let ua_header = headers.get(header::USER_AGENT);
+    log_audit(&state, &session, Some(addr.ip()), ua_header, "CREATE_CATEGORY", path).await;
+
+    proxy_request(&format!("{}/api/create_category", storage_url), Some(body)).await
+}
 
-    return (StatusCode::OK, Json(json!({"status": "System restored. Please refresh."})))
+pub async fn proxy_initialize(
+    State(state): State<AppState>,
+    session: Session, // Empty session, but needed for signature
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(mut body): Json<Value>
+) -> impl IntoResponse {
+    let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
+    if is_debug() { println!("--> [WEB] Proxying INITIALIZE"); }
+    let ua_header = headers.get(header::USER_AGENT);
+    if !bootstrap::is_valid(&state.bootstrap_token, &bootstrap::take_presented(&mut body)) {
+        log_audit_with_severity(&state, &session, Some(addr.ip()), ua_header, "INITIALIZE_DENIED", "system", "warning").await;
+        return (StatusCode::FORBIDDEN, Json(json!({"error": "Invalid bootstrap token"})));
+    }
+    log_audit(&state, &session, Some(addr.ip()), ua_header, "INITIALIZE", "system").await;
+    let (status, response) = proxy_request(&format!("{}/api/initialize", storage_url), Some(body)).await;
+    if status.is_success() {
+        bootstrap::consume(&state.bootstrap_token);
+    }
+    (status, response)
 }
 
 async fn proxy_request(url: &str, body: Option<Value>) -> (StatusCode, Json<Value>) {
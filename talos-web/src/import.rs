@@ -0,0 +1,125 @@
+use axum::Json;
+use axum::extract::{ConnectInfo, Extension, Multipart, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::IntoResponse;
+use axum::body::Bytes;
+use age::secrecy::{ExposeSecret, SecretString};
+use serde_json::{json, Value};
+use std::env;
+use std::net::SocketAddr;
+use tower_sessions::Session;
+use crate::state::AppState;
+use crate::handlers::{is_debug, log_audit};
+use crate::policy::{Access, Policy, authorize, deny};
+
+// Formats understood by Storage's importer
+const FORMATS: [&str; 5] = ["keepass", "bitwarden", "1password", "chrome", "firefox"];
+
+struct ImportUpload {
+    format: Option<String>,
+    file: Option<(String, Bytes)>,
+    // KeePass master password
+    password: Option<SecretString>,
+    policy: Option<String>,
+    prefix: Option<String>,
+    dry_run: bool,
+}
+
+async fn read_upload(mut multipart: Multipart) -> Result<ImportUpload, (StatusCode, Json<Value>)> {
+    let mut upload = ImportUpload { format: None, file: None, password: None, policy: None, prefix: None, dry_run: false };
+    while let Ok(Some(field)) = multipart.next_field().await {
+        match field.name() {
+            Some("format") => upload.format = field.text().await.ok(),
+            Some("password") => upload.password = field.text().await.ok().filter(|p| !p.is_empty()).map(SecretString::from),
+            Some("policy") => upload.policy = field.text().await.ok().filter(|p| !p.is_empty()),
+            Some("prefix") => upload.prefix = field.text().await.ok().filter(|p| !p.is_empty()),
+            Some("dry_run") => upload.dry_run = field.text().await.map(|v| v == "true").unwrap_or(false),
+            Some("file") => {
+                let name = field.file_name().unwrap_or("import").to_string();
+                let data = field.bytes().await
+                    .map_err(|_| (StatusCode::BAD_REQUEST, Json(json!({"error": "Failed to read import file"}))))?;
+                upload.file = Some((name, data));
+            },
+            _ => {},
+        }
+    }
+    Ok(upload)
+}
+
+// Imports a KeePass, Bitwarden, 1Password or browser export through Storage.
+// `dry_run` returns the per-entry report without writing anything.
+pub async fn proxy_import(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Extension(policy): Extension<Policy>,
+    multipart: Multipart
+) -> impl IntoResponse {
+    let storage_url = env::var("STORAGE_URL").unwrap_or_else(|_| "http://talos-storage:4000".to_string());
+    if is_debug() { println!("--> [WEB] Processing IMPORT upload"); }
+
+    let upload = match read_upload(multipart).await {
+        Ok(u) => u,
+        Err(e) => return e,
+    };
+    let Some(format) = upload.format.filter(|f| FORMATS.contains(&f.as_str())) else {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": format!("Format must be one of: {}", FORMATS.join(", "))})));
+    };
+    let Some((file_name, data)) = upload.file else {
+        return (StatusCode::BAD_REQUEST, Json(json!({"error": "No import file provided"})));
+    };
+
+    // Anyone but an administrator imports into a folder they may write to.
+    // Storage refuses a folder it would have to rename, so the one checked
+    // here is the one written to.
+    if !policy.covers_store(Access::Write) {
+        let Some(prefix) = upload.prefix.as_deref() else {
+            return deny(&state, &session, addr, &headers, "IMPORT", "store root").await;
+        };
+        if let Err(denied) = authorize(&state, &session, addr, &headers, &policy, Access::Write, "IMPORT", &[prefix]).await {
+            return denied;
+        }
+    }
+
+    let mut form = reqwest::multipart::Form::new()
+        .text("format", format.clone())
+        .text("dry_run", upload.dry_run.to_string());
+    if let Some(password) = &upload.password {
+        form = form.text("password", password.expose_secret().to_string());
+    }
+    if let Some(policy) = upload.policy {
+        form = form.text("policy", policy);
+    }
+    if let Some(prefix) = upload.prefix {
+        form = form.text("prefix", prefix);
+    }
+    form = form.part("file", reqwest::multipart::Part::bytes(data.to_vec()).file_name(file_name.clone()));
+
+    let ua_header = headers.get(header::USER_AGENT);
+    let response = reqwest::Client::new().post(format!("{}/api/import", storage_url)).multipart(form).send().await;
+    let (status, result) = match response {
+        Ok(res) => {
+            let status = StatusCode::from_u16(res.status().as_u16()).unwrap_or(StatusCode::BAD_GATEWAY);
+            (status, res.json::<Value>().await.unwrap_or_else(|_| json!({"error": "Import failed"})))
+        },
+        Err(e) => {
+            println!("❌ [WEB] Storage Import Failed: {}", e);
+            (StatusCode::BAD_GATEWAY, json!({"error": "Storage node unreachable"}))
+        }
+    };
+
+    if upload.dry_run {
+        return (status, Json(result));
+    }
+    if status.is_success() {
+        let summary = &result["summary"];
+        let target = format!("{} ({}): {} created, {} overwritten, {} renamed, {} skipped, {} failed",
+            format, file_name, summary["created"], summary["overwritten"], summary["renamed"], summary["skipped"], summary["failed"]);
+        log_audit(&state, &session, Some(addr.ip()), ua_header, "IMPORT", &target).await;
+    } else {
+        let target = format!("{} ({}): {}", format, file_name, result["error"].as_str().unwrap_or("unknown error"));
+        log_audit(&state, &session, Some(addr.ip()), ua_header, "IMPORT_FAILURE", &target).await;
+    }
+    (status, Json(result))
+}
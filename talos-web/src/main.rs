@@ -2,17 +2,47 @@ mod handlers;
 mod db;
 mod auth;
 mod state;
+mod backup;
+mod import;
+mod export;
+mod users;
+mod policy;
+mod totp;
+mod passkeys;
+mod tokens;
+mod sessions;
+mod bootstrap;
+mod throttle;
+mod forwarded;
+mod oidc;
+#[path = "../../shared/backup_stream.rs"]
+mod backup_stream;
+#[cfg(test)]
+#[path = "../../test-support/peak_alloc.rs"]
+mod peak_alloc;
 
-use axum::{routing::{get, post}, Router, middleware};
-use tower_sessions::{Expiry, MemoryStore, SessionManagerLayer};
+use axum::{extract::DefaultBodyLimit, routing::{get, post}, Router, middleware};
+use tower_sessions::{Expiry, SessionManagerLayer, SessionStore};
 use time::Duration;
 use tower_http::services::ServeDir;
 use tower_http::compression::CompressionLayer;
 use tower_http::limit::RequestBodyLimitLayer;
-use std::{env, net::SocketAddr, collections::HashMap, sync::{Arc, Mutex}};
-use crate::handlers::{get_version, proxy_list_tree, proxy_decrypt, proxy_save, proxy_delete, proxy_backup, proxy_restore, health_check, proxy_create_category, get_audit_logs, proxy_initialize};
+use std::{env, net::SocketAddr, sync::{Arc, Mutex}};
+use crate::handlers::{get_version, proxy_list_tree, proxy_search, proxy_history, proxy_rebuild_search_index, proxy_decrypt, proxy_save, proxy_delete, proxy_move, proxy_update_metadata, proxy_list_trash, proxy_trash_restore, proxy_trash_purge, health_check, proxy_create_category, get_audit_logs, proxy_initialize};
+use crate::backup::{proxy_backup, proxy_restore, proxy_restore_preview};
+use crate::import::proxy_import;
+use crate::export::proxy_export;
 use crate::db::init_db;
-use crate::auth::{get_auth_status, login, logout, require_auth, proxy_import_key, proxy_backup_key};
+use crate::auth::{get_auth_status, login, login_totp, logout, setup_account, unseal, require_auth, require_csrf, proxy_import_key, proxy_backup_key};
+use crate::users::{list_users, create_user, update_user, change_password};
+use crate::passkeys::{webauthn_from_env, list_passkeys, register_start, register_finish, rename_passkey, delete_passkey, login_start, login_finish};
+use crate::tokens::{list_tokens, create_token, delete_token};
+use crate::sessions::{SqliteSessionStore, list_sessions, revoke_session};
+use crate::throttle::{list_lockouts, clear_lockout};
+use crate::forwarded::resolve_client_addr;
+use crate::oidc::{oidc_from_env, oidc_start, oidc_callback};
+use crate::totp::{totp_status, totp_setup, totp_enable, totp_disable};
+use crate::policy::{require_admin, require_audit_access, list_teams, create_team, delete_team, set_team_member, list_grants, create_grant, delete_grant};
 use crate::state::AppState;
 
 #[tokio::main]
@@ -23,38 +53,112 @@ async fn main() {
     // 2. Create application state
     let app_state = AppState {
         pool: pool.clone(),
-        rate_limiter: Arc::new(Mutex::new(HashMap::new())),
+        webauthn: Arc::new(webauthn_from_env()),
+        bootstrap_token: Arc::new(Mutex::new(None)),
+        oidc: oidc_from_env().map(Arc::new),
     };
+    bootstrap::spawn_issuer(app_state.bootstrap_token.clone());
 
-    // 3. Configure session layer
-    let session_store = MemoryStore::default();
+    // 3. Sessions survive restarts in talos.db
+    let session_store = SqliteSessionStore::new(pool.clone());
+    session_store.spawn_sweeper();
+    throttle::spawn_sweeper(pool.clone());
+
+    println!("🔒 [SYSTEM] SECURE MODE ACTIVE: Authentication required.");
+    let app = app(app_state, session_store);
+
+    let port = env::var("PORT").unwrap_or_else(|_| "3000".to_string()).parse().unwrap();
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    println!("🚀 TALOS-WEB ONLINE // PORT: {}", addr);
+
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
+}
+
+fn app<S: SessionStore + Clone>(app_state: AppState, session_store: S) -> Router {
+    // Configure session layer
     let session_layer = SessionManagerLayer::new(session_store)
         .with_secure(true) // Secure cookie for HTTPS
         .with_http_only(true) // Prevent JavaScript access to cookies
         .with_same_site(tower_sessions::cookie::SameSite::Strict) // CSRF protection
         .with_expiry(Expiry::OnInactivity(Duration::hours(2))); // Increased timeout for military operations
 
-    println!("🔒 [SYSTEM] SECURE MODE ACTIVE: Authentication required.");
+    // Whole-store and account administration
+    let admin_router = Router::new()
+        .route("/api/search/rebuild", post(proxy_rebuild_search_index))
+        .route("/api/backup", get(proxy_backup))
+        .route("/api/export", post(proxy_export))
+        .route("/api/auth/unseal", post(unseal))
+        .route("/api/auth/backup-key", post(proxy_backup_key))
+        .route("/api/users", get(list_users).post(create_user))
+        .route("/api/users/update", post(update_user))
+        .route("/api/lockouts", get(list_lockouts))
+        .route("/api/lockouts/clear", post(clear_lockout))
+        .route("/api/teams", get(list_teams).post(create_team))
+        .route("/api/teams/delete", post(delete_team))
+        .route("/api/teams/members", post(set_team_member))
+        .route("/api/grants", get(list_grants).post(create_grant))
+        .route("/api/grants/delete", post(delete_grant))
+        .route_layer(middleware::from_fn_with_state(app_state.clone(), require_admin));
+
+    // Restore uploads are spooled to disk as they arrive and are exempt from
+    // the body limit, so they are added after it below
+    let restore_router = Router::new()
+        .route("/api/restore", post(proxy_restore).layer(DefaultBodyLimit::disable()))
+        .route("/api/restore/preview", post(proxy_restore_preview).layer(DefaultBodyLimit::disable()))
+        .route_layer(middleware::from_fn_with_state(app_state.clone(), require_admin))
+        .route_layer(middleware::from_fn_with_state(app_state.clone(), require_auth));
+
+    let audit_router = Router::new()
+        .route("/api/audit", get(get_audit_logs))
+        .route_layer(middleware::from_fn_with_state(app_state.clone(), require_audit_access));
 
-    // API routes that require authentication
+    // API routes that require authentication; handlers check the secret
+    // paths against the caller's grants
     let api_router = Router::new()
         .route("/api/tree", get(proxy_list_tree))
         .route("/api/decrypt", post(proxy_decrypt))
+        .route("/api/search", get(proxy_search))
+        .route("/api/history", get(proxy_history))
         .route("/api/save", post(proxy_save))
         .route("/api/delete", post(proxy_delete))
-        .route("/api/backup", get(proxy_backup))
-        .route("/api/restore", post(proxy_restore))
+        .route("/api/move", post(proxy_move))
+        .route("/api/meta", post(proxy_update_metadata))
+        .route("/api/trash", get(proxy_list_trash))
+        .route("/api/trash/restore", post(proxy_trash_restore))
+        .route("/api/trash/purge", post(proxy_trash_purge))
+        .route("/api/import", post(proxy_import))
         .route("/api/create_category", post(proxy_create_category))
-        .route("/api/audit", get(get_audit_logs))
+        .route("/api/account/password", post(change_password))
+        .route("/api/account/totp", get(totp_status))
+        .route("/api/account/totp/setup", post(totp_setup))
+        .route("/api/account/totp/enable", post(totp_enable))
+        .route("/api/account/totp/disable", post(totp_disable))
+        .route("/api/account/passkeys", get(list_passkeys))
+        .route("/api/account/passkeys/register/start", post(register_start))
+        .route("/api/account/passkeys/register/finish", post(register_finish))
+        .route("/api/account/passkeys/rename", post(rename_passkey))
+        .route("/api/account/passkeys/delete", post(delete_passkey))
+        .route("/api/account/tokens", get(list_tokens).post(create_token))
+        .route("/api/account/tokens/delete", post(delete_token))
+        .route("/api/account/sessions", get(list_sessions))
+        .route("/api/account/sessions/revoke", post(revoke_session))
+        .merge(admin_router)
+        .merge(audit_router)
         .route_layer(middleware::from_fn_with_state(app_state.clone(), require_auth));
 
-    let app = Router::new()
+    Router::new()
         // Authentication routes
         .route("/api/auth/status", get(get_auth_status))
         .route("/api/auth/login", post(login))
+        .route("/api/auth/login/totp", post(login_totp))
+        .route("/api/auth/passkey/start", post(login_start))
+        .route("/api/auth/passkey/finish", post(login_finish))
+        .route("/api/auth/oidc/start", get(oidc_start))
+        .route("/api/auth/oidc/callback", get(oidc_callback))
         .route("/api/auth/logout", post(logout))
+        .route("/api/auth/setup", post(setup_account))
         .route("/api/initialize/import", post(proxy_import_key))
-        .route("/api/auth/backup-key", get(proxy_backup_key))
         // Public routes
         .route("/api/version", get(get_version))
         .route("/api/health", get(health_check))
@@ -64,15 +168,188 @@ async fn main() {
         // Serve static files as a fallback
         .fallback_service(ServeDir::new("./static"))
         // Apply layers (middleware)
-        .layer(CompressionLayer::new())
         .layer(RequestBodyLimitLayer::new(10 * 1024 * 1024)) // 10MB limit
+        .merge(restore_router)
+        .layer(CompressionLayer::new())
+        // Inside the session layer, which it reads the token from
+        .layer(middleware::from_fn(require_csrf))
         .layer(session_layer)
-        .with_state(app_state);
+        // Outermost, so everything sees the client behind a trusted proxy
+        .layer(middleware::from_fn(resolve_client_addr))
+        .with_state(app_state)
+}
 
-    let port = env::var("PORT").unwrap_or_else(|_| "3000".to_string()).parse().unwrap();
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
-    println!("🚀 TALOS-WEB ONLINE // PORT: {}", addr);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::extract::ConnectInfo;
+    use axum::http::{header, Request, StatusCode};
+    use serde_json::{json, Value};
+    use sqlx::sqlite::SqlitePoolOptions;
+    use tower::ServiceExt;
+    use tower_sessions::Session;
+    use crate::auth::{issue_csrf_token, CSRF_HEADER};
+    use crate::state::BootstrapToken;
 
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
-}
\ No newline at end of file
+    // Every route that accepts POST; logout comes last as it ends the session
+    const POST_ROUTES: [&str; 42] = [
+        "/api/search/rebuild", "/api/restore", "/api/restore/preview", "/api/export", "/api/auth/unseal",
+        "/api/auth/backup-key", "/api/lockouts/clear",
+        "/api/users", "/api/users/update", "/api/teams", "/api/teams/delete", "/api/teams/members",
+        "/api/grants", "/api/grants/delete", "/api/decrypt", "/api/save", "/api/delete", "/api/move",
+        "/api/meta", "/api/trash/restore", "/api/trash/purge", "/api/import", "/api/create_category",
+        "/api/account/password", "/api/account/totp/setup", "/api/account/totp/enable",
+        "/api/account/totp/disable", "/api/account/passkeys/register/start",
+        "/api/account/passkeys/register/finish", "/api/account/passkeys/rename",
+        "/api/account/passkeys/delete", "/api/account/tokens", "/api/account/tokens/delete",
+        "/api/account/sessions/revoke",
+        "/api/auth/login", "/api/auth/login/totp", "/api/auth/passkey/start", "/api/auth/passkey/finish",
+        "/api/auth/setup", "/api/initialize/import", "/api/initialize", "/api/auth/logout",
+    ];
+
+    async fn test_app() -> (Router, SqliteSessionStore) {
+        let (app, store, _) = test_app_with_bootstrap().await;
+        (app, store)
+    }
+
+    async fn test_state() -> AppState {
+        let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        crate::db::migrate(&pool).await;
+        AppState {
+            pool,
+            webauthn: Arc::new(crate::passkeys::build_webauthn("https://localhost:3443", None).unwrap()),
+            bootstrap_token: Arc::new(Mutex::new(None)),
+            oidc: None,
+        }
+    }
+
+    async fn test_app_with_bootstrap() -> (Router, SqliteSessionStore, BootstrapToken) {
+        let state = test_state().await;
+        let store = SqliteSessionStore::new(state.pool.clone());
+        let bootstrap_token = state.bootstrap_token.clone();
+        (app(state, store.clone()), store, bootstrap_token)
+    }
+
+    // A stored session with a CSRF token, as the cookie a browser would send
+    async fn browser_session(store: &SqliteSessionStore) -> (String, String) {
+        let session = Session::new(None, Arc::new(store.clone()), None);
+        let token = issue_csrf_token(&session).await.unwrap();
+        session.save().await.unwrap();
+        (format!("id={}", session.id().unwrap()), token)
+    }
+
+    // Like browser_session, signed in as a new administrator
+    async fn admin_session(state: &AppState, store: &SqliteSessionStore) -> (String, String) {
+        let id: i64 = sqlx::query_scalar("INSERT INTO users (username, password_hash, role) VALUES ('admin', 'x', 'admin') RETURNING id")
+            .fetch_one(&state.pool)
+            .await
+            .unwrap();
+        let session = Session::new(None, Arc::new(store.clone()), None);
+        session.insert(crate::users::SESSION_USER_ID, id).await.unwrap();
+        let token = issue_csrf_token(&session).await.unwrap();
+        session.save().await.unwrap();
+        (format!("id={}", session.id().unwrap()), token)
+    }
+
+    fn request(method: &str, path: &str, cookie: &str, csrf_token: Option<&str>) -> Request<Body> {
+        let mut builder = Request::builder().method(method).uri(path).header(header::COOKIE, cookie);
+        if let Some(token) = csrf_token {
+            builder = builder.header(CSRF_HEADER, token);
+        }
+        let mut request = builder.body(Body::empty()).unwrap();
+        request.extensions_mut().insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+        request
+    }
+
+    #[tokio::test]
+    async fn every_post_route_requires_the_session_csrf_token() {
+        let (app, store) = test_app().await;
+        let (_, other_token) = browser_session(&store).await;
+
+        for route in POST_ROUTES {
+            let (cookie, token) = browser_session(&store).await;
+            for forged in [None, Some(""), Some("forged"), Some(&token[1..]), Some(other_token.as_str())] {
+                let res = app.clone().oneshot(request("POST", route, &cookie, forged)).await.unwrap();
+                assert_eq!(res.status(), StatusCode::FORBIDDEN, "{} accepted {:?}", route, forged);
+            }
+            // With the right token the request reaches authentication or the handler
+            let res = app.clone().oneshot(request("POST", route, &cookie, Some(&token))).await.unwrap();
+            assert_ne!(res.status(), StatusCode::FORBIDDEN, "{} refused its token", route);
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_and_bearer_requests_skip_the_csrf_check() {
+        let (app, store) = test_app().await;
+        let (cookie, _) = browser_session(&store).await;
+
+        let res = app.clone().oneshot(request("GET", "/api/version", &cookie, None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let res = app.clone().oneshot(request("GET", "/api/tree", &cookie, None)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+
+        // The token itself is still checked by authentication
+        let mut bearer = request("POST", "/api/save", &cookie, None);
+        bearer.headers_mut().insert(header::AUTHORIZATION, "Bearer talos_invalid".parse().unwrap());
+        let res = app.clone().oneshot(bearer).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn genesis_requires_the_bootstrap_token() {
+        let (app, store, bootstrap_token) = test_app_with_bootstrap().await;
+        let (cookie, csrf_token) = browser_session(&store).await;
+        let genesis = |path: &str, body: Value| {
+            let mut request = request("POST", path, &cookie, Some(&csrf_token));
+            request.headers_mut().insert(header::CONTENT_TYPE, "application/json".parse().unwrap());
+            *request.body_mut() = Body::from(body.to_string());
+            request
+        };
+
+        // Refused before anything reaches Storage, whether or not a token was issued yet
+        for path in ["/api/initialize", "/api/initialize/import"] {
+            let res = app.clone().oneshot(genesis(path, json!({"key": "k", "passphrase": ""}))).await.unwrap();
+            assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        }
+        *bootstrap_token.lock().unwrap() = Some("issued-at-startup".to_string());
+        for path in ["/api/initialize", "/api/initialize/import"] {
+            let body = json!({"key": "k", "passphrase": "", "bootstrap_token": "guessed"});
+            let res = app.clone().oneshot(genesis(path, body)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::FORBIDDEN);
+        }
+
+        // The private key is only for signed-in administrators
+        let res = app.clone().oneshot(genesis("/api/auth/backup-key", json!({"key": "k"}))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn only_restore_uploads_may_exceed_the_body_limit() {
+        let state = test_state().await;
+        let store = SqliteSessionStore::new(state.pool.clone());
+        let (cookie, csrf_token) = admin_session(&state, &store).await;
+        let app = app(state, store);
+        let upload = |path: &str, field: &str, content: &[u8]| {
+            let mut body = format!("--b\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"upload\"\r\n\r\n", field).into_bytes();
+            body.extend_from_slice(content);
+            body.extend_from_slice(b"\r\n--b--\r\n");
+            let mut request = request("POST", path, &cookie, Some(&csrf_token));
+            request.headers_mut().insert(header::CONTENT_TYPE, "multipart/form-data; boundary=b".parse().unwrap());
+            request.headers_mut().insert(header::CONTENT_LENGTH, body.len().into());
+            *request.body_mut() = Body::from(body);
+            request
+        };
+        let large = vec![b'x'; 12 * 1024 * 1024];
+
+        // Read in full and turned away on its content, not its size
+        for path in ["/api/restore", "/api/restore/preview"] {
+            let res = app.clone().oneshot(upload(path, "backup", &large)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST, "{}", path);
+            let body: Value = serde_json::from_slice(&axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap()).unwrap();
+            assert_eq!(body["legacy"], true);
+        }
+        let res = app.clone().oneshot(upload("/api/import", "file", &large)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+}
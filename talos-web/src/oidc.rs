@@ -0,0 +1,765 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::Json;
+use data_encoding::BASE64URL_NOPAD;
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use subtle::ConstantTimeEq;
+use tower_sessions::Session;
+use url::Url;
+use crate::db::DbPool;
+use crate::handlers::{is_debug, log_audit};
+use crate::policy::Role;
+use crate::state::AppState;
+use crate::users::{self, start_session, User};
+
+// Single sign-on with an OpenID Connect provider: authorization code flow
+// with PKCE. Accounts are created on their first sign-in and take their role
+// from a claim of the ID token at every sign-in.
+const CALLBACK_PATH: &str = "/api/auth/oidc/callback";
+// Ties the provider's redirect back to the browser that started the login.
+// The session cookie is SameSite=Strict and does not come along on that
+// cross-site redirect, so the login has a Lax cookie of its own.
+const FLOW_COOKIE: &str = "talos_oidc";
+const FLOW_TIMEOUT_SECONDS: i64 = 600;
+const PROVIDER_REFRESH_SECONDS: u64 = 3600;
+// Asymmetric signatures only: the client secret must never be accepted as a
+// verification key
+const ALGORITHMS: [Algorithm; 9] = [
+    Algorithm::RS256, Algorithm::RS384, Algorithm::RS512,
+    Algorithm::PS256, Algorithm::PS384, Algorithm::PS512,
+    Algorithm::ES256, Algorithm::ES384, Algorithm::EdDSA,
+];
+
+pub struct OidcConfig {
+    pub issuer: String,
+    pub client_id: String,
+    pub client_secret: Option<String>,
+    pub redirect_url: String,
+    pub scopes: String,
+    pub username_claim: String,
+    pub role_claim: String,
+    // Claim value and role, the first match wins
+    pub role_map: Vec<(String, Role)>,
+    // For accounts no entry matches; None refuses them
+    pub default_role: Option<Role>,
+}
+
+pub struct Oidc {
+    config: OidcConfig,
+    client: reqwest::Client,
+    provider: tokio::sync::Mutex<Option<Provider>>,
+}
+
+#[derive(Deserialize, Clone)]
+struct Metadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+    #[serde(default)]
+    token_endpoint_auth_methods_supported: Vec<String>,
+}
+
+// Discovery document and signing keys, fetched again every hour or when a
+// token names a key that is not known yet
+#[derive(Clone)]
+struct Provider {
+    metadata: Metadata,
+    jwks: JwkSet,
+    fetched_at: Instant,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+// Enabled by OIDC_ISSUER; an incomplete configuration stops the start
+pub fn oidc_from_env() -> Option<Oidc> {
+    let var = |name: &str| env::var(name).ok().map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    let issuer = var("OIDC_ISSUER")?;
+    let client_id = var("OIDC_CLIENT_ID").unwrap_or_else(|| panic!("Invalid OIDC configuration: OIDC_CLIENT_ID is required"));
+    let origin = env::var("WEBAUTHN_ORIGIN").unwrap_or_else(|_| "https://localhost:3443".to_string());
+    let default_role = match var("OIDC_DEFAULT_ROLE").as_deref() {
+        None => Some(Role::Viewer),
+        Some("none") => None,
+        Some(role) => Some(parse_role(role).unwrap_or_else(|e| panic!("Invalid OIDC configuration: OIDC_DEFAULT_ROLE: {}", e))),
+    };
+    let config = OidcConfig {
+        issuer,
+        client_id,
+        client_secret: var("OIDC_CLIENT_SECRET"),
+        redirect_url: var("OIDC_REDIRECT_URL").unwrap_or_else(|| format!("{}{}", origin.trim_end_matches('/'), CALLBACK_PATH)),
+        scopes: var("OIDC_SCOPES").unwrap_or_else(|| "openid profile email".to_string()),
+        username_claim: var("OIDC_USERNAME_CLAIM").unwrap_or_else(|| "preferred_username".to_string()),
+        role_claim: var("OIDC_ROLE_CLAIM").unwrap_or_else(|| "groups".to_string()),
+        role_map: parse_role_map(&var("OIDC_ROLE_MAP").unwrap_or_default())
+            .unwrap_or_else(|e| panic!("Invalid OIDC configuration: OIDC_ROLE_MAP: {}", e)),
+        default_role,
+    };
+    println!("🔑 [WEB] Single sign-on enabled with {}", config.issuer);
+    Some(Oidc::new(config))
+}
+
+fn parse_role(value: &str) -> Result<Role, String> {
+    serde_json::from_value(Value::String(value.trim().to_lowercase()))
+        .map_err(|_| format!("unknown role '{}'", value.trim()))
+}
+
+// `talos-admins=admin,talos-editors=editor`
+pub fn parse_role_map(value: &str) -> Result<Vec<(String, Role)>, String> {
+    value.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (claim, role) = entry.rsplit_once('=').ok_or_else(|| format!("'{}' is not claim=role", entry))?;
+            Ok((claim.trim().to_string(), parse_role(role)?))
+        })
+        .collect()
+}
+
+// A claim by its name, or by a dotted path into nested objects such as
+// Keycloak's `realm_access.roles`
+fn claim<'a>(claims: &'a Value, name: &str) -> &'a Value {
+    match &claims[name] {
+        Value::Null => name.split('.').fold(claims, |value, key| &value[key]),
+        value => value,
+    }
+}
+
+fn map_role(config: &OidcConfig, claims: &Value) -> Option<Role> {
+    let values: Vec<&str> = match claim(claims, &config.role_claim) {
+        Value::String(value) => vec![value.as_str()],
+        Value::Array(items) => items.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    config.role_map.iter()
+        .find(|(value, _)| values.contains(&value.as_str()))
+        .map(|(_, role)| *role)
+        .or(config.default_role)
+}
+
+fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    BASE64URL_NOPAD.encode(&bytes)
+}
+
+fn hash_flow(flow: &str) -> String {
+    Sha256::digest(flow.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64
+}
+
+fn equal(a: &str, b: &str) -> bool {
+    bool::from(a.as_bytes().ct_eq(b.as_bytes()))
+}
+
+fn form_encode(value: &str) -> String {
+    url::form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+fn flow_cookie(value: &str, max_age: i64) -> String {
+    format!("{}={}; Path={}; Max-Age={}; HttpOnly; Secure; SameSite=Lax", FLOW_COOKIE, value, CALLBACK_PATH, max_age)
+}
+
+fn presented_flow(headers: &HeaderMap) -> Option<String> {
+    headers.get_all(header::COOKIE).iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == FLOW_COOKIE)
+        .map(|(_, value)| value.to_string())
+}
+
+// Errors go back to the login screen, which shows them
+fn failed(reason: &str) -> Response {
+    let location = format!("/?sso_error={}", form_encode(reason));
+    ([(header::SET_COOKIE, flow_cookie("", 0))], Redirect::to(&location)).into_response()
+}
+
+fn not_configured() -> Response {
+    (StatusCode::NOT_FOUND, Json(json!({"error": "Single sign-on is not configured"}))).into_response()
+}
+
+impl Oidc {
+    pub fn new(config: OidcConfig) -> Self {
+        Self { config, client: reqwest::Client::new(), provider: tokio::sync::Mutex::new(None) }
+    }
+
+    async fn provider(&self, refresh: bool) -> Result<Provider, String> {
+        let mut cached = self.provider.lock().await;
+        if let Some(provider) = cached.as_ref()
+            && !refresh && provider.fetched_at.elapsed() < Duration::from_secs(PROVIDER_REFRESH_SECONDS) {
+            return Ok(provider.clone());
+        }
+        let discovery = format!("{}/.well-known/openid-configuration", self.config.issuer.trim_end_matches('/'));
+        let metadata: Metadata = self.fetch(&discovery).await?;
+        // The document must be the issuer's own, or its tokens would be too
+        if metadata.issuer != self.config.issuer {
+            return Err(format!("discovery names issuer {}", metadata.issuer));
+        }
+        let jwks: JwkSet = self.fetch(&metadata.jwks_uri).await?;
+        let provider = Provider { metadata, jwks, fetched_at: Instant::now() };
+        *cached = Some(provider.clone());
+        Ok(provider)
+    }
+
+    async fn fetch<T: for<'de> Deserialize<'de>>(&self, url: &str) -> Result<T, String> {
+        let res = self.client.get(url).send().await
+            .and_then(|res| res.error_for_status())
+            .map_err(|e| format!("{}: {}", url, e))?;
+        res.json::<T>().await.map_err(|e| format!("{}: {}", url, e))
+    }
+
+    fn authorization_url(&self, provider: &Provider, state: &str, nonce: &str, verifier: &str) -> Result<Url, String> {
+        let challenge = BASE64URL_NOPAD.encode(&Sha256::digest(verifier.as_bytes()));
+        let scopes = if self.config.scopes.split_whitespace().any(|s| s == "openid") {
+            self.config.scopes.clone()
+        } else {
+            format!("openid {}", self.config.scopes)
+        };
+        let mut url = Url::parse(&provider.metadata.authorization_endpoint).map_err(|e| e.to_string())?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.config.client_id)
+            .append_pair("redirect_uri", &self.config.redirect_url)
+            .append_pair("scope", &scopes)
+            .append_pair("state", state)
+            .append_pair("nonce", nonce)
+            .append_pair("code_challenge", &challenge)
+            .append_pair("code_challenge_method", "S256");
+        Ok(url)
+    }
+
+    async fn exchange_code(&self, provider: &Provider, code: &str, verifier: &str) -> Result<String, String> {
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", self.config.redirect_url.as_str()),
+            ("code_verifier", verifier),
+            ("client_id", self.config.client_id.as_str()),
+        ];
+        let mut request = self.client.post(&provider.metadata.token_endpoint);
+        if let Some(secret) = &self.config.client_secret {
+            let methods = &provider.metadata.token_endpoint_auth_methods_supported;
+            if methods.is_empty() || methods.iter().any(|m| m == "client_secret_basic") {
+                // RFC 6749 2.3.1: both parts are form encoded before the Basic encoding
+                request = request.basic_auth(form_encode(&self.config.client_id), Some(form_encode(secret)));
+            } else {
+                form.push(("client_secret", secret.as_str()));
+            }
+        }
+        let res = request.form(&form).send().await.map_err(|e| {
+            println!("❌ [WEB] OIDC token request failed: {}", e);
+            "Identity provider unavailable".to_string()
+        })?;
+        if !res.status().is_success() {
+            if is_debug() {
+                println!("--> [WEB] OIDC token endpoint answered {}: {}", res.status(), res.text().await.unwrap_or_default());
+            }
+            return Err("The identity provider rejected the sign-in".to_string());
+        }
+        let body: TokenResponse = res.json().await.map_err(|_| "Invalid token response".to_string())?;
+        body.id_token.ok_or_else(|| "No ID token in the token response".to_string())
+    }
+
+    async fn verify_id_token(&self, provider: &Provider, token: &str, nonce: &str) -> Result<Value, String> {
+        let header = decode_header(token).map_err(|_| "Malformed ID token".to_string())?;
+        if !ALGORITHMS.contains(&header.alg) {
+            return Err(format!("ID token algorithm {:?} is not allowed", header.alg));
+        }
+        let jwk = match find_key(&provider.jwks, header.kid.as_deref()) {
+            Some(jwk) => jwk.clone(),
+            // Keys rotate; look again before giving up
+            None => {
+                let provider = self.provider(true).await?;
+                find_key(&provider.jwks, header.kid.as_deref()).cloned().ok_or_else(|| "Unknown ID token signing key".to_string())?
+            },
+        };
+        if jwk.common.key_algorithm.is_some_and(|alg| alg.to_string() != format!("{:?}", header.alg)) {
+            return Err("ID token algorithm does not match its key".to_string());
+        }
+        let key = DecodingKey::from_jwk(&jwk).map_err(|_| "Unusable ID token signing key".to_string())?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.config.issuer]);
+        validation.set_audience(&[&self.config.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+        let claims = decode::<Value>(token, &key, &validation)
+            .map_err(|e| format!("Invalid ID token: {}", e))?
+            .claims;
+
+        // Only the token issued for this login, not one replayed from another
+        if !claims["nonce"].as_str().is_some_and(|presented| equal(presented, nonce)) {
+            return Err("ID token nonce mismatch".to_string());
+        }
+        if claims["aud"].as_array().is_some_and(|aud| aud.len() > 1) && claims["azp"].as_str() != Some(self.config.client_id.as_str()) {
+            return Err("ID token was issued to another client".to_string());
+        }
+        Ok(claims)
+    }
+}
+
+fn find_key<'a>(jwks: &'a JwkSet, kid: Option<&str>) -> Option<&'a Jwk> {
+    match kid {
+        Some(kid) => jwks.find(kid),
+        None if jwks.keys.len() == 1 => jwks.keys.first(),
+        None => None,
+    }
+}
+
+struct Login {
+    user: User,
+    // Account created or changed by this sign-in, for the audit log
+    change: Option<(&'static str, String)>,
+}
+
+// Finds or creates the account of the provider's subject and brings its role
+// in line with the claims
+async fn provision(pool: &DbPool, config: &OidcConfig, claims: &Value) -> Result<Login, String> {
+    let subject = claims["sub"].as_str().filter(|s| !s.is_empty()).ok_or("ID token has no subject")?;
+    let role = map_role(config, claims).ok_or("No Talos role is assigned to this account")?;
+
+    let existing: Option<i64> = sqlx::query_scalar("SELECT user_id FROM oidc_identities WHERE issuer = ? AND subject = ?")
+        .bind(&config.issuer)
+        .bind(subject)
+        .fetch_optional(pool)
+        .await
+        .map_err(|_| "Failed to look up the account")?;
+    if let Some(id) = existing {
+        let user = users::find_user(pool, id).await.ok_or("Account not found")?;
+        if user.disabled {
+            return Err("This account is disabled".to_string());
+        }
+        sqlx::query("UPDATE users SET role = ?, last_login = CURRENT_TIMESTAMP WHERE id = ?")
+            .bind(role)
+            .bind(id)
+            .execute(pool)
+            .await
+            .map_err(|_| "Failed to update the account")?;
+        let change = (user.role != role).then(|| ("USER_UPDATE", format!("{} role={} via oidc", user.username, role.as_str())));
+        let user = users::find_user(pool, id).await.ok_or("Account not found")?;
+        return Ok(Login { user, change });
+    }
+
+    let name = claim(claims, &config.username_claim).as_str()
+        .or_else(|| claims["email"].as_str())
+        .ok_or("ID token has no username")?;
+    let username = users::normalize_username(name)?;
+    // Nobody knows this password; the account signs in through the provider
+    let hash = users::hash_blocking(&random_token()).await.map_err(|_| "Failed to create the account")?;
+
+    let mut tx = pool.begin().await.map_err(|_| "Failed to create the account")?;
+    let inserted = sqlx::query("INSERT INTO users (username, password_hash, role, last_login) VALUES (?, ?, ?, CURRENT_TIMESTAMP)")
+        .bind(&username)
+        .bind(hash)
+        .bind(role)
+        .execute(&mut *tx)
+        .await;
+    let id = match inserted {
+        Ok(result) => result.last_insert_rowid(),
+        // Local accounts are never taken over by a provider account of the same name
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return Err(format!("The username {} belongs to another account", username));
+        },
+        Err(_) => return Err("Failed to create the account".to_string()),
+    };
+    sqlx::query("INSERT INTO oidc_identities (issuer, subject, user_id) VALUES (?, ?, ?)")
+        .bind(&config.issuer)
+        .bind(subject)
+        .bind(id)
+        .execute(&mut *tx)
+        .await
+        .map_err(|_| "Failed to create the account")?;
+    tx.commit().await.map_err(|_| "Failed to create the account")?;
+
+    let user = users::find_user(pool, id).await.ok_or("Account not found")?;
+    let change = Some(("USER_CREATE", format!("{} role={} via oidc", username, role.as_str())));
+    Ok(Login { user, change })
+}
+
+// Sends the browser to the provider with a fresh state, nonce and PKCE verifier
+pub async fn oidc_start(State(state): State<AppState>) -> Response {
+    let Some(oidc) = state.oidc.clone() else { return not_configured() };
+    let provider = match oidc.provider(false).await {
+        Ok(provider) => provider,
+        Err(e) => {
+            println!("❌ [WEB] OIDC discovery failed: {}", e);
+            return failed("Identity provider unavailable");
+        }
+    };
+
+    let (flow, login_state, nonce, verifier) = (random_token(), random_token(), random_token(), random_token());
+    let url = match oidc.authorization_url(&provider, &login_state, &nonce, &verifier) {
+        Ok(url) => url,
+        Err(e) => {
+            println!("❌ [WEB] OIDC authorization endpoint invalid: {}", e);
+            return failed("Identity provider unavailable");
+        }
+    };
+    let _ = sqlx::query("DELETE FROM oidc_logins WHERE created_at < ?")
+        .bind(now() - FLOW_TIMEOUT_SECONDS)
+        .execute(&state.pool)
+        .await;
+    let stored = sqlx::query("INSERT INTO oidc_logins (flow_hash, state, nonce, code_verifier, created_at) VALUES (?, ?, ?, ?, ?)")
+        .bind(hash_flow(&flow))
+        .bind(&login_state)
+        .bind(&nonce)
+        .bind(&verifier)
+        .bind(now())
+        .execute(&state.pool)
+        .await;
+    if stored.is_err() {
+        return failed("Failed to start sign-in");
+    }
+    if is_debug() { println!("--> [WEB] OIDC login started"); }
+    ([(header::SET_COOKIE, flow_cookie(&flow, FLOW_TIMEOUT_SECONDS))], Redirect::to(url.as_str())).into_response()
+}
+
+#[derive(Deserialize)]
+pub struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+async fn complete(state: &AppState, oidc: &Oidc, headers: &HeaderMap, query: &CallbackQuery) -> Result<Login, String> {
+    if let Some(error) = &query.error {
+        let error: String = error.chars().filter(|c| c.is_ascii_graphic()).take(64).collect();
+        return Err(format!("The identity provider refused the sign-in ({})", error));
+    }
+    let (Some(code), Some(returned_state)) = (&query.code, &query.state) else {
+        return Err("Invalid sign-in response".to_string());
+    };
+    let flow = presented_flow(headers).ok_or("Sign-in was not started in this browser")?;
+    // Each login can be completed once
+    let pending: Option<(String, String, String, i64)> = sqlx::query_as(
+        "DELETE FROM oidc_logins WHERE flow_hash = ? RETURNING state, nonce, code_verifier, created_at",
+    )
+        .bind(hash_flow(&flow))
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|_| "Failed to complete sign-in")?;
+    let (expected_state, nonce, verifier, created_at) = pending.ok_or("Sign-in expired, please try again")?;
+    if now() - created_at > FLOW_TIMEOUT_SECONDS {
+        return Err("Sign-in expired, please try again".to_string());
+    }
+    if !equal(&expected_state, returned_state) {
+        return Err("Invalid sign-in state".to_string());
+    }
+    // The first administrator is created with the Master Key, never by a provider
+    if users::user_count(&state.pool).await == 0 {
+        return Err("Create the first administrator before using single sign-on".to_string());
+    }
+
+    let provider = oidc.provider(false).await.map_err(|e| {
+        println!("❌ [WEB] OIDC discovery failed: {}", e);
+        "Identity provider unavailable".to_string()
+    })?;
+    let id_token = oidc.exchange_code(&provider, code, &verifier).await?;
+    let claims = oidc.verify_id_token(&provider, &id_token, &nonce).await?;
+    provision(&state.pool, &oidc.config, &claims).await
+}
+
+pub async fn oidc_callback(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Query(query): Query<CallbackQuery>,
+) -> Response {
+    let Some(oidc) = state.oidc.clone() else { return not_configured() };
+    let ua_header = headers.get(header::USER_AGENT);
+
+    let login = match complete(&state, &oidc, &headers, &query).await {
+        Ok(login) => login,
+        Err(reason) => {
+            log_audit(&state, &session, Some(addr.ip()), ua_header, "LOGIN_OIDC_FAILURE", &reason).await;
+            return failed(&reason);
+        }
+    };
+    if start_session(&session, &login.user, "oidc", addr, ua_header).await.is_err() {
+        return failed("Failed to start session");
+    }
+    if let Some((action, target)) = &login.change {
+        log_audit(&state, &session, Some(addr.ip()), ua_header, action, target).await;
+    }
+    log_audit(&state, &session, Some(addr.ip()), ua_header, "LOGIN_SUCCESS", &login.user.username).await;
+    ([(header::SET_COOKIE, flow_cookie("", 0))], Redirect::to("/")).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::extract::Form;
+    use axum::http::Request;
+    use axum::routing::{get, post};
+    use axum::Router;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use ring::rand::SystemRandom;
+    use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+    use sqlx::sqlite::SqlitePoolOptions;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+    use crate::sessions::SqliteSessionStore;
+
+    const CLIENT_ID: &str = "talos";
+    const CLIENT_SECRET: &str = "s3cret&=";
+    const KID: &str = "test-key";
+
+    fn generate_key() -> Vec<u8> {
+        EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &SystemRandom::new()).unwrap().as_ref().to_vec()
+    }
+
+    // What the provider remembers about a code between the browser's visit
+    // and Talos redeeming it
+    struct Authorization {
+        challenge: String,
+        redirect_uri: String,
+        id_token: String,
+    }
+
+    // An identity provider serving discovery, JWKS and a token endpoint
+    #[derive(Clone)]
+    struct MockIssuer {
+        url: String,
+        key: Arc<Vec<u8>>,
+        codes: Arc<Mutex<HashMap<String, Authorization>>>,
+    }
+
+    impl MockIssuer {
+        async fn start() -> Self {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let issuer = MockIssuer {
+                url: format!("http://{}", listener.local_addr().unwrap()),
+                key: Arc::new(generate_key()),
+                codes: Arc::default(),
+            };
+            let router = Router::new()
+                .route("/.well-known/openid-configuration", get(Self::discovery))
+                .route("/jwks", get(Self::jwks))
+                .route("/token", post(Self::token))
+                .with_state(issuer.clone());
+            tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+            issuer
+        }
+
+        async fn discovery(State(issuer): State<MockIssuer>) -> Json<Value> {
+            Json(json!({
+                "issuer": issuer.url,
+                "authorization_endpoint": format!("{}/authorize", issuer.url),
+                "token_endpoint": format!("{}/token", issuer.url),
+                "jwks_uri": format!("{}/jwks", issuer.url),
+                "token_endpoint_auth_methods_supported": ["client_secret_basic"],
+            }))
+        }
+
+        async fn jwks(State(issuer): State<MockIssuer>) -> Json<Value> {
+            let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &issuer.key, &SystemRandom::new()).unwrap();
+            // Uncompressed point: 0x04, then x and y
+            let point = pair.public_key().as_ref();
+            Json(json!({"keys": [{
+                "kty": "EC", "crv": "P-256", "use": "sig", "alg": "ES256", "kid": KID,
+                "x": BASE64URL_NOPAD.encode(&point[1..33]),
+                "y": BASE64URL_NOPAD.encode(&point[33..]),
+            }]}))
+        }
+
+        async fn token(State(issuer): State<MockIssuer>, headers: HeaderMap, Form(form): Form<HashMap<String, String>>) -> Response {
+            let credentials = format!("{}:{}", CLIENT_ID, form_encode(CLIENT_SECRET));
+            let expected = format!("Basic {}", data_encoding::BASE64.encode(credentials.as_bytes()));
+            if headers.get(header::AUTHORIZATION).and_then(|v| v.to_str().ok()) != Some(expected.as_str()) {
+                return (StatusCode::UNAUTHORIZED, Json(json!({"error": "invalid_client"}))).into_response();
+            }
+            let authorization = issuer.codes.lock().unwrap().remove(&form["code"]);
+            let Some(authorization) = authorization else {
+                return (StatusCode::BAD_REQUEST, Json(json!({"error": "invalid_grant"}))).into_response();
+            };
+            let challenge = BASE64URL_NOPAD.encode(&Sha256::digest(form["code_verifier"].as_bytes()));
+            if form["grant_type"] != "authorization_code" || challenge != authorization.challenge || form["redirect_uri"] != authorization.redirect_uri {
+                return (StatusCode::BAD_REQUEST, Json(json!({"error": "invalid_grant"}))).into_response();
+            }
+            Json(json!({"access_token": "opaque", "token_type": "Bearer", "id_token": authorization.id_token})).into_response()
+        }
+
+        // Signs the user in at the provider: the browser arrives with the
+        // authorization URL and leaves with a code
+        fn authorize(&self, location: &str, claims: Value, key: &[u8]) -> HashMap<String, String> {
+            let params: HashMap<String, String> = Url::parse(location).unwrap().query_pairs().into_owned().collect();
+            assert!(location.starts_with(&format!("{}/authorize?", self.url)));
+            assert_eq!(params["client_id"], CLIENT_ID);
+            assert_eq!(params["code_challenge_method"], "S256");
+            assert!(params["scope"].split(' ').any(|s| s == "openid"));
+
+            let mut id_token = json!({
+                "iss": self.url, "aud": CLIENT_ID, "nonce": params["nonce"],
+                "iat": now(), "exp": now() + 300,
+            });
+            id_token.as_object_mut().unwrap().extend(claims.as_object().unwrap().clone());
+            let mut header = Header::new(Algorithm::ES256);
+            header.kid = Some(KID.to_string());
+            let id_token = encode(&header, &id_token, &EncodingKey::from_ec_der(key)).unwrap();
+
+            let code = random_token();
+            self.codes.lock().unwrap().insert(code.clone(), Authorization {
+                challenge: params["code_challenge"].clone(),
+                redirect_uri: params["redirect_uri"].clone(),
+                id_token,
+            });
+            HashMap::from([("code".to_string(), code), ("state".to_string(), params["state"].clone())])
+        }
+    }
+
+    fn request(path: &str, cookie: &str) -> Request<Body> {
+        let mut request = Request::builder().uri(path).header(header::COOKIE, cookie).body(Body::empty()).unwrap();
+        request.extensions_mut().insert(ConnectInfo(SocketAddr::from(([127, 0, 0, 1], 40000))));
+        request
+    }
+
+    fn set_cookie(res: &Response, name: &str) -> Option<String> {
+        res.headers().get_all(header::SET_COOKIE).iter()
+            .filter_map(|v| v.to_str().ok())
+            .find(|v| v.starts_with(&format!("{}=", name)))
+            .map(|v| v.split(';').next().unwrap().to_string())
+    }
+
+    fn location(res: &Response) -> String {
+        res.headers()[header::LOCATION].to_str().unwrap().to_string()
+    }
+
+    struct Browser {
+        app: Router,
+        issuer: MockIssuer,
+    }
+
+    impl Browser {
+        // Runs a whole login; returns the final redirect and the session cookie
+        async fn sign_in(&self, claims: Value, key: &[u8], tamper: impl FnOnce(&mut HashMap<String, String>)) -> (String, Option<String>) {
+            let res = self.app.clone().oneshot(request("/api/auth/oidc/start", "")).await.unwrap();
+            assert_eq!(res.status(), StatusCode::SEE_OTHER);
+            let flow = set_cookie(&res, FLOW_COOKIE).unwrap();
+            let mut params = self.issuer.authorize(&location(&res), claims, key);
+            tamper(&mut params);
+            let query = format!("code={}&state={}", params["code"], form_encode(&params["state"]));
+
+            let callback = format!("{}?{}", CALLBACK_PATH, query);
+            let res = self.app.clone().oneshot(request(&callback, &flow)).await.unwrap();
+            assert_eq!(res.status(), StatusCode::SEE_OTHER);
+            // A second attempt with the same login never succeeds
+            let replay = self.app.clone().oneshot(request(&callback, &flow)).await.unwrap();
+            assert!(location(&replay).starts_with("/?sso_error="));
+            (location(&res), set_cookie(&res, "id"))
+        }
+
+        async fn status(&self, cookie: &str) -> Value {
+            let res = self.app.clone().oneshot(request("/api/auth/status", cookie)).await.unwrap();
+            let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice(&body).unwrap()
+        }
+    }
+
+    #[tokio::test]
+    async fn users_sign_in_through_a_mock_issuer() {
+        let issuer = MockIssuer::start().await;
+        let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        crate::db::migrate(&pool).await;
+        let config = OidcConfig {
+            issuer: issuer.url.clone(),
+            client_id: CLIENT_ID.to_string(),
+            client_secret: Some(CLIENT_SECRET.to_string()),
+            redirect_url: format!("https://vault.example.com{}", CALLBACK_PATH),
+            scopes: "profile email".to_string(),
+            username_claim: "preferred_username".to_string(),
+            role_claim: "realm_access.roles".to_string(),
+            role_map: parse_role_map("talos-admins=admin, talos-editors=editor").unwrap(),
+            default_role: Some(Role::Viewer),
+        };
+        let state = AppState {
+            pool: pool.clone(),
+            webauthn: Arc::new(crate::passkeys::build_webauthn("https://vault.example.com", None).unwrap()),
+            bootstrap_token: Arc::new(Mutex::new(None)),
+            oidc: Some(Arc::new(Oidc::new(config))),
+        };
+        let browser = Browser { app: crate::app(state, SqliteSessionStore::new(pool.clone())), issuer: issuer.clone() };
+        let key = issuer.key.clone();
+        let carol = json!({"sub": "0001", "preferred_username": "Carol", "realm_access": {"roles": ["staff", "talos-editors"]}});
+
+        // The first account is never created through the provider
+        let (to, _) = browser.sign_in(carol.clone(), &key, |_| {}).await;
+        assert!(to.contains("first+administrator"), "{}", to);
+        users::insert_user(&pool, "alice", "x", Role::Admin).await.unwrap();
+
+        let (to, cookie) = browser.sign_in(carol.clone(), &key, |_| {}).await;
+        assert_eq!(to, "/");
+        let status = browser.status(&cookie.unwrap()).await;
+        assert_eq!((status["authenticated"].as_bool(), status["auth_method"].as_str()), (Some(true), Some("oidc")));
+        assert_eq!((status["username"].as_str(), status["role"].as_str()), (Some("carol"), Some("editor")));
+        let method: String = sqlx::query_scalar("SELECT auth_method FROM sessions WHERE user_id IS NOT NULL").fetch_one(&pool).await.unwrap();
+        assert_eq!(method, "oidc");
+
+        // The same subject is the same account; its role follows the claims
+        let promoted = json!({"sub": "0001", "preferred_username": "renamed", "realm_access": {"roles": ["talos-admins"]}});
+        let (_, cookie) = browser.sign_in(promoted, &key, |_| {}).await;
+        let status = browser.status(&cookie.unwrap()).await;
+        assert_eq!((status["username"].as_str(), status["role"].as_str()), (Some("carol"), Some("admin")));
+        let (_, cookie) = browser.sign_in(json!({"sub": "0002", "email": "dave@example.com"}), &key, |_| {}).await;
+        assert_eq!(browser.status(&cookie.unwrap()).await["role"], "viewer");
+        assert_eq!(users::user_count(&pool).await, 3);
+
+        // Refused: forged state, foreign signing key, wrong nonce or audience,
+        // and a provider account named like a local one
+        let refusals = [
+            (carol.clone(), key.to_vec(), true, "Invalid+sign-in+state"),
+            (carol.clone(), generate_key(), false, "Invalid+ID+token"),
+            (json!({"sub": "0001", "nonce": "other"}), key.to_vec(), false, "nonce+mismatch"),
+            (json!({"sub": "0001", "aud": "other-client"}), key.to_vec(), false, "Invalid+ID+token"),
+            (json!({"sub": "0003", "preferred_username": "alice"}), key.to_vec(), false, "belongs+to+another+account"),
+        ];
+        for (claims, signing_key, forge_state, reason) in refusals {
+            let (to, cookie) = browser.sign_in(claims, &signing_key, |params| {
+                if forge_state {
+                    params.insert("state".to_string(), "forged".to_string());
+                }
+            }).await;
+            assert!(to.starts_with("/?sso_error=") && to.contains(reason), "expected {}, got {}", reason, to);
+            assert!(cookie.is_none());
+        }
+        assert_eq!(users::user_count(&pool).await, 3);
+        let created: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM audit_logs WHERE action = 'USER_CREATE'").fetch_one(&pool).await.unwrap();
+        assert_eq!(created, 2);
+    }
+
+    #[test]
+    fn roles_come_from_the_configured_claim() {
+        let mut config = OidcConfig {
+            issuer: String::new(), client_id: String::new(), client_secret: None, redirect_url: String::new(),
+            scopes: String::new(), username_claim: String::new(),
+            role_claim: "https://talos/groups".to_string(),
+            role_map: parse_role_map("ops=editor,talos=auditor,ops=admin").unwrap(),
+            default_role: None,
+        };
+        assert_eq!(map_role(&config, &json!({"https://talos/groups": ["talos", "ops"]})), Some(Role::Editor));
+        assert_eq!(map_role(&config, &json!({"https://talos/groups": "talos"})), Some(Role::Auditor));
+        assert_eq!(map_role(&config, &json!({"https://talos/groups": ["other"]})), None);
+        config.default_role = Some(Role::Viewer);
+        assert_eq!(map_role(&config, &json!({})), Some(Role::Viewer));
+
+        assert!(parse_role_map("ops").is_err());
+        assert!(parse_role_map("ops=root").is_err());
+        assert_eq!(parse_role_map(" a=b=Admin ,").unwrap(), vec![("a=b".to_string(), Role::Admin)]);
+    }
+}
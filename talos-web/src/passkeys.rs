@@ -0,0 +1,392 @@
+use axum::Json;
+use axum::extract::{ConnectInfo, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use data_encoding::BASE64URL_NOPAD;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::env;
+use std::net::SocketAddr;
+use tower_sessions::Session;
+use webauthn_rs::prelude::*;
+use crate::db::DbPool;
+use crate::handlers::{is_debug, log_audit};
+use crate::state::AppState;
+use crate::throttle;
+use crate::users::{self, current_user, start_session, User};
+
+const REGISTRATION_KEY: &str = "passkey_registration";
+const AUTHENTICATION_KEY: &str = "passkey_authentication";
+const MAX_NAME_LEN: usize = 64;
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn api_error(status: StatusCode, message: &str) -> ApiError {
+    (status, Json(json!({"error": message})))
+}
+
+// The relying party is the address users open Talos at. Passkeys are bound
+// to its host name, so changing it later invalidates every registered key.
+pub fn webauthn_from_env() -> Webauthn {
+    let origin = env::var("WEBAUTHN_ORIGIN").unwrap_or_else(|_| "https://localhost:3443".to_string());
+    let rp_id = env::var("WEBAUTHN_RP_ID").ok();
+    build_webauthn(&origin, rp_id.as_deref()).unwrap_or_else(|e| panic!("Invalid WebAuthn configuration: {}", e))
+}
+
+pub fn build_webauthn(origin: &str, rp_id: Option<&str>) -> Result<Webauthn, String> {
+    let origin = Url::parse(origin).map_err(|e| format!("WEBAUTHN_ORIGIN: {}", e))?;
+    let host = origin.host_str().ok_or("WEBAUTHN_ORIGIN has no host")?.to_string();
+    WebauthnBuilder::new(rp_id.unwrap_or(&host), &origin)
+        .and_then(|builder| builder.rp_name("Talos").build())
+        .map_err(|e| e.to_string())
+}
+
+fn credential_key(id: &[u8]) -> String {
+    BASE64URL_NOPAD.encode(id)
+}
+
+fn normalize_passkey_name(name: Option<&str>) -> Result<String, String> {
+    let name = name.map(str::trim).filter(|n| !n.is_empty()).unwrap_or("Passkey");
+    if name.chars().count() > MAX_NAME_LEN || name.chars().any(char::is_control) {
+        return Err(format!("Passkey names are at most {} printable characters", MAX_NAME_LEN));
+    }
+    Ok(name.to_string())
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct PasskeyInfo {
+    pub id: i64,
+    pub name: String,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+async fn stored_passkeys(pool: &DbPool, user_id: i64) -> Vec<Passkey> {
+    sqlx::query_scalar::<_, String>("SELECT passkey FROM webauthn_credentials WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|passkey| serde_json::from_str(passkey).ok())
+        .collect()
+}
+
+// Authenticators keep one user handle per account, so every credential of an
+// account reuses the handle of its first one
+async fn user_handle(pool: &DbPool, user_id: i64) -> Uuid {
+    sqlx::query_scalar::<_, String>("SELECT user_handle FROM webauthn_credentials WHERE user_id = ? LIMIT 1")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .and_then(|handle| Uuid::parse_str(&handle).ok())
+        .unwrap_or_else(Uuid::new_v4)
+}
+
+#[derive(Serialize, Deserialize)]
+struct PendingRegistration {
+    name: String,
+    handle: Uuid,
+    state: PasskeyRegistration,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PendingAuthentication {
+    user_id: i64,
+    state: PasskeyAuthentication,
+}
+
+async fn begin_registration(webauthn: &Webauthn, pool: &DbPool, user: &User, name: String) -> WebauthnResult<(CreationChallengeResponse, PendingRegistration)> {
+    let existing: Vec<CredentialID> = stored_passkeys(pool, user.id).await.iter().map(|p| p.cred_id().clone()).collect();
+    let handle = user_handle(pool, user.id).await;
+    let (challenge, state) = webauthn.start_passkey_registration(handle, &user.username, &user.username, Some(existing))?;
+    Ok((challenge, PendingRegistration { name, handle, state }))
+}
+
+async fn finish_registration(webauthn: &Webauthn, pool: &DbPool, user: &User, credential: &RegisterPublicKeyCredential, pending: &PendingRegistration) -> Result<i64, String> {
+    let passkey = webauthn.finish_passkey_registration(credential, &pending.state).map_err(|e| e.to_string())?;
+    let result = sqlx::query("INSERT INTO webauthn_credentials (user_id, user_handle, credential_id, name, passkey) VALUES (?, ?, ?, ?, ?)")
+        .bind(user.id)
+        .bind(pending.handle.to_string())
+        .bind(credential_key(passkey.cred_id()))
+        .bind(&pending.name)
+        .bind(serde_json::to_string(&passkey).map_err(|e| e.to_string())?)
+        .execute(pool)
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(result.last_insert_rowid())
+}
+
+// None when the account does not exist, is disabled or has no passkey; the
+// caller answers all three the same way
+async fn begin_authentication(webauthn: &Webauthn, pool: &DbPool, username: &str) -> Option<(RequestChallengeResponse, PendingAuthentication)> {
+    let user_id: i64 = sqlx::query_scalar("SELECT id FROM users WHERE username = ? AND disabled = 0")
+        .bind(username.trim())
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()?;
+    let passkeys = stored_passkeys(pool, user_id).await;
+    if passkeys.is_empty() {
+        return None;
+    }
+    let (challenge, state) = webauthn.start_passkey_authentication(&passkeys).ok()?;
+    Some((challenge, PendingAuthentication { user_id, state }))
+}
+
+async fn finish_authentication(webauthn: &Webauthn, pool: &DbPool, credential: &PublicKeyCredential, pending: &PendingAuthentication) -> Option<User> {
+    let result = webauthn.finish_passkey_authentication(credential, &pending.state).ok()?;
+    let user = users::find_user(pool, pending.user_id).await.filter(|user| !user.disabled)?;
+    let key = credential_key(result.cred_id());
+    let (row_id, stored): (i64, String) = sqlx::query_as("SELECT id, passkey FROM webauthn_credentials WHERE credential_id = ? AND user_id = ?")
+        .bind(&key)
+        .bind(pending.user_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()?;
+
+    // Keep the signature counter current so a cloned authenticator is noticed
+    let mut passkey: Passkey = serde_json::from_str(&stored).ok()?;
+    passkey.update_credential(&result);
+    let _ = sqlx::query("UPDATE webauthn_credentials SET passkey = ?, last_used_at = CURRENT_TIMESTAMP WHERE id = ?")
+        .bind(serde_json::to_string(&passkey).ok()?)
+        .bind(row_id)
+        .execute(pool)
+        .await;
+    let _ = sqlx::query("UPDATE users SET last_login = CURRENT_TIMESTAMP WHERE id = ?")
+        .bind(user.id)
+        .execute(pool)
+        .await;
+    Some(user)
+}
+
+#[derive(Deserialize)]
+pub struct RegisterStartRequest {
+    name: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct RenamePasskeyRequest {
+    id: i64,
+    name: String,
+}
+
+#[derive(Deserialize)]
+pub struct DeletePasskeyRequest {
+    id: i64,
+}
+
+#[derive(Deserialize)]
+pub struct PasskeyLoginRequest {
+    username: String,
+}
+
+pub async fn list_passkeys(State(state): State<AppState>, session: Session) -> Result<Json<Vec<PasskeyInfo>>, ApiError> {
+    let user = current_user(&state, &session).await.ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "Not authenticated"))?;
+    let passkeys = sqlx::query_as::<_, PasskeyInfo>("SELECT id, name, created_at, last_used_at FROM webauthn_credentials WHERE user_id = ? ORDER BY created_at")
+        .bind(user.id)
+        .fetch_all(&state.pool)
+        .await
+        .unwrap_or_default();
+    Ok(Json(passkeys))
+}
+
+// Registration challenge for the signed-in account; keys it already has are
+// excluded so the same authenticator is not registered twice
+pub async fn register_start(
+    State(state): State<AppState>,
+    session: Session,
+    Json(req): Json<RegisterStartRequest>
+) -> Result<Json<CreationChallengeResponse>, ApiError> {
+    let user = current_user(&state, &session).await.ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "Not authenticated"))?;
+    if is_debug() { println!("--> [WEB] Processing PASSKEY REGISTER START"); }
+    let name = normalize_passkey_name(req.name.as_deref()).map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+
+    let (challenge, pending) = begin_registration(&state.webauthn, &state.pool, &user, name).await
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to start registration"))?;
+    session.insert(REGISTRATION_KEY, pending).await
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to start registration"))?;
+    Ok(Json(challenge))
+}
+
+pub async fn register_finish(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(credential): Json<RegisterPublicKeyCredential>
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let user = current_user(&state, &session).await.ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "Not authenticated"))?;
+    let ua_header = headers.get(header::USER_AGENT);
+    // A challenge is answered once, successful or not
+    let pending: PendingRegistration = session.remove(REGISTRATION_KEY).await.ok().flatten()
+        .ok_or_else(|| api_error(StatusCode::CONFLICT, "No registration in progress"))?;
+
+    match finish_registration(&state.webauthn, &state.pool, &user, &credential, &pending).await {
+        Ok(id) => {
+            let target = format!("{}: {}", user.username, pending.name);
+            log_audit(&state, &session, Some(addr.ip()), ua_header, "PASSKEY_REGISTER", &target).await;
+            Ok((StatusCode::CREATED, Json(json!({"id": id, "name": pending.name}))))
+        },
+        Err(e) => {
+            if is_debug() { println!("--> [WEB] Passkey registration rejected: {}", e); }
+            log_audit(&state, &session, Some(addr.ip()), ua_header, "PASSKEY_REGISTER_FAILURE", &user.username).await;
+            Err(api_error(StatusCode::BAD_REQUEST, "Passkey registration failed"))
+        }
+    }
+}
+
+pub async fn rename_passkey(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<RenamePasskeyRequest>
+) -> Result<Json<Value>, ApiError> {
+    let user = current_user(&state, &session).await.ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "Not authenticated"))?;
+    let name = normalize_passkey_name(Some(&req.name)).map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+    let result = sqlx::query("UPDATE webauthn_credentials SET name = ? WHERE id = ? AND user_id = ?")
+        .bind(&name)
+        .bind(req.id)
+        .bind(user.id)
+        .execute(&state.pool)
+        .await
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to rename passkey"))?;
+    if result.rows_affected() == 0 {
+        return Err(api_error(StatusCode::NOT_FOUND, "Passkey not found"));
+    }
+    let target = format!("{}: {}", user.username, name);
+    log_audit(&state, &session, Some(addr.ip()), headers.get(header::USER_AGENT), "PASSKEY_RENAME", &target).await;
+    Ok(Json(json!({"status": "renamed"})))
+}
+
+// Revokes one of the caller's own passkeys
+pub async fn delete_passkey(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<DeletePasskeyRequest>
+) -> Result<Json<Value>, ApiError> {
+    let user = current_user(&state, &session).await.ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "Not authenticated"))?;
+    let name: String = sqlx::query_scalar("DELETE FROM webauthn_credentials WHERE id = ? AND user_id = ? RETURNING name")
+        .bind(req.id)
+        .bind(user.id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to delete passkey"))?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Passkey not found"))?;
+    let target = format!("{}: {}", user.username, name);
+    log_audit(&state, &session, Some(addr.ip()), headers.get(header::USER_AGENT), "PASSKEY_DELETE", &target).await;
+    Ok(Json(json!({"status": "deleted"})))
+}
+
+pub async fn login_start(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<PasskeyLoginRequest>
+) -> Result<Json<RequestChallengeResponse>, ApiError> {
+    if let Err(retry_after) = throttle::check(&state.pool, addr.ip(), Some(&req.username)).await {
+        let target: String = req.username.trim().chars().take(64).collect();
+        log_audit(&state, &session, Some(addr.ip()), headers.get(header::USER_AGENT), "LOGIN_RATE_LIMITED", &target).await;
+        return Err(throttle::too_many_attempts(retry_after));
+    }
+    let (challenge, pending) = begin_authentication(&state.webauthn, &state.pool, &req.username).await
+        .ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "No passkey available for this account"))?;
+    session.insert(AUTHENTICATION_KEY, pending).await
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to start login"))?;
+    Ok(Json(challenge))
+}
+
+// Passkeys verify the user on the authenticator, so they are a complete
+// login on their own and skip the TOTP step
+pub async fn login_finish(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(credential): Json<PublicKeyCredential>
+) -> Result<Json<Value>, ApiError> {
+    let ua_header = headers.get(header::USER_AGENT);
+    let pending: PendingAuthentication = session.remove(AUTHENTICATION_KEY).await.ok().flatten()
+        .ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "No login in progress"))?;
+
+    let Some(user) = finish_authentication(&state.webauthn, &state.pool, &credential, &pending).await else {
+        let target = users::find_user(&state.pool, pending.user_id).await.map(|u| u.username).unwrap_or_default();
+        throttle::fail(&state, &session, addr.ip(), ua_header, Some(&target)).await;
+        log_audit(&state, &session, Some(addr.ip()), ua_header, "LOGIN_PASSKEY_FAILURE", &target).await;
+        return Err(api_error(StatusCode::UNAUTHORIZED, "Passkey verification failed"));
+    };
+
+    let csrf_token = start_session(&session, &user, "webauthn", addr, ua_header).await
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to start session"))?;
+    throttle::record_success(&state.pool, &user.username).await;
+    log_audit(&state, &session, Some(addr.ip()), ua_header, "LOGIN_SUCCESS", &user.username).await;
+    Ok(Json(json!({"status": "Logged in", "username": user.username, "csrf_token": csrf_token})))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::Role;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use webauthn_authenticator_rs::WebauthnAuthenticator;
+    use webauthn_authenticator_rs::softpasskey::SoftPasskey;
+
+    const ORIGIN: &str = "https://vault.example.com";
+
+    // Runs both ceremonies against a software authenticator, the way a
+    // browser would relay them
+    #[tokio::test]
+    async fn passkeys_register_and_authenticate() {
+        let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        crate::db::migrate(&pool).await;
+        let alice_id = users::insert_user(&pool, "alice", "x", Role::Editor).await.unwrap();
+        users::insert_user(&pool, "bob", "x", Role::Editor).await.unwrap();
+        let alice = users::find_user(&pool, alice_id).await.unwrap();
+
+        let webauthn = build_webauthn(ORIGIN, None).unwrap();
+        let origin = Url::parse(ORIGIN).unwrap();
+        let mut authenticator = WebauthnAuthenticator::new(SoftPasskey::new(true));
+
+        let (challenge, pending) = begin_registration(&webauthn, &pool, &alice, "YubiKey".to_string()).await.unwrap();
+        let credential = authenticator.do_registration(origin.clone(), challenge).unwrap();
+        finish_registration(&webauthn, &pool, &alice, &credential, &pending).await.unwrap();
+        assert!(finish_registration(&webauthn, &pool, &alice, &credential, &pending).await.is_err(), "credential ids are unique");
+
+        // A second key of the same account shares its user handle
+        let mut second = WebauthnAuthenticator::new(SoftPasskey::new(true));
+        let (challenge, pending) = begin_registration(&webauthn, &pool, &alice, "Laptop".to_string()).await.unwrap();
+        assert_eq!(challenge.public_key.exclude_credentials.as_ref().map(Vec::len), Some(1));
+        let credential = second.do_registration(origin.clone(), challenge).unwrap();
+        finish_registration(&webauthn, &pool, &alice, &credential, &pending).await.unwrap();
+        let handles: i64 = sqlx::query_scalar("SELECT COUNT(DISTINCT user_handle) FROM webauthn_credentials").fetch_one(&pool).await.unwrap();
+        assert_eq!(handles, 1);
+
+        assert!(begin_authentication(&webauthn, &pool, "bob").await.is_none());
+        assert!(begin_authentication(&webauthn, &pool, "nobody").await.is_none());
+
+        let (challenge, pending) = begin_authentication(&webauthn, &pool, "alice").await.unwrap();
+        let assertion = authenticator.do_authentication(origin.clone(), challenge).unwrap();
+        let user = finish_authentication(&webauthn, &pool, &assertion, &pending).await.unwrap();
+        assert_eq!(user.id, alice_id);
+        let used: Option<String> = sqlx::query_scalar("SELECT last_used_at FROM webauthn_credentials WHERE name = 'YubiKey'").fetch_one(&pool).await.unwrap();
+        assert!(used.is_some());
+
+        // An assertion only answers the challenge it was made for
+        let (_, other) = begin_authentication(&webauthn, &pool, "alice").await.unwrap();
+        assert!(finish_authentication(&webauthn, &pool, &assertion, &other).await.is_none());
+
+        // Revoked keys and disabled accounts no longer sign in
+        sqlx::query("DELETE FROM webauthn_credentials WHERE name = 'YubiKey'").execute(&pool).await.unwrap();
+        let (challenge, pending) = begin_authentication(&webauthn, &pool, "alice").await.unwrap();
+        let assertion = second.do_authentication(origin.clone(), challenge).unwrap();
+        sqlx::query("UPDATE users SET disabled = 1 WHERE id = ?").bind(alice_id).execute(&pool).await.unwrap();
+        assert!(finish_authentication(&webauthn, &pool, &assertion, &pending).await.is_none());
+        assert!(begin_authentication(&webauthn, &pool, "alice").await.is_none());
+    }
+}
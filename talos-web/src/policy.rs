@@ -0,0 +1,553 @@
+use axum::Json;
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{HeaderMap, Method, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use tower_sessions::Session;
+use crate::db::DbPool;
+use crate::handlers::{is_debug, log_audit, log_audit_with_severity};
+use crate::state::AppState;
+use crate::users::User;
+
+#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy, PartialEq, Eq, Debug)]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    /// Everything, including accounts, backups and the audit log
+    Admin,
+    /// Reads and writes secrets within their grants
+    Editor,
+    /// Reads secrets within their grants, write grants count as read
+    Viewer,
+    /// Reads the audit log, no access to secrets
+    Auditor,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Admin => "admin",
+            Role::Editor => "editor",
+            Role::Viewer => "viewer",
+            Role::Auditor => "auditor",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, sqlx::Type, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+#[sqlx(type_name = "TEXT", rename_all = "lowercase")]
+#[serde(rename_all = "lowercase")]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl Access {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Access::Read => "read",
+            Access::Write => "write",
+        }
+    }
+}
+
+#[derive(Serialize, sqlx::FromRow, Clone, Debug)]
+pub struct Grant {
+    pub pattern: String,
+    pub access: Access,
+}
+
+/// Limits of an API token, on top of its owner's role and grants
+#[derive(Clone, Debug)]
+pub struct TokenScope {
+    pub access: Access,
+    // Pattern the token is confined to, `None` for the whole store
+    pub pattern: Option<String>,
+}
+
+impl TokenScope {
+    fn allows(&self, access: Access, path: &str) -> bool {
+        access <= self.access && self.pattern.as_deref().is_none_or(|pattern| pattern_matches(pattern, path))
+    }
+}
+
+// What the signed-in user may do, loaded per request by `require_auth`
+#[derive(Clone, Debug)]
+pub struct Policy {
+    pub user: User,
+    grants: Vec<Grant>,
+    scope: Option<TokenScope>,
+}
+
+// `*` is the whole store, `folder/*` a folder and everything below it,
+// anything else a single secret or folder
+pub fn pattern_matches(pattern: &str, path: &str) -> bool {
+    let path = path.trim_matches('/');
+    if pattern == "*" {
+        return true;
+    }
+    match pattern.strip_suffix("/*") {
+        Some(folder) => path == folder || path.starts_with(&format!("{}/", folder)),
+        None => path == pattern,
+    }
+}
+
+pub fn normalize_pattern(pattern: &str) -> Result<String, String> {
+    let pattern = pattern.trim().trim_start_matches('/');
+    if pattern == "*" {
+        return Ok(pattern.to_string());
+    }
+    let folder = pattern.strip_suffix("/*").unwrap_or(pattern).trim_end_matches('/');
+    let valid = !folder.is_empty() && folder.split('/').all(|part| !part.is_empty() && part != "." && part != ".." && !part.contains('*'));
+    if !valid {
+        return Err("Pattern must be `*`, `folder/*` or a path without wildcards".to_string());
+    }
+    Ok(if pattern.ends_with("/*") { format!("{}/*", folder) } else { folder.to_string() })
+}
+
+impl Policy {
+    pub async fn load(pool: &DbPool, user: User) -> Policy {
+        let grants = sqlx::query_as::<_, Grant>(
+            "SELECT pattern, access FROM grants WHERE user_id = ?1
+             UNION ALL
+             SELECT g.pattern, g.access FROM grants g JOIN team_members m ON m.team_id = g.team_id WHERE m.user_id = ?1",
+        )
+            .bind(user.id)
+            .fetch_all(pool)
+            .await
+            .unwrap_or_default();
+        Policy { user, grants, scope: None }
+    }
+
+    // Requests authenticated with an API token only get what the token allows
+    pub fn restricted_to(mut self, scope: TokenScope) -> Policy {
+        self.scope = Some(scope);
+        self
+    }
+
+    // Whether no token limit applies to `access` anywhere in the store
+    pub fn unscoped(&self, access: Access) -> bool {
+        self.scope.as_ref().is_none_or(|scope| access <= scope.access && scope.pattern.is_none())
+    }
+
+    // Operations on the store as a whole: administrators, with no token limits
+    pub fn covers_store(&self, access: Access) -> bool {
+        self.user.role == Role::Admin && self.unscoped(access)
+    }
+
+    pub fn role(&self) -> Role {
+        self.user.role
+    }
+
+    pub fn allows(&self, access: Access, path: &str) -> bool {
+        let by_role = match self.user.role {
+            Role::Admin => true,
+            Role::Auditor => false,
+            Role::Viewer if access == Access::Write => false,
+            Role::Editor | Role::Viewer => self.grants.iter().any(|g| g.access >= access && pattern_matches(&g.pattern, path)),
+        };
+        by_role && self.scope.as_ref().is_none_or(|scope| scope.allows(access, path))
+    }
+
+    // Folders leading to a granted path stay visible so it can be reached
+    fn leads_to_grant(&self, folder: &str) -> bool {
+        let folder = folder.trim_matches('/');
+        let below = |pattern: &str| pattern.starts_with(&format!("{}/", folder)) || pattern_matches(pattern, folder);
+        let by_role = match self.user.role {
+            Role::Admin => true,
+            Role::Auditor => false,
+            Role::Editor | Role::Viewer => self.grants.iter().any(|g| below(&g.pattern)),
+        };
+        by_role && self.scope.as_ref().is_none_or(|scope| scope.pattern.as_deref().is_none_or(below))
+    }
+
+    // Drops the nodes of Storage's tree the user may not read
+    pub fn filter_tree(&self, nodes: Vec<Value>) -> Vec<Value> {
+        nodes.into_iter().filter_map(|mut node| {
+            let path = node["path"].as_str().unwrap_or_default().to_string();
+            if self.allows(Access::Read, &path) {
+                return Some(node);
+            }
+            if !node["is_dir"].as_bool().unwrap_or(false) {
+                return None;
+            }
+            let children = match node["children"].take() {
+                Value::Array(children) => self.filter_tree(children),
+                _ => Vec::new(),
+            };
+            if children.is_empty() && !self.leads_to_grant(&path) {
+                return None;
+            }
+            node["children"] = Value::Array(children);
+            Some(node)
+        }).collect()
+    }
+}
+
+// Records the refusal and answers 403
+pub async fn deny(
+    state: &AppState,
+    session: &Session,
+    addr: SocketAddr,
+    headers: &HeaderMap,
+    action: &str,
+    target: &str,
+) -> (StatusCode, Json<Value>) {
+    if is_debug() { println!("--> [WEB] ACCESS DENIED: {} {}", action, target); }
+    log_audit_with_severity(state, session, Some(addr.ip()), headers.get(header::USER_AGENT), "ACCESS_DENIED", &format!("{} {}", action, target), "warning").await;
+    (StatusCode::FORBIDDEN, Json(json!({"error": "Access denied"})))
+}
+
+// Checks `access` to every path, auditing the first refusal
+#[allow(clippy::too_many_arguments)]
+pub async fn authorize(
+    state: &AppState,
+    session: &Session,
+    addr: SocketAddr,
+    headers: &HeaderMap,
+    policy: &Policy,
+    access: Access,
+    action: &str,
+    paths: &[&str],
+) -> Result<(), (StatusCode, Json<Value>)> {
+    match paths.iter().find(|path| !policy.allows(access, path)) {
+        Some(path) => Err(deny(state, session, addr, headers, action, path).await),
+        None => Ok(()),
+    }
+}
+
+async fn require_role(
+    roles: &[Role],
+    state: AppState,
+    session: Session,
+    addr: SocketAddr,
+    request: Request,
+    next: Next,
+) -> Response {
+    let access = if request.method() == Method::GET { Access::Read } else { Access::Write };
+    let allowed = request.extensions().get::<Policy>().is_some_and(|policy| roles.contains(&policy.role()) && policy.unscoped(access));
+    if allowed {
+        return next.run(request).await;
+    }
+    let target = request.uri().path().to_string();
+    deny(&state, &session, addr, request.headers(), request.method().as_str(), &target).await.into_response()
+}
+
+// Whole-store operations: accounts, backups, restores, exports, unsealing.
+// API tokens confined to a folder or to reading cannot reach them.
+pub async fn require_admin(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    require_role(&[Role::Admin], state, session, addr, request, next).await
+}
+
+pub async fn require_audit_access(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Response {
+    require_role(&[Role::Admin, Role::Auditor], state, session, addr, request, next).await
+}
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn api_error(status: StatusCode, message: &str) -> ApiError {
+    (status, Json(json!({"error": message})))
+}
+
+fn db_error(e: sqlx::Error) -> ApiError {
+    match e {
+        sqlx::Error::Database(e) if e.is_unique_violation() => api_error(StatusCode::CONFLICT, "Already exists"),
+        sqlx::Error::Database(e) if e.is_foreign_key_violation() => api_error(StatusCode::NOT_FOUND, "User or team not found"),
+        e => {
+            println!("❌ [WEB] Access policy update failed: {}", e);
+            api_error(StatusCode::INTERNAL_SERVER_ERROR, "Database error")
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct TeamEntry {
+    id: i64,
+    name: String,
+    members: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct TeamRequest {
+    name: String,
+}
+
+#[derive(Deserialize)]
+pub struct IdRequest {
+    id: i64,
+}
+
+#[derive(Deserialize)]
+pub struct MemberRequest {
+    team_id: i64,
+    user_id: i64,
+    // false removes the user from the team
+    #[serde(default = "default_true")]
+    member: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+pub struct GrantRequest {
+    user_id: Option<i64>,
+    team_id: Option<i64>,
+    pattern: String,
+    access: Access,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct GrantEntry {
+    id: i64,
+    user_id: Option<i64>,
+    username: Option<String>,
+    team_id: Option<i64>,
+    team: Option<String>,
+    pattern: String,
+    access: Access,
+}
+
+pub async fn list_teams(State(state): State<AppState>) -> Json<Vec<TeamEntry>> {
+    let rows: Vec<(i64, String, Option<String>)> = sqlx::query_as(
+        "SELECT t.id, t.name, u.username FROM teams t
+         LEFT JOIN team_members m ON m.team_id = t.id LEFT JOIN users u ON u.id = m.user_id
+         ORDER BY t.name, u.username",
+    )
+        .fetch_all(&state.pool)
+        .await
+        .unwrap_or_default();
+    let mut teams: Vec<TeamEntry> = Vec::new();
+    for (id, name, member) in rows {
+        if teams.last().is_none_or(|team| team.id != id) {
+            teams.push(TeamEntry { id, name, members: Vec::new() });
+        }
+        if let (Some(team), Some(member)) = (teams.last_mut(), member) {
+            team.members.push(member);
+        }
+    }
+    Json(teams)
+}
+
+pub async fn create_team(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<TeamRequest>
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let name = crate::users::normalize_name("Team name", &req.name).map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+    let id = sqlx::query("INSERT INTO teams (name) VALUES (?)")
+        .bind(&name)
+        .execute(&state.pool)
+        .await
+        .map_err(db_error)?
+        .last_insert_rowid();
+    log_audit(&state, &session, Some(addr.ip()), headers.get(header::USER_AGENT), "TEAM_CREATE", &name).await;
+    Ok((StatusCode::CREATED, Json(json!({"id": id, "name": name}))))
+}
+
+// Removes the team along with its memberships and grants
+pub async fn delete_team(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<IdRequest>
+) -> Result<Json<Value>, ApiError> {
+    let name: String = sqlx::query_scalar("SELECT name FROM teams WHERE id = ?")
+        .bind(req.id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Team not found"))?;
+    sqlx::query("DELETE FROM teams WHERE id = ?").bind(req.id).execute(&state.pool).await.map_err(db_error)?;
+    log_audit(&state, &session, Some(addr.ip()), headers.get(header::USER_AGENT), "TEAM_DELETE", &name).await;
+    Ok(Json(json!({"status": "deleted"})))
+}
+
+pub async fn set_team_member(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<MemberRequest>
+) -> Result<Json<Value>, ApiError> {
+    let query = if req.member {
+        "INSERT OR IGNORE INTO team_members (team_id, user_id) VALUES (?, ?)"
+    } else {
+        "DELETE FROM team_members WHERE team_id = ? AND user_id = ?"
+    };
+    sqlx::query(query).bind(req.team_id).bind(req.user_id).execute(&state.pool).await.map_err(db_error)?;
+
+    let names: Option<(String, String)> = sqlx::query_as("SELECT t.name, u.username FROM teams t, users u WHERE t.id = ? AND u.id = ?")
+        .bind(req.team_id)
+        .bind(req.user_id)
+        .fetch_optional(&state.pool)
+        .await
+        .unwrap_or(None);
+    let target = names.map(|(team, user)| format!("{} {}", team, user)).unwrap_or_else(|| format!("team {} user {}", req.team_id, req.user_id));
+    let action = if req.member { "TEAM_MEMBER_ADD" } else { "TEAM_MEMBER_REMOVE" };
+    log_audit(&state, &session, Some(addr.ip()), headers.get(header::USER_AGENT), action, &target).await;
+    Ok(Json(json!({"status": "updated"})))
+}
+
+pub async fn list_grants(State(state): State<AppState>) -> Json<Vec<GrantEntry>> {
+    let grants = sqlx::query_as::<_, GrantEntry>(
+        "SELECT g.id, g.user_id, u.username, g.team_id, t.name AS team, g.pattern, g.access FROM grants g
+         LEFT JOIN users u ON u.id = g.user_id LEFT JOIN teams t ON t.id = g.team_id
+         ORDER BY g.pattern, g.id",
+    )
+        .fetch_all(&state.pool)
+        .await
+        .unwrap_or_default();
+    Json(grants)
+}
+
+pub async fn create_grant(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<GrantRequest>
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    if req.user_id.is_some() == req.team_id.is_some() {
+        return Err(api_error(StatusCode::BAD_REQUEST, "Give either user_id or team_id"));
+    }
+    let pattern = normalize_pattern(&req.pattern).map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+    let id = sqlx::query("INSERT INTO grants (user_id, team_id, pattern, access) VALUES (?, ?, ?, ?)")
+        .bind(req.user_id)
+        .bind(req.team_id)
+        .bind(&pattern)
+        .bind(req.access)
+        .execute(&state.pool)
+        .await
+        .map_err(db_error)?
+        .last_insert_rowid();
+
+    let subject = match (req.user_id, req.team_id) {
+        (Some(user_id), _) => sqlx::query_scalar::<_, String>("SELECT username FROM users WHERE id = ?").bind(user_id).fetch_one(&state.pool).await
+            .map(|name| format!("user {}", name)),
+        (_, Some(team_id)) => sqlx::query_scalar::<_, String>("SELECT name FROM teams WHERE id = ?").bind(team_id).fetch_one(&state.pool).await
+            .map(|name| format!("team {}", name)),
+        _ => unreachable!(),
+    }.unwrap_or_default();
+    let target = format!("{} {} {}", subject, req.access.as_str(), pattern);
+    log_audit(&state, &session, Some(addr.ip()), headers.get(header::USER_AGENT), "GRANT_CREATE", &target).await;
+    Ok((StatusCode::CREATED, Json(json!({"id": id, "pattern": pattern}))))
+}
+
+pub async fn delete_grant(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<IdRequest>
+) -> Result<Json<Value>, ApiError> {
+    let grant: (String, Access) = sqlx::query_as("SELECT pattern, access FROM grants WHERE id = ?")
+        .bind(req.id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(db_error)?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Grant not found"))?;
+    sqlx::query("DELETE FROM grants WHERE id = ?").bind(req.id).execute(&state.pool).await.map_err(db_error)?;
+    let target = format!("#{} {} {}", req.id, grant.1.as_str(), grant.0);
+    log_audit(&state, &session, Some(addr.ip()), headers.get(header::USER_AGENT), "GRANT_DELETE", &target).await;
+    Ok(Json(json!({"status": "deleted"})))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[test]
+    fn patterns_cover_folders_and_secrets() {
+        assert!(pattern_matches("*", "anything/at/all"));
+        assert!(pattern_matches("prod/db/*", "prod/db"));
+        assert!(pattern_matches("prod/db/*", "prod/db/root"));
+        assert!(pattern_matches("prod/db/*", "/prod/db/replica/password"));
+        assert!(!pattern_matches("prod/db/*", "prod/dbx/root"));
+        assert!(!pattern_matches("prod/db/*", "prod"));
+        assert!(pattern_matches("prod/db/root", "prod/db/root"));
+        assert!(!pattern_matches("prod/db/root", "prod/db/root/other"));
+
+        assert_eq!(normalize_pattern("/staging/").unwrap(), "staging");
+        assert_eq!(normalize_pattern("staging//*").unwrap(), "staging/*");
+        assert!(normalize_pattern("prod/*/db").is_err());
+        assert!(normalize_pattern("../etc/*").is_err());
+        assert!(normalize_pattern("").is_err());
+    }
+
+    #[tokio::test]
+    async fn grants_decide_per_path_and_role() {
+        let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        crate::db::migrate(&pool).await;
+        for (name, role) in [("root", "admin"), ("dana", "editor"), ("vic", "viewer"), ("aud", "auditor")] {
+            sqlx::query("INSERT INTO users (username, password_hash, role) VALUES (?, 'x', ?)").bind(name).bind(role).execute(&pool).await.unwrap();
+        }
+        sqlx::query("INSERT INTO teams (name) VALUES ('team-db')").execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO team_members (team_id, user_id) SELECT 1, id FROM users WHERE username IN ('dana', 'vic')").execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO grants (team_id, pattern, access) VALUES (1, 'prod/db/*', 'read'), (1, 'staging/*', 'write')").execute(&pool).await.unwrap();
+        sqlx::query("INSERT INTO grants (user_id, pattern, access) VALUES (4, '*', 'write')").execute(&pool).await.unwrap();
+
+        let policy = |id| {
+            let pool = pool.clone();
+            async move { Policy::load(&pool, crate::users::find_user(&pool, id).await.unwrap()).await }
+        };
+        let (root, dana, vic, aud) = (policy(1).await, policy(2).await, policy(3).await, policy(4).await);
+
+        assert!(root.allows(Access::Write, "prod/db/root"));
+        assert!(dana.allows(Access::Read, "prod/db/root"));
+        assert!(!dana.allows(Access::Write, "prod/db/root"));
+        assert!(dana.allows(Access::Write, "staging/web/token"));
+        assert!(!dana.allows(Access::Read, "prod/web/token"));
+        assert!(vic.allows(Access::Read, "staging/web/token"));
+        assert!(!vic.allows(Access::Write, "staging/web/token"));
+        assert!(!aud.allows(Access::Read, "staging/web/token"));
+
+        let tree = json!([
+            {"name": "prod", "path": "prod", "is_dir": true, "children": [
+                {"name": "db", "path": "prod/db", "is_dir": true, "children": [
+                    {"name": "root", "path": "prod/db/root", "is_dir": false, "children": null}
+                ]},
+                {"name": "web", "path": "prod/web", "is_dir": true, "children": [
+                    {"name": "token", "path": "prod/web/token", "is_dir": false, "children": null}
+                ]}
+            ]},
+            {"name": "staging", "path": "staging", "is_dir": true, "children": []},
+            {"name": "misc", "path": "misc", "is_dir": false, "children": null}
+        ]);
+        let filtered = dana.filter_tree(serde_json::from_value(tree.clone()).unwrap());
+        assert_eq!(Value::Array(filtered), json!([
+            {"name": "prod", "path": "prod", "is_dir": true, "children": [
+                {"name": "db", "path": "prod/db", "is_dir": true, "children": [
+                    {"name": "root", "path": "prod/db/root", "is_dir": false, "children": null}
+                ]}
+            ]},
+            {"name": "staging", "path": "staging", "is_dir": true, "children": []}
+        ]));
+        assert!(aud.filter_tree(serde_json::from_value(tree.clone()).unwrap()).is_empty());
+        assert_eq!(root.filter_tree(serde_json::from_value(tree.clone()).unwrap()).len(), 3);
+
+        // Deleting a team takes its grants along
+        sqlx::query("DELETE FROM teams WHERE id = 1").execute(&pool).await.unwrap();
+        assert!(!policy(2).await.allows(Access::Read, "prod/db/root"));
+    }
+}
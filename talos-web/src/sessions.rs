@@ -0,0 +1,311 @@
+use async_trait::async_trait;
+use axum::Json;
+use axum::extract::{ConnectInfo, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tower_sessions::Session;
+use tower_sessions::session::{Id, Record};
+use tower_sessions::session_store::{self, SessionStore};
+use crate::db::DbPool;
+use crate::handlers::{is_debug, log_audit};
+use crate::state::AppState;
+use crate::users::{current_user, SESSION_USER_ID};
+
+// Where the client signed in from, recorded by `start_session`
+pub const SESSION_IP: &str = "ip_address";
+pub const SESSION_USER_AGENT: &str = "user_agent";
+const SWEEP_INTERVAL_SECONDS: u64 = 600;
+
+/// Sessions kept in talos.db, so they survive restarts and can be listed and
+/// revoked. Rows are keyed by a SHA-256 of the session id; the cookie value
+/// itself is never stored.
+#[derive(Clone, Debug)]
+pub struct SqliteSessionStore {
+    pool: DbPool,
+}
+
+impl SqliteSessionStore {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    // Expired sessions cannot be loaded anyway; this only reclaims the rows
+    pub fn spawn_sweeper(&self) {
+        let pool = self.pool.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(SWEEP_INTERVAL_SECONDS));
+            loop {
+                interval.tick().await;
+                match delete_expired(&pool).await {
+                    Ok(removed) if removed > 0 && is_debug() => println!("--> [WEB] Removed {} expired sessions", removed),
+                    Err(e) => println!("⚠️ [WEB] Session cleanup failed: {}", e),
+                    _ => {}
+                }
+            }
+        });
+    }
+
+    async fn insert(&self, record: &Record) -> session_store::Result<bool> {
+        let row = Columns::of(record)?;
+        let result = sqlx::query(
+            "INSERT INTO sessions (session_hash, data, expires_at, user_id, auth_method, ip_address, user_agent)
+             VALUES (?, ?, ?, ?, ?, ?, ?) ON CONFLICT (session_hash) DO NOTHING",
+        )
+            .bind(hash_id(&record.id))
+            .bind(row.data)
+            .bind(record.expiry_date.unix_timestamp())
+            .bind(row.user_id)
+            .bind(row.auth_method)
+            .bind(row.ip_address)
+            .bind(row.user_agent)
+            .execute(&self.pool)
+            .await
+            .map_err(backend)?;
+        Ok(result.rows_affected() == 1)
+    }
+}
+
+fn hash_id(id: &Id) -> String {
+    Sha256::digest(id.to_string().as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn backend(e: sqlx::Error) -> session_store::Error {
+    session_store::Error::Backend(e.to_string())
+}
+
+// The session data, plus the values copied out of it into their own columns
+// for listing and revoking
+struct Columns {
+    data: String,
+    user_id: Option<i64>,
+    auth_method: Option<String>,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+}
+
+impl Columns {
+    fn of(record: &Record) -> session_store::Result<Self> {
+        let text = |key: &str| record.data.get(key).and_then(Value::as_str).map(str::to_string);
+        Ok(Self {
+            data: serde_json::to_string(&record.data).map_err(|e| session_store::Error::Encode(e.to_string()))?,
+            user_id: record.data.get(SESSION_USER_ID).and_then(Value::as_i64),
+            auth_method: text("auth_method"),
+            ip_address: text(SESSION_IP),
+            user_agent: text(SESSION_USER_AGENT),
+        })
+    }
+}
+
+#[async_trait]
+impl SessionStore for SqliteSessionStore {
+    async fn create(&self, record: &mut Record) -> session_store::Result<()> {
+        while !self.insert(record).await? {
+            record.id = Id::default();
+        }
+        Ok(())
+    }
+
+    // Only updates: a session revoked while one of its requests was still
+    // running stays revoked
+    async fn save(&self, record: &Record) -> session_store::Result<()> {
+        let row = Columns::of(record)?;
+        sqlx::query(
+            "UPDATE sessions SET data = ?, expires_at = ?, user_id = ?, auth_method = ?, ip_address = ?, user_agent = ?,
+             last_seen_at = CURRENT_TIMESTAMP WHERE session_hash = ?",
+        )
+            .bind(row.data)
+            .bind(record.expiry_date.unix_timestamp())
+            .bind(row.user_id)
+            .bind(row.auth_method)
+            .bind(row.ip_address)
+            .bind(row.user_agent)
+            .bind(hash_id(&record.id))
+            .execute(&self.pool)
+            .await
+            .map_err(backend)?;
+        Ok(())
+    }
+
+    async fn load(&self, id: &Id) -> session_store::Result<Option<Record>> {
+        let hash = hash_id(id);
+        let row: Option<(String, i64)> = sqlx::query_as("SELECT data, expires_at FROM sessions WHERE session_hash = ? AND expires_at > ?")
+            .bind(&hash)
+            .bind(OffsetDateTime::now_utc().unix_timestamp())
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(backend)?;
+        let Some((data, expires_at)) = row else {
+            return Ok(None);
+        };
+
+        // Activity is recorded at most once a minute
+        sqlx::query("UPDATE sessions SET last_seen_at = CURRENT_TIMESTAMP WHERE session_hash = ? AND last_seen_at < datetime('now', '-1 minute')")
+            .bind(&hash)
+            .execute(&self.pool)
+            .await
+            .map_err(backend)?;
+
+        let decode = |e: String| session_store::Error::Decode(e);
+        Ok(Some(Record {
+            id: *id,
+            data: serde_json::from_str(&data).map_err(|e| decode(e.to_string()))?,
+            expiry_date: OffsetDateTime::from_unix_timestamp(expires_at).map_err(|e| decode(e.to_string()))?,
+        }))
+    }
+
+    async fn delete(&self, id: &Id) -> session_store::Result<()> {
+        sqlx::query("DELETE FROM sessions WHERE session_hash = ?")
+            .bind(hash_id(id))
+            .execute(&self.pool)
+            .await
+            .map_err(backend)?;
+        Ok(())
+    }
+}
+
+pub async fn delete_expired(pool: &DbPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM sessions WHERE expires_at <= ?")
+        .bind(OffsetDateTime::now_utc().unix_timestamp())
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+// Signs an account out everywhere
+pub async fn revoke_user_sessions(pool: &DbPool, user_id: i64) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM sessions WHERE user_id = ?")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct SessionInfo {
+    id: i64,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    auth_method: Option<String>,
+    created_at: String,
+    last_seen_at: String,
+    // The session this request came from
+    current: bool,
+}
+
+#[derive(Deserialize)]
+pub struct RevokeSessionRequest {
+    id: Option<i64>,
+    // Every other session of the account
+    #[serde(default)]
+    all: bool,
+}
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn api_error(status: StatusCode, message: &str) -> ApiError {
+    (status, Json(json!({"error": message})))
+}
+
+pub async fn list_sessions(State(state): State<AppState>, session: Session) -> Result<Json<Vec<SessionInfo>>, ApiError> {
+    let user = current_user(&state, &session).await.ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "Not authenticated"))?;
+    let sessions = sqlx::query_as::<_, SessionInfo>(
+        "SELECT id, ip_address, user_agent, auth_method, created_at, last_seen_at, session_hash = ? AS current
+         FROM sessions WHERE user_id = ? AND expires_at > ? ORDER BY last_seen_at DESC",
+    )
+        .bind(session.id().map(|id| hash_id(&id)))
+        .bind(user.id)
+        .bind(OffsetDateTime::now_utc().unix_timestamp())
+        .fetch_all(&state.pool)
+        .await
+        .unwrap_or_default();
+    Ok(Json(sessions))
+}
+
+// Signs out one of the caller's sessions, or all of them except this one
+pub async fn revoke_session(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<RevokeSessionRequest>
+) -> Result<Json<Value>, ApiError> {
+    let user = current_user(&state, &session).await.ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "Not authenticated"))?;
+    let current = session.id().map(|id| hash_id(&id)).unwrap_or_default();
+    let ua_header = headers.get(header::USER_AGENT);
+
+    if req.all {
+        let result = sqlx::query("DELETE FROM sessions WHERE user_id = ? AND session_hash != ?")
+            .bind(user.id)
+            .bind(&current)
+            .execute(&state.pool)
+            .await
+            .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to revoke sessions"))?;
+        let target = format!("{}: {} other sessions", user.username, result.rows_affected());
+        log_audit(&state, &session, Some(addr.ip()), ua_header, "SESSION_REVOKE_ALL", &target).await;
+        return Ok(Json(json!({"status": "revoked", "count": result.rows_affected()})));
+    }
+
+    let id = req.id.ok_or_else(|| api_error(StatusCode::BAD_REQUEST, "Session id or all is required"))?;
+    let revoked: String = sqlx::query_scalar("DELETE FROM sessions WHERE id = ? AND user_id = ? RETURNING session_hash")
+        .bind(id)
+        .bind(user.id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to revoke session"))?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Session not found"))?;
+    let target = format!("{}: session {}", user.username, id);
+    log_audit(&state, &session, Some(addr.ip()), ua_header, "SESSION_REVOKE", &target).await;
+    Ok(Json(json!({"status": "revoked", "current": revoked == current})))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::Role;
+    use crate::users;
+    use sqlx::sqlite::SqlitePoolOptions;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn sessions_persist_and_stay_revoked() {
+        let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        crate::db::migrate(&pool).await;
+        let user_id = users::insert_user(&pool, "dana", "x", Role::Editor).await.unwrap();
+        let store = SqliteSessionStore::new(pool.clone());
+
+        let session = Session::new(None, Arc::new(store.clone()), None);
+        session.insert(SESSION_USER_ID, user_id).await.unwrap();
+        session.insert("auth_method", "password").await.unwrap();
+        session.insert(SESSION_IP, "192.0.2.7").await.unwrap();
+        session.save().await.unwrap();
+        let id = session.id().unwrap();
+
+        // Only the hash of the id is stored, next to the sign-in details
+        let (hash, ip, method): (String, String, String) = sqlx::query_as("SELECT session_hash, ip_address, auth_method FROM sessions WHERE user_id = ?")
+            .bind(user_id).fetch_one(&pool).await.unwrap();
+        assert_ne!(hash, id.to_string());
+        assert_eq!((ip.as_str(), method.as_str()), ("192.0.2.7", "password"));
+
+        // A fresh store on the same database, as after a restart
+        let reloaded = SqliteSessionStore::new(pool.clone()).load(&id).await.unwrap().unwrap();
+        assert_eq!(reloaded.data.get(SESSION_USER_ID), Some(&json!(user_id)));
+
+        // Revoked sessions cannot be loaded, and a late save does not bring them back
+        assert_eq!(revoke_user_sessions(&pool, user_id).await.unwrap(), 1);
+        assert!(store.load(&id).await.unwrap().is_none());
+        store.save(&reloaded).await.unwrap();
+        assert!(store.load(&id).await.unwrap().is_none());
+
+        // Expired sessions are neither loaded nor kept
+        let mut expired = reloaded.clone();
+        expired.expiry_date = OffsetDateTime::now_utc() - time::Duration::minutes(1);
+        store.create(&mut expired).await.unwrap();
+        assert!(store.load(&expired.id).await.unwrap().is_none());
+        assert_eq!(delete_expired(&pool).await.unwrap(), 1);
+    }
+}
@@ -1,18 +1,16 @@
 use sqlx::SqlitePool;
-use std::collections::HashMap;
-use std::net::IpAddr;
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+use webauthn_rs::Webauthn;
+use crate::oidc::Oidc;
 
-pub struct RateLimitEntry {
-    pub attempts: u32,
-    pub window_start: Instant,
-}
-
-pub type RateLimiter = Arc<Mutex<HashMap<IpAddr, RateLimitEntry>>>;
+// Required for genesis while the Bunker is uninitialized; see `bootstrap`
+pub type BootstrapToken = Arc<Mutex<Option<String>>>;
 
 #[derive(Clone)]
 pub struct AppState {
     pub pool: SqlitePool,
-    pub rate_limiter: RateLimiter,
+    pub webauthn: Arc<Webauthn>,
+    pub bootstrap_token: BootstrapToken,
+    // Single sign-on, when OIDC_ISSUER is set
+    pub oidc: Option<Arc<Oidc>>,
 }
\ No newline at end of file
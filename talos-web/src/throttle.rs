@@ -0,0 +1,381 @@
+use axum::Json;
+use axum::extract::{ConnectInfo, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::net::{IpAddr, SocketAddr};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tower_sessions::Session;
+use crate::db::DbPool;
+use crate::handlers::{is_debug, log_audit, log_audit_with_severity};
+use crate::state::AppState;
+
+// Failed credential checks (passwords, second factors, passkeys, master key)
+// are counted in talos.db per client address, per account and across the
+// whole instance. Past a few free failures the client and the account wait
+// twice as long as before, and an account that keeps failing is locked until
+// an administrator unlocks it or the lockout runs out. The instance count
+// only raises an alert: blocking on it would let anyone lock everyone out.
+const SCOPE_IP: &str = "ip";
+const SCOPE_USER: &str = "user";
+const SCOPE_GLOBAL: &str = "global";
+const GLOBAL_KEY: &str = "*";
+
+struct Backoff {
+    // Failures allowed before any delay
+    free: i64,
+    base_seconds: i64,
+    max_seconds: i64,
+    // Failures further apart than this start counting again from one
+    window_seconds: i64,
+}
+
+const IP_BACKOFF: Backoff = Backoff { free: 5, base_seconds: 2, max_seconds: 900, window_seconds: 3600 };
+const USER_BACKOFF: Backoff = Backoff { free: 5, base_seconds: 2, max_seconds: 900, window_seconds: 86400 };
+// Only a credential stuffing run reaches this many failures in a row
+const GLOBAL_ALERT_THRESHOLD: i64 = 100;
+const GLOBAL_WINDOW_SECONDS: i64 = 300;
+
+const LOCKOUT_THRESHOLD: i64 = 20;
+const LOCKOUT_SECONDS: i64 = 3600;
+const SWEEP_INTERVAL_SECONDS: u64 = 600;
+const MAX_KEY_LEN: usize = 64;
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs() as i64
+}
+
+// IPv6 clients usually control a whole /64, so that is what gets counted
+fn ip_key(ip: IpAddr) -> String {
+    match ip.to_canonical() {
+        IpAddr::V6(v6) => {
+            let mut segments = v6.segments();
+            segments[4..].fill(0);
+            format!("{}/64", std::net::Ipv6Addr::from(segments))
+        },
+        v4 => v4.to_string(),
+    }
+}
+
+// Accounts are counted by the name that was tried, whether or not it exists,
+// so a lockout does not reveal which usernames are real
+fn user_key(username: &str) -> String {
+    username.trim().to_lowercase().chars().take(MAX_KEY_LEN).collect()
+}
+
+fn delay_after(failures: i64, backoff: &Backoff) -> i64 {
+    if failures <= backoff.free {
+        return 0;
+    }
+    let doublings = (failures - backoff.free - 1).min(30) as u32;
+    backoff.base_seconds.saturating_mul(1 << doublings).min(backoff.max_seconds)
+}
+
+fn scopes(ip: IpAddr, user: Option<&str>) -> Vec<(&'static str, String, &'static Backoff)> {
+    let mut scopes = vec![(SCOPE_IP, ip_key(ip), &IP_BACKOFF)];
+    if let Some(user) = user {
+        scopes.push((SCOPE_USER, user_key(user), &USER_BACKOFF));
+    }
+    scopes
+}
+
+/// Seconds until the client or the account may try again, if either of them
+/// is still waiting out a backoff or lockout.
+pub async fn check(pool: &DbPool, ip: IpAddr, user: Option<&str>) -> Result<(), i64> {
+    let blocked_until: Option<i64> = sqlx::query_scalar(
+        "SELECT MAX(blocked_until) FROM login_failures
+         WHERE (scope = ? AND key = ?) OR (scope = ? AND key = ?)",
+    )
+        .bind(SCOPE_IP).bind(ip_key(ip))
+        .bind(SCOPE_USER).bind(user.map(user_key))
+        .fetch_one(pool)
+        .await
+        .unwrap_or_else(|e| {
+            println!("⚠️ [WEB] Rate limit lookup failed: {}", e);
+            None
+        });
+    match blocked_until.map(|until| until - now()) {
+        Some(wait) if wait > 0 => Err(wait),
+        _ => Ok(()),
+    }
+}
+
+// One statement, so concurrent failures cannot lose a count
+async fn count_failure(pool: &DbPool, scope: &str, key: &str, window_seconds: i64, now: i64) -> Option<i64> {
+    sqlx::query_scalar(
+        "INSERT INTO login_failures (scope, key, failures, last_failure_at) VALUES (?, ?, 1, ?)
+         ON CONFLICT (scope, key) DO UPDATE SET
+            failures = CASE WHEN excluded.last_failure_at - last_failure_at <= ? THEN failures + 1 ELSE 1 END,
+            last_failure_at = excluded.last_failure_at
+         RETURNING failures",
+    )
+        .bind(scope)
+        .bind(key)
+        .bind(now)
+        .bind(window_seconds)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| println!("⚠️ [WEB] Failed to record a failed attempt: {}", e))
+        .ok()
+}
+
+#[derive(Default)]
+pub struct Recorded {
+    // This failure locked the account
+    pub locked: bool,
+    // This failure pushed the instance-wide count over the alert threshold
+    pub surge: bool,
+}
+
+/// Counts a failed attempt in every scope and reports what it set off.
+pub async fn record_failure(pool: &DbPool, ip: IpAddr, user: Option<&str>) -> Recorded {
+    let now = now();
+    let mut recorded = Recorded::default();
+    for (scope, key, backoff) in scopes(ip, user) {
+        let Some(failures) = count_failure(pool, scope, &key, backoff.window_seconds, now).await else {
+            continue;
+        };
+
+        let mut delay = delay_after(failures, backoff);
+        if scope == SCOPE_USER && failures >= LOCKOUT_THRESHOLD {
+            delay = delay.max(LOCKOUT_SECONDS);
+            recorded.locked = failures == LOCKOUT_THRESHOLD;
+        }
+        if delay > 0 {
+            let _ = sqlx::query("UPDATE login_failures SET blocked_until = MAX(blocked_until, ?) WHERE scope = ? AND key = ?")
+                .bind(now + delay)
+                .bind(scope)
+                .bind(&key)
+                .execute(pool)
+                .await;
+        }
+    }
+    // Never blocks anything, see the top of this file
+    if let Some(failures) = count_failure(pool, SCOPE_GLOBAL, GLOBAL_KEY, GLOBAL_WINDOW_SECONDS, now).await {
+        recorded.surge = failures == GLOBAL_ALERT_THRESHOLD;
+    }
+    recorded
+}
+
+// A successful sign-in forgives the account, not the address: one valid
+// account must not reset the count of a client guessing at others
+pub async fn record_success(pool: &DbPool, user: &str) {
+    let _ = clear(pool, SCOPE_USER, &user_key(user)).await;
+}
+
+async fn clear(pool: &DbPool, scope: &str, key: &str) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM login_failures WHERE scope = ? AND key = ?")
+        .bind(scope)
+        .bind(key)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+pub async fn unlock_user(pool: &DbPool, username: &str) -> Result<bool, sqlx::Error> {
+    Ok(clear(pool, SCOPE_USER, &user_key(username)).await? > 0)
+}
+
+// Records a failed attempt from a handler and audits a fresh lockout or the
+// start of an instance-wide run of failures
+pub async fn fail(state: &AppState, session: &Session, ip: IpAddr, ua_header: Option<&HeaderValue>, user: Option<&str>) {
+    let recorded = record_failure(&state.pool, ip, user).await;
+    if recorded.locked
+        && let Some(user) = user {
+        log_audit_with_severity(state, session, Some(ip), ua_header, "ACCOUNT_LOCKED", &user_key(user), "high").await;
+    }
+    if recorded.surge {
+        println!("⚠️ [WEB] {} failed sign-ins across the instance without a {} second pause", GLOBAL_ALERT_THRESHOLD, GLOBAL_WINDOW_SECONDS);
+        log_audit_with_severity(state, session, Some(ip), ua_header, "LOGIN_FAILURE_SURGE", SCOPE_GLOBAL, "high").await;
+    }
+}
+
+pub fn too_many_attempts(retry_after: i64) -> (StatusCode, Json<Value>) {
+    (StatusCode::TOO_MANY_REQUESTS, Json(json!({
+        "error": format!("Too many attempts. Try again in {} seconds.", retry_after),
+        "retry_after": retry_after
+    })))
+}
+
+// Entries whose window has passed no longer affect anything
+pub async fn delete_stale(pool: &DbPool) -> Result<u64, sqlx::Error> {
+    let now = now();
+    let result = sqlx::query("DELETE FROM login_failures WHERE blocked_until <= ? AND last_failure_at < ?")
+        .bind(now)
+        .bind(now - USER_BACKOFF.window_seconds)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+pub fn spawn_sweeper(pool: DbPool) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(SWEEP_INTERVAL_SECONDS));
+        loop {
+            interval.tick().await;
+            match delete_stale(&pool).await {
+                Ok(removed) if removed > 0 && is_debug() => println!("--> [WEB] Removed {} stale rate limit entries", removed),
+                Err(e) => println!("⚠️ [WEB] Rate limit cleanup failed: {}", e),
+                _ => {}
+            }
+        }
+    });
+}
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn api_error(status: StatusCode, message: &str) -> ApiError {
+    (status, Json(json!({"error": message})))
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct Lockout {
+    scope: String,
+    key: String,
+    failures: i64,
+    last_failure_at: i64,
+    blocked_until: i64,
+}
+
+// Everything that is currently waiting out a backoff or lockout
+pub async fn list_lockouts(State(state): State<AppState>) -> Result<Json<Vec<Lockout>>, ApiError> {
+    let lockouts = sqlx::query_as::<_, Lockout>(
+        "SELECT scope, key, failures, last_failure_at, blocked_until FROM login_failures
+         WHERE blocked_until > ? ORDER BY blocked_until DESC LIMIT 500",
+    )
+        .bind(now())
+        .fetch_all(&state.pool)
+        .await
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to list lockouts"))?;
+    Ok(Json(lockouts))
+}
+
+#[derive(Deserialize)]
+pub struct ClearLockoutRequest {
+    scope: String,
+    key: String,
+}
+
+// Lifts a backoff early, e.g. for an office behind one address; accounts
+// can also be unlocked with `unlock` on /api/users/update
+pub async fn clear_lockout(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<ClearLockoutRequest>
+) -> Result<Json<Value>, ApiError> {
+    let key = match req.scope.as_str() {
+        SCOPE_IP => req.key.parse::<IpAddr>().map(ip_key).unwrap_or_else(|_| req.key.clone()),
+        SCOPE_USER => user_key(&req.key),
+        SCOPE_GLOBAL => GLOBAL_KEY.to_string(),
+        _ => return Err(api_error(StatusCode::BAD_REQUEST, "Scope must be one of: ip, user, global")),
+    };
+    let cleared = clear(&state.pool, &req.scope, &key).await
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to clear lockout"))?;
+    if cleared == 0 {
+        return Err(api_error(StatusCode::NOT_FOUND, "No failed attempts recorded"));
+    }
+    let target = format!("{} {}", req.scope, key);
+    log_audit(&state, &session, Some(addr.ip()), headers.get(header::USER_AGENT), "LOCKOUT_CLEAR", &target).await;
+    Ok(Json(json!({"status": "cleared"})))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn blocked_until(pool: &DbPool, scope: &str, key: &str) -> i64 {
+        sqlx::query_scalar("SELECT blocked_until FROM login_failures WHERE scope = ? AND key = ?")
+            .bind(scope).bind(key).fetch_one(pool).await.unwrap()
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_its_ceiling() {
+        let delays: Vec<i64> = (1..=10).map(|failures| delay_after(failures, &IP_BACKOFF)).collect();
+        assert_eq!(delays, [0, 0, 0, 0, 0, 2, 4, 8, 16, 32]);
+        assert_eq!(delay_after(14, &IP_BACKOFF), 512);
+        assert_eq!(delay_after(15, &IP_BACKOFF), 900);
+        assert_eq!(delay_after(10_000, &IP_BACKOFF), 900);
+
+        assert_eq!(ip_key("203.0.113.7".parse().unwrap()), "203.0.113.7");
+        assert_eq!(ip_key("::ffff:203.0.113.7".parse().unwrap()), "203.0.113.7");
+        assert_eq!(ip_key("2001:db8:1:2:3:4:5:6".parse().unwrap()), "2001:db8:1:2::/64");
+    }
+
+    #[tokio::test]
+    async fn failures_back_off_and_lock_the_account() {
+        let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        crate::db::migrate(&pool).await;
+        let attacker: IpAddr = "198.51.100.1".parse().unwrap();
+        let other: IpAddr = "198.51.100.2".parse().unwrap();
+
+        for _ in 0..IP_BACKOFF.free {
+            assert!(check(&pool, attacker, Some("alice")).await.is_ok());
+            assert!(!record_failure(&pool, attacker, Some("Alice ")).await.locked);
+        }
+        assert!(check(&pool, attacker, Some("bob")).await.is_ok());
+        record_failure(&pool, attacker, Some("bob")).await;
+        let wait = check(&pool, attacker, None).await.unwrap_err();
+        assert!((1..=2).contains(&wait), "waited {}", wait);
+        // Another client may still try alice, whose count is below the backoff
+        assert!(check(&pool, other, Some("alice")).await.is_ok());
+
+        // Spread over many addresses, the account count still locks it
+        for i in 0..LOCKOUT_THRESHOLD - 6 {
+            let ip = IpAddr::from([192, 0, 2, i as u8]);
+            assert!(!record_failure(&pool, ip, Some("alice")).await.locked);
+        }
+        assert!(record_failure(&pool, other, Some("alice")).await.locked, "the threshold locks the account");
+        assert!(!record_failure(&pool, other, Some("alice")).await.locked, "a lockout is reported once");
+        let wait = check(&pool, IpAddr::from([192, 0, 2, 200]), Some("ALICE")).await.unwrap_err();
+        assert!(wait > LOCKOUT_SECONDS - 5);
+        assert!(check(&pool, IpAddr::from([192, 0, 2, 200]), Some("carol")).await.is_ok());
+
+        // A successful sign-in or an administrator clears the account only
+        assert!(unlock_user(&pool, "alice").await.unwrap());
+        assert!(!unlock_user(&pool, "alice").await.unwrap());
+        assert!(check(&pool, IpAddr::from([192, 0, 2, 200]), Some("alice")).await.is_ok());
+        record_success(&pool, "bob").await;
+        let kept: i64 = sqlx::query_scalar("SELECT failures FROM login_failures WHERE scope = 'ip' AND key = ?")
+            .bind(ip_key(attacker)).fetch_one(&pool).await.unwrap();
+        assert_eq!(kept, IP_BACKOFF.free + 1);
+
+        // Stale entries are swept once their window has passed
+        assert_eq!(delete_stale(&pool).await.unwrap(), 0);
+        sqlx::query("UPDATE login_failures SET last_failure_at = last_failure_at - 90000, blocked_until = 0 WHERE scope = 'ip'")
+            .execute(&pool).await.unwrap();
+        assert!(delete_stale(&pool).await.unwrap() > 0);
+        let remaining: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM login_failures WHERE scope = 'ip'").fetch_one(&pool).await.unwrap();
+        assert_eq!(remaining, 0);
+
+        // An old failure starts the count again instead of adding to it
+        sqlx::query("UPDATE login_failures SET last_failure_at = last_failure_at - 400, blocked_until = 0 WHERE scope = 'global'")
+            .execute(&pool).await.unwrap();
+        record_failure(&pool, attacker, None).await;
+        let failures: i64 = sqlx::query_scalar("SELECT failures FROM login_failures WHERE scope = 'global'").fetch_one(&pool).await.unwrap();
+        assert_eq!(failures, 1);
+        assert_eq!(blocked_until(&pool, SCOPE_GLOBAL, GLOBAL_KEY).await, 0);
+    }
+
+    #[tokio::test]
+    async fn instance_wide_failures_alert_without_blocking() {
+        let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        crate::db::migrate(&pool).await;
+
+        // Spread thinly over addresses and names, so only the instance count grows
+        let mut surges = 0;
+        for i in 0..GLOBAL_ALERT_THRESHOLD * 2 {
+            let ip = IpAddr::from([203, 0, (i / 250) as u8, (i % 250) as u8]);
+            let recorded = record_failure(&pool, ip, Some(&format!("user{}", i))).await;
+            assert!(!recorded.locked);
+            surges += recorded.surge as i64;
+        }
+        assert_eq!(surges, 1, "a surge is reported once");
+        assert_eq!(blocked_until(&pool, SCOPE_GLOBAL, GLOBAL_KEY).await, 0);
+        // Nobody else is kept from signing in
+        assert!(check(&pool, "198.51.100.9".parse().unwrap(), Some("alice")).await.is_ok());
+        assert!(check(&pool, "198.51.100.9".parse().unwrap(), None).await.is_ok());
+    }
+}
@@ -0,0 +1,273 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::Json;
+use axum::extract::{ConnectInfo, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use data_encoding::BASE64URL_NOPAD;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::future::Future;
+use std::net::SocketAddr;
+use tower_sessions::Session;
+use crate::db::DbPool;
+use crate::handlers::{is_debug, log_audit};
+use crate::policy::{Access, TokenScope, normalize_pattern};
+use crate::state::AppState;
+use crate::users::{self, current_user, User};
+
+// Recognisable in logs and by secret scanners
+const TOKEN_PREFIX: &str = "talos_";
+const DEFAULT_LIFETIME_DAYS: i64 = 30;
+const MAX_LIFETIME_DAYS: i64 = 365;
+const MAX_TOKENS_PER_USER: i64 = 50;
+
+/// The API token a request authenticated with, in place of a session
+#[derive(Clone, Debug)]
+pub struct TokenIdentity {
+    pub name: String,
+    pub user_id: i64,
+    pub username: String,
+}
+
+tokio::task_local! {
+    static REQUEST_TOKEN: TokenIdentity;
+}
+
+// Runs the rest of the request as `identity`, so audit entries and Storage
+// calls name the token's owner instead of the (empty) session
+pub async fn with_identity<F: Future>(identity: TokenIdentity, request: F) -> F::Output {
+    REQUEST_TOKEN.scope(identity, request).await
+}
+
+pub fn current() -> Option<TokenIdentity> {
+    REQUEST_TOKEN.try_with(Clone::clone).ok()
+}
+
+// Tokens carry 256 random bits, so a plain SHA-256 is enough to keep them
+// out of reach of someone holding a copy of talos.db
+fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    format!("{}{}", TOKEN_PREFIX, BASE64URL_NOPAD.encode(&bytes))
+}
+
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    let value = headers.get(header::AUTHORIZATION)?.to_str().ok()?;
+    let (scheme, token) = value.split_once(' ')?;
+    scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+}
+
+// Resolves a presented token to its owner and limits. Expired tokens and
+// tokens of disabled accounts are refused; use is recorded on the token.
+pub async fn authenticate(pool: &DbPool, token: &str) -> Option<(TokenIdentity, User, TokenScope)> {
+    if !token.starts_with(TOKEN_PREFIX) {
+        return None;
+    }
+    let (token_id, name, user_id, access, pattern): (i64, String, i64, Access, Option<String>) = sqlx::query_as(
+        "SELECT id, name, user_id, access, path FROM api_tokens WHERE token_hash = ? AND expires_at > CURRENT_TIMESTAMP",
+    )
+        .bind(hash_token(token))
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()?;
+    let user = users::find_user(pool, user_id).await.filter(|user| !user.disabled)?;
+    let _ = sqlx::query("UPDATE api_tokens SET last_used_at = CURRENT_TIMESTAMP WHERE id = ?")
+        .bind(token_id)
+        .execute(pool)
+        .await;
+    let identity = TokenIdentity { name, user_id, username: user.username.clone() };
+    Some((identity, user, TokenScope { access, pattern }))
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct TokenInfo {
+    id: i64,
+    name: String,
+    access: Access,
+    path: Option<String>,
+    created_at: String,
+    expires_at: String,
+    last_used_at: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateTokenRequest {
+    name: String,
+    access: Access,
+    // Confines the token to a pattern such as `ci/*`
+    path: Option<String>,
+    expires_in_days: Option<i64>,
+}
+
+#[derive(Deserialize)]
+pub struct DeleteTokenRequest {
+    id: i64,
+}
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn api_error(status: StatusCode, message: &str) -> ApiError {
+    (status, Json(json!({"error": message})))
+}
+
+pub async fn list_tokens(State(state): State<AppState>, session: Session) -> Result<Json<Vec<TokenInfo>>, ApiError> {
+    let user = current_user(&state, &session).await.ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "Not authenticated"))?;
+    let tokens = sqlx::query_as::<_, TokenInfo>(
+        "SELECT id, name, access, path, created_at, expires_at, last_used_at FROM api_tokens WHERE user_id = ? ORDER BY created_at",
+    )
+        .bind(user.id)
+        .fetch_all(&state.pool)
+        .await
+        .unwrap_or_default();
+    Ok(Json(tokens))
+}
+
+// Creates a token for the signed-in user. Only a session can do this, never
+// another token. The token itself is returned once and only stored hashed.
+pub async fn create_token(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<CreateTokenRequest>
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let user = current_user(&state, &session).await.ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "Not authenticated"))?;
+    if is_debug() { println!("--> [WEB] Processing TOKEN CREATE"); }
+
+    let name = users::normalize_name("Token name", &req.name).map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+    let path = req.path.as_deref().filter(|p| !p.trim().is_empty()).map(normalize_pattern).transpose()
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?
+        .filter(|p| p != "*");
+    let days = req.expires_in_days.unwrap_or(DEFAULT_LIFETIME_DAYS);
+    if !(1..=MAX_LIFETIME_DAYS).contains(&days) {
+        return Err(api_error(StatusCode::BAD_REQUEST, &format!("Tokens expire within 1 to {} days", MAX_LIFETIME_DAYS)));
+    }
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM api_tokens WHERE user_id = ?")
+        .bind(user.id)
+        .fetch_one(&state.pool)
+        .await
+        .unwrap_or(0);
+    if count >= MAX_TOKENS_PER_USER {
+        return Err(api_error(StatusCode::CONFLICT, "Too many tokens, revoke unused ones first"));
+    }
+
+    let token = generate_token();
+    let (id, expires_at): (i64, String) = sqlx::query_as(
+        "INSERT INTO api_tokens (user_id, name, token_hash, access, path, expires_at)
+         VALUES (?, ?, ?, ?, ?, datetime('now', '+' || ? || ' days')) RETURNING id, expires_at",
+    )
+        .bind(user.id)
+        .bind(&name)
+        .bind(hash_token(&token))
+        .bind(req.access)
+        .bind(&path)
+        .bind(days)
+        .fetch_one(&state.pool)
+        .await
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to create token"))?;
+
+    let target = format!("{}: {} access={} path={}", user.username, name, req.access.as_str(), path.as_deref().unwrap_or("*"));
+    log_audit(&state, &session, Some(addr.ip()), headers.get(header::USER_AGENT), "TOKEN_CREATE", &target).await;
+    Ok((StatusCode::CREATED, Json(json!({
+        "id": id,
+        "name": name,
+        "token": token,
+        "expires_at": expires_at,
+    }))))
+}
+
+pub async fn delete_token(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<DeleteTokenRequest>
+) -> Result<Json<Value>, ApiError> {
+    let user = current_user(&state, &session).await.ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "Not authenticated"))?;
+    let name: String = sqlx::query_scalar("DELETE FROM api_tokens WHERE id = ? AND user_id = ? RETURNING name")
+        .bind(req.id)
+        .bind(user.id)
+        .fetch_optional(&state.pool)
+        .await
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to revoke token"))?
+        .ok_or_else(|| api_error(StatusCode::NOT_FOUND, "Token not found"))?;
+    let target = format!("{}: {}", user.username, name);
+    log_audit(&state, &session, Some(addr.ip()), headers.get(header::USER_AGENT), "TOKEN_REVOKE", &target).await;
+    Ok(Json(json!({"status": "revoked"})))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::{Policy, Role};
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    async fn insert_token(pool: &DbPool, user_id: i64, access: &str, path: Option<&str>, days: i64) -> String {
+        let token = generate_token();
+        sqlx::query("INSERT INTO api_tokens (user_id, name, token_hash, access, path, expires_at) VALUES (?, 'ci', ?, ?, ?, datetime('now', ? || ' days'))")
+            .bind(user_id)
+            .bind(hash_token(&token))
+            .bind(access)
+            .bind(path)
+            .bind(days)
+            .execute(pool)
+            .await
+            .unwrap();
+        token
+    }
+
+    #[tokio::test]
+    async fn tokens_authenticate_within_their_scope() {
+        let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        crate::db::migrate(&pool).await;
+        let admin = users::insert_user(&pool, "root", "x", Role::Admin).await.unwrap();
+        let editor = users::insert_user(&pool, "dana", "x", Role::Editor).await.unwrap();
+        sqlx::query("INSERT INTO grants (user_id, pattern, access) VALUES (?, 'staging/*', 'write')").bind(editor).execute(&pool).await.unwrap();
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, "Bearer talos_abc".parse().unwrap());
+        assert_eq!(bearer_token(&headers), Some("talos_abc"));
+        headers.insert(header::AUTHORIZATION, "Basic dXNlcg==".parse().unwrap());
+        assert_eq!(bearer_token(&headers), None);
+
+        // A read-only token of an administrator cannot write or reach whole-store operations
+        let read_token = insert_token(&pool, admin, "read", None, 30).await;
+        let (identity, user, scope) = authenticate(&pool, &read_token).await.unwrap();
+        assert_eq!((identity.username.as_str(), identity.name.as_str()), ("root", "ci"));
+        let policy = Policy::load(&pool, user).await.restricted_to(scope);
+        assert!(policy.allows(Access::Read, "prod/db/root"));
+        assert!(!policy.allows(Access::Write, "prod/db/root"));
+        assert!(policy.unscoped(Access::Read) && !policy.covers_store(Access::Write));
+        let used: Option<String> = sqlx::query_scalar("SELECT last_used_at FROM api_tokens WHERE user_id = ?").bind(admin).fetch_one(&pool).await.unwrap();
+        assert!(used.is_some());
+
+        // A folder token never exceeds its owner's grants
+        let token = insert_token(&pool, editor, "write", Some("staging/ci/*"), 30).await;
+        let (_, user, scope) = authenticate(&pool, &token).await.unwrap();
+        let policy = Policy::load(&pool, user).await.restricted_to(scope);
+        assert!(policy.allows(Access::Write, "staging/ci/deploy"));
+        assert!(!policy.allows(Access::Read, "staging/web/token"));
+        assert!(!policy.allows(Access::Read, "prod/ci/deploy"));
+        assert!(!policy.unscoped(Access::Read));
+
+        // Expired, unknown and revoked tokens, and tokens of disabled accounts, are refused
+        let expired = insert_token(&pool, admin, "write", None, -1).await;
+        assert!(authenticate(&pool, &expired).await.is_none());
+        assert!(authenticate(&pool, &generate_token()).await.is_none());
+        assert!(authenticate(&pool, "not-a-token").await.is_none());
+        sqlx::query("UPDATE users SET disabled = 1 WHERE id = ?").bind(editor).execute(&pool).await.unwrap();
+        assert!(authenticate(&pool, &token).await.is_none());
+        sqlx::query("DELETE FROM api_tokens WHERE token_hash = ?").bind(hash_token(&read_token)).execute(&pool).await.unwrap();
+        assert!(authenticate(&pool, &read_token).await.is_none());
+
+        assert!(current().is_none());
+        let seen = with_identity(identity, async { current().map(|t| t.username) }).await;
+        assert_eq!(seen.as_deref(), Some("root"));
+    }
+}
@@ -0,0 +1,345 @@
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use axum::Json;
+use axum::extract::{ConnectInfo, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use subtle::ConstantTimeEq;
+use tower_sessions::Session;
+use zeroize::Zeroize;
+use crate::db::DbPool;
+use crate::handlers::{is_debug, log_audit, log_audit_with_severity};
+use crate::state::AppState;
+use crate::throttle;
+use crate::users::{authenticate, current_user};
+
+// RFC 6238 defaults, the only parameters every authenticator app supports
+const STEP_SECONDS: u64 = 30;
+const DIGITS: usize = 6;
+// One step either side is accepted to allow for clock drift
+const DRIFT_STEPS: i64 = 1;
+const ISSUER: &str = "Talos";
+const RECOVERY_CODES: usize = 10;
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn api_error(status: StatusCode, message: &str) -> ApiError {
+    (status, Json(json!({"error": message})))
+}
+
+pub fn generate_secret() -> String {
+    let mut bytes = [0u8; 20];
+    OsRng.fill_bytes(&mut bytes);
+    BASE32_NOPAD.encode(&bytes)
+}
+
+fn hotp(key: &[u8], counter: u64) -> String {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(&counter.to_be_bytes());
+    let digest = mac.finalize().into_bytes();
+    let offset = (digest[19] & 0x0f) as usize;
+    let value = u32::from_be_bytes([digest[offset], digest[offset + 1], digest[offset + 2], digest[offset + 3]]) & 0x7fff_ffff;
+    format!("{:0width$}", value % 10u32.pow(DIGITS as u32), width = DIGITS)
+}
+
+fn current_step() -> i64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    (now / STEP_SECONDS) as i64
+}
+
+// Returns the time step the code belongs to. Steps up to `last_step` were
+// already used, so a code cannot be replayed.
+pub fn verify_code(secret: &str, code: &str, step: i64, last_step: i64) -> Option<i64> {
+    let code: String = code.chars().filter(|c| !c.is_whitespace()).collect();
+    if code.len() != DIGITS || !code.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let key = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+    (step - DRIFT_STEPS..=step + DRIFT_STEPS)
+        .filter(|candidate| *candidate > last_step && *candidate >= 0)
+        .find(|candidate| bool::from(hotp(&key, *candidate as u64).as_bytes().ct_eq(code.as_bytes())))
+}
+
+// The URI authenticator apps scan from the QR code
+pub fn provisioning_uri(username: &str, secret: &str) -> String {
+    let label: String = url::form_urlencoded::byte_serialize(format!("{}:{}", ISSUER, username).as_bytes()).collect();
+    format!("otpauth://totp/{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}", label, secret, ISSUER, DIGITS, STEP_SECONDS)
+}
+
+// Recovery codes carry 80 random bits, so a plain SHA-256 is enough to keep
+// them out of reach of someone holding a copy of talos.db
+fn hash_recovery_code(code: &str) -> String {
+    let normalized: String = code.chars().filter(|c| c.is_ascii_alphanumeric()).collect::<String>().to_lowercase();
+    Sha256::digest(normalized.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; 10];
+    OsRng.fill_bytes(&mut bytes);
+    let encoded = BASE32_NOPAD.encode(&bytes).to_lowercase();
+    encoded.as_bytes().chunks(4).map(|c| String::from_utf8_lossy(c).into_owned()).collect::<Vec<_>>().join("-")
+}
+
+struct TotpState {
+    secret: String,
+    enabled: bool,
+    last_step: i64,
+}
+
+async fn load_state(pool: &DbPool, user_id: i64) -> Option<TotpState> {
+    sqlx::query_as::<_, (String, bool, i64)>("SELECT secret, enabled, last_step FROM user_totp WHERE user_id = ?")
+        .bind(user_id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+        .map(|(secret, enabled, last_step)| TotpState { secret, enabled, last_step })
+}
+
+pub async fn is_enabled(pool: &DbPool, user_id: i64) -> bool {
+    load_state(pool, user_id).await.is_some_and(|state| state.enabled)
+}
+
+async fn remaining_recovery_codes(pool: &DbPool, user_id: i64) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM recovery_codes WHERE user_id = ? AND used_at IS NULL")
+        .bind(user_id)
+        .fetch_one(pool)
+        .await
+        .unwrap_or(0)
+}
+
+// Accepts a code for the stored secret once; the step is recorded in the
+// same statement, so two requests cannot both use it
+async fn accept_code(pool: &DbPool, user_id: i64, state: &TotpState, code: &str) -> bool {
+    let Some(step) = verify_code(&state.secret, code, current_step(), state.last_step) else {
+        return false;
+    };
+    sqlx::query("UPDATE user_totp SET last_step = ? WHERE user_id = ? AND last_step < ?")
+        .bind(step)
+        .bind(user_id)
+        .bind(step)
+        .execute(pool)
+        .await
+        .is_ok_and(|result| result.rows_affected() == 1)
+}
+
+async fn use_recovery_code(pool: &DbPool, user_id: i64, code: &str) -> bool {
+    sqlx::query("UPDATE recovery_codes SET used_at = CURRENT_TIMESTAMP WHERE user_id = ? AND code_hash = ? AND used_at IS NULL")
+        .bind(user_id)
+        .bind(hash_recovery_code(code))
+        .execute(pool)
+        .await
+        .is_ok_and(|result| result.rows_affected() == 1)
+}
+
+async fn replace_recovery_codes(pool: &DbPool, user_id: i64) -> Result<Vec<String>, sqlx::Error> {
+    let codes: Vec<String> = (0..RECOVERY_CODES).map(|_| generate_recovery_code()).collect();
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id = ?").bind(user_id).execute(&mut *tx).await?;
+    for code in &codes {
+        sqlx::query("INSERT INTO recovery_codes (user_id, code_hash) VALUES (?, ?)")
+            .bind(user_id)
+            .bind(hash_recovery_code(code))
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(codes)
+}
+
+pub async fn remove(pool: &DbPool, user_id: i64) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM user_totp WHERE user_id = ?").bind(user_id).execute(&mut *tx).await?;
+    sqlx::query("DELETE FROM recovery_codes WHERE user_id = ?").bind(user_id).execute(&mut *tx).await?;
+    tx.commit().await
+}
+
+/// Second factor used to complete a login
+#[derive(Debug, PartialEq)]
+pub enum Factor {
+    Totp,
+    RecoveryCode,
+}
+
+// Checks a login's second step: an authenticator code, or failing that a
+// single-use recovery code
+pub async fn check_second_factor(pool: &DbPool, user_id: i64, code: Option<&str>, recovery_code: Option<&str>) -> Option<Factor> {
+    let state = load_state(pool, user_id).await.filter(|state| state.enabled)?;
+    if let Some(code) = code.filter(|c| !c.trim().is_empty())
+        && accept_code(pool, user_id, &state, code).await
+    {
+        return Some(Factor::Totp);
+    }
+    if let Some(recovery_code) = recovery_code.filter(|c| !c.trim().is_empty())
+        && use_recovery_code(pool, user_id, recovery_code).await
+    {
+        return Some(Factor::RecoveryCode);
+    }
+    None
+}
+
+#[derive(Deserialize, Zeroize)]
+#[zeroize(drop)]
+pub struct EnableRequest {
+    code: String,
+}
+
+#[derive(Deserialize, Zeroize)]
+#[zeroize(drop)]
+pub struct DisableRequest {
+    password: String,
+    code: Option<String>,
+    recovery_code: Option<String>,
+}
+
+pub async fn totp_status(State(state): State<AppState>, session: Session) -> Result<Json<Value>, ApiError> {
+    let user = current_user(&state, &session).await.ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "Not authenticated"))?;
+    Ok(Json(json!({
+        "enabled": is_enabled(&state.pool, user.id).await,
+        "recovery_codes_remaining": remaining_recovery_codes(&state.pool, user.id).await,
+    })))
+}
+
+// Starts enrolment with a fresh secret. It only takes effect once a code from
+// the authenticator has been confirmed through `totp_enable`.
+pub async fn totp_setup(State(state): State<AppState>, session: Session) -> Result<Json<Value>, ApiError> {
+    let user = current_user(&state, &session).await.ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "Not authenticated"))?;
+    if is_debug() { println!("--> [WEB] Processing TOTP SETUP"); }
+    if is_enabled(&state.pool, user.id).await {
+        return Err(api_error(StatusCode::CONFLICT, "Two-factor authentication is already enabled"));
+    }
+
+    let secret = generate_secret();
+    sqlx::query("INSERT OR REPLACE INTO user_totp (user_id, secret, enabled, last_step) VALUES (?, ?, 0, 0)")
+        .bind(user.id)
+        .bind(&secret)
+        .execute(&state.pool)
+        .await
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to start enrolment"))?;
+    Ok(Json(json!({
+        "secret": secret,
+        "uri": provisioning_uri(&user.username, &secret),
+    })))
+}
+
+// Confirms enrolment with the first code and returns the recovery codes,
+// which are shown this once and only stored hashed
+pub async fn totp_enable(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<EnableRequest>
+) -> Result<Json<Value>, ApiError> {
+    let user = current_user(&state, &session).await.ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "Not authenticated"))?;
+    let ua_header = headers.get(header::USER_AGENT);
+    if let Err(retry_after) = throttle::check(&state.pool, addr.ip(), Some(&user.username)).await {
+        return Err(throttle::too_many_attempts(retry_after));
+    }
+    let pending = load_state(&state.pool, user.id).await.filter(|s| !s.enabled)
+        .ok_or_else(|| api_error(StatusCode::CONFLICT, "No enrolment in progress"))?;
+    if !accept_code(&state.pool, user.id, &pending, &req.code).await {
+        throttle::fail(&state, &session, addr.ip(), ua_header, Some(&user.username)).await;
+        log_audit(&state, &session, Some(addr.ip()), ua_header, "TOTP_ENROLL_FAILURE", &user.username).await;
+        return Err(api_error(StatusCode::UNAUTHORIZED, "Invalid code"));
+    }
+
+    let codes = replace_recovery_codes(&state.pool, user.id).await
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to create recovery codes"))?;
+    sqlx::query("UPDATE user_totp SET enabled = 1 WHERE user_id = ?")
+        .bind(user.id)
+        .execute(&state.pool)
+        .await
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to enable two-factor authentication"))?;
+    log_audit(&state, &session, Some(addr.ip()), ua_header, "TOTP_ENROLL", &user.username).await;
+    Ok(Json(json!({"status": "enabled", "recovery_codes": codes})))
+}
+
+// Turning the second factor off needs the password and a current code or a
+// recovery code, so a hijacked session alone cannot do it
+pub async fn totp_disable(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<DisableRequest>
+) -> Result<Json<Value>, ApiError> {
+    let user = current_user(&state, &session).await.ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "Not authenticated"))?;
+    let ua_header = headers.get(header::USER_AGENT);
+    if let Err(retry_after) = throttle::check(&state.pool, addr.ip(), Some(&user.username)).await {
+        return Err(throttle::too_many_attempts(retry_after));
+    }
+    if !is_enabled(&state.pool, user.id).await {
+        return Err(api_error(StatusCode::CONFLICT, "Two-factor authentication is not enabled"));
+    }
+    let verified = authenticate(&state.pool, &user.username, &req.password).await.is_some()
+        && check_second_factor(&state.pool, user.id, req.code.as_deref(), req.recovery_code.as_deref()).await.is_some();
+    if !verified {
+        throttle::fail(&state, &session, addr.ip(), ua_header, Some(&user.username)).await;
+        log_audit(&state, &session, Some(addr.ip()), ua_header, "TOTP_DISABLE_FAILURE", &user.username).await;
+        return Err(api_error(StatusCode::UNAUTHORIZED, "Invalid password or code"));
+    }
+
+    remove(&state.pool, user.id).await
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to disable two-factor authentication"))?;
+    log_audit_with_severity(&state, &session, Some(addr.ip()), ua_header, "TOTP_DISABLE", &user.username, "warning").await;
+    Ok(Json(json!({"status": "disabled"})))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    // RFC 6238 appendix B, SHA-1 with the 20 byte ASCII key "12345678901234567890"
+    #[test]
+    fn codes_match_the_rfc_test_vectors() {
+        let secret = BASE32_NOPAD.encode(b"12345678901234567890");
+        for (time, expected) in [(59u64, "287082"), (1111111109, "081804"), (1234567890, "005924"), (2000000000, "279037")] {
+            let step = (time / STEP_SECONDS) as i64;
+            assert_eq!(verify_code(&secret, expected, step, 0), Some(step));
+            assert_eq!(verify_code(&secret, expected, step + 1, 0), Some(step), "previous step still accepted");
+            assert_eq!(verify_code(&secret, expected, step + 2, 0), None, "two steps back is too old");
+            assert_eq!(verify_code(&secret, expected, step, step), None, "a used step cannot be replayed");
+        }
+        assert_eq!(verify_code(&secret, "28708", 1, 0), None);
+        assert_eq!(verify_code(&secret, "abcdef", 1, 0), None);
+
+        assert_eq!(
+            provisioning_uri("alice@example.com", "JBSWY3DP"),
+            "otpauth://totp/Talos%3Aalice%40example.com?secret=JBSWY3DP&issuer=Talos&algorithm=SHA1&digits=6&period=30"
+        );
+    }
+
+    #[tokio::test]
+    async fn second_factor_accepts_each_code_once() {
+        let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        crate::db::migrate(&pool).await;
+        sqlx::query("INSERT INTO users (username, password_hash) VALUES ('alice', 'x')").execute(&pool).await.unwrap();
+
+        let secret = generate_secret();
+        sqlx::query("INSERT INTO user_totp (user_id, secret, enabled, last_step) VALUES (1, ?, 1, 0)").bind(&secret).execute(&pool).await.unwrap();
+        let codes = replace_recovery_codes(&pool, 1).await.unwrap();
+        assert_eq!(codes.len(), RECOVERY_CODES);
+        assert_eq!(codes[0].len(), 19);
+
+        let code = hotp(&BASE32_NOPAD.decode(secret.as_bytes()).unwrap(), current_step() as u64);
+        assert_eq!(check_second_factor(&pool, 1, Some(&code), None).await, Some(Factor::Totp));
+        assert_eq!(check_second_factor(&pool, 1, Some(&code), None).await, None);
+
+        let typed = codes[3].to_uppercase().replace('-', " ");
+        assert_eq!(check_second_factor(&pool, 1, None, Some(&typed)).await, Some(Factor::RecoveryCode));
+        assert_eq!(check_second_factor(&pool, 1, None, Some(&codes[3])).await, None);
+        assert_eq!(remaining_recovery_codes(&pool, 1).await, 9);
+
+        remove(&pool, 1).await.unwrap();
+        assert!(!is_enabled(&pool, 1).await);
+        assert_eq!(check_second_factor(&pool, 1, None, Some(&codes[4])).await, None);
+    }
+}
@@ -0,0 +1,386 @@
+use argon2::password_hash::SaltString;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use axum::Json;
+use axum::extract::{ConnectInfo, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode, header};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::LazyLock;
+use tower_sessions::Session;
+use zeroize::{Zeroize, Zeroizing};
+use crate::db::DbPool;
+use crate::handlers::{is_debug, log_audit};
+use crate::policy::Role;
+use crate::sessions::{self, SESSION_IP, SESSION_USER_AGENT};
+use crate::state::AppState;
+use crate::throttle;
+use crate::totp;
+
+pub const MIN_PASSWORD_LEN: usize = 12;
+const MAX_USERNAME_LEN: usize = 64;
+
+pub const SESSION_USER_ID: &str = "user_id";
+pub const SESSION_USERNAME: &str = "username";
+
+// Checked against when the account is unknown or disabled, so a failed login
+// costs the same whether or not the username exists
+static DUMMY_HASH: LazyLock<String> = LazyLock::new(|| hash_password("talos-unknown-account").expect("Failed to hash dummy password"));
+
+#[derive(Serialize, sqlx::FromRow, Clone, Debug)]
+pub struct User {
+    pub id: i64,
+    pub username: String,
+    pub role: Role,
+    pub disabled: bool,
+    pub created_at: String,
+    pub last_login: Option<String>,
+}
+
+type ApiError = (StatusCode, Json<Value>);
+
+fn api_error(status: StatusCode, message: &str) -> ApiError {
+    (status, Json(json!({"error": message})))
+}
+
+// Argon2id with the crate defaults (19 MiB, 2 passes, OWASP's minimum); the
+// parameters are stored in the PHC string, so raising them later still
+// verifies existing hashes
+pub fn hash_password(password: &str) -> Result<String, String> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default().hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+        .map_err(|e| e.to_string())
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash)
+        .is_ok_and(|parsed| Argon2::default().verify_password(password.as_bytes(), &parsed).is_ok())
+}
+
+// Argon2 is deliberately slow, so it runs off the async workers
+pub(crate) async fn hash_blocking(password: &str) -> Result<String, String> {
+    let password = Zeroizing::new(password.to_string());
+    tokio::task::spawn_blocking(move || hash_password(&password)).await.unwrap_or_else(|e| Err(e.to_string()))
+}
+
+pub fn normalize_username(name: &str) -> Result<String, String> {
+    normalize_name("Username", name)
+}
+
+// Usernames and team names share one format
+pub fn normalize_name(kind: &str, name: &str) -> Result<String, String> {
+    let name = name.trim().to_lowercase();
+    if name.is_empty() || name.len() > MAX_USERNAME_LEN {
+        return Err(format!("{} must be 1 to {} characters", kind, MAX_USERNAME_LEN));
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || "._-@".contains(c)) {
+        return Err(format!("{} may only contain letters, digits and . _ - @", kind));
+    }
+    Ok(name)
+}
+
+pub fn check_password_policy(password: &str) -> Result<(), String> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(format!("Password must be at least {} characters", MIN_PASSWORD_LEN));
+    }
+    Ok(())
+}
+
+pub async fn find_user(pool: &DbPool, id: i64) -> Option<User> {
+    sqlx::query_as::<_, User>("SELECT id, username, role, disabled, created_at, last_login FROM users WHERE id = ?")
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten()
+}
+
+pub async fn user_count(pool: &DbPool) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM users")
+        .fetch_one(pool)
+        .await
+        .unwrap_or(0)
+}
+
+// Returns the account when the password matches and the account is enabled
+pub async fn authenticate(pool: &DbPool, username: &str, password: &str) -> Option<User> {
+    let row: Option<(i64, String, bool)> = sqlx::query_as("SELECT id, password_hash, disabled FROM users WHERE username = ?")
+        .bind(username.trim())
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten();
+    let (hash, enabled) = match &row {
+        Some((_, hash, disabled)) => (hash.clone(), !disabled),
+        None => (DUMMY_HASH.clone(), false),
+    };
+    let password = Zeroizing::new(password.to_string());
+    let matches = tokio::task::spawn_blocking(move || verify_password(&password, &hash)).await.unwrap_or(false);
+    if !(matches && enabled) {
+        return None;
+    }
+
+    let (id, ..) = row?;
+    let _ = sqlx::query("UPDATE users SET last_login = CURRENT_TIMESTAMP WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await;
+    find_user(pool, id).await
+}
+
+pub async fn insert_user(pool: &DbPool, username: &str, password_hash: &str, role: Role) -> Result<i64, sqlx::Error> {
+    let result = sqlx::query("INSERT INTO users (username, password_hash, role) VALUES (?, ?, ?)")
+        .bind(username)
+        .bind(password_hash)
+        .bind(role)
+        .execute(pool)
+        .await?;
+    Ok(result.last_insert_rowid())
+}
+
+// Creates the first account as an administrator. Returns None once any
+// account exists, also when two setups race.
+pub async fn insert_first_admin(pool: &DbPool, username: &str, password_hash: &str) -> Result<Option<i64>, sqlx::Error> {
+    let result = sqlx::query("INSERT INTO users (username, password_hash, role) SELECT ?, ?, 'admin' WHERE NOT EXISTS (SELECT 1 FROM users)")
+        .bind(username)
+        .bind(password_hash)
+        .execute(pool)
+        .await?;
+    Ok((result.rows_affected() == 1).then(|| result.last_insert_rowid()))
+}
+
+// Enabled administrators other than `id`; the last one cannot be removed
+async fn other_active_admins(pool: &DbPool, id: i64) -> i64 {
+    sqlx::query_scalar("SELECT COUNT(*) FROM users WHERE role = 'admin' AND disabled = 0 AND id != ?")
+        .bind(id)
+        .fetch_one(pool)
+        .await
+        .unwrap_or(0)
+}
+
+// Binds the session to an account. The session id and its CSRF token change
+// on login, so values planted before authentication are worthless afterwards.
+// Returns the new CSRF token.
+pub async fn start_session(session: &Session, user: &User, auth_method: &str, addr: SocketAddr, ua_header: Option<&HeaderValue>) -> Result<String, tower_sessions::session::Error> {
+    session.cycle_id().await?;
+    session.insert(SESSION_USER_ID, user.id).await?;
+    session.insert(SESSION_USERNAME, &user.username).await?;
+    session.insert("auth_method", auth_method).await?;
+    session.insert(SESSION_IP, addr.ip().to_string()).await?;
+    if let Some(ua) = ua_header.and_then(|v| v.to_str().ok()) {
+        session.insert(SESSION_USER_AGENT, ua.chars().take(256).collect::<String>()).await?;
+    }
+    crate::auth::issue_csrf_token(session).await
+}
+
+// The signed-in account, as long as it still exists and is enabled
+pub async fn current_user(state: &AppState, session: &Session) -> Option<User> {
+    let id: i64 = session.get(SESSION_USER_ID).await.ok().flatten()?;
+    find_user(&state.pool, id).await.filter(|user| !user.disabled)
+}
+
+#[derive(Deserialize, Zeroize)]
+#[zeroize(drop)]
+pub struct CreateUserRequest {
+    username: String,
+    password: String,
+    #[serde(default = "default_role")]
+    #[zeroize(skip)]
+    role: Role,
+}
+
+// New accounts see nothing until they are given grants
+fn default_role() -> Role {
+    Role::Viewer
+}
+
+#[derive(Deserialize, Zeroize)]
+#[zeroize(drop)]
+pub struct UpdateUserRequest {
+    id: i64,
+    disabled: Option<bool>,
+    #[zeroize(skip)]
+    role: Option<Role>,
+    // Resets the password without knowing the old one
+    password: Option<String>,
+    // Removes the second factor of a user who lost their authenticator
+    #[serde(default)]
+    reset_totp: bool,
+    // Ends every session of the user
+    #[serde(default)]
+    logout: bool,
+    // Lifts a lockout after too many failed sign-ins
+    #[serde(default)]
+    unlock: bool,
+}
+
+#[derive(Deserialize, Zeroize)]
+#[zeroize(drop)]
+pub struct ChangePasswordRequest {
+    current_password: String,
+    new_password: String,
+}
+
+pub async fn list_users(State(state): State<AppState>) -> Json<Vec<User>> {
+    let users = sqlx::query_as::<_, User>("SELECT id, username, role, disabled, created_at, last_login FROM users ORDER BY username")
+        .fetch_all(&state.pool)
+        .await
+        .unwrap_or_default();
+    Json(users)
+}
+
+pub async fn create_user(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<CreateUserRequest>
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    if is_debug() { println!("--> [WEB] Processing USER CREATE"); }
+
+    let username = normalize_username(&req.username).map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+    check_password_policy(&req.password).map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+    let hash = hash_blocking(&req.password).await.map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to hash password"))?;
+
+    let id = match insert_user(&state.pool, &username, &hash, req.role).await {
+        Ok(id) => id,
+        Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+            return Err(api_error(StatusCode::CONFLICT, "Username already exists"));
+        },
+        Err(e) => {
+            println!("❌ [WEB] User creation failed: {}", e);
+            return Err(api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to create user"));
+        }
+    };
+
+    let target = format!("{} role={}", username, req.role.as_str());
+    log_audit(&state, &session, Some(addr.ip()), headers.get(header::USER_AGENT), "USER_CREATE", &target).await;
+    Ok((StatusCode::CREATED, Json(json!({"id": id, "username": username}))))
+}
+
+// Disables an account, changes its role, resets its password or its second
+// factor, or unlocks it. Accounts are disabled rather than deleted so audit
+// entries keep their author.
+pub async fn update_user(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<UpdateUserRequest>
+) -> Result<Json<Value>, ApiError> {
+    if is_debug() { println!("--> [WEB] Processing USER UPDATE"); }
+
+    let user = find_user(&state.pool, req.id).await.ok_or_else(|| api_error(StatusCode::NOT_FOUND, "User not found"))?;
+    let loses_admin = user.role == Role::Admin && !user.disabled
+        && (req.disabled == Some(true) || req.role.is_some_and(|role| role != Role::Admin));
+    if loses_admin && other_active_admins(&state.pool, user.id).await == 0 {
+        return Err(api_error(StatusCode::CONFLICT, "The last active administrator cannot be disabled or demoted"));
+    }
+
+    let mut changes = Vec::new();
+    if let Some(password) = &req.password {
+        check_password_policy(password).map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+        let hash = hash_blocking(password).await.map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to hash password"))?;
+        sqlx::query("UPDATE users SET password_hash = ? WHERE id = ?").bind(hash).bind(user.id).execute(&state.pool).await
+            .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to update user"))?;
+        changes.push("password reset".to_string());
+    }
+    if let Some(disabled) = req.disabled {
+        sqlx::query("UPDATE users SET disabled = ? WHERE id = ?").bind(disabled).bind(user.id).execute(&state.pool).await
+            .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to update user"))?;
+        changes.push(format!("disabled={}", disabled));
+    }
+    if let Some(role) = req.role {
+        sqlx::query("UPDATE users SET role = ? WHERE id = ?").bind(role).bind(user.id).execute(&state.pool).await
+            .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to update user"))?;
+        changes.push(format!("role={}", role.as_str()));
+    }
+    if req.reset_totp {
+        totp::remove(&state.pool, user.id).await
+            .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to update user"))?;
+        changes.push("totp reset".to_string());
+    }
+    if req.logout {
+        let revoked = sessions::revoke_user_sessions(&state.pool, user.id).await
+            .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to update user"))?;
+        changes.push(format!("signed out ({} sessions)", revoked));
+    }
+    if req.unlock {
+        throttle::unlock_user(&state.pool, &user.username).await
+            .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to update user"))?;
+        changes.push("unlocked".to_string());
+    }
+
+    let target = format!("{} {}", user.username, changes.join(" "));
+    log_audit(&state, &session, Some(addr.ip()), headers.get(header::USER_AGENT), "USER_UPDATE", target.trim_end()).await;
+    Ok(Json(json!({"status": "updated"})))
+}
+
+// Changes the signed-in user's own password; the current one is required
+pub async fn change_password(
+    State(state): State<AppState>,
+    session: Session,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(req): Json<ChangePasswordRequest>
+) -> Result<Json<Value>, ApiError> {
+    let user = current_user(&state, &session).await.ok_or_else(|| api_error(StatusCode::UNAUTHORIZED, "Not authenticated"))?;
+    let ua_header = headers.get(header::USER_AGENT);
+
+    if let Err(retry_after) = throttle::check(&state.pool, addr.ip(), Some(&user.username)).await {
+        log_audit(&state, &session, Some(addr.ip()), ua_header, "PASSWORD_CHANGE_RATE_LIMITED", &user.username).await;
+        return Err(throttle::too_many_attempts(retry_after));
+    }
+    if authenticate(&state.pool, &user.username, &req.current_password).await.is_none() {
+        throttle::fail(&state, &session, addr.ip(), ua_header, Some(&user.username)).await;
+        log_audit(&state, &session, Some(addr.ip()), ua_header, "PASSWORD_CHANGE_FAILURE", &user.username).await;
+        return Err(api_error(StatusCode::UNAUTHORIZED, "Current password is incorrect"));
+    }
+    check_password_policy(&req.new_password).map_err(|e| api_error(StatusCode::BAD_REQUEST, &e))?;
+
+    let hash = hash_blocking(&req.new_password).await.map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to hash password"))?;
+    sqlx::query("UPDATE users SET password_hash = ? WHERE id = ?").bind(hash).bind(user.id).execute(&state.pool).await
+        .map_err(|_| api_error(StatusCode::INTERNAL_SERVER_ERROR, "Failed to update password"))?;
+    log_audit(&state, &session, Some(addr.ip()), ua_header, "PASSWORD_CHANGE", &user.username).await;
+    Ok(Json(json!({"status": "Password changed"})))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sqlx::sqlite::SqlitePoolOptions;
+
+    #[tokio::test]
+    async fn accounts_authenticate_with_argon2id_hashes() {
+        let pool = SqlitePoolOptions::new().max_connections(1).connect("sqlite::memory:").await.unwrap();
+        crate::db::migrate(&pool).await;
+
+        let hash = hash_password("correct horse battery").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert!(verify_password("correct horse battery", &hash));
+        assert!(!verify_password("Correct horse battery", &hash));
+        assert!(!verify_password("correct horse battery", "not a hash"));
+
+        let admin = insert_first_admin(&pool, "alice", &hash).await.unwrap().unwrap();
+        assert_eq!(insert_first_admin(&pool, "mallory", &hash).await.unwrap(), None);
+        let bob = insert_user(&pool, "bob", &hash_password("bobs password!").unwrap(), Role::Editor).await.unwrap();
+        assert!(insert_user(&pool, "ALICE", &hash, Role::Viewer).await.is_err());
+
+        let alice = authenticate(&pool, "alice", "correct horse battery").await.unwrap();
+        assert_eq!((alice.id, alice.role), (admin, Role::Admin));
+        assert!(alice.last_login.is_some());
+        assert!(authenticate(&pool, "alice", "wrong").await.is_none());
+        assert!(authenticate(&pool, "nobody", "correct horse battery").await.is_none());
+
+        sqlx::query("UPDATE users SET disabled = 1 WHERE id = ?").bind(bob).execute(&pool).await.unwrap();
+        assert!(authenticate(&pool, "bob", "bobs password!").await.is_none());
+        assert_eq!(other_active_admins(&pool, admin).await, 0);
+        assert_eq!(user_count(&pool).await, 2);
+
+        assert_eq!(normalize_username(" Alice@Example.com ").unwrap(), "alice@example.com");
+        assert!(normalize_username("a b").is_err());
+        assert!(check_password_policy("short").is_err());
+    }
+}
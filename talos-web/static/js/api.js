@@ -1,12 +1,34 @@
+const toBase64Url = (buf) => btoa(String.fromCharCode(...new Uint8Array(buf)))
+    .replace(/\+/g, '-').replace(/\//g, '_').replace(/=+$/, '');
+const fromBase64Url = (str) => Uint8Array.from(atob(str.replace(/-/g, '+').replace(/_/g, '/')), c => c.charCodeAt(0)).buffer;
+
+// The session's CSRF token, from the auth status and login responses. Every
+// request that is not a plain read carries it in the X-CSRF-Token header.
+let csrfToken = null;
+const request = (url, options = {}) => {
+    const method = (options.method || 'GET').toUpperCase();
+    if (method === 'GET' || !csrfToken) return fetch(url, options);
+    return fetch(url, { ...options, headers: { ...options.headers, 'X-CSRF-Token': csrfToken } });
+};
+
 export const API = {
-    async fetchTree() {
-        const res = await fetch(`/api/tree`);
+    async fetchTree(tag = '') {
+        const params = tag ? `?${new URLSearchParams({ tag })}` : '';
+        const res = await request(`/api/tree${params}`);
+        if (!res.ok) throw new Error(res.statusText);
+        return await res.json();
+    },
+
+    async search(q, fields = null, mode = 'fuzzy') {
+        const params = new URLSearchParams({ q, mode });
+        if (fields) params.set('fields', fields);
+        const res = await request(`/api/search?${params}`);
         if (!res.ok) throw new Error(res.statusText);
         return await res.json();
     },
 
     async decrypt(path, reveal = false) {
-        const res = await fetch('/api/decrypt', {
+        const res = await request('/api/decrypt', {
             method: 'POST',
             headers: { 'Content-Type': 'application/json' },
             body: JSON.stringify({ path, reveal })
@@ -18,32 +40,107 @@ export const API = {
         return await res.json();
     },
 
-    async save(path, content, original_path) {
-        const res = await fetch('/api/save', {
+    // Decrypts for editing and keeps the version token (ETag) for the later save
+    async fetchForEdit(path) {
+        const res = await request('/api/decrypt', {
             method: 'POST',
             headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ path, reveal: false })
+        });
+        if (!res.ok) {
+            const err = await res.json();
+            throw new Error(typeof err === 'string' ? err : (err.error || 'Decryption failed'));
+        }
+        return { content: await res.json(), version: res.headers.get('ETag') };
+    },
+
+    async save(path, content, original_path, version) {
+        const headers = { 'Content-Type': 'application/json' };
+        if (version) headers['If-Match'] = version;
+        const res = await request('/api/save', {
+            method: 'POST',
+            headers,
             body: JSON.stringify({ path, content, original_path })
         });
         if (!res.ok) {
             const err = await res.json();
-            throw new Error(err.error || 'Save failed');
+            const error = new Error(err.error || 'Save failed');
+            error.conflict = res.status === 409;
+            throw error;
         }
     },
 
-    async delete(path) {
-        const res = await fetch('/api/delete', {
+    async delete(path, recursive = false, confirm = null) {
+        const res = await request('/api/delete', {
             method: 'POST',
             headers: { 'Content-Type': 'application/json' },
-            body: JSON.stringify({ path })
+            body: JSON.stringify(recursive ? { path, recursive, confirm } : { path })
         });
         if (!res.ok) {
             const err = await res.json();
-            throw new Error(err.error || 'Delete failed');
+            const error = new Error(err.error || 'Delete failed');
+            error.notEmpty = res.status === 409;
+            throw error;
+        }
+    },
+
+    async move(from, to) {
+        const res = await request('/api/move', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ from, to })
+        });
+        if (!res.ok) {
+            const err = await res.json();
+            throw new Error(err.error || 'Move failed');
+        }
+    },
+
+    async updateMetadata(path, { tags, favourite } = {}) {
+        const res = await request('/api/meta', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ path, tags, favourite })
+        });
+        if (!res.ok) {
+            const err = await res.json();
+            throw new Error(err.error || 'Metadata update failed');
+        }
+        return await res.json();
+    },
+
+    async fetchTrash() {
+        const res = await request('/api/trash');
+        if (!res.ok) throw new Error(res.statusText);
+        return await res.json();
+    },
+
+    async restoreFromTrash(id) {
+        const res = await request('/api/trash/restore', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ id })
+        });
+        if (!res.ok) {
+            const err = await res.json();
+            throw new Error(err.error || 'Restore from trash failed');
+        }
+    },
+
+    async purgeTrash(id) {
+        const res = await request('/api/trash/purge', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify(id ? { id } : { all: true })
+        });
+        if (!res.ok) {
+            const err = await res.json();
+            throw new Error(err.error || 'Purge failed');
         }
     },
 
     async createCategory(path) {
-        const res = await fetch('/api/create_category', {
+        const res = await request('/api/create_category', {
             method: 'POST',
             headers: { 'Content-Type': 'application/json' },
             body: JSON.stringify({ path })
@@ -54,23 +151,40 @@ export const API = {
         }
     },
 
-    async restore(file) {
+    async restore(file, options = {}) {
+        return await this.restoreRequest('/api/restore', file, options);
+    },
+
+    async previewRestore(file, options = {}) {
+        return await this.restoreRequest('/api/restore/preview', file, options);
+    },
+
+    async restoreRequest(url, file, { passphrase = null, legacy = false, paths = null, policy = null } = {}) {
         const formData = new FormData();
+        if (passphrase) formData.append('passphrase', passphrase);
+        if (legacy) formData.append('legacy', 'true');
+        if (paths) formData.append('paths', JSON.stringify(paths));
+        if (policy) formData.append('policy', policy);
         formData.append('backup', file);
         
-        const res = await fetch('/api/restore', {
+        const res = await request(url, {
             method: 'POST',
             body: formData
         });
         if (!res.ok) {
             const err = await res.json();
-            throw new Error(err.error || 'Restore failed');
+            const error = new Error(err.error || 'Restore failed');
+            error.legacy = !!err.legacy;
+            error.passphrase = !!err.passphrase;
+            error.report = err.report || null;
+            throw error;
         }
+        return await res.json();
     },
 
     async checkHealth() {
         try {
-            const res = await fetch('/api/health');
+            const res = await request('/api/health');
             return await res.json();
         } catch(e) {
             return { storage: false, bunker: false };
@@ -78,25 +192,27 @@ export const API = {
     },
 
     async fetchVersion() {
-        const res = await fetch('/api/version');
+        const res = await request('/api/version');
         return await res.json();
     },
 
     async fetchAuthStatus() {
-        const res = await fetch('/api/auth/status');
-        return await res.json();
+        const res = await request('/api/auth/status');
+        const status = await res.json();
+        csrfToken = status.csrf_token || null;
+        return status;
     },
 
     async fetchAuditLogs() {
-        const res = await fetch('/api/audit');
+        const res = await request('/api/audit');
         return await res.json();
     },
 
-    async initializeSystem(masterKey) {
-        const res = await fetch('/api/initialize', {
+    async initializeSystem(masterKey, bootstrapToken) {
+        const res = await request('/api/initialize', {
             method: 'POST',
             headers: { 'Content-Type': 'application/json' },
-            body: JSON.stringify({ key: masterKey })
+            body: JSON.stringify({ key: masterKey, bootstrap_token: bootstrapToken })
         });
         if (!res.ok) {
             const err = await res.json();
@@ -104,11 +220,11 @@ export const API = {
         }
     },
 
-    async importSystem(privateKey, passphrase) {
-        const res = await fetch('/api/initialize/import', {
+    async importSystem(privateKey, passphrase, bootstrapToken) {
+        const res = await request('/api/initialize/import', {
             method: 'POST',
             headers: { 'Content-Type': 'application/json' },
-            body: JSON.stringify({ key: privateKey, passphrase })
+            body: JSON.stringify({ key: privateKey, passphrase, bootstrap_token: bootstrapToken })
         });
         if (!res.ok) {
             const err = await res.json();
@@ -116,11 +232,26 @@ export const API = {
         }
     },
 
-    async login(masterKey) {
-        const res = await fetch('/api/auth/login', {
+    // Resolves to true when the account asks for a second factor
+    async login(username, password) {
+        const res = await request('/api/auth/login', {
             method: 'POST',
             headers: { 'Content-Type': 'application/json' },
-            body: JSON.stringify({ key: masterKey })
+            body: JSON.stringify({ username, password })
+        });
+        const data = await res.json();
+        if (!res.ok) throw new Error(data.error || 'Login failed');
+        csrfToken = data.csrf_token || csrfToken;
+        return data.totp_required === true;
+    },
+
+    // Authenticator codes are six digits; anything else is a recovery code
+    async loginSecondFactor(code) {
+        const body = /^\s*\d{6}\s*$/.test(code) ? { code } : { recovery_code: code };
+        const res = await request('/api/auth/login/totp', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify(body)
         });
         if (!res.ok) {
             const err = await res.json();
@@ -128,8 +259,216 @@ export const API = {
         }
     },
 
+    // WebAuthn ceremonies: the server speaks base64url JSON, the browser ArrayBuffers
+    async loginWithPasskey(username) {
+        const res = await request('/api/auth/passkey/start', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ username })
+        });
+        const options = await res.json();
+        if (!res.ok) throw new Error(options.error || 'Passkey login failed');
+        const pk = options.publicKey;
+        pk.challenge = fromBase64Url(pk.challenge);
+        (pk.allowCredentials || []).forEach(c => c.id = fromBase64Url(c.id));
+        const cred = await navigator.credentials.get({ publicKey: pk });
+        const finish = await request('/api/auth/passkey/finish', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({
+                id: cred.id,
+                rawId: toBase64Url(cred.rawId),
+                type: cred.type,
+                response: {
+                    authenticatorData: toBase64Url(cred.response.authenticatorData),
+                    clientDataJSON: toBase64Url(cred.response.clientDataJSON),
+                    signature: toBase64Url(cred.response.signature),
+                    userHandle: cred.response.userHandle ? toBase64Url(cred.response.userHandle) : null
+                },
+                extensions: cred.getClientExtensionResults()
+            })
+        });
+        if (!finish.ok) {
+            const err = await finish.json();
+            throw new Error(err.error || 'Passkey login failed');
+        }
+    },
+
+    async fetchPasskeys() {
+        const res = await request('/api/account/passkeys');
+        return await res.json();
+    },
+
+    async registerPasskey(name) {
+        const res = await request('/api/account/passkeys/register/start', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ name })
+        });
+        const options = await res.json();
+        if (!res.ok) throw new Error(options.error || 'Registration failed');
+        const pk = options.publicKey;
+        pk.challenge = fromBase64Url(pk.challenge);
+        pk.user.id = fromBase64Url(pk.user.id);
+        (pk.excludeCredentials || []).forEach(c => c.id = fromBase64Url(c.id));
+        const cred = await navigator.credentials.create({ publicKey: pk });
+        const finish = await request('/api/account/passkeys/register/finish', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({
+                id: cred.id,
+                rawId: toBase64Url(cred.rawId),
+                type: cred.type,
+                response: {
+                    attestationObject: toBase64Url(cred.response.attestationObject),
+                    clientDataJSON: toBase64Url(cred.response.clientDataJSON),
+                    transports: cred.response.getTransports ? cred.response.getTransports() : undefined
+                },
+                extensions: cred.getClientExtensionResults()
+            })
+        });
+        if (!finish.ok) {
+            const err = await finish.json();
+            throw new Error(err.error || 'Registration failed');
+        }
+    },
+
+    async renamePasskey(id, name) {
+        const res = await request('/api/account/passkeys/rename', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ id, name })
+        });
+        if (!res.ok) {
+            const err = await res.json();
+            throw new Error(err.error || 'Rename failed');
+        }
+    },
+
+    async deletePasskey(id) {
+        const res = await request('/api/account/passkeys/delete', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ id })
+        });
+        if (!res.ok) {
+            const err = await res.json();
+            throw new Error(err.error || 'Delete failed');
+        }
+    },
+
+    async fetchTokens() {
+        const res = await request('/api/account/tokens');
+        return await res.json();
+    },
+
+    async createToken(name, access, path, expiresInDays) {
+        const res = await request('/api/account/tokens', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({
+                name,
+                access,
+                path: path || null,
+                expires_in_days: expiresInDays ? Number(expiresInDays) : null
+            })
+        });
+        const data = await res.json();
+        if (!res.ok) throw new Error(data.error || 'Token creation failed');
+        return data;
+    },
+
+    async deleteToken(id) {
+        const res = await request('/api/account/tokens/delete', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ id })
+        });
+        if (!res.ok) {
+            const err = await res.json();
+            throw new Error(err.error || 'Revoke failed');
+        }
+    },
+
+    async fetchSessions() {
+        const res = await request('/api/account/sessions');
+        return await res.json();
+    },
+
+    // Revokes one session by id, or every other session with `null`
+    async revokeSession(id) {
+        const res = await request('/api/account/sessions/revoke', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify(id ? { id } : { all: true })
+        });
+        const data = await res.json();
+        if (!res.ok) throw new Error(data.error || 'Revoke failed');
+        return data;
+    },
+
+    async fetchTotpStatus() {
+        const res = await request('/api/account/totp');
+        return await res.json();
+    },
+
+    async setupTotp() {
+        const res = await request('/api/account/totp/setup', { method: 'POST' });
+        const data = await res.json();
+        if (!res.ok) throw new Error(data.error || 'Setup failed');
+        return data;
+    },
+
+    async enableTotp(code) {
+        const res = await request('/api/account/totp/enable', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ code })
+        });
+        const data = await res.json();
+        if (!res.ok) throw new Error(data.error || 'Enable failed');
+        return data.recovery_codes;
+    },
+
+    async disableTotp(password, code) {
+        const second = /^\s*\d{6}\s*$/.test(code) ? { code } : { recovery_code: code };
+        const res = await request('/api/account/totp/disable', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ password, ...second })
+        });
+        if (!res.ok) {
+            const err = await res.json();
+            throw new Error(err.error || 'Disable failed');
+        }
+    },
+
+    async setupAccount(masterKey, username, password) {
+        const res = await request('/api/auth/setup', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ key: masterKey, username, password })
+        });
+        if (!res.ok) {
+            const err = await res.json();
+            throw new Error(err.error || 'Account setup failed');
+        }
+    },
+
+    async unseal(masterKey) {
+        const res = await request('/api/auth/unseal', {
+            method: 'POST',
+            headers: { 'Content-Type': 'application/json' },
+            body: JSON.stringify({ key: masterKey })
+        });
+        if (!res.ok) {
+            const err = await res.json();
+            throw new Error(err.error || 'Unseal failed');
+        }
+    },
+
     async logout() {
-        await fetch('/api/auth/logout', {
+        await request('/api/auth/logout', {
             method: 'POST'
         });
     }
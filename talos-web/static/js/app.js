@@ -40,6 +40,87 @@ const App = {
         };
         UI.elements.btnCloseAudit.onclick = () => UI.closeAuditModal();
 
+        // Two-factor authentication for the signed-in account
+        document.getElementById('btn-totp').onclick = async () => {
+            try {
+                UI.openTotpModal(await API.fetchTotpStatus());
+                await this.refreshPasskeys();
+                document.getElementById('token-created').classList.add('hidden');
+                await this.refreshTokens();
+                await this.refreshSessions();
+            } catch (e) { UI.showNotification("Failed to load account security", "error"); }
+        };
+        document.getElementById('btn-revoke-sessions').onclick = async () => {
+            if (!confirm("SIGN OUT ALL OTHER SESSIONS?")) return;
+            try {
+                const { count } = await API.revokeSession(null);
+                UI.showNotification(`${count} SESSIONS SIGNED OUT`, "success");
+                await this.refreshSessions();
+            } catch (err) { UI.showNotification(err.message, "error"); }
+        };
+        document.getElementById('token-create-form').onsubmit = async (e) => {
+            e.preventDefault();
+            const name = document.getElementById('token-name');
+            const path = document.getElementById('token-path');
+            const expiry = document.getElementById('token-expiry');
+            try {
+                const { token } = await API.createToken(name.value, document.getElementById('token-access').value, path.value, expiry.value);
+                name.value = path.value = expiry.value = '';
+                UI.showCreatedToken(token);
+                await this.refreshTokens();
+            } catch (err) { UI.showNotification(err.message, "error"); }
+        };
+        document.getElementById('passkey-register-form').onsubmit = async (e) => {
+            e.preventDefault();
+            const input = document.getElementById('passkey-name');
+            try {
+                await API.registerPasskey(input.value);
+                input.value = '';
+                UI.showNotification("PASSKEY ADDED", "success");
+                await this.refreshPasskeys();
+            } catch (err) { UI.showNotification(err.message, "error"); }
+        };
+        document.getElementById('btn-close-totp').onclick = () => UI.closeTotpModal();
+        document.getElementById('btn-totp-setup').onclick = async () => {
+            try {
+                const { secret, uri } = await API.setupTotp();
+                UI.showTotpEnrolment(secret, uri);
+            } catch (err) { UI.showNotification(err.message, "error"); }
+        };
+        document.getElementById('totp-enable-form').onsubmit = async (e) => {
+            e.preventDefault();
+            const input = document.getElementById('totp-enable-code');
+            try {
+                UI.showRecoveryCodes(await API.enableTotp(input.value));
+                UI.showNotification("2FA ENABLED", "success");
+            } catch (err) { UI.showNotification(err.message, "error"); }
+            input.value = '';
+        };
+        document.getElementById('totp-disable-form').onsubmit = async (e) => {
+            e.preventDefault();
+            const password = document.getElementById('totp-disable-password');
+            const code = document.getElementById('totp-disable-code');
+            try {
+                await API.disableTotp(password.value, code.value);
+                UI.closeTotpModal();
+                UI.showNotification("2FA DISABLED", "success");
+            } catch (err) { UI.showNotification(err.message, "error"); }
+            password.value = '';
+            code.value = '';
+        };
+
+        // Trash Bin
+        document.getElementById('btn-trash').onclick = () => this.openTrash();
+        UI.elements.btnCloseTrash.onclick = () => UI.closeTrashModal();
+        UI.elements.btnCloseRestore.onclick = () => UI.closeRestoreModal();
+        UI.elements.btnEmptyTrash.onclick = async () => {
+            if (!confirm("PERMANENTLY PURGE EVERYTHING IN THE TRASH?")) return;
+            try {
+                await API.purgeTrash(null);
+                this.openTrash();
+            } catch (err) { UI.showNotification("ERROR PURGING: " + err.message, "error"); }
+        };
+
         // Entry Generator Logic
         const runEntryGen = () => {
             const len = parseInt(UI.elements.entryGenLength.value);
@@ -60,18 +141,36 @@ const App = {
             if (healthy) this.loadFiles();
         };
 
+        let searchDebounce = null;
         UI.elements.treeSearch.addEventListener('input', (e) => {
             const searchTerm = e.target.value;
-            UI.elements.treeContainer.jstree(true).search(searchTerm);
             UI.elements.clearSearch.classList.toggle('hidden', !searchTerm);
+            clearTimeout(searchDebounce);
+            searchDebounce = setTimeout(async () => {
+                // Server-side search covers username, URL, tags and title
+                try {
+                    const hits = searchTerm ? await API.search(searchTerm) : [];
+                    UI.searchMatches = new Set(hits.map(h => h.path));
+                } catch (err) {
+                    UI.searchMatches = new Set();
+                }
+                UI.elements.treeContainer.jstree(true).search(searchTerm);
+            }, 250);
         });
 
         UI.elements.clearSearch.addEventListener('click', () => {
             UI.elements.treeSearch.value = '';
+            UI.searchMatches = new Set();
             UI.elements.treeContainer.jstree(true).clear_search();
             UI.elements.clearSearch.classList.add('hidden');
         });
 
+        let tagDebounce = null;
+        UI.elements.tagFilter.addEventListener('input', () => {
+            clearTimeout(tagDebounce);
+            tagDebounce = setTimeout(() => this.loadFiles(), 250);
+        });
+
         // jsTree event listener for selection
         UI.elements.treeContainer.on('select_node.jstree', (e, data) => {
             if (!data.node.data.is_dir) {
@@ -95,10 +194,13 @@ const App = {
             if (!status.initialized) {
                 this.initSetupMode();
             } else if (!status.authenticated) {
-                this.initLoginMode();
+                this.initLoginMode(status.users_configured, status.oidc);
+            } else if (status.sealed) {
+                this.initUnsealMode(status.role === 'admin');
             } else {
                 // Authenticated: Show method
-                UI.setAuthMethod(status.auth_method);
+                UI.setAuthMethod(status.auth_method, status.username);
+                UI.applyRole(status.role);
                 this.startSessionTimer();
                 this.loadFiles();
             }
@@ -180,6 +282,11 @@ const App = {
             e.preventDefault();
             const btn = UI.elements.setupForm.querySelector('button[type="submit"]');
             const key = UI.elements.setupKey.value;
+            const bootstrapToken = document.getElementById('setup-bootstrap').value;
+            if (!bootstrapToken) {
+                UI.showNotification("Enter the bootstrap token from the talos-web log.", "error");
+                return;
+            }
             
             // Disable UI to prevent double submission
             btn.disabled = true;
@@ -192,7 +299,7 @@ const App = {
             } catch (c) { console.error(c); }
 
             try {
-                await API.initializeSystem(key);
+                await API.initializeSystem(key, bootstrapToken);
                 UI.showNotification("SYSTEM INITIALIZED. RELOADING...", "success");
                 setTimeout(() => window.location.reload(), 2000);
             } catch (err) {
@@ -207,14 +314,19 @@ const App = {
             const btn = UI.elements.importForm.querySelector('button[type="submit"]');
             const privateKey = UI.elements.importKey.value;
             const passphrase = UI.elements.importPassphrase.value;
+            const bootstrapToken = document.getElementById('setup-bootstrap').value;
             if (!privateKey) {
                 UI.showNotification("Please provide the GPG private key.", "error");
                 return;
             }
+            if (!bootstrapToken) {
+                UI.showNotification("Enter the bootstrap token from the talos-web log.", "error");
+                return;
+            }
             btn.disabled = true;
             btn.innerText = "IMPORTING...";
             try {
-                await API.importSystem(privateKey, passphrase);
+                await API.importSystem(privateKey, passphrase, bootstrapToken);
                 UI.showNotification("SYSTEM IMPORTED. RELOADING...", "success");
                 setTimeout(() => window.location.reload(), 2000);
             } catch (err) {
@@ -225,17 +337,120 @@ const App = {
         };
     },
 
-    initLoginMode() {
+    initLoginMode(usersConfigured = true, sso = false) {
+        if (!usersConfigured) UI.setAccountSetupMode();
         UI.openLoginModal();
+        // Single sign-on comes back here with the reason when it fails
+        const params = new URLSearchParams(window.location.search);
+        if (params.has('sso_error')) {
+            UI.showNotification("SSO FAILED: " + params.get('sso_error'), "error");
+            history.replaceState(null, '', window.location.pathname);
+        }
+        const ssoButton = document.getElementById('btn-login-sso');
+        if (usersConfigured && sso) ssoButton.classList.remove('hidden');
+        const passkeyButton = document.getElementById('btn-login-passkey');
+        if (!usersConfigured || !window.PublicKeyCredential) passkeyButton.classList.add('hidden');
+        passkeyButton.onclick = async () => {
+            if (!UI.elements.loginUsername.value) {
+                UI.showNotification("ENTER YOUR USERNAME FIRST", "error");
+                return;
+            }
+            try {
+                await API.loginWithPasskey(UI.elements.loginUsername.value);
+                window.location.reload();
+            } catch (err) { UI.showNotification("ACCESS DENIED", "error"); }
+        };
+        let awaitingSecondFactor = false;
         UI.elements.loginForm.onsubmit = async (e) => {
             e.preventDefault();
-            const key = UI.elements.loginKey.value;
+            const username = UI.elements.loginUsername.value;
+            const password = UI.elements.loginPassword.value;
             try {
-                await API.login(key);
+                if (awaitingSecondFactor) {
+                    await API.loginSecondFactor(UI.elements.loginTotp.value);
+                } else if (usersConfigured) {
+                    if (await API.login(username, password)) {
+                        awaitingSecondFactor = true;
+                        UI.elements.loginPassword.value = '';
+                        UI.setSecondFactorMode();
+                        passkeyButton.classList.add('hidden');
+                        ssoButton.classList.add('hidden');
+                        return;
+                    }
+                } else {
+                    await API.setupAccount(UI.elements.loginKey.value, username, password);
+                }
                 window.location.reload();
             } catch (err) {
-                UI.showNotification("ACCESS DENIED", "error");
+                // An expired or exhausted second step starts over from the password
+                if (awaitingSecondFactor && err.message.includes('expired')) window.location.reload();
+                UI.showNotification(usersConfigured ? "ACCESS DENIED" : err.message, "error");
+                UI.elements.loginPassword.value = '';
                 UI.elements.loginKey.value = '';
+                UI.elements.loginTotp.value = '';
+            }
+        };
+    },
+
+    async refreshPasskeys() {
+        const rename = async (pk) => {
+            const name = prompt("NEW NAME FOR THIS PASSKEY", pk.name);
+            if (!name) return;
+            try {
+                await API.renamePasskey(pk.id, name);
+                await this.refreshPasskeys();
+            } catch (err) { UI.showNotification(err.message, "error"); }
+        };
+        const revoke = async (pk) => {
+            if (!confirm(`REVOKE PASSKEY "${pk.name}"?`)) return;
+            try {
+                await API.deletePasskey(pk.id);
+                await this.refreshPasskeys();
+            } catch (err) { UI.showNotification(err.message, "error"); }
+        };
+        UI.renderPasskeys(await API.fetchPasskeys(), rename, revoke);
+    },
+
+    async refreshTokens() {
+        const revoke = async (t) => {
+            if (!confirm(`REVOKE TOKEN "${t.name}"?`)) return;
+            try {
+                await API.deleteToken(t.id);
+                await this.refreshTokens();
+            } catch (err) { UI.showNotification(err.message, "error"); }
+        };
+        UI.renderTokens(await API.fetchTokens(), revoke);
+    },
+
+    async refreshSessions() {
+        const revoke = async (s) => {
+            if (!confirm(s.current ? "SIGN OUT THIS SESSION?" : "REVOKE THIS SESSION?")) return;
+            try {
+                await API.revokeSession(s.id);
+                if (s.current) return window.location.reload();
+                await this.refreshSessions();
+            } catch (err) { UI.showNotification(err.message, "error"); }
+        };
+        UI.renderSessions(await API.fetchSessions(), revoke);
+    },
+
+    // Logged in, but the Bunker restarted and lost the key; only administrators can unseal
+    initUnsealMode(isAdmin) {
+        UI.openUnsealModal();
+        UI.elements.btnUnsealLogout.onclick = () => API.logout().then(() => window.location.reload());
+        if (!isAdmin) {
+            UI.elements.unsealHint.innerText = 'The vault is sealed. Ask an administrator to unseal it.';
+            UI.elements.unsealForm.classList.add('hidden');
+            return;
+        }
+        UI.elements.unsealForm.onsubmit = async (e) => {
+            e.preventDefault();
+            try {
+                await API.unseal(UI.elements.unsealKey.value);
+                window.location.reload();
+            } catch (err) {
+                UI.showNotification("UNSEAL FAILED", "error");
+                UI.elements.unsealKey.value = '';
             }
         };
     },
@@ -272,7 +487,7 @@ const App = {
 
     async loadFiles() {
         try {
-            const tree = await API.fetchTree();
+            const tree = await API.fetchTree(UI.elements.tagFilter.value.trim());
             UI.renderTree(tree, (node) => this.getContextMenuItems(node));
         } catch (e) {
             console.warn("Tree load skipped:", e.message);
@@ -305,20 +520,49 @@ const App = {
                 label: "Edit",
                 icon: "https://cdn.jsdelivr.net/npm/lucide-static@latest/icons/file-edit.svg",
                 action: async () => {
-                    const content = await API.decrypt(node.data.path);
+                    const { content, version } = await API.fetchForEdit(node.data.path);
+                    UI.clearForm();
                     UI.elements.entryPath.value = node.data.path;
                     UI.elements.entryOriginalPath.value = node.data.path;
+                    UI.elements.entryVersion.value = version || '';
                     UI.parseContentToForm(content);
                     UI.openModal();
                 }
             };
         }
 
+        if (!node.data.is_dir) {
+            items.favourite = {
+                label: node.data.favourite ? "Remove Favourite" : "Add to Favourites",
+                icon: "https://cdn.jsdelivr.net/npm/lucide-static@latest/icons/star.svg",
+                action: () => this.handleMetadata(node.data.path, { favourite: !node.data.favourite })
+            };
+            items.tags = {
+                label: "Edit Tags",
+                icon: "https://cdn.jsdelivr.net/npm/lucide-static@latest/icons/tag.svg",
+                action: () => {
+                    const input = prompt("Tags (comma separated):", node.data.tags.join(', '));
+                    if (input === null) return;
+                    const tags = input.split(',').map(t => t.trim()).filter(Boolean);
+                    this.handleMetadata(node.data.path, { tags });
+                }
+            };
+        }
+
+        items.move = {
+            label: node.data.is_dir ? "Rename / Move Category" : "Rename / Move",
+            icon: "https://cdn.jsdelivr.net/npm/lucide-static@latest/icons/folder-input.svg",
+            action: () => this.executeSafe(async () => {
+                const to = prompt(`Move ${node.data.path} to:`, node.data.path);
+                if (to && to !== node.data.path) this.handleMove(node.data.path, to);
+            })
+        };
+
         items.delete = {
             label: "Delete",
             icon: "https://cdn.jsdelivr.net/npm/lucide-static@latest/icons/trash-2.svg",
             action: () => this.executeSafe(() => {
-                if (confirm(`DELETE ${node.data.path} PERMANENTLY?`)) {
+                if (confirm(`MOVE ${node.data.path} TO TRASH?`)) {
                     this.handleDelete(node.data.path);
                 }
             })
@@ -326,6 +570,17 @@ const App = {
         return items;
     },
 
+    async handleMetadata(path, changes) {
+        this.executeSafe(async () => {
+            try {
+                await API.updateMetadata(path, changes);
+                this.loadFiles();
+            } catch (err) {
+                UI.showNotification("ERROR: " + err.message, "error");
+            }
+        });
+    },
+
     async handleDecrypt(path) {
         this.executeSafe(async () => {
             try {
@@ -369,16 +624,20 @@ const App = {
         e.preventDefault();
         this.executeSafe(async () => {
             try {
-                const { path, content, original_path } = UI.getFormData();
+                const { path, content, original_path, version } = UI.getFormData();
                 if (!path || path.endsWith('/')) {
                     UI.showNotification("ERROR: A name for the secret is required.", "error");
                     return;
                 }
 
-                await API.save(path, content, original_path);
+                await API.save(path, content, original_path, version);
                 UI.closeModal();
                 this.loadFiles();
             } catch (err) {
+                if (err.conflict) {
+                    UI.showNotification("CONFLICT: SECRET CHANGED BY ANOTHER USER. REOPEN IT TO EDIT.", "error");
+                    return;
+                }
                 UI.showNotification("ERROR SAVING: " + err.message, "error");
             }
         });
@@ -386,7 +645,15 @@ const App = {
 
     async handleDelete(path) {
         try {
-            await API.delete(path);
+            try {
+                await API.delete(path);
+            } catch (err) {
+                if (!err.notEmpty) throw err;
+                // Non-empty category: require the user to type the path to confirm
+                const confirmation = prompt(`${path} IS NOT EMPTY. TYPE ITS PATH TO DELETE EVERYTHING INSIDE:`);
+                if (confirmation === null) return;
+                await API.delete(path, true, confirmation);
+            }
             UI.elements.header.innerText = 'IDLE_SYSTEM';
             UI.elements.viewer.innerText = '';
             this.loadFiles();
@@ -395,21 +662,87 @@ const App = {
         }
     },
 
+    async openTrash() {
+        try {
+            const entries = await API.fetchTrash();
+            UI.renderTrash(entries,
+                async (entry) => {
+                    try {
+                        await API.restoreFromTrash(entry.id);
+                        UI.showNotification(`RESTORED ${entry.original_path}`, "success");
+                        this.openTrash();
+                        this.loadFiles();
+                    } catch (err) { UI.showNotification("ERROR RESTORING: " + err.message, "error"); }
+                },
+                async (entry) => {
+                    if (!confirm(`PERMANENTLY PURGE ${entry.original_path}?`)) return;
+                    try {
+                        await API.purgeTrash(entry.id);
+                        this.openTrash();
+                    } catch (err) { UI.showNotification("ERROR PURGING: " + err.message, "error"); }
+                });
+            UI.openTrashModal();
+        } catch (e) { UI.showNotification("Failed to fetch trash", "error"); }
+    },
+
+    async handleMove(from, to) {
+        try {
+            await API.move(from, to.replace(/^\/+|\/+$/g, ''));
+            UI.showNotification(`MOVED TO ${to}`, "success");
+            this.loadFiles();
+        } catch (err) {
+            UI.showNotification("ERROR MOVING: " + err.message, "error");
+        }
+    },
+
     async handleRestore(e) {
         const file = e.target.files[0];
         if (!file) return;
         
         this.executeSafe(async () => {
-            if (confirm("WARNING: This will overwrite existing secrets. Continue?")) {
+            const options = {};
+            // Preview first, retrying with a passphrase or the legacy flag when the server asks for them
+            let preview = null;
+            for (;;) {
                 try {
-                    await API.restore(file);
-                    UI.showNotification("Restored successfully!", "success");
-                    this.loadFiles();
+                    preview = await API.previewRestore(file, options);
                 } catch (err) {
+                    if (err.passphrase && !options.passphrase) {
+                        options.passphrase = prompt("Backup passphrase:");
+                        if (options.passphrase) continue;
+                    } else if (err.legacy && !options.legacy
+                        && confirm("This backup is NOT encrypted (legacy format) and cannot be verified. Import anyway?")) {
+                        options.legacy = true;
+                        continue;
+                    }
                     UI.showNotification("ERROR RESTORING: " + err.message, "error");
                 }
+                break;
             }
             e.target.value = ''; // reset input
+            if (!preview) return;
+
+            UI.renderRestorePreview(preview);
+            UI.elements.btnConfirmRestore.onclick = async () => {
+                const paths = UI.getSelectedRestorePaths();
+                if (!paths.length) return;
+                const policy = UI.elements.restorePolicy.value;
+                if (!confirm(`RESTORE ${paths.length} ENTRIES (${policy.toUpperCase()})? The current state is snapshotted first.`)) return;
+                try {
+                    const result = await API.restore(file, { ...options, paths, policy });
+                    UI.closeRestoreModal();
+                    const restored = (result.restored || []).length + (result.kept_both || []).length;
+                    UI.showNotification(`Restored ${restored} entries, skipped ${(result.skipped || []).length}`, "success");
+                    this.loadFiles();
+                } catch (err) {
+                    // Validation reports list every offending entry; the store was left untouched
+                    const details = err.report
+                        ? ' // ' + err.report.errors.slice(0, 3).map(e => `${e.path}: ${e.error}`).join(' // ')
+                        : '';
+                    UI.showNotification("ERROR RESTORING: " + err.message + details, "error");
+                }
+            };
+            UI.openRestoreModal();
         });
     },
 
@@ -2,6 +2,8 @@ import { API } from './api.js';
 
 export const UI = {
     elements: {},
+    // Paths returned by the server-side search for the current query
+    searchMatches: new Set(),
 
     init() {
         this.elements = {
@@ -9,6 +11,7 @@ export const UI = {
             header: document.getElementById('viewer-header'),
             treeSearch: document.getElementById('tree-search'),
             clearSearch: document.getElementById('clear-search'),
+            tagFilter: document.getElementById('tag-filter'),
             viewer: document.getElementById('viewer-content'),
             modal: document.getElementById('modal'),
             form: document.getElementById('encrypt-form'),
@@ -20,6 +23,7 @@ export const UI = {
             authText: document.getElementById('auth-text'),
             entryPath: document.getElementById('entry-path'),
             entryOriginalPath: document.getElementById('entry-original-path'),
+            entryVersion: document.getElementById('entry-version'),
             entryUser: document.getElementById('entry-user'),
             entrySecret: document.getElementById('entry-secret'),
             entryUrl: document.getElementById('entry-url'),
@@ -51,10 +55,33 @@ export const UI = {
             auditModal: document.getElementById('audit-modal'),
             auditTableBody: document.getElementById('audit-table-body'),
             btnCloseAudit: document.getElementById('btn-close-audit'),
+            // Trash Elements
+            trashModal: document.getElementById('trash-modal'),
+            trashTableBody: document.getElementById('trash-table-body'),
+            btnCloseTrash: document.getElementById('btn-close-trash'),
+            btnEmptyTrash: document.getElementById('btn-empty-trash'),
+            restoreModal: document.getElementById('restore-modal'),
+            restoreTableBody: document.getElementById('restore-table-body'),
+            restoreSummary: document.getElementById('restore-summary'),
+            restorePolicy: document.getElementById('restore-policy'),
+            btnConfirmRestore: document.getElementById('btn-confirm-restore'),
+            btnCloseRestore: document.getElementById('btn-close-restore'),
             // Login Elements
             loginModal: document.getElementById('login-modal'),
             loginForm: document.getElementById('login-form'),
+            loginTitle: document.getElementById('login-title'),
+            loginHint: document.getElementById('login-hint'),
             loginKey: document.getElementById('login-key'),
+            loginUsername: document.getElementById('login-username'),
+            loginPassword: document.getElementById('login-password'),
+            loginTotp: document.getElementById('login-totp'),
+            btnLogin: document.getElementById('btn-login'),
+            // Unseal Elements
+            unsealModal: document.getElementById('unseal-modal'),
+            unsealForm: document.getElementById('unseal-form'),
+            unsealKey: document.getElementById('unseal-key'),
+            unsealHint: document.getElementById('unseal-hint'),
+            btnUnsealLogout: document.getElementById('btn-unseal-logout'),
             notificationArea: document.getElementById('notification-area'),
         };
     },
@@ -68,9 +95,159 @@ export const UI = {
     openAuditModal() { this.elements.auditModal.classList.remove('hidden'); },
     closeAuditModal() { this.elements.auditModal.classList.add('hidden'); },
 
+    openTrashModal() { this.elements.trashModal.classList.remove('hidden'); },
+    closeTrashModal() { this.elements.trashModal.classList.add('hidden'); },
+
+    openRestoreModal() { this.elements.restoreModal.classList.remove('hidden'); },
+    closeRestoreModal() { this.elements.restoreModal.classList.add('hidden'); },
+
     openLoginModal() { this.elements.loginModal.classList.remove('hidden'); },
     closeLoginModal() { this.elements.loginModal.classList.add('hidden'); },
 
+    openUnsealModal() { this.elements.unsealModal.classList.remove('hidden'); },
+
+    // Password accepted; the same form now asks for the second factor
+    setSecondFactorMode() {
+        this.elements.loginTitle.innerText = 'Second Factor';
+        this.elements.loginHint.innerText = 'Enter the code from your authenticator app or a recovery code.';
+        this.elements.loginHint.classList.remove('hidden');
+        this.elements.loginUsername.classList.add('hidden');
+        this.elements.loginUsername.required = false;
+        this.elements.loginPassword.classList.add('hidden');
+        this.elements.loginPassword.required = false;
+        this.elements.loginTotp.classList.remove('hidden');
+        this.elements.loginTotp.required = true;
+        this.elements.loginTotp.focus();
+        this.elements.btnLogin.innerText = 'Verify';
+    },
+
+    openTotpModal(status) {
+        const el = (id) => document.getElementById(id);
+        ['btn-totp-setup', 'totp-enable-form', 'totp-recovery', 'totp-disable-form'].forEach(id => el(id).classList.add('hidden'));
+        el('totp-qr').innerHTML = '';
+        if (status.enabled) {
+            el('totp-status').innerText = `Enabled. ${status.recovery_codes_remaining} recovery codes left.`;
+            el('totp-disable-form').classList.remove('hidden');
+        } else {
+            el('totp-status').innerText = 'Not enabled. Sign-ins only need your password.';
+            el('btn-totp-setup').classList.remove('hidden');
+        }
+        el('totp-modal').classList.remove('hidden');
+    },
+
+    showTotpEnrolment(secret, uri) {
+        const el = (id) => document.getElementById(id);
+        el('btn-totp-setup').classList.add('hidden');
+        el('totp-secret').innerText = secret;
+        if (window.QRCode) new QRCode(el('totp-qr'), { text: uri, width: 160, height: 160 });
+        el('totp-enable-form').classList.remove('hidden');
+        el('totp-enable-code').focus();
+    },
+
+    showRecoveryCodes(codes) {
+        const el = (id) => document.getElementById(id);
+        el('totp-enable-form').classList.add('hidden');
+        el('totp-status').innerText = 'Enabled.';
+        el('totp-recovery-codes').innerText = codes.join('\n');
+        el('totp-recovery').classList.remove('hidden');
+    },
+
+    renderPasskeys(passkeys, onRename, onDelete) {
+        const list = document.getElementById('passkey-list');
+        list.innerHTML = '';
+        if (passkeys.length === 0) {
+            list.innerHTML = '<li class="text-zinc-600">No passkeys registered.</li>';
+            return;
+        }
+        passkeys.forEach(pk => {
+            const li = document.createElement('li');
+            li.className = 'flex items-center justify-between border border-zinc-900 p-2';
+            const label = document.createElement('span');
+            label.innerText = `${pk.name} · ${pk.last_used_at ? 'used ' + pk.last_used_at : 'never used'}`;
+            const actions = document.createElement('span');
+            actions.className = 'flex gap-3';
+            const rename = document.createElement('button');
+            rename.className = 'text-zinc-500 hover:text-white uppercase';
+            rename.innerText = 'Rename';
+            rename.onclick = () => onRename(pk);
+            const remove = document.createElement('button');
+            remove.className = 'text-zinc-500 hover:text-red-500 uppercase';
+            remove.innerText = 'Revoke';
+            remove.onclick = () => onDelete(pk);
+            actions.append(rename, remove);
+            li.append(label, actions);
+            list.appendChild(li);
+        });
+    },
+
+    renderTokens(tokens, onDelete) {
+        const list = document.getElementById('token-list');
+        list.innerHTML = '';
+        if (tokens.length === 0) {
+            list.innerHTML = '<li class="text-zinc-600">No API tokens.</li>';
+            return;
+        }
+        tokens.forEach(t => {
+            const li = document.createElement('li');
+            li.className = 'flex items-center justify-between border border-zinc-900 p-2';
+            const label = document.createElement('span');
+            label.innerText = `${t.name} · ${t.access} ${t.path || '*'} · expires ${t.expires_at}`;
+            const remove = document.createElement('button');
+            remove.className = 'text-zinc-500 hover:text-red-500 uppercase';
+            remove.innerText = 'Revoke';
+            remove.onclick = () => onDelete(t);
+            li.append(label, remove);
+            list.appendChild(li);
+        });
+    },
+
+    renderSessions(sessions, onRevoke) {
+        const list = document.getElementById('session-list');
+        list.innerHTML = '';
+        sessions.forEach(s => {
+            const li = document.createElement('li');
+            li.className = 'flex items-center justify-between gap-3 border border-zinc-900 p-2';
+            const label = document.createElement('span');
+            label.className = 'min-w-0';
+            const where = document.createElement('div');
+            where.innerText = `${s.ip_address || 'unknown'} · ${s.auth_method || ''}${s.current ? ' · this session' : ''}`;
+            if (s.current) where.classList.add('text-green-500');
+            const agent = document.createElement('div');
+            agent.className = 'text-zinc-600 truncate';
+            agent.innerText = s.user_agent || 'unknown client';
+            agent.title = s.user_agent || '';
+            const when = document.createElement('div');
+            when.className = 'text-zinc-600';
+            when.innerText = `signed in ${s.created_at} · last seen ${s.last_seen_at}`;
+            label.append(where, agent, when);
+            const remove = document.createElement('button');
+            remove.className = 'text-zinc-500 hover:text-red-500 uppercase';
+            remove.innerText = s.current ? 'Sign Out' : 'Revoke';
+            remove.onclick = () => onRevoke(s);
+            li.append(label, remove);
+            list.appendChild(li);
+        });
+    },
+
+    showCreatedToken(token) {
+        document.getElementById('token-value').innerText = token;
+        document.getElementById('token-created').classList.remove('hidden');
+    },
+
+    closeTotpModal() { document.getElementById('totp-modal').classList.add('hidden'); },
+
+    // First start: the login form also asks for the Master Key and creates the administrator
+    setAccountSetupMode() {
+        this.elements.loginTitle.innerText = 'Create Administrator';
+        this.elements.loginHint.innerText = 'No accounts exist yet. Prove the Master Key to create the first one.';
+        this.elements.loginHint.classList.remove('hidden');
+        this.elements.loginKey.classList.remove('hidden');
+        this.elements.loginKey.required = true;
+        this.elements.loginPassword.placeholder = 'NEW PASSWORD (12+ CHARACTERS)';
+        this.elements.loginPassword.autocomplete = 'new-password';
+        this.elements.btnLogin.innerText = 'Create Account';
+    },
+
     showNotification(message, type = 'info') {
         const notif = document.createElement('div');
         let colors = 'border-zinc-500 text-zinc-300 shadow-[0_0_10px_rgba(113,113,122,0.3)]';
@@ -102,7 +279,24 @@ export const UI = {
         updateIndicator(this.elements.statusBunker, status.bunker);
     },
 
-    setAuthMethod(method) {
+    // Hides controls the server would refuse for this role anyway
+    applyRole(role) {
+        const visible = {
+            'btn-backup': role === 'admin',
+            'btn-restore': role === 'admin',
+            'btn-empty-trash': role === 'admin',
+            'btn-audit': role === 'admin' || role === 'auditor',
+            'btn-trash': role !== 'auditor',
+            'btn-new-category': role === 'admin' || role === 'editor',
+            'btn-new-secret': role === 'admin' || role === 'editor',
+        };
+        Object.entries(visible).forEach(([id, show]) => {
+            const el = document.getElementById(id);
+            if (el) el.classList.toggle('hidden', !show);
+        });
+    },
+
+    setAuthMethod(method, username = null) {
         this.elements.authIndicator.classList.remove('hidden');
         if (method === 'mtls') {
             this.elements.authIcon.setAttribute('data-lucide', 'award');
@@ -112,7 +306,7 @@ export const UI = {
         } else {
             this.elements.authIcon.setAttribute('data-lucide', 'key');
             this.elements.authIcon.classList.replace('text-green-500', 'text-zinc-500');
-            this.elements.authText.innerText = 'MASTER KEY';
+            this.elements.authText.innerText = username || 'PASSWORD';
             this.elements.authText.classList.replace('text-green-500', 'text-zinc-500');
         }
         // @ts-ignore
@@ -127,14 +321,33 @@ export const UI = {
         }
     },
 
+    escapeHtml(text) {
+        const div = document.createElement('div');
+        div.textContent = text;
+        return div.innerHTML;
+    },
+
+    nodeLabel(node) {
+        let label = this.escapeHtml(node.name);
+        if (node.favourite) label = `<span class="text-yellow-500">★</span> ${label}`;
+        if (node.tags && node.tags.length) {
+            label += ` <span class="text-zinc-600 text-[10px]">${node.tags.map(t => '#' + this.escapeHtml(t)).join(' ')}</span>`;
+        }
+        return label;
+    },
+
     transformDataForJsTree(nodes) {
         return nodes.map(node => ({
-            text: node.name,
+            text: this.nodeLabel(node),
             icon: node.is_dir ? 'jstree-folder' : 'jstree-file',
             children: node.children ? this.transformDataForJsTree(node.children) : [],
             data: {
+                name: node.name,
                 path: node.path,
-                is_dir: node.is_dir
+                is_dir: node.is_dir,
+                version: node.version || null,
+                tags: node.tags || [],
+                favourite: !!node.favourite
             }
         }));
     },
@@ -160,6 +373,9 @@ export const UI = {
                 'search': {
                     'case_insensitive': true,
                     'show_only_matches': true,
+                    // Match on the node name or on the server-side metadata search
+                    'search_callback': (str, node) =>
+                        node.data.name.toLowerCase().includes(str.toLowerCase()) || this.searchMatches.has(node.data.path)
                 }
             });
         }
@@ -225,6 +441,7 @@ export const UI = {
         return {
             path: this.elements.entryPath.value,
             original_path: this.elements.entryOriginalPath.value || null,
+            version: this.elements.entryVersion.value || null,
             content: content
         };
     },
@@ -249,6 +466,7 @@ export const UI = {
     clearForm() {
         this.elements.form.reset();
         this.elements.entryOriginalPath.value = '';
+        this.elements.entryVersion.value = '';
     },
 
     renderSecretView(path, text) {
@@ -366,6 +584,82 @@ export const UI = {
         lucide.createIcons();
     },
 
+    renderTrash(entries, onRestore, onPurge) {
+        this.elements.trashTableBody.innerHTML = '';
+        entries.forEach(entry => {
+            const row = document.createElement('tr');
+            row.className = 'border-b border-zinc-900/50 hover:bg-zinc-900/30 transition-colors';
+
+            const cell = (text, className) => {
+                const td = document.createElement('td');
+                td.className = className;
+                td.innerText = text;
+                row.appendChild(td);
+                return td;
+            };
+            cell(`${entry.original_path}${entry.is_dir ? '/' : ''}`, 'py-2 text-zinc-300');
+            cell(new Date(entry.deleted_at).toLocaleString(), 'py-2 text-zinc-500 text-xs');
+            cell(entry.deleted_by, 'py-2 text-zinc-400');
+            cell(String(entry.secrets.length), 'py-2 text-zinc-400');
+
+            const actions = cell('', 'py-2 text-right space-x-3');
+            const restoreBtn = document.createElement('button');
+            restoreBtn.className = 'text-green-500 hover:text-white uppercase';
+            restoreBtn.innerText = 'Restore';
+            restoreBtn.onclick = () => onRestore(entry);
+            const purgeBtn = document.createElement('button');
+            purgeBtn.className = 'text-red-500 hover:text-white uppercase';
+            purgeBtn.innerText = 'Purge';
+            purgeBtn.onclick = () => onPurge(entry);
+            actions.appendChild(restoreBtn);
+            actions.appendChild(purgeBtn);
+
+            this.elements.trashTableBody.appendChild(row);
+        });
+    },
+
+    renderRestorePreview(preview) {
+        const counts = {};
+        preview.entries.forEach(e => counts[e.action] = (counts[e.action] || 0) + 1);
+        const created = preview.created_at ? `BACKUP FROM ${new Date(preview.created_at).toLocaleString()}` : 'LEGACY BACKUP (UNVERIFIED)';
+        this.elements.restoreSummary.innerText = `${created} // ${Object.entries(counts).map(([a, n]) => `${n} ${a.toUpperCase()}`).join(' // ')}`;
+
+        const colors = { add: 'text-green-400', overwrite: 'text-orange-400', unchanged: 'text-zinc-600', untouched: 'text-zinc-600' };
+        this.elements.restoreTableBody.innerHTML = '';
+        preview.entries.forEach(entry => {
+            const row = document.createElement('tr');
+            row.className = 'border-b border-zinc-900/50 hover:bg-zinc-900/30 transition-colors';
+
+            const cell = (text, className) => {
+                const td = document.createElement('td');
+                td.className = className;
+                td.innerText = text;
+                row.appendChild(td);
+                return td;
+            };
+            const select = cell('', 'py-2');
+            // Only entries the restore would change can be selected
+            if (entry.action === 'add' || entry.action === 'overwrite') {
+                const checkbox = document.createElement('input');
+                checkbox.type = 'checkbox';
+                checkbox.checked = true;
+                checkbox.dataset.path = entry.path;
+                select.appendChild(checkbox);
+            }
+            const when = (t) => t ? new Date(t).toLocaleString() : '-';
+            cell(entry.path, 'py-2 text-zinc-300');
+            cell(entry.action.toUpperCase(), `py-2 ${colors[entry.action] || 'text-zinc-400'}`);
+            cell(when(entry.backup_modified), 'py-2 text-zinc-500 text-xs');
+            cell(when(entry.current_modified), 'py-2 text-zinc-500 text-xs');
+
+            this.elements.restoreTableBody.appendChild(row);
+        });
+    },
+
+    getSelectedRestorePaths() {
+        return [...this.elements.restoreTableBody.querySelectorAll('input[type=checkbox]:checked')].map(c => c.dataset.path);
+    },
+
     renderAuditLogs(logs) {
         this.elements.auditTableBody.innerHTML = '';
         logs.forEach(log => {
@@ -382,6 +676,7 @@ export const UI = {
                 <td class="py-2 text-zinc-500 text-xs">${new Date(log.timestamp + 'Z').toLocaleString()}</td>
                 <td class="py-2 font-bold ${actionColor}">${log.action}</td>
                 <td class="py-2 text-zinc-300">${log.target}</td>
+                <td class="py-2 text-zinc-300">${log.username || '-'}</td>
                 <td class="py-2 text-zinc-400">${log.ip_address || '-'}</td>
                 <td class="py-2 text-zinc-500 text-xs truncate max-w-xs" title="${log.user_agent || ''}">${log.user_agent || '-'}</td>
                 <td class="py-2 text-zinc-400">${log.auth_method || 'system'}</td>
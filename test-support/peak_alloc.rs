@@ -0,0 +1,44 @@
+// Test-only global allocator shared by the talos-storage and talos-web test
+// binaries. Live heap bytes are counted per thread, so a test bounding the
+// memory of code it runs on its own thread is not skewed by tests running
+// in parallel.
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+struct PeakAlloc;
+
+thread_local! {
+    // Signed, as memory allocated on one thread may be freed on another
+    static LIVE: Cell<isize> = const { Cell::new(0) };
+    static PEAK: Cell<isize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            // try_with: a thread's locals are gone while it exits
+            let _ = LIVE.try_with(|live| {
+                live.set(live.get() + layout.size() as isize);
+                let _ = PEAK.try_with(|peak| peak.set(peak.get().max(live.get())));
+            });
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        let _ = LIVE.try_with(|live| live.set(live.get() - layout.size() as isize));
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAlloc = PeakAlloc;
+
+// Highest heap growth on the current thread while `f` runs, in bytes
+pub fn peak_since(f: impl FnOnce()) -> usize {
+    let baseline = LIVE.with(Cell::get);
+    PEAK.with(|peak| peak.set(baseline));
+    f();
+    (PEAK.with(Cell::get) - baseline).max(0) as usize
+}